@@ -0,0 +1,335 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::{
+    extract::{FromRef, MatchedPath, Query, RawPathParams, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, RequestExt,
+};
+use axum_extra::extract::cookie::{Cookie, Key, SameSite, SignedCookieJar};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256, Sha512};
+use std::sync::OnceLock;
+
+use crate::background_discovery::unix_now;
+use crate::config::{AuthConfig, User};
+use crate::routes::AppState;
+use crate::tokens::{bearer_secret, required_scope};
+
+// Cookie holding the signed session
+pub const SESSION_COOKIE: &str = "wololo_session";
+
+// The logged-in user, added to the request by `require_login`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrentUser(pub String);
+
+// An Argon2id PHC string for a password, with a random salt
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// Whether `password_hash` is a PHC string Argon2 can check against
+pub fn is_valid_hash(password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
+pub fn verify_password(password_hash: &str, password: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+// Users from auth.users followed by those in auth.users_file
+pub fn load_users(auth: &AuthConfig) -> Result<Vec<User>, String> {
+    let mut users = auth.users.clone();
+    if let Some(path) = &auth.users_file {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let from_file: Vec<User> = serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        users.extend(from_file);
+    }
+    Ok(users)
+}
+
+// The users as they are now; auth.users alone when auth.users_file cannot be read.
+// Requests use the copy cached by ConfigHandle::users instead of reading the file.
+pub fn current_users(auth: &AuthConfig) -> Vec<User> {
+    load_users(auth).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        auth.users.clone()
+    })
+}
+
+// The user with this username and password. Unknown users are checked against a dummy hash
+// so they take as long as wrong passwords.
+pub fn authenticate(users: &[User], username: &str, password: &str) -> Option<User> {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+    match users.iter().find(|user| user.username == username) {
+        Some(user) => verify_password(&user.password_hash, password).then(|| user.clone()),
+        None => {
+            let dummy = DUMMY_HASH.get_or_init(|| hash_password("").unwrap_or_default());
+            verify_password(dummy, password);
+            None
+        }
+    }
+}
+
+// Key for signing session cookies: derived from auth.session_secret, or random
+pub fn session_key(auth: &AuthConfig) -> Key {
+    match &auth.session_secret {
+        Some(secret) => Key::from(&Sha512::digest(secret.as_bytes())),
+        None => Key::generate(),
+    }
+}
+
+impl FromRef<AppState> for Key {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.session_key.clone()
+    }
+}
+
+// Short digest of a password hash, so changing the password ends the user's sessions
+fn password_fingerprint(password_hash: &str) -> String {
+    Sha256::digest(password_hash.as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// The session cookie for a user, valid for auth.session_hours. Its value is
+// "<expiry in Unix seconds>:<password fingerprint>:<username>", signed by the jar it is
+// added to.
+pub fn session_cookie(user: &User, auth: &AuthConfig, now: u64) -> Cookie<'static> {
+    let seconds = auth.session_hours.saturating_mul(3600);
+    let value = format!(
+        "{}:{}:{}",
+        now + seconds,
+        password_fingerprint(&user.password_hash),
+        user.username
+    );
+    Cookie::build((SESSION_COOKIE, value))
+        .path("/")
+        .http_only(true)
+        .secure(auth.secure_cookie)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(seconds as i64))
+        .build()
+}
+
+// The user of a correctly signed session cookie that has not expired, as long as the user
+// still exists with the same password
+pub fn session_user(jar: &SignedCookieJar, users: &[User], now: u64) -> Option<String> {
+    let cookie = jar.get(SESSION_COOKIE)?;
+    let mut parts = cookie.value().splitn(3, ':');
+    let (expires_at, fingerprint, username) = (parts.next()?, parts.next()?, parts.next()?);
+    let expires_at: u64 = expires_at.parse().ok()?;
+    if now >= expires_at {
+        return None;
+    }
+    users
+        .iter()
+        .any(|user| {
+            user.username == username && password_fingerprint(&user.password_hash) == fingerprint
+        })
+        .then(|| username.to_string())
+}
+
+// Middleware that lets requests with a valid session or API token through when auth.enabled
+// is set. Pages redirect to /login, htmx requests get an HX-Redirect there and anything else
+// a 401.
+pub async fn require_login(
+    State(app_state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let config = app_state.config.get();
+    if !config.auth.enabled {
+        return next.run(request).await;
+    }
+
+    let jar = SignedCookieJar::from_headers(request.headers(), app_state.session_key.clone());
+    if let Some(username) = session_user(&jar, &app_state.config.users(), unix_now()) {
+        request.extensions_mut().insert(CurrentUser(username));
+        return next.run(request).await;
+    }
+    if bearer_secret(request.headers()).is_some() {
+        return check_token(app_state, request, next).await;
+    }
+
+    let headers = request.headers();
+    if headers.contains_key("HX-Request") {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [("HX-Redirect", "/login")],
+            "Login required",
+        )
+            .into_response();
+    }
+    if request.method() == Method::GET && accepts_html(headers) {
+        let next = request
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        let query = serde_urlencoded::to_string([("next", next)]).unwrap_or_default();
+        return Redirect::to(&format!("/login?{}", query)).into_response();
+    }
+    (StatusCode::UNAUTHORIZED, "Login required").into_response()
+}
+
+// Let a request with a bearer token through when the token has the route's scope and, for
+// tokens limited to some devices, the route is for one of them
+async fn check_token(app_state: AppState, mut request: Request, next: Next) -> Response {
+    let secret = bearer_secret(request.headers())
+        .unwrap_or_default()
+        .to_string();
+    let Some(token) = app_state.tokens.authenticate(&secret, unix_now()).await else {
+        return (StatusCode::UNAUTHORIZED, "Invalid API token").into_response();
+    };
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let Some(scope) = required_scope(request.method(), &route) else {
+        return (StatusCode::FORBIDDEN, "API tokens cannot be used here").into_response();
+    };
+    let device_id = match request.extract_parts::<RawPathParams>().await {
+        Ok(params) => params
+            .iter()
+            .find(|(name, _)| *name == "device_id")
+            .map(|(_, value)| value.to_string()),
+        Err(_) => None,
+    };
+    let config = app_state.config.get();
+    let device = device_id.as_deref().map(|id| config.find_device(id));
+    if let Err(e) = token.check(scope, device) {
+        return (StatusCode::FORBIDDEN, e).into_response();
+    }
+    // Routes that take further commands, like the WebSocket, check them against the token
+    request.extensions_mut().insert(token);
+    next.run(request).await
+}
+
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+// Where to go after logging in: a path on this server, or the dashboard
+fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next
+        }
+        _ => "/",
+    }
+}
+
+fn login_page(
+    app_state: &AppState,
+    status: StatusCode,
+    username: &str,
+    next: &str,
+    error: Option<&str>,
+) -> Response {
+    let data = json!({
+        "username": username,
+        "next": next,
+        "error": error,
+    });
+    match app_state.handlebars.render("login", &data) {
+        Ok(html) => (status, Html(html)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to render login page: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to render login page",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+// Handler for GET /login
+pub async fn login_page_handler(
+    State(app_state): State<AppState>,
+    jar: SignedCookieJar,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    let next = safe_next(query.next.as_deref());
+    let auth = &app_state.config.get().auth;
+    // Nothing to log in to, or already logged in
+    if !auth.enabled || session_user(&jar, &app_state.config.users(), unix_now()).is_some() {
+        return Redirect::to(next).into_response();
+    }
+    login_page(&app_state, StatusCode::OK, "", next, None)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+// Handler for POST /login
+pub async fn login_handler(
+    State(app_state): State<AppState>,
+    jar: SignedCookieJar,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let auth = app_state.config.get().auth.clone();
+    let next = safe_next(form.next.as_deref());
+    if !auth.enabled {
+        return Redirect::to(next).into_response();
+    }
+
+    // Argon2 is deliberately slow; keep it off the async workers
+    let (username, password) = (form.username.clone(), form.password);
+    let users = app_state.config.users();
+    let user = tokio::task::spawn_blocking(move || authenticate(&users, &username, &password))
+        .await
+        .unwrap_or(None);
+
+    let Some(user) = user else {
+        eprintln!("Failed login for '{}'", form.username);
+        return login_page(
+            &app_state,
+            StatusCode::UNAUTHORIZED,
+            &form.username,
+            next,
+            Some("Wrong username or password"),
+        );
+    };
+    println!("{} logged in", user.username);
+    let jar = jar.add(session_cookie(&user, &auth, unix_now()));
+    (jar, Redirect::to(next)).into_response()
+}
+
+// Handler for POST /logout
+pub async fn logout_handler(jar: SignedCookieJar) -> Response {
+    let jar = jar.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    (jar, Redirect::to("/login")).into_response()
+}
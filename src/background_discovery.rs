@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::events::Event;
+use crate::json_store::JsonStore;
+use crate::routes::{discover_network_devices, normalize_mac, AppState, DiscoveredDevice};
+
+// A MAC address background discovery has come across
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeenDevice {
+    pub mac_address: String,
+    pub ip_address: String,
+    pub hostname: Option<String>,
+    // Unix timestamps in seconds
+    pub first_seen: u64,
+    pub last_seen: u64,
+    // False until someone dismisses the new-device alert
+    pub acknowledged: bool,
+}
+
+// Persistent record of every MAC address seen, sorted by MAC
+#[derive(Clone)]
+pub struct SeenDevices {
+    devices: JsonStore<Vec<SeenDevice>>,
+}
+
+impl SeenDevices {
+    // Load the seen list from disk; a missing or unreadable file starts an empty list
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self {
+            devices: JsonStore::load(path),
+        }
+    }
+
+    // A seen list that is never written to disk (used by tests)
+    pub fn in_memory() -> Self {
+        Self {
+            devices: JsonStore::in_memory(),
+        }
+    }
+
+    // Record a scan and return the devices that are neither configured nor seen before.
+    // The very first scan only establishes a baseline so an empty list does not alert on everything.
+    pub async fn record(&self, found: &[DiscoveredDevice], config: &Config) -> Vec<SeenDevice> {
+        let configured: std::collections::HashSet<String> = config
+            .devices
+            .iter()
+            .map(|device| normalize_mac(&device.mac_address))
+            .collect();
+        let now = unix_now();
+
+        let mut new_devices = Vec::new();
+        let mut devices = self.devices.lock().await;
+        let baseline = devices.is_empty();
+
+        for device in found {
+            let Some(mac) = &device.mac_address else {
+                continue;
+            };
+            let key = normalize_mac(mac);
+
+            match devices
+                .iter_mut()
+                .find(|seen| normalize_mac(&seen.mac_address) == key)
+            {
+                Some(seen) => {
+                    seen.ip_address = device.ip_address.clone();
+                    if device.hostname.is_some() {
+                        seen.hostname = device.hostname.clone();
+                    }
+                    seen.last_seen = now;
+                }
+                None => {
+                    let alert = !baseline && !configured.contains(&key);
+                    let seen = SeenDevice {
+                        mac_address: mac.clone(),
+                        ip_address: device.ip_address.clone(),
+                        hostname: device.hostname.clone(),
+                        first_seen: now,
+                        last_seen: now,
+                        acknowledged: !alert,
+                    };
+                    if alert {
+                        new_devices.push(seen.clone());
+                    }
+                    devices.push(seen);
+                }
+            }
+        }
+
+        devices.sort_by(|a, b| a.mac_address.cmp(&b.mac_address));
+        devices.save().await;
+        new_devices
+    }
+
+    // Devices whose new-device alert has not been dismissed, oldest first
+    pub async fn unacknowledged(&self) -> Vec<SeenDevice> {
+        let devices = self.devices.lock().await;
+        let mut list: Vec<SeenDevice> = devices
+            .iter()
+            .filter(|device| !device.acknowledged)
+            .cloned()
+            .collect();
+        list.sort_by_key(|device| device.first_seen);
+        list
+    }
+
+    // Dismiss the alert for one MAC, or for every device when `mac` is None.
+    // Returns how many alerts were dismissed.
+    pub async fn acknowledge(&self, mac: Option<&str>) -> usize {
+        let mut devices = self.devices.lock().await;
+        let target = mac.map(normalize_mac);
+        let mut count = 0;
+        for device in devices.iter_mut() {
+            if !device.acknowledged
+                && target
+                    .as_ref()
+                    .is_none_or(|t| *t == normalize_mac(&device.mac_address))
+            {
+                device.acknowledged = true;
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            devices.save().await;
+        }
+        count
+    }
+
+    pub async fn all(&self) -> Vec<SeenDevice> {
+        self.devices.lock().await.clone()
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Run one background scan, record it and publish an event per new device
+pub async fn run_background_scan(app_state: &AppState) -> Vec<SeenDevice> {
+    let config = app_state.config.get();
+    let background = &config.discovery.background;
+
+    let started = Instant::now();
+    let found =
+        discover_network_devices(Some(background.interfaces.clone()), &config.discovery).await;
+    app_state
+        .metrics
+        .record_scan("background", started.elapsed(), found.len());
+    let new_devices = app_state.seen_devices.record(&found, &config).await;
+
+    for device in &new_devices {
+        println!(
+            "New device detected on the network: {} ({})",
+            device.mac_address, device.ip_address
+        );
+        app_state.events.publish(Event::NewDevice {
+            device: device.clone(),
+        });
+    }
+
+    new_devices
+}
+
+// Start the periodic background discovery task
+pub fn spawn_background_discovery(app_state: AppState) -> JoinHandle<()> {
+    let interval_seconds = app_state
+        .config
+        .get()
+        .discovery
+        .background
+        .interval_seconds
+        .max(60);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            println!("Running background discovery scan...");
+            let new_devices = run_background_scan(&app_state).await;
+            println!(
+                "Background discovery finished: {} new device(s)",
+                new_devices.len()
+            );
+        }
+    })
+}
@@ -0,0 +1,449 @@
+use clap::{Args, Parser, Subcommand};
+use ipnet::Ipv4Net;
+use std::path::{Path, PathBuf};
+
+use crate::auth::hash_password;
+use crate::config::{Config, DEFAULT_CONFIG_PATH};
+use crate::export::{export_devices, ExportFormat};
+use crate::history::{ActionKind, ActionRecord, History};
+use crate::import::{parse_inventory, InventoryFormat};
+use crate::routes::{
+    diff_discovered_devices, discover_network_devices, generate_config_yaml_with_updates,
+    normalize_mac, ping_device, scan_cidr, send_wake_packet, DeviceStatus, DeviceUpdate,
+    DiscoveredDevice,
+};
+use crate::validation::{check_config_file, ConfigIssue, Severity};
+
+// Exit codes shared by every subcommand so scripts can tell failures apart.
+// 2 is what clap uses for bad arguments.
+pub const EXIT_OK: u8 = 0;
+// The command ran but did not fully succeed (device offline, packet not sent, bad rows)
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+// The config file is missing or invalid
+pub const EXIT_CONFIG: u8 = 3;
+// No device or group with that name
+pub const EXIT_NOT_FOUND: u8 = 4;
+
+#[derive(Parser)]
+#[command(name = "wololo", version, about = "Wake-on-LAN web server")]
+pub struct Cli {
+    /// Config file to use
+    #[arg(long, short, global = true, env = "WOLOLO_CONFIG", default_value = DEFAULT_CONFIG_PATH)]
+    pub config: PathBuf,
+
+    // Runs the web server when no subcommand is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server (the default)
+    Serve,
+    /// Send a wake-on-LAN packet to a device, or to every device in a group
+    Wake {
+        /// Device id, device name or group name
+        target: String,
+    },
+    /// Ping configured devices and print whether they are up
+    Status {
+        /// Only check this device (by id or name) or group
+        target: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Scan the network for devices
+    Scan {
+        /// Range to scan (e.g. 192.168.1.0/24); defaults to each interface's /24
+        #[arg(long)]
+        cidr: Option<Ipv4Net>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Check or print the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Import devices from an nmap XML or CSV file and print the resulting config
+    Import {
+        /// nmap -oX output or a CSV of name,mac,ip
+        file: PathBuf,
+        /// Input format (detected from the contents by default)
+        #[arg(long, value_enum, default_value_t = InventoryFormat::Auto)]
+        format: InventoryFormat,
+        /// Also update IPs and names of configured devices that changed
+        #[arg(long)]
+        apply_updates: bool,
+        /// Write the generated config here instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Export the configured devices as CSV, JSON, /etc/ethers or an Ansible inventory
+    Export {
+        #[arg(value_enum)]
+        format: ExportFormat,
+        /// Write the export here instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Read a password from stdin and print its hash for auth.users
+    HashPassword,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Load the config and report any problems
+    Validate,
+    /// Print the effective config, with defaults filled in and secrets redacted
+    Print {
+        /// Print passwords, tokens and secrets as they are
+        #[arg(long)]
+        show_secrets: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+// Load and validate the config, printing warnings and every error for the caller
+pub fn load_config_or_report(path: &Path) -> Result<Config, u8> {
+    match check_config_file(&path.to_string_lossy()) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+            Ok(config)
+        }
+        Err(issues) => {
+            report_issues(path, &issues);
+            Err(EXIT_CONFIG)
+        }
+    }
+}
+
+fn report_issues(path: &Path, issues: &[ConfigIssue]) {
+    for issue in issues {
+        eprintln!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    eprintln!(
+        "{}: {} error(s), {} warning(s)",
+        path.display(),
+        errors,
+        issues.len() - errors
+    );
+}
+
+// `wololo wake`: send packets directly from this machine, no server needed
+pub async fn wake_command(config: &Config, target: &str) -> u8 {
+    let devices = config.devices_matching(target);
+    if devices.is_empty() {
+        eprintln!("No device or group named '{}'", target);
+        return EXIT_NOT_FOUND;
+    }
+
+    let history = History::from_config(&config.history);
+    let mut code = EXIT_OK;
+    for device in devices {
+        let result = send_wake_packet(device).await;
+        match &result {
+            Ok(()) => println!(
+                "Wake packet sent to {} ({})",
+                device.name, device.mac_address
+            ),
+            Err(e) => {
+                eprintln!("{}: {}", device.name, e);
+                code = EXIT_FAILURE;
+            }
+        }
+        let record = ActionRecord::new(
+            device,
+            ActionKind::Wake,
+            "cli",
+            result.map_err(|e| e.to_string()),
+        );
+        history
+            .blocking(move |history| history.record_action(&record))
+            .await;
+    }
+    code
+}
+
+#[derive(serde::Serialize)]
+struct StatusLine<'a> {
+    name: &'a str,
+    ip_address: &'a str,
+    status: DeviceStatus,
+}
+
+// `wololo status`: exits 0 only when every checked device is online
+pub async fn status_command(config: &Config, target: Option<&str>, json: bool) -> u8 {
+    let devices = match target {
+        Some(target) => config.devices_matching(target),
+        None => config.devices.iter().collect(),
+    };
+    if devices.is_empty() {
+        match target {
+            Some(target) => {
+                eprintln!("No device or group named '{}'", target);
+                return EXIT_NOT_FOUND;
+            }
+            None => eprintln!("No devices configured"),
+        }
+    }
+
+    let statuses =
+        futures::future::join_all(devices.iter().map(|device| ping_device(&device.ip_address)))
+            .await;
+    let lines: Vec<StatusLine> = devices
+        .iter()
+        .zip(statuses)
+        .map(|(device, status)| StatusLine {
+            name: &device.name,
+            ip_address: &device.ip_address,
+            status,
+        })
+        .collect();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&lines).unwrap_or_default()
+        );
+    } else {
+        let width = lines
+            .iter()
+            .map(|line| line.name.len())
+            .max()
+            .unwrap_or(4)
+            .max(4);
+        println!("{:<width$}  {:<15}  STATUS", "NAME", "IP", width = width);
+        for line in &lines {
+            println!(
+                "{:<width$}  {:<15}  {}",
+                line.name,
+                line.ip_address,
+                line.status.as_str(),
+                width = width
+            );
+        }
+    }
+
+    if lines.iter().all(|line| line.status == DeviceStatus::Online) {
+        EXIT_OK
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+// `wololo scan`: list hosts that answer, marking the ones already in the config
+pub async fn scan_command(config: &Config, cidr: Option<Ipv4Net>, json: bool) -> u8 {
+    let found: Vec<DiscoveredDevice> = match cidr {
+        Some(network) => match scan_cidr(network, &config.discovery).await {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        },
+        None => discover_network_devices(None, &config.discovery).await,
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&found).unwrap_or_default()
+        );
+        return EXIT_OK;
+    }
+
+    println!(
+        "{:<15}  {:<17}  {:<10}  HOSTNAME",
+        "IP", "MAC", "CONFIGURED"
+    );
+    for device in &found {
+        let configured = device
+            .mac_address
+            .as_ref()
+            .and_then(|mac| {
+                config
+                    .devices
+                    .iter()
+                    .find(|d| normalize_mac(&d.mac_address) == normalize_mac(mac))
+            })
+            .map(|d| d.name.as_str())
+            .unwrap_or("-");
+        println!(
+            "{:<15}  {:<17}  {:<10}  {}",
+            device.ip_address,
+            device.mac_address.as_deref().unwrap_or("-"),
+            configured,
+            device.hostname.as_deref().unwrap_or("-")
+        );
+    }
+    eprintln!("{} host(s) found", found.len());
+    EXIT_OK
+}
+
+// `wololo config validate`: list every problem; warnings alone do not fail
+pub fn config_validate_command(path: &Path) -> u8 {
+    match check_config_file(&path.to_string_lossy()) {
+        Ok((config, warnings)) => {
+            if !warnings.is_empty() {
+                report_issues(path, &warnings);
+            }
+            println!(
+                "{} is valid ({} device(s))",
+                path.display(),
+                config.devices.len()
+            );
+            EXIT_OK
+        }
+        Err(issues) => {
+            report_issues(path, &issues);
+            EXIT_CONFIG
+        }
+    }
+}
+
+// `wololo config print`
+pub fn config_print_command(config: &Config, show_secrets: bool) -> u8 {
+    let printed = if show_secrets {
+        serde_yaml::to_string(config)
+    } else {
+        serde_yaml::to_string(&config.redacted())
+    };
+    match printed {
+        Ok(yaml) => {
+            print!("{}", yaml);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize config: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+// `wololo hash-password`: hash the first line of stdin for a password_hash entry
+pub fn hash_password_command() -> u8 {
+    let mut password = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut password) {
+        eprintln!("Failed to read the password: {}", e);
+        return EXIT_FAILURE;
+    }
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        eprintln!("The password is empty");
+        return EXIT_USAGE;
+    }
+    match hash_password(password) {
+        Ok(hash) => {
+            println!("{}", hash);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Failed to hash the password: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+// `wololo import`: diff an inventory file against the config and emit the merged YAML
+pub async fn import_command(
+    config: &Config,
+    file: &Path,
+    format: InventoryFormat,
+    apply_updates: bool,
+    output: Option<&Path>,
+) -> u8 {
+    let contents = match std::fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", file.display(), e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let report = parse_inventory(format, &contents, &file.display().to_string());
+    for error in &report.errors {
+        match error.line {
+            Some(line) => eprintln!("{}:{}: {}", error.source, line, error.message),
+            None => eprintln!("{}: {}", error.source, error.message),
+        }
+    }
+
+    let diff = diff_discovered_devices(config, &report.devices);
+    eprintln!(
+        "{} device(s) read: {} new, {} changed, {} unchanged, {} configured but not in file",
+        report.devices.len(),
+        diff.new_devices.len(),
+        diff.changed.len(),
+        diff.unchanged.len(),
+        diff.not_seen.len()
+    );
+
+    let updates: Vec<DeviceUpdate> = if apply_updates {
+        diff.changed
+            .iter()
+            .map(|change| DeviceUpdate {
+                mac_address: change.device.mac_address.clone(),
+                ip_address: change.new_ip_address.clone(),
+                name: change.new_name.clone(),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let yaml = generate_config_yaml_with_updates(config, &diff.new_devices, &updates).await;
+
+    if let Err(code) = write_output(output, &yaml) {
+        return code;
+    }
+
+    if report.errors.is_empty() {
+        EXIT_OK
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+// `wololo export`: write the device list in another tool's format
+pub fn export_command(config: &Config, format: ExportFormat, output: Option<&Path>) -> u8 {
+    let exported = match export_devices(&config.devices, format) {
+        Ok(exported) => exported,
+        Err(e) => {
+            eprintln!("Failed to export devices: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match write_output(output, &exported) {
+        Ok(()) => EXIT_OK,
+        Err(code) => code,
+    }
+}
+
+// Write to the file if one was given, otherwise stdout
+fn write_output(output: Option<&Path>, contents: &str) -> Result<(), u8> {
+    match output {
+        Some(path) => {
+            std::fs::write(path, contents).map_err(|e| {
+                eprintln!("Failed to write {}: {}", path.display(), e);
+                EXIT_FAILURE
+            })?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
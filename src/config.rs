@@ -1,12 +1,64 @@
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::validation::{check_config_file, ConfigErrors};
+
+// Static segments routed under /devices/, which a device id would be shadowed by
+pub const RESERVED_DEVICE_IDS: &[&str] = &["manager"];
 
 // Struct for individual device configuration
-#[derive(Debug, Deserialize, Clone, Serialize)]
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
 pub struct Device {
+    // Stable key for URLs and scripts; generated from the MAC address when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
     pub mac_address: String,
     pub ip_address: String,
+    // Groups the device belongs to (e.g. "servers", "office")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    // Free-form labels
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // Command run on the server to shut the device down, e.g. ["ssh", "nas", "sudo", "poweroff"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shutdown_command: Vec<String>,
+}
+
+impl Device {
+    // The configured id, or the MAC address as "aa-bb-cc-dd-ee-ff". Only letters, digits,
+    // '-' and '_' are used, so the id is safe in URL paths and HTML element IDs.
+    pub fn device_id(&self) -> String {
+        let id = self.base_id();
+        // Validation rejects a reserved id; one generated from the name gets a prefix instead
+        if RESERVED_DEVICE_IDS.contains(&id.as_str()) {
+            format!("device-{}", id)
+        } else {
+            id
+        }
+    }
+
+    fn base_id(&self) -> String {
+        if let Some(id) = self.id.as_deref().filter(|id| !id.is_empty()) {
+            return id.to_string();
+        }
+        let hex: Vec<char> = self
+            .mac_address
+            .chars()
+            .filter(|c| c.is_ascii_hexdigit())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if hex.len() == 12 {
+            hex.chunks(2)
+                .map(|pair| pair.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join("-")
+        } else {
+            // Not a usable MAC (validation rejects these); fall back to the name
+            crate::export::host_name(&self.name)
+        }
+    }
 }
 
 // Functions to provide default values for ServerConfig
@@ -36,6 +88,113 @@ fn default_sound_enabled() -> bool {
     true
 }
 
+// Functions to provide default values for DiscoveryConfig
+fn default_discovery_max_concurrency() -> usize {
+    32
+}
+
+fn default_discovery_packets_per_second() -> u32 {
+    50 // 0 disables rate limiting
+}
+
+fn default_discovery_host_timeout() -> u32 {
+    1
+}
+
+fn default_discovery_retries() -> u32 {
+    0
+}
+
+fn default_discovery_result_ttl() -> u64 {
+    1800 // Keep scan results and generated configs for 30 minutes
+}
+
+// Functions to provide default values for BackgroundDiscoveryConfig
+fn default_background_interval() -> u64 {
+    900 // Every 15 minutes
+}
+
+fn default_seen_devices_path() -> String {
+    "seen_devices.json".to_string()
+}
+
+// Functions to provide default values for PollerConfig
+fn default_poller_enabled() -> bool {
+    true
+}
+
+fn default_poller_interval() -> u64 {
+    60 // Every minute
+}
+
+fn default_poller_max_concurrency() -> usize {
+    16
+}
+
+fn default_wake_verification() -> u64 {
+    300 // 5 minutes
+}
+
+// Functions to provide default values for HistoryConfig
+fn default_history_enabled() -> bool {
+    true
+}
+
+fn default_history_path() -> String {
+    "wololo.db".to_string()
+}
+
+fn default_history_retention_days() -> u32 {
+    30
+}
+
+fn default_webhook_queue_path() -> String {
+    "webhook_queue.json".to_string()
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    8
+}
+
+// Functions to provide default values for MqttConfig
+fn default_mqtt_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "wololo".to_string()
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "wololo".to_string()
+}
+
+fn default_mqtt_keep_alive() -> u64 {
+    30
+}
+
+fn default_home_assistant_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+// Functions to provide default values for notification channels
+fn default_smtp_port() -> u16 {
+    587
+}
+
+// Functions to provide default values for AuthConfig
+fn default_session_hours() -> u64 {
+    24 // One day
+}
+
+fn default_tokens_path() -> String {
+    "api_tokens.json".to_string()
+}
+
 // Struct for server configuration
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct ServerConfig {
@@ -94,8 +253,433 @@ impl Default for SoundConfig {
     }
 }
 
+// Struct for the server-side status poller
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct PollerConfig {
+    #[serde(default = "default_poller_enabled")]
+    pub enabled: bool,
+    // How often every device is pinged
+    #[serde(default = "default_poller_interval")]
+    pub interval_seconds: u64,
+    // A woken device that is not online after this long is reported as wake_unverified;
+    // 0 turns the check off
+    #[serde(default = "default_wake_verification")]
+    pub wake_verification_seconds: u64,
+    // Maximum number of devices pinged at the same time in one round
+    #[serde(default = "default_poller_max_concurrency")]
+    pub max_concurrency: usize,
+}
+
+// Implement Default for PollerConfig
+impl Default for PollerConfig {
+    fn default() -> Self {
+        PollerConfig {
+            enabled: default_poller_enabled(),
+            interval_seconds: default_poller_interval(),
+            wake_verification_seconds: default_wake_verification(),
+            max_concurrency: default_poller_max_concurrency(),
+        }
+    }
+}
+
+impl PollerConfig {
+    // max_concurrency as a round uses it: at least 1 and at most MAX_SCAN_CONCURRENCY
+    pub fn concurrency(&self) -> usize {
+        self.max_concurrency.clamp(1, MAX_SCAN_CONCURRENCY)
+    }
+}
+
+// Struct for status and action history storage
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct HistoryConfig {
+    #[serde(default = "default_history_enabled")]
+    pub enabled: bool,
+    // SQLite database file
+    #[serde(default = "default_history_path")]
+    pub path: String,
+    // Records older than this are deleted
+    #[serde(default = "default_history_retention_days")]
+    pub retention_days: u32,
+}
+
+// Implement Default for HistoryConfig
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enabled: default_history_enabled(),
+            path: default_history_path(),
+            retention_days: default_history_retention_days(),
+        }
+    }
+}
+
+// Struct for outgoing webhooks
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct WebhooksConfig {
+    // Deliveries that have not succeeded yet are kept here across restarts
+    #[serde(default = "default_webhook_queue_path")]
+    pub queue_path: String,
+    // Attempts per delivery before it is dropped
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+}
+
+// Implement Default for WebhooksConfig
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        WebhooksConfig {
+            queue_path: default_webhook_queue_path(),
+            max_attempts: default_webhook_max_attempts(),
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+// One URL that events are POSTed to
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Default)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    // Event types to send, e.g. "status_changed"; empty sends every event
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    // Extra request headers, e.g. Authorization
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    // Signs each body with HMAC-SHA256 in the X-Wololo-Signature header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    // Handlebars template for the body; the event JSON is sent when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+// Struct for the MQTT broker connection
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_mqtt_host")]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    // Must be unique on the broker; a second client with the same id disconnects the first
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    // Connect with TLS (usually port 8883)
+    #[serde(default)]
+    pub tls: bool,
+    // PEM file with the CA that signed the broker certificate; the system roots when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    // Every topic starts with this, e.g. "wololo/devices/nas/status"
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default = "default_mqtt_keep_alive")]
+    pub keep_alive_seconds: u64,
+    #[serde(default)]
+    pub home_assistant: HomeAssistantConfig,
+}
+
+// Implement Default for MqttConfig
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            enabled: false,
+            host: default_mqtt_host(),
+            port: default_mqtt_port(),
+            client_id: default_mqtt_client_id(),
+            username: None,
+            password: None,
+            tls: false,
+            ca_file: None,
+            topic_prefix: default_mqtt_topic_prefix(),
+            keep_alive_seconds: default_mqtt_keep_alive(),
+            home_assistant: HomeAssistantConfig::default(),
+        }
+    }
+}
+
+// Struct for Home Assistant MQTT discovery
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct HomeAssistantConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Must match the discovery prefix configured in Home Assistant
+    #[serde(default = "default_home_assistant_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+// Implement Default for HomeAssistantConfig
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        HomeAssistantConfig {
+            enabled: false,
+            discovery_prefix: default_home_assistant_discovery_prefix(),
+        }
+    }
+}
+
+// Struct for human-readable alerts
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Default)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    // Which events go to which channels; every event goes to every channel when empty
+    #[serde(default)]
+    pub rules: Vec<NotificationRule>,
+}
+
+// One place notifications are sent to
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct NotificationChannel {
+    // Referenced by rules
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+    // Handlebars templates for the title and the text; the built-in wording when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    // Nothing is sent to this channel during these hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelKind {
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    // `url` is the topic URL, e.g. https://ntfy.sh/my-alerts
+    Ntfy {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+        // 1 (min) to 5 (max)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<u8>,
+    },
+    // `url` is the server, e.g. https://gotify.lan; `token` an application token
+    Gotify {
+        url: String,
+        token: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<u8>,
+    },
+    // Any service that takes {"title", "message"} as JSON
+    Push {
+        url: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    // Plain connection upgraded with STARTTLS (usually port 587)
+    #[default]
+    Starttls,
+    // TLS from the start (usually port 465)
+    Tls,
+    // Unencrypted, for a relay on the local network
+    None,
+}
+
+// Local time range such as 22:00 to 07:00; it may wrap past midnight
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct QuietHours {
+    // "HH:MM"
+    pub start: String,
+    pub end: String,
+}
+
+// Sends the events matching all of its filters to its channels. Empty filters match everything.
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Default)]
+pub struct NotificationRule {
+    pub channels: Vec<String>,
+    // Event types, e.g. "status_changed"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    // Device ids or names; events about other devices are skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // Only status changes to these statuses, e.g. ["offline", "unreachable"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statuses: Vec<String>,
+    // Only failed wakes and shutdowns
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failures_only: bool,
+}
+
+// Struct for logging in to the web UI and API
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<User>,
+    // YAML file with more users in the same form as `users`; read at every login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users_file: Option<String>,
+    // Key for signing session cookies. A random one is made at startup when not set, which
+    // logs everyone out on restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_secret: Option<String>,
+    // How long a login lasts
+    #[serde(default = "default_session_hours")]
+    pub session_hours: u64,
+    // Mark the session cookie Secure, for servers only reached over HTTPS
+    #[serde(default)]
+    pub secure_cookie: bool,
+    // API tokens, stored hashed, are kept here
+    #[serde(default = "default_tokens_path")]
+    pub tokens_path: String,
+}
+
+// Implement Default for AuthConfig
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            enabled: false,
+            users: Vec::new(),
+            users_file: None,
+            session_secret: None,
+            session_hours: default_session_hours(),
+            secure_cookie: false,
+            tokens_path: default_tokens_path(),
+        }
+    }
+}
+
+// A local user
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct User {
+    pub username: String,
+    // Argon2 PHC string, as printed by `wololo hash-password`
+    pub password_hash: String,
+}
+
+// Struct for network discovery configuration
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct DiscoveryConfig {
+    // Maximum number of hosts probed at the same time
+    #[serde(default = "default_discovery_max_concurrency")]
+    pub max_concurrency: usize,
+    // Upper bound on ping probes sent per second across the whole scan
+    #[serde(default = "default_discovery_packets_per_second")]
+    pub packets_per_second: u32,
+    #[serde(default = "default_discovery_host_timeout")]
+    pub host_timeout_seconds: u32,
+    // Extra ping attempts for hosts that did not answer the first probe
+    #[serde(default = "default_discovery_retries")]
+    pub retries: u32,
+    // How long a scan result (and the config generated from it) stays downloadable
+    #[serde(default = "default_discovery_result_ttl")]
+    pub result_ttl_seconds: u64,
+    #[serde(default)]
+    pub background: BackgroundDiscoveryConfig,
+    // DHCP server lease files to import devices from
+    #[serde(default)]
+    pub dhcp_leases: Vec<DhcpLeaseSource>,
+}
+
+// Lease file formats understood by the DHCP importer
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DhcpLeaseFormat {
+    // Guess from the file name and contents
+    #[default]
+    Auto,
+    Dnsmasq,
+    Isc,
+    Kea,
+}
+
+// A DHCP lease file to import devices from
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct DhcpLeaseSource {
+    pub path: String,
+    #[serde(default)]
+    pub format: DhcpLeaseFormat,
+}
+
+// Struct for periodic background discovery
+#[derive(Debug, Deserialize, Clone, Serialize, PartialEq)]
+pub struct BackgroundDiscoveryConfig {
+    #[serde(default)] // Off unless explicitly enabled
+    pub enabled: bool,
+    #[serde(default = "default_background_interval")]
+    pub interval_seconds: u64,
+    // Interfaces to scan; empty means all interfaces
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    // File remembering every MAC address background discovery has seen
+    #[serde(default = "default_seen_devices_path")]
+    pub seen_devices_path: String,
+}
+
+// Implement Default for BackgroundDiscoveryConfig
+impl Default for BackgroundDiscoveryConfig {
+    fn default() -> Self {
+        BackgroundDiscoveryConfig {
+            enabled: false,
+            interval_seconds: default_background_interval(),
+            interfaces: Vec::new(),
+            seen_devices_path: default_seen_devices_path(),
+        }
+    }
+}
+
+// Implement Default for DiscoveryConfig
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            max_concurrency: default_discovery_max_concurrency(),
+            packets_per_second: default_discovery_packets_per_second(),
+            host_timeout_seconds: default_discovery_host_timeout(),
+            retries: default_discovery_retries(),
+            result_ttl_seconds: default_discovery_result_ttl(),
+            background: BackgroundDiscoveryConfig::default(),
+            dhcp_leases: Vec::new(),
+        }
+    }
+}
+
+// Upper bound on hosts probed at once, whether set in the config or per scan request
+pub const MAX_SCAN_CONCURRENCY: usize = 256;
+
+impl DiscoveryConfig {
+    // max_concurrency as every scan uses it: at least 1 and at most MAX_SCAN_CONCURRENCY
+    pub fn concurrency(&self) -> usize {
+        self.max_concurrency.clamp(1, MAX_SCAN_CONCURRENCY)
+    }
+}
+
 // Main configuration struct
-#[derive(Debug, Deserialize, Clone, Serialize)]
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
 pub struct Config {
     #[serde(default)] // If the whole server section is missing, use ServerConfig::default()
     pub server: ServerConfig,
@@ -103,17 +687,279 @@ pub struct Config {
     pub sync: SyncConfig,
     #[serde(default)] // If the whole sound section is missing, use SoundConfig::default()
     pub sound: SoundConfig,
+    #[serde(default)] // If the whole discovery section is missing, use DiscoveryConfig::default()
+    pub discovery: DiscoveryConfig,
+    #[serde(default)] // If the whole poller section is missing, use PollerConfig::default()
+    pub poller: PollerConfig,
+    #[serde(default)] // If the whole history section is missing, use HistoryConfig::default()
+    pub history: HistoryConfig,
+    #[serde(default)] // If the whole webhooks section is missing, use WebhooksConfig::default()
+    pub webhooks: WebhooksConfig,
+    #[serde(default)] // If the whole mqtt section is missing, use MqttConfig::default()
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    // If the whole notifications section is missing, use NotificationsConfig::default()
+    pub notifications: NotificationsConfig,
+    #[serde(default)] // If the whole auth section is missing, use AuthConfig::default()
+    pub auth: AuthConfig,
     pub devices: Vec<Device>,
 }
 
-// Function to load and parse config from a specific file path
+// Written in place of secrets when the running config is shown or downloaded
+pub const REDACTED: &str = "<redacted>";
+
+impl Config {
+    // A copy with every password, token, secret and header value replaced by REDACTED. The
+    // running config has ${VAR} references expanded, so it must not leave the server as is.
+    pub fn redacted(&self) -> Config {
+        fn redact(value: &mut String) {
+            if !value.is_empty() {
+                *value = REDACTED.to_string();
+            }
+        }
+        fn redact_headers(headers: &mut BTreeMap<String, String>) {
+            headers.values_mut().for_each(redact);
+        }
+
+        let mut config = self.clone();
+        config.auth.session_secret.iter_mut().for_each(redact);
+        for user in &mut config.auth.users {
+            redact(&mut user.password_hash);
+        }
+        config.mqtt.password.iter_mut().for_each(redact);
+        for endpoint in &mut config.webhooks.endpoints {
+            endpoint.secret.iter_mut().for_each(redact);
+            redact_headers(&mut endpoint.headers);
+        }
+        for channel in &mut config.notifications.channels {
+            match &mut channel.kind {
+                ChannelKind::Smtp { password, .. } => password.iter_mut().for_each(redact),
+                ChannelKind::Ntfy { token, .. } => token.iter_mut().for_each(redact),
+                ChannelKind::Gotify { token, .. } => redact(token),
+                ChannelKind::Push { headers, .. } => redact_headers(headers),
+            }
+        }
+        config
+    }
+
+    // By id, or else by exact name
+    pub fn find_device(&self, id_or_name: &str) -> Option<&Device> {
+        find_device_index(&self.devices, id_or_name).map(|index| &self.devices[index])
+    }
+
+    // A device by id or name, or else every device in the group of that name
+    pub fn devices_matching(&self, name_or_group: &str) -> Vec<&Device> {
+        match self.find_device(name_or_group) {
+            Some(device) => vec![device],
+            None => self
+                .devices
+                .iter()
+                .filter(|device| device.groups.iter().any(|group| group == name_or_group))
+                .collect(),
+        }
+    }
+}
+
+// Position of a device by id, or by exact name for links and scripts written before ids existed
+pub fn find_device_index(devices: &[Device], id_or_name: &str) -> Option<usize> {
+    devices
+        .iter()
+        .position(|device| device.device_id() == id_or_name)
+        .or_else(|| devices.iter().position(|device| device.name == id_or_name))
+}
+
+// Config file used when neither --config nor WOLOLO_CONFIG is given
+pub const DEFAULT_CONFIG_PATH: &str = "config.yaml";
+
+// Function to load, parse and validate config from a specific file path.
+// Warnings are logged; any error fails the load with every problem listed.
 pub fn load_config_from_path(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    let config_str = fs::read_to_string(path)?;
-    let config: Config = serde_yaml::from_str(&config_str)?;
-    Ok(config)
+    match check_config_file(path) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+            Ok(config)
+        }
+        Err(issues) => Err(Box::new(ConfigErrors(issues))),
+    }
 }
 
-// Function to load and parse config from default path
+// Function to load and parse config from WOLOLO_CONFIG, or config.yaml in the working directory
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    load_config_from_path("config.yaml")
+    load_config_from_path(&config_path())
+}
+
+pub fn config_path() -> String {
+    std::env::var("WOLOLO_CONFIG")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string())
+}
+
+// Expand ${VAR} in every string value (keys are left alone). An unquoted value that is
+// nothing but a single reference is re-read as YAML so `port: ${PORT}` still yields a number;
+// `quoted` holds the paths (see yaml_quoted_paths) of values that must stay strings.
+pub(crate) fn interpolate_value(
+    value: &mut serde_yaml::Value,
+    env: &dyn Fn(&str) -> Option<String>,
+    quoted: &HashSet<String>,
+) -> Result<(), String> {
+    interpolate_at(value, env, quoted, String::new())
+}
+
+fn interpolate_at(
+    value: &mut serde_yaml::Value,
+    env: &dyn Fn(&str) -> Option<String>,
+    quoted: &HashSet<String>,
+    path: String,
+) -> Result<(), String> {
+    match value {
+        serde_yaml::Value::String(text) if text.contains('$') => {
+            let expanded = interpolate_str(text, env)?;
+            let whole_reference =
+                text.starts_with("${") && text.ends_with('}') && text.matches("${").count() == 1;
+            *value = if whole_reference && !quoted.contains(&path) {
+                match serde_yaml::from_str::<serde_yaml::Value>(&expanded) {
+                    Ok(scalar @ (serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_))) => {
+                        scalar
+                    }
+                    _ => serde_yaml::Value::String(expanded),
+                }
+            } else {
+                serde_yaml::Value::String(expanded)
+            };
+        }
+        serde_yaml::Value::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_at(item, env, quoted, format!("{}[{}]", path, index))?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                let key = key.as_str().unwrap_or_default();
+                let item_path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                interpolate_at(item, env, quoted, item_path)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// ${VAR} is replaced with the variable, ${VAR:-fallback} falls back when it is unset or empty,
+// and $$ is a literal $. Referencing an unset variable without a fallback is an error.
+pub fn interpolate_str(text: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unterminated ${{ in '{}'", text))?;
+            let reference = &after[..end];
+            let (name, fallback) = match reference.split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (reference, None),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid variable name '{}' in '{}'", name, text));
+            }
+
+            match (env(name).filter(|v| !v.is_empty()), fallback) {
+                (Some(value), _) => output.push_str(&value),
+                (None, Some(fallback)) => output.push_str(fallback),
+                (None, None) => {
+                    return Err(format!("environment variable {} is not set", name));
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+// Sets one config field from a WOLOLO_* variable: (config, variable name, value)
+type EnvOverride = fn(&mut Config, &str, &str) -> Result<(), String>;
+
+// WOLOLO_<SECTION>_<FIELD> variables that override the file, e.g. WOLOLO_SERVER_PORT=8080
+pub const ENV_OVERRIDES: &[(&str, EnvOverride)] = &[
+    ("WOLOLO_SERVER_IP", |config, _, value| {
+        config.server.ip = value.to_string();
+        Ok(())
+    }),
+    ("WOLOLO_SERVER_PORT", |config, name, value| {
+        config.server.port = parse_env(name, value)?;
+        Ok(())
+    }),
+    ("WOLOLO_SERVER_EXTERNAL_URL", |config, _, value| {
+        config.server.external_url = value.to_string();
+        Ok(())
+    }),
+    ("WOLOLO_SYNC_ENABLED", |config, name, value| {
+        config.sync.enabled = parse_env_bool(name, value)?;
+        Ok(())
+    }),
+    ("WOLOLO_SYNC_INTERVAL_SECONDS", |config, name, value| {
+        config.sync.interval_seconds = parse_env(name, value)?;
+        Ok(())
+    }),
+    ("WOLOLO_SOUND_ENABLED", |config, name, value| {
+        config.sound.enabled = parse_env_bool(name, value)?;
+        Ok(())
+    }),
+];
+
+pub(crate) fn apply_env_overrides(
+    config: &mut Config,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    for &(name, set) in ENV_OVERRIDES {
+        let Some(value) = env(name) else {
+            continue;
+        };
+        let value = value.trim();
+        // An empty variable (e.g. `WOLOLO_SERVER_PORT=` in a compose file) leaves the file's value
+        if value.is_empty() {
+            continue;
+        }
+        set(config, name, value)?;
+    }
+    Ok(())
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("{}='{}' is invalid: {}", name, value, e))
+}
+
+fn parse_env_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!(
+            "{}='{}' is not a boolean (use true or false)",
+            name, value
+        )),
+    }
 }
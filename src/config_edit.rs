@@ -0,0 +1,594 @@
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::{find_device_index, Device};
+use crate::reload::ConfigHandle;
+use crate::validation::{check_config, ConfigIssue, Severity};
+
+// Timestamped copies of the config file kept next to it; older ones are deleted
+pub const MAX_CONFIG_BACKUPS: usize = 10;
+
+// A change made from the device manager. Devices are picked by id (or name, see
+// `find_device_index`) as they are in the file before the edit.
+#[derive(Debug, Clone)]
+pub enum DeviceEdit {
+    Add(Device),
+    Update { id: String, device: Device },
+    Delete { id: String },
+    // Move the device to `position` in the list (0 is the top)
+    Move { id: String, position: usize },
+}
+
+#[derive(Debug)]
+pub enum EditError {
+    // The running config was not loaded from a file
+    NoFile,
+    // The file changed after the caller read it
+    Conflict,
+    NotFound(String),
+    // The file as it is, or as it would be after the edit, has errors
+    Invalid(Vec<ConfigIssue>),
+    Io(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NoFile => write!(f, "The configuration was not loaded from a file"),
+            EditError::Conflict => write!(
+                f,
+                "The config file was changed by someone else. Reload and try again."
+            ),
+            EditError::NotFound(name) => write!(f, "Device '{}' not found", name),
+            EditError::Invalid(issues) => {
+                let lines: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            EditError::Io(e) => write!(f, "Failed to write config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+// The config file as the device manager sees it
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub contents: String,
+    // Sent back with every edit to detect changes made in between
+    pub hash: String,
+    pub devices: Vec<Device>,
+}
+
+pub fn content_hash(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn errors_only(issues: Vec<ConfigIssue>) -> Vec<ConfigIssue> {
+    issues
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .collect()
+}
+
+// Read the file behind the running config. This can differ from the running config when
+// a reload was rejected, so edits always start from what is on disk.
+pub fn read_config_file(handle: &ConfigHandle) -> Result<ConfigFile, EditError> {
+    let path = handle.path().ok_or(EditError::NoFile)?.to_path_buf();
+    let contents = fs::read_to_string(&path).map_err(|e| EditError::Io(e.to_string()))?;
+    let (config, _) = check_config(&contents, &path.to_string_lossy(), &process_env)
+        .map_err(|issues| EditError::Invalid(errors_only(issues)))?;
+
+    Ok(ConfigFile {
+        hash: content_hash(&contents),
+        devices: config.devices,
+        contents,
+        path,
+    })
+}
+
+// Apply an edit to the config file and write it back. `expected_hash` is the hash of the
+// file the caller made the edit against; if the file has changed since, nothing is written.
+// The caller reloads the running config afterwards.
+pub fn apply_device_edit(
+    handle: &ConfigHandle,
+    expected_hash: &str,
+    edit: &DeviceEdit,
+) -> Result<ConfigFile, EditError> {
+    let _guard = handle.lock_for_write();
+
+    let file = read_config_file(handle)?;
+    if file.hash != expected_hash {
+        return Err(EditError::Conflict);
+    }
+
+    let contents = edit_devices_text(&file.contents, &file.devices, edit)?;
+    let (config, _) = check_config(&contents, &file.path.to_string_lossy(), &process_env)
+        .map_err(|issues| EditError::Invalid(errors_only(issues)))?;
+
+    write_config_atomically(&file.path, &contents).map_err(|e| EditError::Io(e.to_string()))?;
+
+    Ok(ConfigFile {
+        hash: content_hash(&contents),
+        devices: config.devices,
+        contents,
+        path: file.path,
+    })
+}
+
+// Apply an edit to the text of a config file. Only the `devices` list is rewritten; other
+// sections, and the comments and unknown keys of untouched devices, stay as written.
+// `current` is the parsed device list of `contents`, in file order.
+pub fn edit_devices_text(
+    contents: &str,
+    current: &[Device],
+    edit: &DeviceEdit,
+) -> Result<String, EditError> {
+    let find = |id: &str| {
+        find_device_index(current, id).ok_or_else(|| EditError::NotFound(id.to_string()))
+    };
+
+    let raw: Value = serde_yaml::from_str(contents).unwrap_or(Value::Null);
+    let raw_devices = raw
+        .get("devices")
+        .and_then(Value::as_sequence)
+        .cloned()
+        .unwrap_or_default();
+    let mut block = DevicesBlock::parse(contents, &raw_devices);
+
+    match edit {
+        DeviceEdit::Add(device) => {
+            let value = serde_yaml::to_value(device).map_err(|e| EditError::Io(e.to_string()))?;
+            block.items.push(render_item(&value, &block.indent));
+        }
+        DeviceEdit::Update { id, device } => {
+            let index = find(id)?;
+            block.items[index] = update_item(
+                &block.items[index],
+                &block.indent,
+                raw_devices.get(index),
+                &current[index],
+                device,
+            );
+        }
+        DeviceEdit::Delete { id } => {
+            let index = find(id)?;
+            block.items.remove(index);
+        }
+        DeviceEdit::Move { id, position } => {
+            let index = find(id)?;
+            let item = block.items.remove(index);
+            block.items.insert((*position).min(block.items.len()), item);
+        }
+    }
+
+    Ok(block.render())
+}
+
+// The `devices:` section of a config file, split into one piece of text per device
+struct DevicesBlock {
+    before: String,
+    // The `devices:` line itself
+    header: String,
+    // Comments between the header and the first device that are not attached to it
+    prelude: String,
+    // Each device entry, including the comment lines directly above it
+    items: Vec<String>,
+    indent: String,
+    after: String,
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+// "-" followed by a space or the end of the line starts a list entry
+fn starts_list_item(text: &str) -> bool {
+    text.strip_prefix('-')
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\r', '\n']))
+}
+
+impl DevicesBlock {
+    fn parse(contents: &str, raw_devices: &[Value]) -> Self {
+        let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+
+        let Some(start) = lines.iter().position(|line| line.starts_with("devices:")) else {
+            // No devices section yet: add one at the end
+            let mut before = contents.to_string();
+            if !before.is_empty() && !before.ends_with('\n') {
+                before.push('\n');
+            }
+            return Self::rebuilt(before, raw_devices, String::new());
+        };
+
+        // The section ends at the next top-level key
+        let mut end = lines[start + 1..]
+            .iter()
+            .position(|line| {
+                !is_blank(line) && !line.starts_with([' ', '\t', '#']) && !starts_list_item(line)
+            })
+            .map_or(lines.len(), |offset| start + 1 + offset);
+        // Blank lines and unindented comments just above that key belong to it
+        while end > start + 1 && (is_blank(lines[end - 1]) || lines[end - 1].starts_with('#')) {
+            end -= 1;
+        }
+
+        let before = lines[..start].concat();
+        let after = lines[end..].concat();
+        let header = lines[start];
+        let body = &lines[start + 1..end];
+
+        // `devices: []` and other inline forms are rewritten as a block list
+        let inline = header["devices:".len()..].trim();
+        if !inline.is_empty() && !inline.starts_with('#') {
+            return Self::rebuilt(before, raw_devices, after);
+        }
+
+        let Some(first) = body
+            .iter()
+            .find(|line| !is_blank(line) && !is_comment(line))
+        else {
+            return Self::rebuilt(before, raw_devices, after);
+        };
+        let indent = leading_whitespace(first);
+        let starts: Vec<usize> = body
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                leading_whitespace(line) == indent && starts_list_item(&line[indent.len()..])
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if starts.is_empty() || starts.len() != raw_devices.len() {
+            return Self::rebuilt(before, raw_devices, after);
+        }
+
+        // Comment lines directly above an entry move with it
+        let mut item_starts = starts.clone();
+        for (n, item_start) in item_starts.iter_mut().enumerate() {
+            let limit = if n == 0 { 0 } else { starts[n - 1] + 1 };
+            while *item_start > limit && is_comment(body[*item_start - 1]) {
+                *item_start -= 1;
+            }
+        }
+        let items = item_starts
+            .iter()
+            .enumerate()
+            .map(|(n, &item_start)| {
+                let item_end = item_starts.get(n + 1).copied().unwrap_or(body.len());
+                let mut item = body[item_start..item_end].concat();
+                if !item.ends_with('\n') {
+                    item.push('\n');
+                }
+                item
+            })
+            .collect();
+
+        Self {
+            before,
+            header: if header.ends_with('\n') {
+                header.to_string()
+            } else {
+                format!("{}\n", header)
+            },
+            prelude: body[..item_starts[0]].concat(),
+            items,
+            indent: indent.to_string(),
+            after,
+        }
+    }
+
+    // Used when the existing list cannot be split safely: regenerate it from its parsed form
+    fn rebuilt(before: String, raw_devices: &[Value], after: String) -> Self {
+        let indent = "  ".to_string();
+        Self {
+            before,
+            header: "devices:\n".to_string(),
+            prelude: String::new(),
+            items: raw_devices
+                .iter()
+                .map(|device| render_item(device, &indent))
+                .collect(),
+            indent,
+            after,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = self.before.clone();
+        if self.items.is_empty() {
+            output.push_str("devices: []\n");
+        } else {
+            output.push_str(&self.header);
+            output.push_str(&self.prelude);
+            for item in &self.items {
+                output.push_str(item);
+            }
+        }
+        output.push_str(&self.after);
+        output
+    }
+}
+
+// One list entry as YAML, indented to sit under `devices:`
+fn render_item(device: &Value, indent: &str) -> String {
+    let yaml = serde_yaml::to_string(&Value::Sequence(vec![device.clone()])).unwrap_or_default();
+    yaml.lines()
+        .map(|line| format!("{}{}\n", indent, line))
+        .collect()
+}
+
+// Change the fields that differ. Single-line values are replaced in place so comments
+// survive; anything else rebuilds the entry from its parsed form, which keeps unknown keys
+// but not comments inside the entry.
+fn update_item(
+    item: &str,
+    indent: &str,
+    raw: Option<&Value>,
+    current: &Device,
+    updated: &Device,
+) -> String {
+    let scalars = [
+        ("name", &current.name, &updated.name),
+        ("mac_address", &current.mac_address, &updated.mac_address),
+        ("ip_address", &current.ip_address, &updated.ip_address),
+    ];
+
+    let mut text = item.to_string();
+    let mut rebuild = current.id != updated.id
+        || current.groups != updated.groups
+        || current.tags != updated.tags;
+    for (key, old, new) in scalars {
+        if rebuild || old == new {
+            continue;
+        }
+        match replace_scalar(&text, indent, key, new) {
+            Some(replaced) => text = replaced,
+            None => rebuild = true,
+        }
+    }
+    if !rebuild {
+        return text;
+    }
+
+    let mut map = raw
+        .and_then(Value::as_mapping)
+        .cloned()
+        .unwrap_or_else(Mapping::new);
+    if current.id != updated.id {
+        match &updated.id {
+            Some(id) => {
+                map.insert("id".into(), id.clone().into());
+            }
+            None => {
+                map.remove("id");
+            }
+        }
+    }
+    for (key, old, new) in scalars {
+        if old != new {
+            map.insert(key.into(), new.clone().into());
+        }
+    }
+    for (key, old, new) in [
+        ("groups", &current.groups, &updated.groups),
+        ("tags", &current.tags, &updated.tags),
+    ] {
+        if old == new {
+            continue;
+        }
+        if new.is_empty() {
+            map.remove(key);
+        } else {
+            let values = new.iter().cloned().map(Value::from).collect();
+            map.insert(key.into(), Value::Sequence(values));
+        }
+    }
+
+    let comments: String = item
+        .split_inclusive('\n')
+        .take_while(|line| is_comment(line))
+        .collect();
+    comments + &render_item(&Value::Mapping(map), indent)
+}
+
+// Replace the value of `key: value` in a list entry, keeping any trailing comment.
+// Returns None when the key is missing or its value is not a single-line scalar.
+fn replace_scalar(item: &str, indent: &str, key: &str, value: &str) -> Option<String> {
+    let rendered = serde_yaml::to_string(&Value::String(value.to_string())).ok()?;
+    let rendered = rendered.trim_end_matches('\n');
+    if rendered.contains('\n') {
+        return None;
+    }
+
+    // Keys line up after the "- " of the entry's first line
+    let lines: Vec<&str> = item.split_inclusive('\n').collect();
+    let first = lines.iter().position(|line| !is_comment(line))?;
+    let after_dash = lines[first].get(indent.len() + 1..)?;
+    let key_column =
+        indent.len() + 1 + (after_dash.len() - after_dash.trim_start_matches(' ').len());
+
+    let mut output = String::new();
+    let mut replaced = false;
+    for (index, line) in lines.iter().enumerate() {
+        let at_key = !replaced
+            && index >= first
+            && !is_comment(line)
+            && line.len() > key_column
+            && (index == first || leading_whitespace(line).len() == key_column);
+        let new_line = at_key
+            .then(|| {
+                let rest = line[key_column..].strip_prefix(key)?.strip_prefix(':')?;
+                let trailing = scalar_remainder(rest)?;
+                Some(format!(
+                    "{}{}: {}{}",
+                    &line[..key_column],
+                    key,
+                    rendered,
+                    trailing
+                ))
+            })
+            .flatten();
+        match new_line {
+            Some(new_line) => {
+                output.push_str(&new_line);
+                replaced = true;
+            }
+            None => output.push_str(line),
+        }
+    }
+
+    replaced.then_some(output)
+}
+
+// What follows a single-line scalar value (a comment and/or the line ending), or None if
+// the value is a block, flow collection, anchor or otherwise more than a plain scalar
+fn scalar_remainder(rest: &str) -> Option<&str> {
+    if !rest.is_empty() && !rest.starts_with([' ', '\t', '\r', '\n']) {
+        return None;
+    }
+    let value = rest.trim_start_matches([' ', '\t']);
+    let offset = rest.len() - value.len();
+    let end = match value.chars().next()? {
+        '\r' | '\n' | '#' | '|' | '>' | '[' | '{' | '&' | '*' | '!' => return None,
+        '"' => {
+            let mut escaped = false;
+            let close = value[1..].char_indices().find(|&(_, c)| {
+                let found = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                found
+            })?;
+            1 + close.0 + 1
+        }
+        '\'' => {
+            let bytes = value.as_bytes();
+            let mut index = 1;
+            loop {
+                match bytes.get(index)? {
+                    b'\'' if bytes.get(index + 1) == Some(&b'\'') => index += 2,
+                    b'\'' => break index + 1,
+                    _ => index += 1,
+                }
+            }
+        }
+        _ => {
+            let line_end = value.find(['\r', '\n']).unwrap_or(value.len());
+            value[..line_end]
+                .find(" #")
+                .or_else(|| value[..line_end].find("\t#"))
+                .unwrap_or(line_end)
+        }
+    };
+    let end = offset + end;
+    // Plain scalars keep their trailing spaces in the remainder, which is harmless
+    let remainder = &rest[end..];
+    let remainder_trimmed = remainder.trim_start_matches([' ', '\t']);
+    if !remainder_trimmed.is_empty() && !remainder_trimmed.starts_with(['#', '\r', '\n']) {
+        return None;
+    }
+    Some(remainder)
+}
+
+// Replace the file in one step: write a temp file beside it and rename it over the original,
+// after copying the original to a timestamped backup. Returns the backup's path.
+pub fn write_config_atomically(path: &Path, contents: &str) -> std::io::Result<PathBuf> {
+    // Write to the file a symlink points at rather than replacing the link
+    let target = fs::canonicalize(path)?;
+    let directory = target.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "config.yaml".to_string());
+
+    let backup = backup_path(&directory, &file_name);
+    fs::copy(&target, &backup)?;
+
+    let temp = directory.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let written = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::set_permissions(&temp, fs::metadata(&target)?.permissions())?;
+        fs::rename(&temp, &target)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    prune_backups(&directory, &file_name, MAX_CONFIG_BACKUPS);
+    Ok(backup)
+}
+
+// config.yaml.20261018-142501.bak, with -1, -2, ... added for several saves in one second
+fn backup_path(directory: &Path, file_name: &str) -> PathBuf {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut candidate = directory.join(format!("{}.{}.bak", file_name, stamp));
+    let mut counter = 1;
+    while candidate.exists() {
+        candidate = directory.join(format!("{}.{}-{}.bak", file_name, stamp, counter));
+        counter += 1;
+    }
+    candidate
+}
+
+// Backups of this file, oldest first
+pub fn list_backups(directory: &Path, file_name: &str) -> Vec<PathBuf> {
+    let prefix = format!("{}.", file_name);
+    let mut backups: Vec<((String, u32), PathBuf)> = fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let stamp = name.strip_prefix(&prefix)?.strip_suffix(".bak")?;
+            Some((backup_order(stamp), entry.path()))
+        })
+        .collect();
+    backups.sort();
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+// "20261018-142501-10" as ("20261018-142501", 10), so the counter sorts as a number:
+// "…-142501" (counter 0) before "…-142501-2" before "…-142501-10"
+fn backup_order(stamp: &str) -> (String, u32) {
+    match stamp.rsplit_once('-') {
+        Some((time, counter)) if time.contains('-') => match counter.parse() {
+            Ok(counter) => (time.to_string(), counter),
+            Err(_) => (stamp.to_string(), 0),
+        },
+        _ => (stamp.to_string(), 0),
+    }
+}
+
+fn prune_backups(directory: &Path, file_name: &str, keep: usize) {
+    let backups = list_backups(directory, file_name);
+    let excess = backups.len().saturating_sub(keep);
+    for backup in &backups[..excess] {
+        if let Err(e) = fs::remove_file(backup) {
+            eprintln!(
+                "Failed to remove old config backup {}: {}",
+                backup.display(),
+                e
+            );
+        }
+    }
+}
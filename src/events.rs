@@ -0,0 +1,104 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::background_discovery::SeenDevice;
+use crate::history::{ActionRecord, StatusChange};
+
+// Number of events buffered for slow subscribers before they start lagging
+const EVENT_BUS_CAPACITY: usize = 256;
+
+// Events published by the server for anything that wants to react to them
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    // Background discovery saw a MAC address that is neither configured nor seen before
+    NewDevice {
+        device: SeenDevice,
+    },
+    // config.yaml was reloaded and the new config is in use
+    ConfigReloaded {
+        device_count: usize,
+    },
+    // A reload was rejected; the previous config is still in use
+    ConfigReloadFailed {
+        errors: Vec<String>,
+    },
+    // The poller saw a device come online, go offline or become unreachable
+    StatusChanged {
+        change: StatusChange,
+    },
+    // A device was woken or shut down (or the attempt failed)
+    DeviceAction {
+        action: ActionRecord,
+    },
+    // A device was still not online poller.wake_verification_seconds after a successful wake
+    WakeUnverified {
+        device_id: String,
+        device_name: String,
+        // Unix seconds of the wake
+        woken_at: u64,
+        waited_seconds: u64,
+    },
+}
+
+impl Event {
+    // Every value of `kind`, for validating filters
+    pub const KINDS: [&'static str; 6] = [
+        "new_device",
+        "config_reloaded",
+        "config_reload_failed",
+        "status_changed",
+        "device_action",
+        "wake_unverified",
+    ];
+
+    // The serialized "type" of the event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::NewDevice { .. } => "new_device",
+            Event::ConfigReloaded { .. } => "config_reloaded",
+            Event::ConfigReloadFailed { .. } => "config_reload_failed",
+            Event::StatusChanged { .. } => "status_changed",
+            Event::DeviceAction { .. } => "device_action",
+            Event::WakeUnverified { .. } => "wake_unverified",
+        }
+    }
+
+    // Id of the device the event is about, if any
+    pub fn device_id(&self) -> Option<&str> {
+        match self {
+            Event::StatusChanged { change } => Some(&change.device_id),
+            Event::DeviceAction { action } => Some(&action.device_id),
+            Event::WakeUnverified { device_id, .. } => Some(device_id),
+            _ => None,
+        }
+    }
+}
+
+// In-process publish/subscribe bus shared through AppState
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    // Publish an event; having no subscribers is not an error
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
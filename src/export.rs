@@ -0,0 +1,208 @@
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::config::Device;
+
+// Formats the device inventory can be exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    // /etc/ethers: "<mac> <hostname>" per line
+    Ethers,
+    // Ansible YAML inventory, one Ansible group per device group
+    Ansible,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "ethers" => Some(Self::Ethers),
+            "ansible" => Some(Self::Ansible),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Ethers => "text/plain; charset=utf-8",
+            Self::Ansible => "application/x-yaml",
+        }
+    }
+
+    // Suggested download name
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Csv => "devices.csv",
+            Self::Json => "devices.json",
+            Self::Ethers => "ethers",
+            Self::Ansible => "inventory.yaml",
+        }
+    }
+}
+
+pub fn export_devices(devices: &[Device], format: ExportFormat) -> Result<String, String> {
+    match format {
+        ExportFormat::Csv => export_csv(devices),
+        ExportFormat::Json => export_json(devices),
+        ExportFormat::Ethers => Ok(export_ethers(devices)),
+        ExportFormat::Ansible => export_ansible_inventory(devices),
+    }
+}
+
+// CSV with a header row; groups and tags are joined with ";".
+// The columns match what `wololo import` reads back.
+pub fn export_csv(devices: &[Device]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["name", "mac_address", "ip_address", "groups", "tags"])
+        .map_err(|e| e.to_string())?;
+    for device in devices {
+        writer
+            .write_record([
+                device.name.as_str(),
+                device.mac_address.as_str(),
+                device.ip_address.as_str(),
+                &device.groups.join(";"),
+                &device.tags.join(";"),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+// Unlike the YAML config, JSON always carries the groups and tags keys
+#[derive(Serialize)]
+struct ExportedDevice<'a> {
+    // Always present, generated from the MAC address when not configured
+    id: String,
+    name: &'a str,
+    mac_address: &'a str,
+    ip_address: &'a str,
+    groups: &'a [String],
+    tags: &'a [String],
+}
+
+pub fn export_json(devices: &[Device]) -> Result<String, String> {
+    let list: Vec<ExportedDevice> = devices
+        .iter()
+        .map(|device| ExportedDevice {
+            id: device.device_id(),
+            name: &device.name,
+            mac_address: &device.mac_address,
+            ip_address: &device.ip_address,
+            groups: &device.groups,
+            tags: &device.tags,
+        })
+        .collect();
+    let mut json = serde_json::to_string_pretty(&list).map_err(|e| e.to_string())?;
+    json.push('\n');
+    Ok(json)
+}
+
+// /etc/ethers maps MAC addresses to host names, which cannot contain spaces
+pub fn export_ethers(devices: &[Device]) -> String {
+    let mut output = String::from("# Generated by wololo\n");
+    for device in devices {
+        output.push_str(&format!(
+            "{} {}\n",
+            device.mac_address.to_ascii_lowercase().replace('-', ":"),
+            host_name(&device.name)
+        ));
+    }
+    output
+}
+
+// Ansible YAML inventory: every device under `all.hosts`, device groups as child groups
+pub fn export_ansible_inventory(devices: &[Device]) -> Result<String, String> {
+    let mut hosts = Mapping::new();
+    let mut groups: Vec<(String, Mapping)> = Vec::new();
+
+    for device in devices {
+        let name = host_name(&device.name);
+
+        let mut vars = Mapping::new();
+        vars.insert("ansible_host".into(), device.ip_address.clone().into());
+        vars.insert("mac_address".into(), device.mac_address.clone().into());
+        if !device.tags.is_empty() {
+            vars.insert(
+                "tags".into(),
+                Value::Sequence(device.tags.iter().cloned().map(Value::from).collect()),
+            );
+        }
+        hosts.insert(name.clone().into(), Value::Mapping(vars));
+
+        for group in &device.groups {
+            let group = group_name(group);
+            let members = match groups.iter_mut().find(|(existing, _)| *existing == group) {
+                Some((_, members)) => members,
+                None => {
+                    groups.push((group, Mapping::new()));
+                    &mut groups.last_mut().unwrap().1
+                }
+            };
+            members.insert(name.clone().into(), Value::Mapping(Mapping::new()));
+        }
+    }
+
+    let mut all = Mapping::new();
+    all.insert("hosts".into(), Value::Mapping(hosts));
+    if !groups.is_empty() {
+        let mut children = Mapping::new();
+        for (group, members) in groups {
+            let mut entry = Mapping::new();
+            entry.insert("hosts".into(), Value::Mapping(members));
+            children.insert(group.into(), Value::Mapping(entry));
+        }
+        all.insert("children".into(), Value::Mapping(children));
+    }
+
+    let mut inventory = Mapping::new();
+    inventory.insert("all".into(), Value::Mapping(all));
+    serde_yaml::to_string(&inventory).map_err(|e| e.to_string())
+}
+
+// Lowercase, with anything that is not a letter, digit, '.' or '-' turned into '-'
+pub fn host_name(name: &str) -> String {
+    let mut host = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            host.push(c.to_ascii_lowercase());
+        } else if !host.ends_with('-') {
+            host.push('-');
+        }
+    }
+    let host = host.trim_matches('-').to_string();
+    if host.is_empty() {
+        "device".to_string()
+    } else {
+        host
+    }
+}
+
+// Ansible group names may only contain letters, digits and underscores
+fn group_name(group: &str) -> String {
+    let name: String = group
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
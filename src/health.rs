@@ -0,0 +1,210 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::background_discovery::unix_now;
+use crate::routes::{AppState, PAGE_TEMPLATES};
+
+// How long the loopback ping of /readyz may take
+const PING_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+// How long a ping result is reused, so frequent probes do not each spawn a ping
+const PING_CHECK_CACHE: Duration = Duration::from_secs(10);
+
+static LAST_PING_CHECK: Mutex<Option<(Instant, PingCheck)>> = Mutex::const_new(None);
+
+// Result of the readiness checks; `ready` is false when any check failed
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub config: ConfigCheck,
+    pub templates: TemplateCheck,
+    pub poller: PollerCheck,
+    pub ping: PingCheck,
+}
+
+impl Readiness {
+    // What /readyz shows: only whether each check passed, since the endpoint is open
+    pub fn summary(&self) -> ReadinessSummary {
+        ReadinessSummary {
+            ready: self.ready,
+            config: self.config.ok,
+            templates: self.templates.ok,
+            poller: self.poller.ok,
+            ping: self.ping.ok,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessSummary {
+    pub ready: bool,
+    pub config: bool,
+    pub templates: bool,
+    pub poller: bool,
+    pub ping: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigCheck {
+    pub ok: bool,
+    pub devices: usize,
+    // A rejected reload does not fail the check; the previous config is still served
+    pub last_reload_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateCheck {
+    pub ok: bool,
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PollerCheck {
+    pub ok: bool,
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    // Seconds since the last round finished; None before the first round
+    pub last_round_age_seconds: Option<u64>,
+    pub lag_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PingCheck {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Run every readiness check
+pub async fn check_readiness(app_state: &AppState) -> Readiness {
+    let config = app_state.config.get();
+
+    let config_check = ConfigCheck {
+        ok: true,
+        devices: config.devices.len(),
+        last_reload_error: app_state.config.last_error(),
+    };
+
+    let missing: Vec<String> = PAGE_TEMPLATES
+        .iter()
+        .filter(|(name, _)| !app_state.handlebars.has_template(name))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let templates = TemplateCheck {
+        ok: missing.is_empty(),
+        missing,
+    };
+
+    let poller = check_poller(app_state, unix_now());
+    let ping = check_ping().await;
+
+    Readiness {
+        ready: config_check.ok && templates.ok && poller.ok && ping.ok,
+        config: config_check,
+        templates,
+        poller,
+        ping,
+    }
+}
+
+// The poller is healthy once it has finished a round and is not a whole interval overdue
+pub fn check_poller(app_state: &AppState, now: u64) -> PollerCheck {
+    let settings = &app_state.config.get().poller;
+    let interval_seconds = settings.interval_seconds.max(1);
+    let round = app_state.statuses.last_round();
+
+    let error = if !settings.enabled {
+        None
+    } else {
+        match round {
+            None => Some("the poller has not finished a round yet".to_string()),
+            Some(round) if round.lag(now) > interval_seconds => Some(format!(
+                "the last round finished {} seconds ago",
+                now.saturating_sub(round.finished_at)
+            )),
+            Some(_) => None,
+        }
+    };
+
+    PollerCheck {
+        ok: error.is_none(),
+        enabled: settings.enabled,
+        interval_seconds,
+        last_round_age_seconds: round.map(|round| now.saturating_sub(round.finished_at)),
+        lag_seconds: round.map(|round| round.lag(now)),
+        error,
+    }
+}
+
+// Reuse the last ping result for PING_CHECK_CACHE. Holding the lock while pinging makes
+// concurrent probes wait for one ping instead of starting their own.
+async fn check_ping() -> PingCheck {
+    let mut last = LAST_PING_CHECK.lock().await;
+    if let Some((checked_at, check)) = last.as_ref() {
+        if checked_at.elapsed() < PING_CHECK_CACHE {
+            return check.clone();
+        }
+    }
+
+    let check = ping_loopback().await;
+    if let Some(error) = &check.error {
+        eprintln!("Readiness check failed: {}", error);
+    }
+    *last = Some((Instant::now(), check.clone()));
+    check
+}
+
+// Status checks shell out to ping, which needs a raw socket or unprivileged ICMP. Pinging
+// the loopback address shows whether either is available to this process.
+async fn ping_loopback() -> PingCheck {
+    let output = tokio::time::timeout(
+        PING_CHECK_TIMEOUT,
+        Command::new("ping")
+            .args(["-c", "1", "-W", "1", "127.0.0.1"])
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await;
+
+    let error = match output {
+        Err(_) => Some("ping of 127.0.0.1 timed out".to_string()),
+        Ok(Err(e)) => Some(format!("Failed to run ping: {}", e)),
+        Ok(Ok(output)) if output.status.success() => None,
+        Ok(Ok(output)) => Some(format!(
+            "ping of 127.0.0.1 failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    };
+
+    PingCheck {
+        ok: error.is_none(),
+        error,
+    }
+}
+
+// Handler for GET /healthz: the process is up and serving requests
+pub async fn healthz_handler() -> Response {
+    Json(json!({ "status": "ok" })).into_response()
+}
+
+// Handler for GET /readyz: 200 when every check passes, 503 otherwise. The body only says
+// which checks passed; reload errors and ping output stay in the log.
+pub async fn readyz_handler(State(app_state): State<AppState>) -> Response {
+    let readiness = check_readiness(&app_state).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness.summary())).into_response()
+}
@@ -0,0 +1,632 @@
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::background_discovery::unix_now;
+use crate::config::{Device, HistoryConfig};
+use crate::events::{Event, EventBus};
+use crate::routes::DeviceStatus;
+
+// Most rows a single history query returns
+pub const MAX_HISTORY_LIMIT: usize = 1000;
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+// How often rows older than the retention period are deleted
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+// Things a user or another system can do to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    Wake,
+    Shutdown,
+}
+
+impl ActionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ActionKind::Wake => "wake",
+            ActionKind::Shutdown => "shutdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wake" => Some(ActionKind::Wake),
+            "shutdown" => Some(ActionKind::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+// A device went from one status to another. `previous` is None the first time a device
+// is ever checked.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatusChange {
+    pub device_id: String,
+    pub device_name: String,
+    pub status: DeviceStatus,
+    pub previous: Option<DeviceStatus>,
+    // Unix timestamp in seconds
+    pub at: u64,
+}
+
+// One poller check of one device
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Sample {
+    pub device_id: String,
+    pub status: DeviceStatus,
+    // Round-trip time of the ping, when it was answered
+    pub latency_ms: Option<f64>,
+    pub at: u64,
+}
+
+// Samples from one slice of a timeline
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TimelineBucket {
+    // Unix seconds, start inclusive and end exclusive
+    pub start: u64,
+    pub end: u64,
+    pub samples: usize,
+    pub online: usize,
+    // Average latency of the answered pings
+    pub latency_ms: Option<f64>,
+}
+
+impl TimelineBucket {
+    // Share of samples that were online, None without samples
+    pub fn uptime(&self) -> Option<f64> {
+        (self.samples > 0).then(|| self.online as f64 / self.samples as f64)
+    }
+}
+
+// Split `from..to` into `count` equal buckets (oldest first) and sort the samples into them
+pub fn timeline(samples: &[Sample], from: u64, to: u64, count: usize) -> Vec<TimelineBucket> {
+    let count = count.max(1);
+    let span = to.saturating_sub(from).max(1);
+    let mut buckets: Vec<TimelineBucket> = (0..count)
+        .map(|index| TimelineBucket {
+            start: from + span * index as u64 / count as u64,
+            end: from + span * (index as u64 + 1) / count as u64,
+            samples: 0,
+            online: 0,
+            latency_ms: None,
+        })
+        .collect();
+    let mut latency_totals = vec![(0.0, 0usize); count];
+
+    for sample in samples {
+        if sample.at < from || sample.at >= to {
+            continue;
+        }
+        let index = ((sample.at - from) as u128 * count as u128 / span as u128) as usize;
+        let index = index.min(count - 1);
+        buckets[index].samples += 1;
+        if sample.status == DeviceStatus::Online {
+            buckets[index].online += 1;
+        }
+        if let Some(latency) = sample.latency_ms {
+            latency_totals[index].0 += latency;
+            latency_totals[index].1 += 1;
+        }
+    }
+    for (bucket, (total, answered)) in buckets.iter_mut().zip(latency_totals) {
+        if answered > 0 {
+            bucket.latency_ms = Some(total / answered as f64);
+        }
+    }
+    buckets
+}
+
+// Percentage of samples that were online, None without samples
+pub fn uptime_percent(samples: &[Sample]) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let online = samples
+        .iter()
+        .filter(|sample| sample.status == DeviceStatus::Online)
+        .count();
+    Some(online as f64 * 100.0 / samples.len() as f64)
+}
+
+// A wake or shutdown, who asked for it and whether it worked
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ActionRecord {
+    pub device_id: String,
+    pub device_name: String,
+    pub action: ActionKind,
+    // Where the request came from: "web", "cli", ...
+    pub source: String,
+    pub success: bool,
+    // Error message when it failed
+    pub detail: Option<String>,
+    pub at: u64,
+}
+
+impl ActionRecord {
+    // A record of something that just happened to `device`
+    pub fn new(
+        device: &Device,
+        action: ActionKind,
+        source: &str,
+        result: Result<(), String>,
+    ) -> Self {
+        Self {
+            device_id: device.device_id(),
+            device_name: device.name.clone(),
+            action,
+            source: source.to_string(),
+            success: result.is_ok(),
+            detail: result.err(),
+            at: unix_now(),
+        }
+    }
+}
+
+// What the dashboard cards show from history, keyed by device id
+#[derive(Debug, Clone, Default)]
+pub struct CardHistory {
+    // Samples in the requested window, oldest first
+    pub samples: HashMap<String, Vec<Sample>>,
+    // Time of the last recorded status change
+    pub last_changes: HashMap<String, u64>,
+    pub last_actions: HashMap<String, ActionRecord>,
+}
+
+// Filters for reading history back; newest rows come first
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub device_id: Option<String>,
+    // Inclusive bounds, Unix seconds
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // Only used for actions
+    pub action: Option<ActionKind>,
+    // 0 means the default (100); capped at MAX_HISTORY_LIMIT
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    fn effective_limit(&self) -> usize {
+        match self.limit {
+            0 => DEFAULT_HISTORY_LIMIT,
+            limit => limit.min(MAX_HISTORY_LIMIT),
+        }
+    }
+
+    // WHERE clause shared by both tables, with its parameters
+    fn conditions(&self, include_action: bool) -> (String, Vec<rusqlite::types::Value>) {
+        let mut clauses = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(device_id) = &self.device_id {
+            clauses.push("device_id = ?");
+            values.push(device_id.clone().into());
+        }
+        if let Some(since) = self.since {
+            clauses.push("at >= ?");
+            values.push((since as i64).into());
+        }
+        if let Some(until) = self.until {
+            clauses.push("at <= ?");
+            values.push((until as i64).into());
+        }
+        if let (true, Some(action)) = (include_action, self.action) {
+            clauses.push("action = ?");
+            values.push(action.as_str().to_string().into());
+        }
+
+        let sql = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        (sql, values)
+    }
+}
+
+// Status transitions and device actions, kept in a SQLite file. A disabled store accepts
+// writes and returns nothing, so callers never need to check whether history is on.
+#[derive(Clone)]
+pub struct History {
+    connection: Option<Arc<Mutex<Connection>>>,
+}
+
+impl History {
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        // Let the CLI write while the server holds the file open
+        connection.busy_timeout(Duration::from_secs(5))?;
+        Self::with_connection(connection)
+    }
+
+    // A store that only lives as long as the process (used by tests)
+    pub fn in_memory() -> Self {
+        Self::with_connection(Connection::open_in_memory().expect("in-memory SQLite"))
+            .expect("history schema")
+    }
+
+    // The store described by the history section. A database that cannot be opened turns
+    // history off rather than stopping the caller.
+    pub fn from_config(config: &HistoryConfig) -> Self {
+        if !config.enabled {
+            return Self::disabled();
+        }
+        match Self::open(&config.path) {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!(
+                    "Failed to open history database {}: {}. History is disabled.",
+                    config.path, e
+                );
+                Self::disabled()
+            }
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self { connection: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    fn with_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS status_changes (
+                id INTEGER PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                device_name TEXT NOT NULL,
+                status TEXT NOT NULL,
+                previous TEXT,
+                at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS status_changes_device_at ON status_changes (device_id, at);
+            CREATE TABLE IF NOT EXISTS actions (
+                id INTEGER PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                device_name TEXT NOT NULL,
+                action TEXT NOT NULL,
+                source TEXT NOT NULL,
+                success INTEGER NOT NULL,
+                detail TEXT,
+                at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS actions_device_at ON actions (device_id, at);
+            CREATE TABLE IF NOT EXISTS samples (
+                id INTEGER PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                latency_ms REAL,
+                at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS samples_device_at ON samples (device_id, at);",
+        )?;
+        Ok(Self {
+            connection: Some(Arc::new(Mutex::new(connection))),
+        })
+    }
+
+    // Run `f` on the blocking pool. SQLite calls block, so async code reaches the store
+    // through here instead of calling its methods on a runtime worker.
+    pub async fn blocking<T: Default + Send + 'static>(
+        &self,
+        f: impl FnOnce(&History) -> T + Send + 'static,
+    ) -> T {
+        let history = self.clone();
+        match tokio::task::spawn_blocking(move || f(&history)).await {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("History: task failed: {}", e);
+                T::default()
+            }
+        }
+    }
+
+    // Run `f` against the database, logging (not returning) errors: history is best effort
+    // and must never break waking a device
+    fn with<T>(&self, what: &str, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Option<T> {
+        let connection = self.connection.as_ref()?;
+        let connection = connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match f(&connection) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                eprintln!("History: failed to {}: {}", what, e);
+                None
+            }
+        }
+    }
+
+    pub fn record_status_change(&self, change: &StatusChange) {
+        self.with("record status change", |connection| {
+            connection.execute(
+                "INSERT INTO status_changes (device_id, device_name, status, previous, at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    change.device_id,
+                    change.device_name,
+                    change.status.as_str(),
+                    change.previous.map(DeviceStatus::as_str),
+                    change.at as i64
+                ],
+            )
+        });
+    }
+
+    pub fn record_sample(&self, sample: &Sample) {
+        self.with("record sample", |connection| {
+            connection.execute(
+                "INSERT INTO samples (device_id, status, latency_ms, at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    sample.device_id,
+                    sample.status.as_str(),
+                    sample.latency_ms,
+                    sample.at as i64
+                ],
+            )
+        });
+    }
+
+    // Every sample of a device taken in `since..until`, oldest first
+    pub fn samples(&self, device_id: &str, since: u64, until: u64) -> Vec<Sample> {
+        self.with("read samples", |connection| {
+            let mut statement = connection.prepare(
+                "SELECT device_id, status, latency_ms, at FROM samples
+                 WHERE device_id = ?1 AND at >= ?2 AND at < ?3 ORDER BY at, id",
+            )?;
+            let rows = statement.query_map(
+                params![device_id, since as i64, until as i64],
+                sample_from_row,
+            )?;
+            rows.collect()
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn record_action(&self, action: &ActionRecord) {
+        self.with("record action", |connection| {
+            connection.execute(
+                "INSERT INTO actions (device_id, device_name, action, source, success, detail, at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    action.device_id,
+                    action.device_name,
+                    action.action.as_str(),
+                    action.source,
+                    action.success,
+                    action.detail,
+                    action.at as i64
+                ],
+            )
+        });
+    }
+
+    pub fn status_changes(&self, query: &HistoryQuery) -> Vec<StatusChange> {
+        let (conditions, mut values) = query.conditions(false);
+        values.push((query.effective_limit() as i64).into());
+        let sql = format!(
+            "SELECT device_id, device_name, status, previous, at FROM status_changes {}
+             ORDER BY at DESC, id DESC LIMIT ?",
+            conditions
+        );
+        self.with("read status history", |connection| {
+            let mut statement = connection.prepare(&sql)?;
+            let rows = statement.query_map(params_from_iter(values), |row| {
+                Ok(StatusChange {
+                    device_id: row.get(0)?,
+                    device_name: row.get(1)?,
+                    status: DeviceStatus::from_name(&row.get::<_, String>(2)?)
+                        .unwrap_or(DeviceStatus::Unreachable),
+                    previous: row
+                        .get::<_, Option<String>>(3)?
+                        .and_then(|previous| DeviceStatus::from_name(&previous)),
+                    at: row.get::<_, i64>(4)? as u64,
+                })
+            })?;
+            rows.collect()
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn actions(&self, query: &HistoryQuery) -> Vec<ActionRecord> {
+        let (conditions, mut values) = query.conditions(true);
+        values.push((query.effective_limit() as i64).into());
+        let sql = format!(
+            "SELECT device_id, device_name, action, source, success, detail, at FROM actions {}
+             ORDER BY at DESC, id DESC LIMIT ?",
+            conditions
+        );
+        self.with("read action history", |connection| {
+            let mut statement = connection.prepare(&sql)?;
+            let rows = statement.query_map(params_from_iter(values), action_from_row)?;
+            rows.collect()
+        })
+        .unwrap_or_default()
+    }
+
+    // The most recent recorded status of every device, so a restart does not record
+    // everything as changed again
+    pub fn latest_statuses(&self) -> HashMap<String, DeviceStatus> {
+        self.with("read latest statuses", |connection| {
+            let mut statement = connection.prepare(
+                "SELECT device_id, status FROM status_changes
+                 WHERE id IN (SELECT MAX(id) FROM status_changes GROUP BY device_id)",
+            )?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut latest = HashMap::new();
+            for row in rows {
+                let (device_id, status) = row?;
+                if let Some(status) = DeviceStatus::from_name(&status) {
+                    latest.insert(device_id, status);
+                }
+            }
+            Ok(latest)
+        })
+        .unwrap_or_default()
+    }
+
+    // Delete rows older than `cutoff` (Unix seconds). Returns how many were deleted.
+    pub fn prune(&self, cutoff: u64) -> usize {
+        self.with("prune history", |connection| {
+            let statuses = connection.execute(
+                "DELETE FROM status_changes WHERE at < ?1",
+                params![cutoff as i64],
+            )?;
+            let actions =
+                connection.execute("DELETE FROM actions WHERE at < ?1", params![cutoff as i64])?;
+            let samples =
+                connection.execute("DELETE FROM samples WHERE at < ?1", params![cutoff as i64])?;
+            Ok(statuses + actions + samples)
+        })
+        .unwrap_or(0)
+    }
+
+    // Everything the dashboard cards need for every device (or just `device_id`), with one
+    // query per table rather than several per card
+    pub fn card_history(&self, device_id: Option<&str>, since: u64, until: u64) -> CardHistory {
+        self.with("read card history", |connection| {
+            let mut history = CardHistory::default();
+
+            let mut statement = connection.prepare(
+                "SELECT device_id, status, latency_ms, at FROM samples
+                 WHERE (?1 IS NULL OR device_id = ?1) AND at >= ?2 AND at < ?3
+                 ORDER BY at, id",
+            )?;
+            let rows = statement.query_map(
+                params![device_id, since as i64, until as i64],
+                sample_from_row,
+            )?;
+            for sample in rows {
+                let sample = sample?;
+                history
+                    .samples
+                    .entry(sample.device_id.clone())
+                    .or_default()
+                    .push(sample);
+            }
+
+            let mut statement = connection.prepare(
+                "SELECT device_id, MAX(at) FROM status_changes
+                 WHERE ?1 IS NULL OR device_id = ?1 GROUP BY device_id",
+            )?;
+            let rows = statement.query_map(params![device_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+            })?;
+            history.last_changes = rows.collect::<rusqlite::Result<_>>()?;
+
+            let mut statement = connection.prepare(
+                "SELECT device_id, device_name, action, source, success, detail, at FROM actions
+                 WHERE id IN (SELECT MAX(id) FROM actions
+                              WHERE ?1 IS NULL OR device_id = ?1 GROUP BY device_id)",
+            )?;
+            let rows = statement.query_map(params![device_id], action_from_row)?;
+            for action in rows {
+                let action = action?;
+                history
+                    .last_actions
+                    .insert(action.device_id.clone(), action);
+            }
+
+            Ok(history)
+        })
+        .unwrap_or_default()
+    }
+
+    // Time of the last recorded status change for a device, if any
+    pub fn last_change_at(&self, device_id: &str) -> Option<u64> {
+        self.with("read last change", |connection| {
+            connection
+                .query_row(
+                    "SELECT MAX(at) FROM status_changes WHERE device_id = ?1",
+                    params![device_id],
+                    |row| row.get::<_, Option<i64>>(0),
+                )
+                .optional()
+        })
+        .flatten()
+        .flatten()
+        .map(|at| at as u64)
+    }
+}
+
+// A row of `SELECT device_id, status, latency_ms, at FROM samples`
+fn sample_from_row(row: &rusqlite::Row) -> rusqlite::Result<Sample> {
+    Ok(Sample {
+        device_id: row.get(0)?,
+        status: DeviceStatus::from_name(&row.get::<_, String>(1)?)
+            .unwrap_or(DeviceStatus::Unreachable),
+        latency_ms: row.get(2)?,
+        at: row.get::<_, i64>(3)? as u64,
+    })
+}
+
+// A row of `SELECT device_id, device_name, action, source, success, detail, at FROM actions`
+fn action_from_row(row: &rusqlite::Row) -> rusqlite::Result<ActionRecord> {
+    Ok(ActionRecord {
+        device_id: row.get(0)?,
+        device_name: row.get(1)?,
+        action: ActionKind::from_name(&row.get::<_, String>(2)?).unwrap_or(ActionKind::Wake),
+        source: row.get(3)?,
+        success: row.get(4)?,
+        detail: row.get(5)?,
+        at: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+// Unix seconds ("1700000000") or an RFC 3339 time ("2024-05-01T08:00:00Z")
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .and_then(|time| u64::try_from(time.timestamp()).ok())
+}
+
+// Record a wake or shutdown and tell event subscribers about it
+pub async fn record_device_action(
+    history: &History,
+    events: &EventBus,
+    device: &Device,
+    action: ActionKind,
+    source: &str,
+    result: Result<(), String>,
+) -> ActionRecord {
+    let record = ActionRecord::new(device, action, source, result);
+    let stored = record.clone();
+    history
+        .blocking(move |history| history.record_action(&stored))
+        .await;
+    events.publish(Event::DeviceAction {
+        action: record.clone(),
+    });
+    record
+}
+
+// Delete history older than `retention_days` now and then every hour
+pub fn spawn_history_retention(history: History, retention_days: u32) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = unix_now().saturating_sub(u64::from(retention_days) * 86_400);
+            let deleted = history.blocking(move |history| history.prune(cutoff)).await;
+            if deleted > 0 {
+                println!(
+                    "History: deleted {} record(s) older than {} day(s)",
+                    deleted, retention_days
+                );
+            }
+        }
+    })
+}
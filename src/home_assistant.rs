@@ -0,0 +1,131 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::config::{Config, Device};
+use crate::mqtt::{Topics, OFFLINE, ONLINE};
+
+// Node id in discovery topics and prefix of unique ids: the MQTT client id with anything
+// Home Assistant does not accept replaced by '_'
+pub fn node_id(client_id: &str) -> String {
+    client_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Home Assistant publishes "online" here when it starts; discovery is sent again then
+pub fn birth_topic(config: &Config) -> String {
+    format!(
+        "{}/status",
+        config
+            .mqtt
+            .home_assistant
+            .discovery_prefix
+            .trim_end_matches('/')
+    )
+}
+
+// "aa:bb:cc:dd:ee:ff", the form Home Assistant uses to match network connections
+fn mac_connection(device: &Device) -> Option<String> {
+    let hex: Vec<char> = device
+        .mac_address
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    (hex.len() == 12).then(|| {
+        hex.chunks(2)
+            .map(|pair| pair.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(":")
+    })
+}
+
+// Discovery config topic and payload of every entity: a connectivity binary_sensor and a wake
+// button for each device, plus a shutdown button when it has a shutdown_command.
+// Empty when Home Assistant discovery is disabled.
+pub fn discovery_messages(config: &Config, topics: &Topics) -> BTreeMap<String, String> {
+    let home_assistant = &config.mqtt.home_assistant;
+    if !home_assistant.enabled {
+        return BTreeMap::new();
+    }
+    let prefix = home_assistant.discovery_prefix.trim_end_matches('/');
+    let node = node_id(&config.mqtt.client_id);
+
+    let mut messages = BTreeMap::new();
+    for device in &config.devices {
+        let device_id = device.device_id();
+        let mut device_info = json!({
+            "identifiers": [format!("{}_{}", node, device_id)],
+            "name": device.name,
+        });
+        if let Some(mac) = mac_connection(device) {
+            device_info["connections"] = json!([["mac", mac]]);
+        }
+        // Fields every entity of the device shares
+        let entity = |object_id: &str, fields: Value| {
+            let mut payload = json!({
+                "unique_id": format!("{}_{}_{}", node, device_id, object_id),
+                "availability_topic": topics.availability(),
+                "payload_available": ONLINE,
+                "payload_not_available": OFFLINE,
+                "device": device_info,
+                "origin": {
+                    "name": "wololo",
+                    "sw_version": env!("CARGO_PKG_VERSION"),
+                },
+            });
+            if let (Some(payload), Value::Object(fields)) = (payload.as_object_mut(), fields) {
+                payload.extend(fields);
+            }
+            payload.to_string()
+        };
+
+        messages.insert(
+            format!("{}/binary_sensor/{}/{}/config", prefix, node, device_id),
+            entity(
+                "status",
+                json!({
+                    // The device name alone, as this is its main entity
+                    "name": null,
+                    "device_class": "connectivity",
+                    "state_topic": topics.device_status(&device_id),
+                    "value_template": format!("{{{{ 'ON' if value == '{}' else 'OFF' }}}}", ONLINE),
+                }),
+            ),
+        );
+        messages.insert(
+            format!("{}/button/{}/{}_wake/config", prefix, node, device_id),
+            entity(
+                "wake",
+                json!({
+                    "name": "Wake",
+                    "icon": "mdi:power",
+                    "command_topic": topics.device_command(&device_id),
+                    "payload_press": "wake",
+                }),
+            ),
+        );
+        if !device.shutdown_command.is_empty() {
+            messages.insert(
+                format!("{}/button/{}/{}_shutdown/config", prefix, node, device_id),
+                entity(
+                    "shutdown",
+                    json!({
+                        "name": "Shut down",
+                        "icon": "mdi:power-off",
+                        "command_topic": topics.device_command(&device_id),
+                        "payload_press": "shutdown",
+                    }),
+                ),
+            );
+        }
+    }
+    messages
+}
@@ -0,0 +1,513 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::{DhcpLeaseFormat, DhcpLeaseSource};
+use crate::routes::{normalize_mac, DiscoveredDevice};
+
+// Status shown for devices that come from a DHCP lease rather than a ping
+const LEASE_STATUS: &str = "DHCP lease";
+
+// A line or record that could not be turned into a device
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ImportError {
+    pub source: String,
+    // 1-based line number, when the format has meaningful lines
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+// Devices parsed from one or more files, plus everything that failed to parse
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub devices: Vec<DiscoveredDevice>,
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    fn error(&mut self, source: &str, line: Option<usize>, message: impl Into<String>) {
+        self.errors.push(ImportError {
+            source: source.to_string(),
+            line,
+            message: message.into(),
+        });
+    }
+
+    // Append another report, keeping only the last record seen for each MAC address
+    pub fn merge(&mut self, other: ImportReport) {
+        self.devices.extend(other.devices);
+        self.errors.extend(other.errors);
+        self.devices = dedupe_by_mac(std::mem::take(&mut self.devices));
+    }
+}
+
+// Keep the last entry per MAC (lease files append newer leases at the end), preserving order
+fn dedupe_by_mac(devices: Vec<DiscoveredDevice>) -> Vec<DiscoveredDevice> {
+    let mut last_index: HashMap<String, usize> = HashMap::new();
+    for (index, device) in devices.iter().enumerate() {
+        if let Some(mac) = &device.mac_address {
+            last_index.insert(normalize_mac(mac), index);
+        }
+    }
+
+    devices
+        .into_iter()
+        .enumerate()
+        .filter(|(index, device)| match &device.mac_address {
+            Some(mac) => last_index.get(&normalize_mac(mac)) == Some(index),
+            None => true,
+        })
+        .map(|(_, device)| device)
+        .collect()
+}
+
+fn is_valid_mac(mac: &str) -> bool {
+    let parts: Vec<&str> = mac.split([':', '-']).collect();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_valid_ip(ip: &str) -> bool {
+    ip.parse::<std::net::IpAddr>().is_ok()
+}
+
+fn lease_device(ip: &str, mac: &str, hostname: Option<&str>) -> DiscoveredDevice {
+    DiscoveredDevice {
+        ip_address: ip.to_string(),
+        mac_address: Some(mac.to_ascii_uppercase().replace('-', ":")),
+        hostname: hostname
+            .map(|h| h.trim().trim_end_matches('.'))
+            .filter(|h| !h.is_empty() && *h != "*")
+            .map(|h| h.to_string()),
+        status: LEASE_STATUS.to_string(),
+    }
+}
+
+// dnsmasq: "<expiry> <mac> <ip> <hostname|*> <client-id|*>" per line
+pub fn parse_dnsmasq_leases(contents: &str, source: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // DUID lines describe the server itself, not a lease
+        if line.starts_with("duid ") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            report.error(source, Some(line_number), "expected at least 4 fields");
+            continue;
+        }
+        let (mac, ip, hostname) = (fields[1], fields[2], fields[3]);
+        if !is_valid_mac(mac) {
+            report.error(
+                source,
+                Some(line_number),
+                format!("invalid MAC address '{}'", mac),
+            );
+            continue;
+        }
+        if !is_valid_ip(ip) {
+            report.error(
+                source,
+                Some(line_number),
+                format!("invalid IP address '{}'", ip),
+            );
+            continue;
+        }
+        report.devices.push(lease_device(ip, mac, Some(hostname)));
+    }
+
+    report.devices = dedupe_by_mac(report.devices);
+    report
+}
+
+// ISC dhcpd: "lease <ip> { ... hardware ethernet <mac>; client-hostname "<name>"; }" blocks
+pub fn parse_isc_leases(contents: &str, source: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    struct Lease {
+        line: usize,
+        ip: String,
+        mac: Option<String>,
+        hostname: Option<String>,
+        state: Option<String>,
+    }
+    let mut current: Option<Lease> = None;
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("lease ") {
+            let ip = rest.trim_end_matches('{').trim();
+            current = Some(Lease {
+                line: line_number,
+                ip: ip.to_string(),
+                mac: None,
+                hostname: None,
+                state: None,
+            });
+            continue;
+        }
+
+        let Some(lease) = current.as_mut() else {
+            // Outside a lease block (server-duid, failover state, ...)
+            continue;
+        };
+
+        if line.starts_with('}') {
+            let lease = current.take().unwrap();
+            // Free, expired and abandoned leases no longer describe a live device
+            if matches!(
+                lease.state.as_deref(),
+                Some("free" | "expired" | "abandoned" | "released" | "backup")
+            ) {
+                continue;
+            }
+            if !is_valid_ip(&lease.ip) {
+                report.error(
+                    source,
+                    Some(lease.line),
+                    format!("invalid IP address '{}'", lease.ip),
+                );
+                continue;
+            }
+            match lease.mac {
+                Some(mac) if is_valid_mac(&mac) => {
+                    report
+                        .devices
+                        .push(lease_device(&lease.ip, &mac, lease.hostname.as_deref()));
+                }
+                Some(mac) => {
+                    report.error(
+                        source,
+                        Some(lease.line),
+                        format!("invalid MAC address '{}'", mac),
+                    );
+                }
+                None => {
+                    report.error(
+                        source,
+                        Some(lease.line),
+                        "lease has no hardware ethernet address",
+                    );
+                }
+            }
+            continue;
+        }
+
+        let statement = line.trim_end_matches(';');
+        if let Some(mac) = statement.strip_prefix("hardware ethernet ") {
+            lease.mac = Some(mac.trim().to_string());
+        } else if let Some(name) = statement.strip_prefix("client-hostname ") {
+            lease.hostname = Some(name.trim().trim_matches('"').to_string());
+        } else if let Some(state) = statement.strip_prefix("binding state ") {
+            lease.state = Some(state.trim().to_string());
+        }
+    }
+
+    if let Some(lease) = current {
+        report.error(source, Some(lease.line), "unterminated lease block");
+    }
+
+    report.devices = dedupe_by_mac(report.devices);
+    report
+}
+
+// Kea memfile CSV: header row naming address, hwaddr, hostname and state columns
+pub fn parse_kea_leases(contents: &str, source: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+    let mut lines = contents.lines().enumerate();
+
+    let header: Vec<&str> = match lines.next() {
+        Some((_, header)) => header.split(',').map(|h| h.trim()).collect(),
+        None => return report,
+    };
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let (Some(address_col), Some(hwaddr_col)) = (column("address"), column("hwaddr")) else {
+        report.error(
+            source,
+            Some(1),
+            "missing 'address' or 'hwaddr' column in header",
+        );
+        return report;
+    };
+    let hostname_col = column("hostname");
+    let state_col = column("state");
+
+    for (index, line) in lines {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let field = |col: usize| fields.get(col).map(|f| f.trim()).unwrap_or("");
+
+        // State 0 is an active lease; 1 (declined) and 2 (expired-reclaimed) are not
+        if let Some(col) = state_col {
+            if !matches!(field(col), "" | "0") {
+                continue;
+            }
+        }
+
+        let (ip, mac) = (field(address_col), field(hwaddr_col));
+        if !is_valid_ip(ip) {
+            report.error(
+                source,
+                Some(line_number),
+                format!("invalid IP address '{}'", ip),
+            );
+            continue;
+        }
+        if !is_valid_mac(mac) {
+            report.error(
+                source,
+                Some(line_number),
+                format!("invalid MAC address '{}'", mac),
+            );
+            continue;
+        }
+        let hostname = hostname_col.map(field);
+        report.devices.push(lease_device(ip, mac, hostname));
+    }
+
+    report.devices = dedupe_by_mac(report.devices);
+    report
+}
+
+// Pick a parser when the configured format is `auto`
+pub fn detect_lease_format(path: &str, contents: &str) -> DhcpLeaseFormat {
+    let first_line = contents
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("");
+    if first_line.starts_with("address,") {
+        DhcpLeaseFormat::Kea
+    } else if path.ends_with("dhcpd.leases")
+        || (contents.contains("lease ") && contents.contains('{'))
+    {
+        DhcpLeaseFormat::Isc
+    } else {
+        DhcpLeaseFormat::Dnsmasq
+    }
+}
+
+pub fn parse_leases(format: DhcpLeaseFormat, contents: &str, source: &str) -> ImportReport {
+    let format = match format {
+        DhcpLeaseFormat::Auto => detect_lease_format(source, contents),
+        other => other,
+    };
+    match format {
+        DhcpLeaseFormat::Dnsmasq | DhcpLeaseFormat::Auto => parse_dnsmasq_leases(contents, source),
+        DhcpLeaseFormat::Isc => parse_isc_leases(contents, source),
+        DhcpLeaseFormat::Kea => parse_kea_leases(contents, source),
+    }
+}
+
+// Read every configured lease file; unreadable files are reported rather than fatal
+pub async fn import_dhcp_leases(sources: &[DhcpLeaseSource]) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for source in sources {
+        match tokio::fs::read_to_string(Path::new(&source.path)).await {
+            Ok(contents) => report.merge(parse_leases(source.format, &contents, &source.path)),
+            Err(e) => report.error(&source.path, None, format!("failed to read file: {}", e)),
+        }
+    }
+
+    report
+}
+
+// Inventory file formats accepted by the upload endpoint and `wololo import`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum InventoryFormat {
+    // Guess from the contents
+    #[default]
+    Auto,
+    // nmap -oX output
+    Nmap,
+    // CSV with name, mac and ip columns
+    Csv,
+}
+
+// Status shown for devices imported from an inventory file
+const INVENTORY_STATUS: &str = "Imported";
+
+// nmap -oX: every <host> that is up, with its IPv4/IPv6, MAC and first hostname
+pub fn parse_nmap_xml(contents: &str, source: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    // nmap writes a <!DOCTYPE nmaprun> line, which roxmltree rejects by default
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let document = match roxmltree::Document::parse_with_options(contents, options) {
+        Ok(document) => document,
+        Err(e) => {
+            let position = e.pos();
+            report.error(
+                source,
+                Some(position.row as usize),
+                format!("invalid XML: {}", e),
+            );
+            return report;
+        }
+    };
+
+    if document.root_element().tag_name().name() != "nmaprun" {
+        report.error(source, None, "not an nmap XML report (missing <nmaprun>)");
+        return report;
+    }
+
+    for host in document
+        .descendants()
+        .filter(|node| node.has_tag_name("host"))
+    {
+        let line = document.text_pos_at(host.range().start).row as usize;
+
+        let state = host
+            .children()
+            .find(|node| node.has_tag_name("status"))
+            .and_then(|status| status.attribute("state"));
+        if state.is_some_and(|state| state != "up") {
+            continue;
+        }
+
+        let address = |kind: &str| {
+            host.children()
+                .filter(|node| node.has_tag_name("address"))
+                .find(|node| node.attribute("addrtype") == Some(kind))
+                .and_then(|node| node.attribute("addr"))
+        };
+        let Some(ip) = address("ipv4").or_else(|| address("ipv6")) else {
+            report.error(source, Some(line), "host has no IP address");
+            continue;
+        };
+        let mac = address("mac");
+        if let Some(mac) = mac {
+            if !is_valid_mac(mac) {
+                report.error(source, Some(line), format!("invalid MAC address '{}'", mac));
+                continue;
+            }
+        }
+        let hostname = host
+            .descendants()
+            .find(|node| node.has_tag_name("hostname"))
+            .and_then(|node| node.attribute("name"));
+
+        report.devices.push(DiscoveredDevice {
+            ip_address: ip.to_string(),
+            mac_address: mac.map(|mac| mac.to_ascii_uppercase().replace('-', ":")),
+            hostname: hostname.map(|h| h.to_string()),
+            status: INVENTORY_STATUS.to_string(),
+        });
+    }
+
+    report.devices = dedupe_by_mac(report.devices);
+    report
+}
+
+// CSV of name/mac/ip. A header row may name the columns (name, mac or mac_address,
+// ip or ip_address) in any order; without one the columns are name, mac, ip.
+pub fn parse_device_csv(contents: &str, source: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let mut columns = (0, 1, 2);
+    let mut first = true;
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line() as usize);
+                report.error(source, line, format!("invalid CSV: {}", e));
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line() as usize);
+
+        // Skip blank rows and "#" comments; the reader's own comment support loses line numbers
+        if record.iter().all(|field| field.is_empty())
+            || record.get(0).is_some_and(|field| field.starts_with('#'))
+        {
+            continue;
+        }
+
+        if first {
+            first = false;
+            let lowered: Vec<String> = record.iter().map(|f| f.to_ascii_lowercase()).collect();
+            let find = |names: &[&str]| lowered.iter().position(|f| names.contains(&f.as_str()));
+            if let (Some(name), Some(mac), Some(ip)) = (
+                find(&["name", "hostname"]),
+                find(&["mac", "mac_address"]),
+                find(&["ip", "ip_address"]),
+            ) {
+                columns = (name, mac, ip);
+                continue;
+            }
+        }
+
+        let field = |index: usize| record.get(index).unwrap_or("");
+        let (name, mac, ip) = (field(columns.0), field(columns.1), field(columns.2));
+        if !is_valid_mac(mac) {
+            report.error(source, line, format!("invalid MAC address '{}'", mac));
+            continue;
+        }
+        if !is_valid_ip(ip) {
+            report.error(source, line, format!("invalid IP address '{}'", ip));
+            continue;
+        }
+
+        report.devices.push(DiscoveredDevice {
+            ip_address: ip.to_string(),
+            mac_address: Some(mac.to_ascii_uppercase().replace('-', ":")),
+            hostname: (!name.is_empty()).then(|| name.to_string()),
+            status: INVENTORY_STATUS.to_string(),
+        });
+    }
+
+    report.devices = dedupe_by_mac(report.devices);
+    report
+}
+
+pub fn detect_inventory_format(contents: &str) -> InventoryFormat {
+    let start = contents.trim_start();
+    if start.starts_with("<?xml") || start.starts_with("<nmaprun") || contents.contains("<nmaprun")
+    {
+        InventoryFormat::Nmap
+    } else {
+        InventoryFormat::Csv
+    }
+}
+
+// Parse an uploaded or local inventory file
+pub fn parse_inventory(format: InventoryFormat, contents: &str, source: &str) -> ImportReport {
+    let format = match format {
+        InventoryFormat::Auto => detect_inventory_format(contents),
+        other => other,
+    };
+    match format {
+        InventoryFormat::Nmap => parse_nmap_xml(contents, source),
+        InventoryFormat::Csv | InventoryFormat::Auto => parse_device_csv(contents, source),
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+// A value kept in memory and mirrored to a JSON file, shared by the seen device list, the
+// webhook queue and the API tokens
+pub struct JsonStore<T> {
+    path: Option<PathBuf>,
+    value: Arc<Mutex<T>>,
+}
+
+// Derived Clone would require T: Clone
+impl<T> Clone for JsonStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    // Load the file; a missing or unreadable file starts from the default value
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let value = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {}: {}. Starting empty.", path.display(), e);
+                T::default()
+            }),
+            Err(_) => T::default(),
+        };
+
+        Self {
+            path: Some(path),
+            value: Arc::new(Mutex::new(value)),
+        }
+    }
+
+    // A store that is never written to disk (used by tests)
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            value: Arc::new(Mutex::new(T::default())),
+        }
+    }
+
+    pub async fn lock(&self) -> JsonGuard<'_, T> {
+        JsonGuard {
+            path: self.path.as_deref(),
+            value: self.value.lock().await,
+        }
+    }
+}
+
+// Access to the value; call save after changing it
+pub struct JsonGuard<'a, T> {
+    path: Option<&'a Path>,
+    value: MutexGuard<'a, T>,
+}
+
+impl<T: Serialize> JsonGuard<'_, T> {
+    // Write the value atomically (temp file + rename) so a crash never leaves half a file.
+    // The lock is held throughout, so concurrent saves cannot interleave or land out of order.
+    pub async fn save(&self) {
+        let Some(path) = self.path else {
+            return;
+        };
+
+        let contents = match serde_json::to_string_pretty(&*self.value) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Failed to serialize {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let tmp_path = path.with_extension("json.tmp");
+        if let Err(e) = tokio::fs::write(&tmp_path, contents).await {
+            eprintln!("Failed to write {}: {}", tmp_path.display(), e);
+            return;
+        }
+        if let Err(e) = tokio::fs::rename(&tmp_path, path).await {
+            eprintln!("Failed to replace {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl<T> Deref for JsonGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for JsonGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
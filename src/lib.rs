@@ -1,6 +1,26 @@
 // Lib file to support testing
+pub mod auth;
+pub mod background_discovery;
+pub mod cli;
 pub mod config;
+pub mod config_edit;
+pub mod events;
+pub mod export;
+pub mod health;
+pub mod history;
+pub mod home_assistant;
+pub mod import;
+pub mod json_store;
+pub mod metrics;
+pub mod mqtt;
+pub mod notifications;
+pub mod poller;
+pub mod reload;
 pub mod routes;
+pub mod tokens;
+pub mod validation;
+pub mod webhooks;
+pub mod websocket;
 
 pub use config::*;
 pub use routes::*;
@@ -11,9 +31,8 @@ pub use routes::AppState;
 impl AppState {
     pub fn new_for_test(config: config::Config) -> Self {
         use handlebars::Handlebars;
-        use std::collections::HashMap;
         use std::sync::Arc;
-        use tokio::sync::Mutex;
+        use std::time::Duration;
 
         let mut hb = Handlebars::new();
         // Register minimal templates for testing
@@ -21,11 +40,29 @@ impl AppState {
             .unwrap();
         hb.register_template_string("discovery", "discovery template")
             .unwrap();
+        hb.register_template_string("devices", "{{{manager}}}")
+            .unwrap();
+        hb.register_template_string("device", "{{{detail}}}")
+            .unwrap();
+        hb.register_template_string("login", "login {{error}}")
+            .unwrap();
+
+        let discovered_devices =
+            ScanStore::new(Duration::from_secs(config.discovery.result_ttl_seconds));
+        let session_key = auth::session_key(&config.auth);
 
         Self {
-            config,
+            config: reload::ConfigHandle::new(config),
             handlebars: Arc::new(hb),
-            discovered_devices: Arc::new(Mutex::new(HashMap::new())),
+            discovered_devices,
+            events: events::EventBus::new(),
+            seen_devices: background_discovery::SeenDevices::in_memory(),
+            history: history::History::in_memory(),
+            statuses: poller::StatusBoard::new(),
+            metrics: metrics::Metrics::new(),
+            webhooks: webhooks::WebhookQueue::in_memory(),
+            session_key,
+            tokens: tokens::TokenStore::in_memory(),
         }
     }
 }
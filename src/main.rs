@@ -1,19 +1,28 @@
+use clap::Parser;
 use handlebars::Handlebars;
 use rust_embed::RustEmbed;
-use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::Arc; // Added for Arc
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tokio::sync::Mutex; // Required for IpAddr::from_str // Added for Handlebars
 
-mod config;
-use config::load_config; // Ensure Config is imported
-
-extern crate serde_json;
-
-mod routes;
-use routes::{app_router, AppState};
+// Use the library crate so the binary and the tests share one copy of every module
+use wololo::auth::session_key;
+use wololo::background_discovery::{spawn_background_discovery, SeenDevices};
+use wololo::cli::{self, Cli, Command, ConfigCommand};
+use wololo::events::EventBus;
+use wololo::history::{spawn_history_retention, History};
+use wololo::metrics::Metrics;
+use wololo::mqtt::spawn_mqtt;
+use wololo::notifications::spawn_notifications;
+use wololo::poller::{spawn_status_poller, spawn_wake_verifier, StatusBoard};
+use wololo::reload::{spawn_config_reloader, ConfigHandle};
+use wololo::routes::{app_router, AppState, ScanStore, PAGE_TEMPLATES};
+use wololo::tokens::TokenStore;
+use wololo::webhooks::{spawn_webhooks, WebhookQueue};
 
 #[derive(RustEmbed, Clone)]
 #[folder = "frontend/"]
@@ -24,65 +33,177 @@ pub struct FEAssets;
 pub struct Assets;
 
 #[tokio::main]
-async fn main() {
-    let config = match load_config() {
-        Ok(cfg) => {
-            println!("Loaded configuration: {:#?}", cfg);
-            cfg
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    ExitCode::from(run(cli).await)
+}
+
+async fn run(cli: Cli) -> u8 {
+    match dispatch(cli).await {
+        Ok(code) | Err(code) => code,
+    }
+}
+
+// Run a subcommand. Commands that need the config load it first; a config that fails to
+// load ends the command with its exit code as the error.
+async fn dispatch(cli: Cli) -> Result<u8, u8> {
+    let config_path = cli.config;
+    let load = || cli::load_config_or_report(&config_path);
+
+    Ok(match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let config = load()?;
+            println!(
+                "Loaded configuration from {} ({} device(s))",
+                config_path.display(),
+                config.devices.len()
+            );
+            serve(config, &config_path).await
         }
-        Err(e) => {
-            eprintln!("Failed to load configuration: {}. Exiting.", e);
-            return;
+        Command::Wake { target } => cli::wake_command(&load()?, &target).await,
+        Command::Status { target, output } => {
+            cli::status_command(&load()?, target.as_deref(), output.json).await
         }
-    };
+        Command::Scan { cidr, output } => cli::scan_command(&load()?, cidr, output.json).await,
+        // Reports load errors itself
+        Command::Config {
+            command: ConfigCommand::Validate,
+        } => cli::config_validate_command(&config_path),
+        Command::Config {
+            command: ConfigCommand::Print { show_secrets },
+        } => cli::config_print_command(&load()?, show_secrets),
+        Command::Import {
+            file,
+            format,
+            apply_updates,
+            output,
+        } => {
+            let config = load()?;
+            cli::import_command(&config, &file, format, apply_updates, output.as_deref()).await
+        }
+        Command::Export { format, output } => {
+            cli::export_command(&load()?, format, output.as_deref())
+        }
+        // Needs no config
+        Command::HashPassword => cli::hash_password_command(),
+    })
+}
 
+async fn serve(config: wololo::config::Config, config_path: &Path) -> u8 {
     // Initialize Handlebars
     let mut hb = Handlebars::<'static>::new();
     hb.set_strict_mode(true); // Optional: enable strict mode
 
-    // Register the index.html template
-    match FEAssets::get("index.html") {
-        Some(file) => {
-            let template_content =
-                std::str::from_utf8(&file.data).expect("index.html is not valid UTF-8");
-            if let Err(e) = hb.register_template_string("index", template_content) {
-                eprintln!("Failed to register index.html template: {}. Exiting.", e);
-                return;
+    // Register the page templates
+    for (name, file_name) in PAGE_TEMPLATES {
+        match FEAssets::get(file_name) {
+            Some(file) => {
+                let Ok(template_content) = std::str::from_utf8(&file.data) else {
+                    eprintln!("{} is not valid UTF-8. Exiting.", file_name);
+                    return cli::EXIT_FAILURE;
+                };
+                if let Err(e) = hb.register_template_string(name, template_content) {
+                    eprintln!("Failed to register {} template: {}. Exiting.", file_name, e);
+                    return cli::EXIT_FAILURE;
+                }
             }
-        }
-        None => {
-            eprintln!("frontend/index.html not found in embedded assets. Exiting.");
-            return;
-        }
-    }
-
-    // Register the discovery.html template
-    match FEAssets::get("discovery.html") {
-        Some(file) => {
-            let template_content =
-                std::str::from_utf8(&file.data).expect("discovery.html is not valid UTF-8");
-            if let Err(e) = hb.register_template_string("discovery", template_content) {
+            None => {
                 eprintln!(
-                    "Failed to register discovery.html template: {}. Exiting.",
-                    e
+                    "frontend/{} not found in embedded assets. Exiting.",
+                    file_name
                 );
-                return;
+                return cli::EXIT_FAILURE;
             }
         }
-        None => {
-            eprintln!("frontend/discovery.html not found in embedded assets. Exiting.");
-            return;
-        }
     }
     let hb_arc = Arc::new(hb);
 
     // Create the application state
     let app_state = AppState {
-        config: config.clone(), // config needs to be Clone
+        config: ConfigHandle::with_path(config.clone(), config_path),
         handlebars: hb_arc,
-        discovered_devices: Arc::new(Mutex::new(HashMap::new())),
+        discovered_devices: ScanStore::new(Duration::from_secs(
+            config.discovery.result_ttl_seconds,
+        )),
+        events: EventBus::new(),
+        seen_devices: SeenDevices::load(&config.discovery.background.seen_devices_path),
+        history: History::from_config(&config.history),
+        statuses: StatusBoard::new(),
+        metrics: Metrics::new(),
+        webhooks: WebhookQueue::load(&config.webhooks.queue_path),
+        session_key: session_key(&config.auth),
+        tokens: TokenStore::load(&config.auth.tokens_path),
     };
 
+    if config.auth.enabled {
+        println!("Login required for the web UI and API");
+        if config.auth.session_secret.is_none() {
+            println!("auth.session_secret is not set; sessions end when the server restarts");
+        }
+    }
+
+    // Reload config.yaml on SIGHUP and when the file changes
+    spawn_config_reloader(app_state.config.clone(), app_state.events.clone());
+
+    // Start periodic background discovery if enabled
+    if config.discovery.background.enabled {
+        println!(
+            "Background discovery enabled, scanning every {} seconds",
+            config.discovery.background.interval_seconds.max(60)
+        );
+        spawn_background_discovery(app_state.clone());
+    }
+
+    // Keep the history database within its retention period
+    if app_state.history.is_enabled() {
+        println!(
+            "Recording history in {} (kept for {} days)",
+            config.history.path, config.history.retention_days
+        );
+        spawn_history_retention(app_state.history.clone(), config.history.retention_days);
+    }
+
+    // Ping every device periodically and record status changes
+    if config.poller.enabled {
+        println!(
+            "Status poller enabled, checking devices every {} seconds",
+            config.poller.interval_seconds.max(1)
+        );
+        spawn_status_poller(app_state.clone());
+    }
+
+    // Report wakes that did not bring the device online
+    spawn_wake_verifier(app_state.clone());
+
+    // Send events to webhooks; endpoints added by a reload are picked up without a restart
+    if !config.webhooks.endpoints.is_empty() {
+        println!(
+            "Sending events to {} webhook(s)",
+            config.webhooks.endpoints.len()
+        );
+    }
+    spawn_webhooks(app_state.clone());
+
+    // Send human-readable alerts to the notification channels
+    if !config.notifications.channels.is_empty() {
+        println!(
+            "Sending notifications to {} channel(s)",
+            config.notifications.channels.len()
+        );
+    }
+    spawn_notifications(app_state.clone());
+
+    // Publish device statuses to an MQTT broker and take commands from it
+    if config.mqtt.enabled {
+        println!(
+            "Connecting to MQTT broker {}:{}",
+            config.mqtt.host, config.mqtt.port
+        );
+        if let Err(e) = spawn_mqtt(app_state.clone()) {
+            eprintln!("MQTT disabled: {}", e);
+        }
+    }
+
     // Use server config for IP and Port
     let configured_ip = match IpAddr::from_str(&config.server.ip) {
         Ok(ip) => ip,
@@ -108,11 +229,13 @@ async fn main() {
         Ok(listener) => listener,
         Err(e) => {
             eprintln!("Failed to bind to address {}: {}. Exiting.", addr, e);
-            return;
+            return cli::EXIT_FAILURE;
         }
     };
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    if let Err(e) = axum::serve(listener, app.into_make_service()).await {
+        eprintln!("Server error: {}", e);
+        return cli::EXIT_FAILURE;
+    }
+    cli::EXIT_OK
 }
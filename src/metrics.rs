@@ -0,0 +1,303 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::background_discovery::unix_now;
+use crate::config::Device;
+use crate::routes::{AppState, DeviceStatus};
+
+// Wake-on-LAN is the only way wololo wakes devices for now
+pub const WOL_BACKEND: &str = "wol";
+
+// Counters kept since the server started. Label sets are rendered once when a series is first
+// seen and used as map keys, so the output is sorted and stable between scrapes.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    wake_attempts: BTreeMap<String, u64>,
+    wake_failures: BTreeMap<String, u64>,
+    http_requests: BTreeMap<String, u64>,
+    scans: BTreeMap<String, ScanStats>,
+}
+
+#[derive(Default)]
+struct ScanStats {
+    count: u64,
+    duration_sum: f64,
+    last_duration: f64,
+    last_hosts: usize,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn record_wake(&self, device: &Device, backend: &str, source: &str, success: bool) {
+        let labels = format!(
+            "{},backend=\"{}\",source=\"{}\"",
+            device_labels(device),
+            escape_label(backend),
+            escape_label(source)
+        );
+        let mut counters = self.counters();
+        if !success {
+            *counters.wake_failures.entry(labels.clone()).or_default() += 1;
+        }
+        *counters.wake_attempts.entry(labels).or_default() += 1;
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16) {
+        let labels = format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            escape_label(method),
+            escape_label(route),
+            status
+        );
+        *self.counters().http_requests.entry(labels).or_default() += 1;
+    }
+
+    // `kind` is "manual" for scans from the discovery page, "background" for scheduled ones
+    pub fn record_scan(&self, kind: &str, duration: Duration, hosts_found: usize) {
+        let mut counters = self.counters();
+        let stats = counters
+            .scans
+            .entry(format!("kind=\"{}\"", escape_label(kind)))
+            .or_default();
+        stats.count += 1;
+        stats.duration_sum += duration.as_secs_f64();
+        stats.last_duration = duration.as_secs_f64();
+        stats.last_hosts = hosts_found;
+    }
+}
+
+// Labels that identify a device. Groups and tags are joined with commas.
+fn device_labels(device: &Device) -> String {
+    format!(
+        "device=\"{}\",device_id=\"{}\",group=\"{}\",tags=\"{}\"",
+        escape_label(&device.name),
+        escape_label(&device.device_id()),
+        escape_label(&device.groups.join(",")),
+        escape_label(&device.tags.join(","))
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Everything in the Prometheus text exposition format
+pub fn render_metrics(app_state: &AppState) -> String {
+    let config = app_state.config.get();
+    let statuses = app_state.statuses.all();
+    let now = unix_now();
+    let mut out = String::new();
+
+    // Device gauges come from the poller, so they are missing until its first round
+    let polled: Vec<(&Device, _)> = config
+        .devices
+        .iter()
+        .filter_map(|device| {
+            statuses
+                .get(&device.device_id())
+                .map(|state| (device, state))
+        })
+        .collect();
+
+    write_header(
+        &mut out,
+        "wololo_device_up",
+        "gauge",
+        "Whether the device answered the poller's last ping (1) or not (0).",
+    );
+    for (device, state) in &polled {
+        let up = u8::from(state.status == DeviceStatus::Online);
+        let _ = writeln!(out, "wololo_device_up{{{}}} {}", device_labels(device), up);
+    }
+
+    write_header(
+        &mut out,
+        "wololo_device_probe_latency_seconds",
+        "gauge",
+        "Round-trip time of the poller's last answered ping.",
+    );
+    for (device, state) in &polled {
+        if let Some(latency_ms) = state.latency_ms {
+            let _ = writeln!(
+                out,
+                "wololo_device_probe_latency_seconds{{{}}} {}",
+                device_labels(device),
+                latency_ms / 1000.0
+            );
+        }
+    }
+
+    let counters = app_state.metrics.counters();
+
+    write_header(
+        &mut out,
+        "wololo_wake_attempts_total",
+        "counter",
+        "Wake attempts by device, backend and source.",
+    );
+    for (labels, count) in &counters.wake_attempts {
+        let _ = writeln!(out, "wololo_wake_attempts_total{{{}}} {}", labels, count);
+    }
+    write_header(
+        &mut out,
+        "wololo_wake_failures_total",
+        "counter",
+        "Wake attempts that failed to send, by device, backend and source.",
+    );
+    for (labels, count) in &counters.wake_failures {
+        let _ = writeln!(out, "wololo_wake_failures_total{{{}}} {}", labels, count);
+    }
+
+    write_header(
+        &mut out,
+        "wololo_discovery_scan_duration_seconds",
+        "summary",
+        "Time taken by network discovery scans.",
+    );
+    for (labels, stats) in &counters.scans {
+        let _ = writeln!(
+            out,
+            "wololo_discovery_scan_duration_seconds_sum{{{}}} {}",
+            labels, stats.duration_sum
+        );
+        let _ = writeln!(
+            out,
+            "wololo_discovery_scan_duration_seconds_count{{{}}} {}",
+            labels, stats.count
+        );
+    }
+    write_header(
+        &mut out,
+        "wololo_discovery_last_scan_duration_seconds",
+        "gauge",
+        "Time taken by the most recent discovery scan.",
+    );
+    for (labels, stats) in &counters.scans {
+        let _ = writeln!(
+            out,
+            "wololo_discovery_last_scan_duration_seconds{{{}}} {}",
+            labels, stats.last_duration
+        );
+    }
+    write_header(
+        &mut out,
+        "wololo_discovery_last_scan_hosts",
+        "gauge",
+        "Hosts found by the most recent discovery scan.",
+    );
+    for (labels, stats) in &counters.scans {
+        let _ = writeln!(
+            out,
+            "wololo_discovery_last_scan_hosts{{{}}} {}",
+            labels, stats.last_hosts
+        );
+    }
+
+    write_header(
+        &mut out,
+        "wololo_http_requests_total",
+        "counter",
+        "HTTP requests by method, route and status code.",
+    );
+    for (labels, count) in &counters.http_requests {
+        let _ = writeln!(out, "wololo_http_requests_total{{{}}} {}", labels, count);
+    }
+    drop(counters);
+
+    if let Some(round) = app_state.statuses.last_round() {
+        write_header(
+            &mut out,
+            "wololo_poller_last_round_timestamp_seconds",
+            "gauge",
+            "Unix time the poller last finished checking every device.",
+        );
+        let _ = writeln!(
+            out,
+            "wololo_poller_last_round_timestamp_seconds {}",
+            round.finished_at
+        );
+        write_header(
+            &mut out,
+            "wololo_poller_last_round_duration_seconds",
+            "gauge",
+            "Time the poller's last round took.",
+        );
+        let _ = writeln!(
+            out,
+            "wololo_poller_last_round_duration_seconds {}",
+            round.duration.as_secs_f64()
+        );
+        write_header(
+            &mut out,
+            "wololo_poller_lag_seconds",
+            "gauge",
+            "Seconds the next poller round is overdue; 0 while the poller keeps up.",
+        );
+        let _ = writeln!(out, "wololo_poller_lag_seconds {}", round.lag(now));
+    }
+
+    out
+}
+
+// Handler for GET /metrics
+pub async fn metrics_handler(State(app_state): State<AppState>) -> Response {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        render_metrics(&app_state),
+    )
+        .into_response()
+}
+
+// Middleware counting requests by route. The route is the matched pattern (e.g.
+// "/wake/:device_id"), so device ids do not create new series.
+pub async fn count_requests(
+    State(app_state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+    app_state
+        .metrics
+        .record_request(&method, &route, response.status().as_u16());
+    response
+}
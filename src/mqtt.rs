@@ -0,0 +1,422 @@
+use rumqttc::{
+    AsyncClient, Event as MqttEvent, EventLoop, LastWill, MqttOptions, Packet, QoS,
+    TlsConfiguration, Transport,
+};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::background_discovery::unix_now;
+use crate::config::{Device, MqttConfig};
+use crate::events::Event;
+use crate::home_assistant::{birth_topic, discovery_messages};
+use crate::poller::record_check;
+use crate::routes::{probe_device, shutdown_and_record, wake_and_record, AppState};
+
+// Source recorded in history for actions sent over MQTT
+pub const MQTT_SOURCE: &str = "mqtt";
+
+// Payloads of the availability topic. The broker publishes OFFLINE as our last will when
+// the connection drops without a clean disconnect.
+pub const ONLINE: &str = "online";
+pub const OFFLINE: &str = "offline";
+
+// Wait before reconnecting after the connection failed or dropped
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Requests waiting for the event loop before publishing blocks
+const CLIENT_CAPACITY: usize = 100;
+
+// Topic layout under mqtt.topic_prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topics {
+    prefix: String,
+}
+
+// What a command topic addresses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandTarget<'a> {
+    All,
+    Device(&'a str),
+}
+
+impl Topics {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // "online" while wololo is connected, "offline" otherwise (retained)
+    pub fn availability(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    // The device's status, e.g. "online" (retained)
+    pub fn device_status(&self, device_id: &str) -> String {
+        format!("{}/devices/{}/status", self.prefix, device_id)
+    }
+
+    // "wake", "shutdown" or "refresh" for one device
+    pub fn device_command(&self, device_id: &str) -> String {
+        format!("{}/devices/{}/command", self.prefix, device_id)
+    }
+
+    // Outcome of each device command as JSON
+    pub fn device_result(&self, device_id: &str) -> String {
+        format!("{}/devices/{}/result", self.prefix, device_id)
+    }
+
+    // "refresh" checks every device
+    pub fn command(&self) -> String {
+        format!("{}/command", self.prefix)
+    }
+
+    fn device_command_filter(&self) -> String {
+        self.device_command("+")
+    }
+
+    pub fn command_target<'a>(&self, topic: &'a str) -> Option<CommandTarget<'a>> {
+        let rest = topic.strip_prefix(&self.prefix)?.strip_prefix('/')?;
+        if rest == "command" {
+            return Some(CommandTarget::All);
+        }
+        let device_id = rest.strip_prefix("devices/")?.strip_suffix("/command")?;
+        (!device_id.is_empty() && !device_id.contains('/'))
+            .then_some(CommandTarget::Device(device_id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MqttCommand {
+    Wake,
+    Shutdown,
+    // Ping now and publish the status
+    Refresh,
+}
+
+impl MqttCommand {
+    // Payloads are matched case-insensitively, ignoring surrounding whitespace
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?.trim();
+        match payload.to_ascii_lowercase().as_str() {
+            "wake" => Some(MqttCommand::Wake),
+            "shutdown" => Some(MqttCommand::Shutdown),
+            "refresh" => Some(MqttCommand::Refresh),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MqttCommand::Wake => "wake",
+            MqttCommand::Shutdown => "shutdown",
+            MqttCommand::Refresh => "refresh",
+        }
+    }
+}
+
+// Broker connection settings, including the last will that marks wololo offline
+pub fn mqtt_options(config: &MqttConfig) -> Result<MqttOptions, String> {
+    let topics = Topics::new(&config.topic_prefix);
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_seconds.max(1)));
+    options.set_last_will(LastWill::new(
+        topics.availability(),
+        OFFLINE,
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    if config.tls {
+        // rustls needs a crypto provider; this fails harmlessly when one is already installed
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let tls = match &config.ca_file {
+            Some(path) => TlsConfiguration::Simple {
+                ca: std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+                alpn: None,
+                client_auth: None,
+            },
+            None => TlsConfiguration::default(),
+        };
+        options.set_transport(Transport::tls_with_config(tls));
+    }
+    Ok(options)
+}
+
+// Run a command for one device and describe the outcome for its result topic
+pub async fn run_device_command(
+    app_state: &AppState,
+    device_id: &str,
+    command: MqttCommand,
+) -> Value {
+    let config = app_state.config.get();
+    let Some(device) = config.find_device(device_id) else {
+        return command_error(command, format!("Device '{}' not found", device_id));
+    };
+
+    match command {
+        MqttCommand::Wake => match wake_and_record(app_state, device, MQTT_SOURCE).await {
+            Ok(()) => {
+                println!("Wake-on-LAN packet sent to device: {}", device.name);
+                json!({ "command": command.as_str(), "ok": true })
+            }
+            Err(e) => command_error(command, e.to_string()),
+        },
+        MqttCommand::Shutdown => {
+            if device.shutdown_command.is_empty() {
+                return command_error(
+                    command,
+                    format!("{} has no shutdown_command configured", device.name),
+                );
+            }
+            match shutdown_and_record(app_state, device, MQTT_SOURCE).await {
+                Ok(()) => {
+                    println!("Shutdown command run for device: {}", device.name);
+                    json!({ "command": command.as_str(), "ok": true })
+                }
+                Err(e) => command_error(command, e),
+            }
+        }
+        MqttCommand::Refresh => {
+            let status = refresh_device(app_state, device).await;
+            json!({ "command": command.as_str(), "ok": true, "status": status })
+        }
+    }
+}
+
+fn command_error(command: MqttCommand, error: String) -> Value {
+    json!({ "command": command.as_str(), "ok": false, "error": error })
+}
+
+// Ping a device and record the check. A changed status reaches the broker through the
+// status_changed event.
+async fn refresh_device(app_state: &AppState, device: &Device) -> &'static str {
+    let (status, latency_ms) = probe_device(&device.ip_address).await;
+    record_check(app_state, device, status, latency_ms, unix_now()).await;
+    status.as_str()
+}
+
+async fn publish_retained(client: &AsyncClient, topic: String, payload: &str) {
+    if let Err(e) = client
+        .publish(&topic, QoS::AtLeastOnce, true, payload.as_bytes().to_vec())
+        .await
+    {
+        eprintln!("Failed to publish to MQTT topic {}: {}", topic, e);
+    }
+}
+
+// Retained topics published for the current devices, so those of removed devices can be
+// cleared
+#[derive(Default)]
+struct Published {
+    device_ids: HashSet<String>,
+    // Home Assistant discovery topic and payload
+    discovery: BTreeMap<String, String>,
+}
+
+// What the connection tasks share
+#[derive(Clone)]
+struct Link {
+    app_state: AppState,
+    client: AsyncClient,
+    topics: Topics,
+    published: Arc<Mutex<Published>>,
+}
+
+// Publish discovery configs and statuses of the configured devices, and clear the retained
+// topics of removed ones. After a reconnect everything is sent again; otherwise only what
+// changed since the last call.
+async fn publish_devices(link: &Link, everything: bool) {
+    let config = link.app_state.config.get();
+    let mut published = link.published.lock().await;
+
+    // Discovery first, so Home Assistant knows an entity before its state arrives
+    let discovery = discovery_messages(&config, &link.topics);
+    if config.mqtt.home_assistant.enabled {
+        let filter = birth_topic(&config);
+        if let Err(e) = link.client.subscribe(&filter, QoS::AtLeastOnce).await {
+            eprintln!("Failed to subscribe to MQTT topic {}: {}", filter, e);
+        }
+    }
+    for (topic, payload) in &discovery {
+        if everything || published.discovery.get(topic) != Some(payload) {
+            publish_retained(&link.client, topic.clone(), payload).await;
+        }
+    }
+    for topic in published.discovery.keys() {
+        if !discovery.contains_key(topic) {
+            // An empty retained message removes the entity
+            publish_retained(&link.client, topic.clone(), "").await;
+        }
+    }
+
+    let device_ids: HashSet<String> = config.devices.iter().map(Device::device_id).collect();
+    for device_id in published.device_ids.difference(&device_ids) {
+        publish_retained(&link.client, link.topics.device_status(device_id), "").await;
+    }
+    for device_id in &device_ids {
+        if everything || !published.device_ids.contains(device_id) {
+            if let Some(state) = link.app_state.statuses.get(device_id) {
+                publish_retained(
+                    &link.client,
+                    link.topics.device_status(device_id),
+                    state.status.as_str(),
+                )
+                .await;
+            }
+        }
+    }
+
+    *published = Published {
+        device_ids,
+        discovery,
+    };
+}
+
+// After every (re)connect: subscribe to the command topics, publish every device and
+// announce that wololo is online
+async fn on_connect(link: &Link) {
+    for filter in [link.topics.device_command_filter(), link.topics.command()] {
+        if let Err(e) = link.client.subscribe(&filter, QoS::AtLeastOnce).await {
+            eprintln!("Failed to subscribe to MQTT topic {}: {}", filter, e);
+        }
+    }
+    publish_devices(link, true).await;
+    publish_retained(&link.client, link.topics.availability(), ONLINE).await;
+}
+
+async fn handle_message(link: &Link, topic: &str, payload: &[u8]) {
+    // Home Assistant restarted and needs the discovery configs again
+    let config = link.app_state.config.get();
+    if config.mqtt.home_assistant.enabled && topic == birth_topic(&config) {
+        if payload == ONLINE.as_bytes() {
+            publish_devices(link, true).await;
+        }
+        return;
+    }
+
+    let Some(target) = link.topics.command_target(topic) else {
+        return;
+    };
+    let Some(command) = MqttCommand::parse(payload) else {
+        eprintln!(
+            "Ignoring MQTT command '{}' on {}; use wake, shutdown or refresh",
+            String::from_utf8_lossy(payload),
+            topic
+        );
+        return;
+    };
+
+    match target {
+        CommandTarget::Device(device_id) => {
+            let result = run_device_command(&link.app_state, device_id, command).await;
+            if let Err(e) = link
+                .client
+                .publish(
+                    link.topics.device_result(device_id),
+                    QoS::AtLeastOnce,
+                    false,
+                    result.to_string(),
+                )
+                .await
+            {
+                eprintln!("Failed to publish MQTT command result: {}", e);
+            }
+        }
+        CommandTarget::All if command == MqttCommand::Refresh => {
+            let checks = config
+                .devices
+                .iter()
+                .map(|device| refresh_device(&link.app_state, device));
+            futures::future::join_all(checks).await;
+        }
+        CommandTarget::All => {
+            eprintln!(
+                "Ignoring MQTT command '{}' on {}; only refresh applies to every device",
+                command.as_str(),
+                topic
+            );
+        }
+    }
+}
+
+// Poll the connection forever, reconnecting after failures. Messages are handled in their own
+// tasks so the event loop keeps running while a command waits on a ping or shutdown.
+async fn run_event_loop(link: Link, mut event_loop: EventLoop) {
+    let (host, port) = event_loop.mqtt_options.broker_address();
+    loop {
+        match event_loop.poll().await {
+            Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                println!("Connected to MQTT broker {}:{}", host, port);
+                let link = link.clone();
+                tokio::spawn(async move { on_connect(&link).await });
+            }
+            Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                let link = link.clone();
+                tokio::spawn(async move {
+                    handle_message(&link, &publish.topic, &publish.payload).await
+                });
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!(
+                    "MQTT connection to {}:{} failed: {}. Retrying in {} seconds.",
+                    host,
+                    port,
+                    e,
+                    RECONNECT_DELAY.as_secs()
+                );
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+// Publish status changes as they happen, and devices added or removed by a reload
+async fn publish_events(link: Link, mut events: broadcast::Receiver<Event>) {
+    loop {
+        match events.recv().await {
+            Ok(Event::StatusChanged { change }) => {
+                publish_retained(
+                    &link.client,
+                    link.topics.device_status(&change.device_id),
+                    change.status.as_str(),
+                )
+                .await;
+            }
+            Ok(Event::ConfigReloaded { .. }) => publish_devices(&link, false).await,
+            Ok(_) => {}
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!("MQTT fell behind; {} events were not published", missed);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+// Connect to the broker from the mqtt section. Changes to that section other than
+// home_assistant take effect after a restart; devices added by a reload are picked up
+// right away.
+pub fn spawn_mqtt(app_state: AppState) -> Result<JoinHandle<()>, String> {
+    let config = app_state.config.get().mqtt.clone();
+    let options = mqtt_options(&config)?;
+    let (client, event_loop) = AsyncClient::new(options, CLIENT_CAPACITY);
+    let events = app_state.events.subscribe();
+    let link = Link {
+        app_state,
+        client,
+        topics: Topics::new(&config.topic_prefix),
+        published: Arc::new(Mutex::new(Published::default())),
+    };
+
+    tokio::spawn(publish_events(link.clone(), events));
+    Ok(tokio::spawn(run_event_loop(link, event_loop)))
+}
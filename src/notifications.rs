@@ -0,0 +1,413 @@
+use chrono::NaiveTime;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::config::{
+    ChannelKind, Config, NotificationChannel, NotificationRule, QuietHours, SmtpSecurity,
+};
+use crate::events::Event;
+use crate::routes::AppState;
+use crate::webhooks::{http_client, template_registry};
+use crate::websocket::Subscription;
+
+// How long connecting to a mail server may take
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What is sent to a channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+}
+
+// The built-in wording for an event
+pub fn default_notification(event: &Event) -> Notification {
+    let (title, message) = match event {
+        Event::NewDevice { device } => {
+            let name = device.hostname.as_deref().unwrap_or(&device.ip_address);
+            (
+                format!("New device {} on the network", name),
+                format!(
+                    "{} ({}) was seen on the network for the first time",
+                    device.mac_address, device.ip_address
+                ),
+            )
+        }
+        Event::ConfigReloaded { device_count } => (
+            "Configuration reloaded".to_string(),
+            format!("config.yaml was reloaded with {} devices", device_count),
+        ),
+        Event::ConfigReloadFailed { errors } => (
+            "Configuration reload failed".to_string(),
+            format!(
+                "config.yaml was not reloaded; the previous config is still in use.\n{}",
+                errors.join("\n")
+            ),
+        ),
+        Event::StatusChanged { change } => {
+            let title = match change.status.as_str() {
+                "online" => format!("{} came online", change.device_name),
+                "offline" => format!("{} went offline", change.device_name),
+                status => format!("{} became {}", change.device_name, status),
+            };
+            let message = match change.previous {
+                Some(previous) => format!("{} (was {})", title, previous.as_str()),
+                None => title.clone(),
+            };
+            (title, message)
+        }
+        Event::DeviceAction { action } => {
+            let verb = match action.action.as_str() {
+                "wake" => "woken",
+                _ => "shut down",
+            };
+            if action.success {
+                let title = format!("{} was {}", action.device_name, verb);
+                let message = format!("{} from {}", title, action.source);
+                (title, message)
+            } else {
+                let title = format!(
+                    "{} of {} failed",
+                    capitalize(action.action.as_str()),
+                    action.device_name
+                );
+                let message = match &action.detail {
+                    Some(detail) => format!("{}: {}", title, detail),
+                    None => title.clone(),
+                };
+                (title, message)
+            }
+        }
+        Event::WakeUnverified {
+            device_name,
+            waited_seconds,
+            ..
+        } => (
+            format!("Wake of {} failed verification", device_name),
+            format!(
+                "{} was still not online {} seconds after being woken",
+                device_name, waited_seconds
+            ),
+        ),
+    };
+    Notification { title, message }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// The notification for a channel: its templates rendered with the event plus the built-in
+// `title` and `message`. A template that fails to render falls back to the built-in wording.
+pub fn render_notification(channel: &NotificationChannel, event: &Event) -> Notification {
+    let default = default_notification(event);
+    let mut data = serde_json::to_value(event).unwrap_or_default();
+    if let Value::Object(fields) = &mut data {
+        fields.insert("title".to_string(), json!(default.title));
+        fields.insert("message".to_string(), json!(default.message));
+    }
+
+    let render = |template: &Option<String>, fallback: &String| {
+        let Some(template) = template else {
+            return fallback.clone();
+        };
+        match template_registry().render_template(template, &data) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!(
+                    "Notification template for {} failed: {}. Using the default text instead.",
+                    channel.name, e
+                );
+                fallback.clone()
+            }
+        }
+    };
+    Notification {
+        title: render(&channel.title_template, &default.title),
+        message: render(&channel.template, &default.message),
+    }
+}
+
+// "HH:MM" as a time of day
+pub fn parse_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+// Whether `now` falls between start (inclusive) and end (exclusive). Ranges with start after
+// end wrap past midnight; an unparsable range is never quiet.
+pub fn in_quiet_hours(quiet_hours: &QuietHours, now: NaiveTime) -> bool {
+    let (Some(start), Some(end)) = (parse_time(&quiet_hours.start), parse_time(&quiet_hours.end))
+    else {
+        return false;
+    };
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+// Whether a rule wants an event. Rules with `statuses` only match status changes to one of
+// them; rules with `failures_only` only match failed actions, unverified wakes and rejected
+// reloads.
+pub fn rule_matches(rule: &NotificationRule, event: &Event, config: &Config) -> bool {
+    let subscription = Subscription {
+        devices: rule.devices.clone(),
+        groups: rule.groups.clone(),
+        tags: rule.tags.clone(),
+        events: rule.events.clone(),
+    };
+    if !subscription.matches(event, config) {
+        return false;
+    }
+    if !rule.statuses.is_empty() {
+        let Event::StatusChanged { change } = event else {
+            return false;
+        };
+        if !rule
+            .statuses
+            .iter()
+            .any(|status| status == change.status.as_str())
+        {
+            return false;
+        }
+    }
+    !rule.failures_only || is_failure(event)
+}
+
+fn is_failure(event: &Event) -> bool {
+    match event {
+        Event::DeviceAction { action } => !action.success,
+        Event::WakeUnverified { .. } | Event::ConfigReloadFailed { .. } => true,
+        _ => false,
+    }
+}
+
+// Channels an event goes to: every channel when there are no rules, otherwise the channels
+// of every matching rule
+pub fn channels_for<'a>(event: &Event, config: &'a Config) -> Vec<&'a NotificationChannel> {
+    let notifications = &config.notifications;
+    if notifications.rules.is_empty() {
+        return notifications.channels.iter().collect();
+    }
+    let matching: Vec<&NotificationRule> = notifications
+        .rules
+        .iter()
+        .filter(|rule| rule_matches(rule, event, config))
+        .collect();
+    notifications
+        .channels
+        .iter()
+        .filter(|channel| {
+            matching
+                .iter()
+                .any(|rule| rule.channels.contains(&channel.name))
+        })
+        .collect()
+}
+
+async fn send_email(kind: &ChannelKind, notification: &Notification) -> Result<(), String> {
+    let ChannelKind::Smtp {
+        host,
+        port,
+        security,
+        username,
+        password,
+        from,
+        to,
+    } = kind
+    else {
+        return Err("not an SMTP channel".to_string());
+    };
+
+    let builder = match security {
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+        SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            host,
+        )),
+    };
+    let mut builder = builder
+        .map_err(|e| e.to_string())?
+        .port(*port)
+        .timeout(Some(SMTP_TIMEOUT));
+    if let Some(username) = username {
+        builder = builder.credentials(Credentials::new(
+            username.clone(),
+            password.clone().unwrap_or_default(),
+        ));
+    }
+
+    let parse = |address: &String| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid address '{}': {}", address, e))
+    };
+    let mut email = Message::builder()
+        .from(parse(from)?)
+        .subject(&notification.title);
+    for address in to {
+        email = email.to(parse(address)?);
+    }
+    let email = email
+        .header(ContentType::TEXT_PLAIN)
+        .body(notification.message.clone())
+        .map_err(|e| e.to_string())?;
+
+    builder
+        .build()
+        .send(email)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Send a notification to one channel
+pub async fn send(
+    client: &reqwest::Client,
+    channel: &NotificationChannel,
+    notification: &Notification,
+    event: &Event,
+) -> Result<(), String> {
+    let request = match &channel.kind {
+        ChannelKind::Smtp { .. } => return send_email(&channel.kind, notification).await,
+        ChannelKind::Ntfy {
+            url,
+            token,
+            priority,
+        } => {
+            // Query parameters rather than headers, so titles are not limited to ASCII
+            let mut query = vec![("title", notification.title.clone())];
+            if let Some(priority) = priority {
+                query.push(("priority", priority.to_string()));
+            }
+            let mut request = client
+                .post(url)
+                .query(&query)
+                .body(notification.message.clone());
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request
+        }
+        ChannelKind::Gotify {
+            url,
+            token,
+            priority,
+        } => {
+            let mut body = json!({
+                "title": notification.title,
+                "message": notification.message,
+            });
+            if let Some(priority) = priority {
+                body["priority"] = json!(priority);
+            }
+            client
+                .post(format!("{}/message", url.trim_end_matches('/')))
+                .header("X-Gotify-Key", token)
+                .header("Content-Type", "application/json")
+                .body(body.to_string())
+        }
+        ChannelKind::Push { url, headers } => {
+            let body = json!({
+                "title": notification.title,
+                "message": notification.message,
+                "event": event,
+            });
+            let mut request = client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body.to_string());
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request
+        }
+    };
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+// Send an event to every channel that wants it and is not in its quiet hours. Failures are
+// logged and not retried. Returns how many were sent.
+pub async fn notify(
+    app_state: &AppState,
+    client: &reqwest::Client,
+    event: &Event,
+    now: NaiveTime,
+) -> usize {
+    let config = app_state.config.get();
+    let sends = channels_for(event, &config)
+        .into_iter()
+        .filter(|channel| {
+            !channel
+                .quiet_hours
+                .as_ref()
+                .is_some_and(|quiet_hours| in_quiet_hours(quiet_hours, now))
+        })
+        .map(|channel| async move {
+            let notification = render_notification(channel, event);
+            match send(client, channel, &notification, event).await {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to send {} notification to {}: {}",
+                        event.kind(),
+                        channel.name,
+                        e
+                    );
+                    false
+                }
+            }
+        });
+
+    futures::future::join_all(sends)
+        .await
+        .into_iter()
+        .filter(|sent| *sent)
+        .count()
+}
+
+// Send notifications for events as they are published; channels and rules changed by a
+// reload are picked up without a restart
+pub fn spawn_notifications(app_state: AppState) -> JoinHandle<()> {
+    let mut events = app_state.events.subscribe();
+    tokio::spawn(async move {
+        let client = http_client();
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if app_state.config.get().notifications.channels.is_empty() {
+                        continue;
+                    }
+                    // A slow mail server must not hold up the events after it
+                    let app_state = app_state.clone();
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let now = chrono::Local::now().time();
+                        notify(&app_state, &client, &event, now).await;
+                    });
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("Notifications fell behind; {} events were not sent", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
@@ -0,0 +1,325 @@
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::background_discovery::unix_now;
+use crate::config::{Config, Device};
+use crate::events::Event;
+use crate::history::{ActionKind, ActionRecord, Sample, StatusChange};
+use crate::routes::{probe_device, AppState, DeviceStatus};
+
+// Latest known status of one device
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DeviceState {
+    pub status: DeviceStatus,
+    // Round-trip time of the last answered check
+    pub latency_ms: Option<f64>,
+    // Unix seconds of the last status change, if known
+    pub since: Option<u64>,
+    // Unix seconds of the last check; 0 when the status was only restored from history
+    pub checked_at: u64,
+}
+
+// One completed round of checks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollRound {
+    // Unix seconds when the round finished
+    pub finished_at: u64,
+    pub duration: Duration,
+    pub interval_seconds: u64,
+}
+
+impl PollRound {
+    // Seconds the next round is overdue; 0 while the poller keeps up
+    pub fn lag(&self, now: u64) -> u64 {
+        now.saturating_sub(self.finished_at)
+            .saturating_sub(self.interval_seconds)
+    }
+}
+
+// Current status of every device as seen by the poller, keyed by device id
+#[derive(Clone, Default)]
+pub struct StatusBoard {
+    states: Arc<RwLock<HashMap<String, DeviceState>>>,
+    last_round: Arc<RwLock<Option<PollRound>>>,
+}
+
+impl StatusBoard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<DeviceState> {
+        self.states
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(device_id)
+            .cloned()
+    }
+
+    pub fn all(&self) -> HashMap<String, DeviceState> {
+        self.states
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    // Remember a status without counting it as a check, e.g. the last one recorded before
+    // a restart
+    pub fn restore(&self, device_id: &str, status: DeviceStatus, since: Option<u64>) {
+        self.states
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(
+                device_id.to_string(),
+                DeviceState {
+                    status,
+                    latency_ms: None,
+                    since,
+                    checked_at: 0,
+                },
+            );
+    }
+
+    // Record a check and return the change when the status is different from the last one
+    pub fn update(
+        &self,
+        device: &Device,
+        status: DeviceStatus,
+        latency_ms: Option<f64>,
+        now: u64,
+    ) -> Option<StatusChange> {
+        let device_id = device.device_id();
+        let mut states = self
+            .states
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let previous = states.get(&device_id).map(|state| state.status);
+        let changed = previous != Some(status);
+        let since = match states.get(&device_id) {
+            Some(state) if !changed => state.since,
+            _ => Some(now),
+        };
+        states.insert(
+            device_id.clone(),
+            DeviceState {
+                status,
+                latency_ms,
+                since,
+                checked_at: now,
+            },
+        );
+
+        changed.then(|| StatusChange {
+            device_id,
+            device_name: device.name.clone(),
+            status,
+            previous,
+            at: now,
+        })
+    }
+
+    pub fn finish_round(&self, round: PollRound) {
+        *self
+            .last_round
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(round);
+    }
+
+    // The last completed round, or None before the first one
+    pub fn last_round(&self) -> Option<PollRound> {
+        *self
+            .last_round
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Forget devices that are no longer configured
+    pub fn retain(&self, device_ids: &HashSet<String>) {
+        self.states
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .retain(|device_id, _| device_ids.contains(device_id));
+    }
+}
+
+// Record one check of a device: a history sample, the status board, and when the status
+// changed, the change in history and on the event bus
+pub async fn record_check(
+    app_state: &AppState,
+    device: &Device,
+    status: DeviceStatus,
+    latency_ms: Option<f64>,
+    now: u64,
+) -> Option<StatusChange> {
+    let sample = Sample {
+        device_id: device.device_id(),
+        status,
+        latency_ms,
+        at: now,
+    };
+    let change = app_state.statuses.update(device, status, latency_ms, now);
+
+    let recorded = change.clone();
+    app_state
+        .history
+        .blocking(move |history| {
+            history.record_sample(&sample);
+            if let Some(change) = &recorded {
+                history.record_status_change(change);
+            }
+        })
+        .await;
+
+    let change = change?;
+
+    println!(
+        "{} is now {} (was {})",
+        change.device_name,
+        change.status.as_str(),
+        change
+            .previous
+            .map(DeviceStatus::as_str)
+            .unwrap_or("unknown")
+    );
+    app_state.events.publish(Event::StatusChanged {
+        change: change.clone(),
+    });
+    Some(change)
+}
+
+// Ping every configured device with at most poller.max_concurrency pings in flight.
+// Results come back in config order.
+pub async fn probe_devices(config: &Config) -> Vec<(Device, DeviceStatus, Option<f64>)> {
+    stream::iter(config.devices.clone())
+        .map(|device| async move {
+            let (status, latency_ms) = probe_device(&device.ip_address).await;
+            (device, status, latency_ms)
+        })
+        .buffered(config.poller.concurrency())
+        .collect()
+        .await
+}
+
+// Ping every configured device once and record each check
+pub async fn poll_devices(app_state: &AppState) -> Vec<StatusChange> {
+    let config = app_state.config.get();
+    let started = Instant::now();
+
+    let results = probe_devices(&config).await;
+
+    let now = unix_now();
+    let mut changes = Vec::new();
+    for (device, status, latency_ms) in results {
+        changes.extend(record_check(app_state, &device, status, latency_ms, now).await);
+    }
+    app_state
+        .statuses
+        .retain(&config.devices.iter().map(Device::device_id).collect());
+    app_state.statuses.finish_round(PollRound {
+        finished_at: unix_now(),
+        duration: started.elapsed(),
+        interval_seconds: config.poller.interval_seconds.max(1),
+    });
+
+    changes
+}
+
+// Start the periodic status poller. Statuses recorded before the last restart are restored
+// first so an unchanged device is not recorded again.
+pub fn spawn_status_poller(app_state: AppState) -> JoinHandle<()> {
+    let interval_seconds = app_state.config.get().poller.interval_seconds.max(1);
+
+    tokio::spawn(async move {
+        let restored = app_state
+            .history
+            .blocking(|history| {
+                history
+                    .latest_statuses()
+                    .into_iter()
+                    .map(|(device_id, status)| {
+                        let since = history.last_change_at(&device_id);
+                        (device_id, status, since)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        for (device_id, status, since) in restored {
+            app_state.statuses.restore(&device_id, status, since);
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            poll_devices(&app_state).await;
+        }
+    })
+}
+
+// Ping a device some time after it was woken and publish wake_unverified if it is not online.
+// Returns whether it was online.
+pub async fn verify_wake(app_state: &AppState, action: &ActionRecord, waited_seconds: u64) -> bool {
+    let config = app_state.config.get();
+    // Removed from the config in the meantime
+    let Some(device) = config.find_device(&action.device_id) else {
+        return true;
+    };
+
+    let (status, latency_ms) = probe_device(&device.ip_address).await;
+    record_check(app_state, device, status, latency_ms, unix_now()).await;
+    if status == DeviceStatus::Online {
+        return true;
+    }
+
+    eprintln!(
+        "{} is still {} {} seconds after being woken",
+        device.name,
+        status.as_str(),
+        waited_seconds
+    );
+    app_state.events.publish(Event::WakeUnverified {
+        device_id: action.device_id.clone(),
+        device_name: device.name.clone(),
+        woken_at: action.at,
+        waited_seconds,
+    });
+    false
+}
+
+// Check every successful wake once poller.wake_verification_seconds have passed
+pub fn spawn_wake_verifier(app_state: AppState) -> JoinHandle<()> {
+    let mut events = app_state.events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let action = match events.recv().await {
+                Ok(Event::DeviceAction { action }) => action,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!(
+                        "Wake verification fell behind; {} events were missed",
+                        missed
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let wait = app_state.config.get().poller.wake_verification_seconds;
+            if action.action != ActionKind::Wake || !action.success || wait == 0 {
+                continue;
+            }
+
+            let app_state = app_state.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                verify_wake(&app_state, &action, wait).await;
+            });
+        }
+    })
+}
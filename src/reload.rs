@@ -0,0 +1,351 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::auth::current_users;
+use crate::config::{Config, MqttConfig, User};
+use crate::config_edit::content_hash;
+use crate::events::{Event, EventBus};
+use crate::validation::{check_config, read_config_text, ConfigIssue};
+
+// Editors and ConfigMap updates touch the file several times in a row; wait for them to settle
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// The running config, swappable at runtime. Cloning shares the same config.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    current: Arc<RwLock<Arc<Config>>>,
+    // Where reloads read from; None for configs built in code (tests)
+    path: Option<PathBuf>,
+    // Why the last reload was rejected, until a later one succeeds
+    last_error: Arc<RwLock<Option<String>>>,
+    // Held while the config file is being rewritten so edits from this process queue up
+    write_lock: Arc<Mutex<()>>,
+    // auth.users plus auth.users_file, read when the config or the users file changes rather
+    // than on every request
+    users: Arc<RwLock<Arc<Vec<User>>>>,
+    // Hash of the file contents last loaded or rejected, so a write that was already
+    // reloaded (e.g. by the device manager) is not reloaded again when the watcher sees it
+    checked_hash: Arc<RwLock<Option<String>>>,
+}
+
+impl ConfigHandle {
+    pub fn new(config: Config) -> Self {
+        let users = current_users(&config.auth);
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+            path: None,
+            last_error: Arc::new(RwLock::new(None)),
+            write_lock: Arc::new(Mutex::new(())),
+            users: Arc::new(RwLock::new(Arc::new(users))),
+            checked_hash: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    // `config` must be what `path` contains now
+    pub fn with_path(config: Config, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let hash = std::fs::read_to_string(&path)
+            .ok()
+            .map(|contents| content_hash(&contents));
+        Self {
+            path: Some(path),
+            checked_hash: Arc::new(RwLock::new(hash)),
+            ..Self::new(config)
+        }
+    }
+
+    // Snapshot of the current config. Hold on to it for the whole request so a reload
+    // halfway through cannot mix two configs.
+    pub fn get(&self) -> Arc<Config> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    // Everyone who can log in, as of the last config load or users file change
+    pub fn users(&self) -> Arc<Vec<User>> {
+        self.users
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    // Read auth.users_file again
+    pub fn refresh_users(&self) {
+        let users = current_users(&self.get().auth);
+        *self
+            .users
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(users);
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn lock_for_write(&self) -> MutexGuard<'_, ()> {
+        self.write_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn replace(&self, config: Config) {
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(config);
+        self.set_last_error(None);
+        self.refresh_users();
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn set_last_error(&self, error: Option<String>) {
+        *self
+            .last_error
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = error;
+    }
+
+    // Whether the file differs from what was last loaded or rejected. A file that cannot be
+    // read counts as changed so the reload reports the error.
+    pub fn file_changed(&self) -> bool {
+        let Some(path) = &self.path else {
+            return false;
+        };
+        let hash = std::fs::read_to_string(path)
+            .ok()
+            .map(|contents| content_hash(&contents));
+        hash.is_none()
+            || hash
+                != *self
+                    .checked_hash
+                    .read()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Re-read and validate the file. The old config stays in place if the new one has errors.
+    // Returns the warnings of the config that was loaded, or every error found.
+    pub fn reload(&self) -> Result<Vec<ConfigIssue>, Vec<ConfigIssue>> {
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+
+        let source = path.to_string_lossy();
+        let checked = read_config_text(&source).and_then(|contents| {
+            *self
+                .checked_hash
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(content_hash(&contents));
+            check_config(&contents, &source, &|name| std::env::var(name).ok())
+        });
+        match checked {
+            Ok((config, warnings)) => {
+                self.replace(config);
+                Ok(warnings)
+            }
+            Err(issues) => {
+                let message = issues
+                    .iter()
+                    .map(|issue| issue.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.set_last_error(Some(message));
+                Err(issues)
+            }
+        }
+    }
+}
+
+// Reload, log the outcome and tell subscribers about it
+pub fn reload_and_report(handle: &ConfigHandle, events: &EventBus, reason: &str) {
+    let before = handle.get();
+
+    match handle.reload() {
+        Ok(warnings) => {
+            for warning in &warnings {
+                eprintln!("{}", warning);
+            }
+            let after = handle.get();
+            println!(
+                "Reloaded configuration ({}): {} device(s)",
+                reason,
+                after.devices.len()
+            );
+            if before.server.ip != after.server.ip || before.server.port != after.server.port {
+                eprintln!("server.ip and server.port changes take effect after a restart");
+            }
+            if before.discovery.background != after.discovery.background {
+                eprintln!("discovery.background changes take effect after a restart");
+            }
+            let mqtt_connection = |config: &Config| MqttConfig {
+                home_assistant: Default::default(),
+                ..config.mqtt.clone()
+            };
+            if mqtt_connection(&before) != mqtt_connection(&after) {
+                eprintln!(
+                    "mqtt changes other than mqtt.home_assistant take effect after a restart"
+                );
+            }
+            if before.auth.session_secret != after.auth.session_secret {
+                eprintln!("auth.session_secret changes take effect after a restart");
+            }
+            if before.auth.tokens_path != after.auth.tokens_path {
+                eprintln!("auth.tokens_path changes take effect after a restart");
+            }
+            events.publish(Event::ConfigReloaded {
+                device_count: after.devices.len(),
+            });
+        }
+        Err(issues) => {
+            eprintln!(
+                "Configuration reload ({}) rejected, keeping the previous config:",
+                reason
+            );
+            for issue in &issues {
+                eprintln!("{}", issue);
+            }
+            events.publish(Event::ConfigReloadFailed {
+                errors: issues.iter().map(|issue| issue.to_string()).collect(),
+            });
+        }
+    }
+}
+
+// Sent by the watcher when auth.users_file changed; only the users are read again
+const USERS_FILE_CHANGED: &str = "users file changed";
+
+// Reload on SIGHUP and whenever the config file changes on disk
+pub fn spawn_config_reloader(handle: ConfigHandle, events: EventBus) -> Option<JoinHandle<()>> {
+    let path = handle.path()?.to_path_buf();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<&'static str>();
+
+    // Watch the directory rather than the file: editors and Kubernetes replace the file
+    // (rename or symlink swap), which would silently end a watch on the file itself.
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // auth.users_file as it was at startup; a new path is read on reload but watched after a
+    // restart
+    let users_file = handle.get().auth.users_file.as_ref().map(PathBuf::from);
+    let users_file_name = users_file
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_os_string());
+    let users_directory = users_file
+        .as_ref()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .filter(|users_directory| *users_directory != directory);
+    let watch_sender = sender.clone();
+    let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        let touches_config = event.paths.iter().any(|changed| {
+            changed.file_name().map(|name| name.to_os_string()) == file_name
+                || changed.ends_with("..data") // ConfigMap symlink swap
+        });
+        if touches_config {
+            let _ = watch_sender.send("file changed");
+        }
+        let touches_users = users_file_name.is_some()
+            && event.paths.iter().any(|changed| {
+                changed.file_name().map(|name| name.to_os_string()) == users_file_name
+                    || changed.ends_with("..data")
+            });
+        if touches_users {
+            let _ = watch_sender.send(USERS_FILE_CHANGED);
+        }
+    });
+    let watcher = match watcher {
+        Ok(mut watcher) => match watcher.watch(&directory, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                if let Some(users_directory) = &users_directory {
+                    if let Err(e) = watcher.watch(users_directory, RecursiveMode::NonRecursive) {
+                        eprintln!("Failed to watch {}: {}", users_directory.display(), e);
+                    }
+                }
+                Some(watcher)
+            }
+            Err(e) => {
+                eprintln!("Failed to watch {}: {}", directory.display(), e);
+                None
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to start config file watcher: {}", e);
+            None
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        let signal_sender = sender.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    eprintln!("Failed to listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                if signal_sender.send("SIGHUP").is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    Some(tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while let Some(first) = receiver.recv().await {
+            // Collapse a burst of notifications into one reload
+            tokio::time::sleep(RELOAD_DEBOUNCE).await;
+            let mut users_changed = false;
+            let mut reason = None;
+            for next in
+                std::iter::once(first).chain(std::iter::from_fn(|| receiver.try_recv().ok()))
+            {
+                if next == USERS_FILE_CHANGED {
+                    users_changed = true;
+                } else if reason.is_none() || next == "SIGHUP" {
+                    reason = Some(next);
+                }
+            }
+            // SIGHUP always reloads; a file event only when the contents changed
+            if let Some(reason) =
+                reason.filter(|reason| *reason == "SIGHUP" || handle.file_changed())
+            {
+                reload_and_report(&handle, &events, reason);
+            }
+            if users_changed {
+                handle.refresh_users();
+                println!("Reloaded auth.users_file: {} user(s)", handle.users().len());
+            }
+        }
+    }))
+}
@@ -1,38 +1,190 @@
 // Assets will be provided by main.rs when used as binary
 use axum::{
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse, Json},
-    routing::{get, post},
-    Router,
+    extract::{Form, Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post},
+    Extension, Router,
 };
 // ServeEmbed will be used in main.rs
-use axum_extra::extract::cookie::CookieJar;
+use axum_extra::extract::cookie::{Cookie, CookieJar, Key, SameSite};
 
-use futures::future::join_all;
+use futures::stream::{self, StreamExt};
 use ipnet::Ipv4Net;
 use network_interface::{NetworkInterface, NetworkInterfaceConfig};
 use regex::Regex;
 use serde_json::json; // For constructing data for Handlebars - THIS REQUIRES serde_json in Cargo.toml
+use std::convert::Infallible;
 use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
 use wol::{send_wol, MacAddr};
 
-use crate::config::Config;
+use crate::auth::{login_handler, login_page_handler, logout_handler, require_login, CurrentUser};
+use crate::background_discovery::unix_now;
+use crate::background_discovery::SeenDevices;
+use crate::config::{find_device_index, Config, Device, DiscoveryConfig, MAX_SCAN_CONCURRENCY};
+use crate::config_edit::{apply_device_edit, read_config_file, DeviceEdit, EditError};
+use crate::events::{Event, EventBus};
+use crate::export::{export_devices, ExportFormat};
+use crate::health::{healthz_handler, readyz_handler};
+use crate::history::{
+    parse_timestamp, record_device_action, timeline, uptime_percent, ActionKind, ActionRecord,
+    CardHistory, History, HistoryQuery, TimelineBucket, MAX_HISTORY_LIMIT,
+};
+use crate::import::{
+    import_dhcp_leases, parse_inventory, ImportError, ImportReport, InventoryFormat,
+};
+use crate::metrics::{count_requests, metrics_handler, Metrics, WOL_BACKEND};
+use crate::poller::StatusBoard;
+use crate::reload::{reload_and_report, ConfigHandle};
+use crate::tokens::{create_token_handler, list_tokens_handler, revoke_token_handler, TokenStore};
+use crate::webhooks::WebhookQueue;
+use crate::websocket::websocket_handler;
 use handlebars::Handlebars;
-use serde_urlencoded;
-use serde_yaml;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
 // AppState definition for routes module
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Config,
+    pub config: ConfigHandle,
     pub handlebars: Arc<Handlebars<'static>>,
-    pub discovered_devices: Arc<Mutex<HashMap<String, Vec<DiscoveredDevice>>>>,
+    pub discovered_devices: ScanStore,
+    pub events: EventBus,
+    pub seen_devices: SeenDevices,
+    pub history: History,
+    pub statuses: StatusBoard,
+    pub metrics: Metrics,
+    pub webhooks: WebhookQueue,
+    // Signs session cookies
+    pub session_key: Key,
+    pub tokens: TokenStore,
+}
+
+// Handlebars templates every page needs, with their file in frontend/
+pub const PAGE_TEMPLATES: [(&str, &str); 5] = [
+    ("index", "index.html"),
+    ("discovery", "discovery.html"),
+    ("devices", "devices.html"),
+    ("device", "device.html"),
+    ("login", "login.html"),
+];
+
+// Cookie identifying a browser's discovery session
+const DISCOVERY_SESSION_COOKIE: &str = "wololo_discovery";
+
+// A single scan's results, owned by the discovery session that ran it
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub session_id: String,
+    pub devices: Vec<DiscoveredDevice>,
+    pub generated_config: Option<String>,
+    created_at: Instant,
+}
+
+// Scan results and generated configs keyed by scan ID, expiring after a TTL
+#[derive(Clone)]
+pub struct ScanStore {
+    scans: Arc<Mutex<HashMap<String, ScanResult>>>,
+    ttl: Duration,
+}
+
+impl ScanStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            scans: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    // Store a scan for the given session and return its new scan ID
+    pub async fn insert(&self, session_id: &str, devices: Vec<DiscoveredDevice>) -> String {
+        let scan_id = Uuid::new_v4().simple().to_string();
+        let mut scans = self.scans.lock().await;
+        self.prune(&mut scans);
+        scans.insert(
+            scan_id.clone(),
+            ScanResult {
+                session_id: session_id.to_string(),
+                devices,
+                generated_config: None,
+                created_at: Instant::now(),
+            },
+        );
+        scan_id
+    }
+
+    // Look up a scan, only if it belongs to the session and has not expired
+    pub async fn get(&self, scan_id: &str, session_id: &str) -> Option<ScanResult> {
+        let mut scans = self.scans.lock().await;
+        self.prune(&mut scans);
+        scans
+            .get(scan_id)
+            .filter(|scan| scan.session_id == session_id)
+            .cloned()
+    }
+
+    // Attach a generated config to a scan; returns false if the scan is gone or not ours
+    pub async fn set_generated_config(
+        &self,
+        scan_id: &str,
+        session_id: &str,
+        yaml: String,
+    ) -> bool {
+        let mut scans = self.scans.lock().await;
+        self.prune(&mut scans);
+        match scans.get_mut(scan_id) {
+            Some(scan) if scan.session_id == session_id => {
+                scan.generated_config = Some(yaml);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub async fn len(&self) -> usize {
+        let mut scans = self.scans.lock().await;
+        self.prune(&mut scans);
+        scans.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    fn prune(&self, scans: &mut HashMap<String, ScanResult>) {
+        let ttl = self.ttl;
+        scans.retain(|_, scan| scan.created_at.elapsed() < ttl);
+    }
+}
+
+// Return the discovery session ID from the cookie jar, issuing a new one if missing
+fn discovery_session(jar: CookieJar) -> (CookieJar, String) {
+    if let Some(cookie) = jar.get(DISCOVERY_SESSION_COOKIE) {
+        let value = cookie.value();
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit()) {
+            let session_id = value.to_string();
+            return (jar, session_id);
+        }
+    }
+
+    let session_id = Uuid::new_v4().simple().to_string();
+    let cookie = Cookie::build((DISCOVERY_SESSION_COOKIE, session_id.clone()))
+        .path("/discovery")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .build();
+    (jar.add(cookie), session_id)
 }
 
 // Handler for the /hello route
@@ -43,14 +195,23 @@ async fn hello_handler(_jar: CookieJar, _headers: HeaderMap) -> Html<&'static st
 // Handler for the / route, serving frontend/index.html via Handlebars using AppState
 async fn root_handler(
     State(app_state): State<AppState>, // Extract the whole AppState
+    user: Option<Extension<CurrentUser>>,
 ) -> impl IntoResponse {
     // Access config and handlebars from app_state
     // Adjust .devices and .server.external_url according to your actual Config struct fields
+    let config = app_state.config.get();
+    let new_device_count = app_state.seen_devices.unacknowledged().await.len();
     let data = json!({
-        "devices": &app_state.config.devices, // Ensure config.devices exists and is Serialize
-        "external_url": &app_state.config.server.external_url,
-        "sync_enabled": &app_state.config.sync.enabled,
-        "sync_interval": &app_state.config.sync.interval_seconds,
+        "devices": &config.devices, // Ensure config.devices exists and is Serialize
+        "new_device_count": new_device_count,
+        "external_url": &config.server.external_url,
+        "sync_enabled": &config.sync.enabled,
+        "sync_interval": &config.sync.interval_seconds,
+        "config_error": app_state.config.last_error(),
+        // Cards are pushed over /api/v1/events while the poller is watching the devices
+        "live_updates": config.poller.enabled,
+        // Shows the logout button; only set while auth is enabled
+        "user": user.map(|Extension(CurrentUser(username))| username),
     });
 
     match app_state.handlebars.render("index", &data) {
@@ -66,112 +227,128 @@ async fn root_handler(
     }
 }
 
+// Why a wake-on-LAN packet could not be sent
+#[derive(Debug)]
+pub enum WakeError {
+    InvalidMac(String),
+    InvalidIp(String),
+    Send(String),
+}
+
+impl std::fmt::Display for WakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WakeError::InvalidMac(e) => write!(f, "{}", e),
+            WakeError::InvalidIp(e) => write!(f, "Invalid IP address: {}", e),
+            WakeError::Send(e) => write!(f, "Failed to wake device: {}", e),
+        }
+    }
+}
+
+// Send a magic packet to the device's /24 broadcast address.
+// Shared by the web handler and the `wololo wake` command.
+pub async fn send_wake_packet(device: &Device) -> Result<(), WakeError> {
+    // Parse MAC address - Convert from string to MacAddr
+    let mac_addr = parse_mac_address(&device.mac_address).map_err(WakeError::InvalidMac)?;
+
+    // Calculate broadcast address from device IP
+    let broadcast_ip = calculate_broadcast_address(&device.ip_address)
+        .await
+        .map_err(|e| WakeError::InvalidIp(e.to_string()))?;
+
+    // Send wake-on-LAN packet
+    send_wol(mac_addr, Some(broadcast_ip), None).map_err(|e| WakeError::Send(e.to_string()))
+}
+
+// Send a wake packet and record the attempt in history
+pub async fn wake_and_record(
+    app_state: &AppState,
+    device: &Device,
+    source: &str,
+) -> Result<(), WakeError> {
+    let result = send_wake_packet(device).await;
+    app_state
+        .metrics
+        .record_wake(device, WOL_BACKEND, source, result.is_ok());
+    record_device_action(
+        &app_state.history,
+        &app_state.events,
+        device,
+        ActionKind::Wake,
+        source,
+        result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    )
+    .await;
+    result
+}
+
 // Handler for wake-on-LAN requests
 async fn wake_device_handler(
     State(app_state): State<AppState>,
-    Path(device_name): Path<String>,
+    Path(device_id): Path<String>,
 ) -> impl IntoResponse {
-    // Find the device by name
-    let device = app_state
-        .config
-        .devices
-        .iter()
-        .find(|d| d.name == device_name);
+    // Find the device by id, or by name for older links
+    let config = app_state.config.get();
+    let device = config.find_device(&device_id);
 
     match device {
-        Some(device) => {
-            // Parse MAC address - Convert from string to MacAddr
-            let mac_addr = match parse_mac_address(&device.mac_address) {
-                Ok(mac) => mac,
-                Err(e) => {
-                    eprintln!("Invalid MAC address for device '{}': {}", device_name, e);
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Html(format!(
-                            r#"<div class="flex items-center gap-2 text-red-400">
-                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
-                                </svg>
-                                <span>{}</span>
-                            </div>"#,
-                            e
-                        )),
-                    )
-                        .into_response();
-                }
-            };
-
-            // Calculate broadcast address from device IP
-            let broadcast_ip = match calculate_broadcast_address(&device.ip_address) {
-                Ok(ip) => ip,
-                Err(e) => {
-                    eprintln!("Invalid IP address for device '{}': {}", device_name, e);
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Html(format!(
-                            r#"<div class="flex items-center gap-2 text-red-400">
-                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
-                                </svg>
-                                <span>Invalid IP address: {}</span>
-                            </div>"#,
-                            e
-                        )),
-                    )
-                        .into_response();
-                }
-            };
-
-            // Send wake-on-LAN packet
-            match send_wol(mac_addr, Some(broadcast_ip), None) {
-                Ok(_) => {
-                    println!("Wake-on-LAN packet sent to device: {}", device_name);
-                    let sound_script = if app_state.config.sound.enabled {
-                        r#"<script>
-                            (function() {
-                                const audio = new Audio('/assets/wololo.mp3');
-                                audio.play().catch(err => console.log('Could not play sound:', err));
-                            })();
-                        </script>"#
-                    } else {
-                        ""
-                    };
+        Some(device) => match wake_and_record(&app_state, device, "web").await {
+            Ok(()) => {
+                println!("Wake-on-LAN packet sent to device: {}", device.name);
+                let sound_script = if config.sound.enabled {
+                    r#"<script>
+                        (function() {
+                            const audio = new Audio('/assets/wololo.mp3');
+                            audio.play().catch(err => console.log('Could not play sound:', err));
+                        })();
+                    </script>"#
+                } else {
+                    ""
+                };
+                Html(format!(
+                    r#"<div class="wake-animation">
+                        <div class="wake-success-icon">
+                            <svg class="w-4 h-4 text-emerald-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M5 13l4 4L19 7"></path>
+                            </svg>
+                        </div>
+                        <span class="text-emerald-400 font-medium">Wake packet sent to {}</span>
+                    </div>{}"#,
+                    html_escape::encode_text(&device.name), sound_script
+                ))
+                .into_response()
+            }
+            Err(e) => {
+                let (status, class) = match e {
+                    WakeError::InvalidMac(_) | WakeError::InvalidIp(_) => {
+                        eprintln!("Invalid address for device '{}': {}", device.name, e);
+                        (StatusCode::BAD_REQUEST, "")
+                    }
+                    WakeError::Send(_) => {
+                        eprintln!(
+                            "Failed to send wake-on-LAN packet to '{}': {}",
+                            device.name, e
+                        );
+                        (StatusCode::INTERNAL_SERVER_ERROR, " class=\"font-medium\"")
+                    }
+                };
+                (
+                    status,
                     Html(format!(
-                        r#"<div class="wake-animation">
-                            <div class="wake-success-icon">
-                                <svg class="w-4 h-4 text-emerald-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M5 13l4 4L19 7"></path>
-                                </svg>
-                            </div>
-                            <span class="text-emerald-400 font-medium">Wake packet sent to {}</span>
-                        </div>{}"#,
-                        device_name, sound_script
-                    ))
+                        r#"<div class="flex items-center gap-2 text-red-400">
+                            <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                            </svg>
+                            <span{}>{}</span>
+                        </div>"#,
+                        class, e
+                    )),
+                )
                     .into_response()
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Failed to send wake-on-LAN packet to '{}': {}",
-                        device_name, e
-                    );
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Html(format!(
-                            r#"<div class="flex items-center gap-2 text-red-400">
-                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
-                                </svg>
-                                <span class="font-medium">Failed to wake device: {}</span>
-                            </div>"#,
-                            e
-                        )),
-                    )
-                        .into_response()
-                }
             }
-        }
+        },
         None => {
-            eprintln!("Device '{}' not found", device_name);
+            eprintln!("Device '{}' not found", device_id);
             (
                 StatusCode::NOT_FOUND,
                 Html(format!(
@@ -181,7 +358,7 @@ async fn wake_device_handler(
                         </svg>
                         <span class="font-medium">Device '{}' not found</span>
                     </div>"#,
-                    device_name
+                    html_escape::encode_text(&device_id)
                 )),
             )
                 .into_response()
@@ -192,14 +369,11 @@ async fn wake_device_handler(
 // Handler for ping requests
 async fn ping_device_handler(
     State(app_state): State<AppState>,
-    Path(device_name): Path<String>,
+    Path(device_id): Path<String>,
 ) -> impl IntoResponse {
-    // Find the device by name
-    let device = app_state
-        .config
-        .devices
-        .iter()
-        .find(|d| d.name == device_name);
+    // Find the device by id, or by name for older links
+    let config = app_state.config.get();
+    let device = config.find_device(&device_id);
 
     match device {
         Some(device) => {
@@ -218,7 +392,8 @@ async fn ping_device_handler(
                     <span class="text-sm font-medium {}">{}</span>
                 </div>"#,
                 status_bg_color, status_class, status_text
-            )).into_response()
+            ))
+            .into_response()
         }
         None => (
             StatusCode::NOT_FOUND,
@@ -226,19 +401,126 @@ async fn ping_device_handler(
                 r#"<div class="flex items-center space-x-2 px-3 py-1 rounded-full bg-gray-700">
                     <span class="text-sm font-medium text-red-400">Device '{}' not found</span>
                 </div>"#,
-                device_name
+                html_escape::encode_text(&device_id)
             )),
         )
             .into_response(),
     }
 }
 
+// How long a device's shutdown_command may run before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Run the device's shutdown_command on the server
+pub async fn run_shutdown_command(device: &Device) -> Result<(), String> {
+    let Some((program, args)) = device.shutdown_command.split_first() else {
+        return Err(format!(
+            "{} has no shutdown_command configured",
+            device.name
+        ));
+    };
+
+    let output = tokio::time::timeout(
+        SHUTDOWN_TIMEOUT,
+        Command::new(program).args(args).kill_on_drop(true).output(),
+    )
+    .await
+    .map_err(|_| {
+        format!(
+            "Shutdown command timed out after {} seconds",
+            SHUTDOWN_TIMEOUT.as_secs()
+        )
+    })?
+    .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "Shutdown command failed ({}): {}",
+            output.status,
+            stderr.trim()
+        ))
+    }
+}
+
+// Run a device's shutdown command and record the attempt in history
+pub async fn shutdown_and_record(
+    app_state: &AppState,
+    device: &Device,
+    source: &str,
+) -> Result<(), String> {
+    let result = run_shutdown_command(device).await;
+    record_device_action(
+        &app_state.history,
+        &app_state.events,
+        device,
+        ActionKind::Shutdown,
+        source,
+        result.clone(),
+    )
+    .await;
+    result
+}
+
+// Handler for shutdown requests
+async fn shutdown_device_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    let config = app_state.config.get();
+    let Some(device) = config.find_device(&device_id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Html(format!(
+                r#"<span class="font-medium text-red-400">Device '{}' not found</span>"#,
+                html_escape::encode_text(&device_id)
+            )),
+        )
+            .into_response();
+    };
+    if device.shutdown_command.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Html(format!(
+                r#"<span class="font-medium text-red-400">{} has no shutdown_command configured</span>"#,
+                html_escape::encode_text(&device.name)
+            )),
+        )
+            .into_response();
+    }
+
+    match shutdown_and_record(&app_state, device, "web").await {
+        Ok(()) => {
+            println!("Shutdown command run for device: {}", device.name);
+            Html(format!(
+                r#"<span class="text-emerald-400 font-medium">Shutdown sent to {}</span>"#,
+                html_escape::encode_text(&device.name)
+            ))
+            .into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to shut down '{}': {}", device.name, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Html(format!(
+                    r#"<span class="font-medium text-red-400">{}</span>"#,
+                    html_escape::encode_text(&e)
+                )),
+            )
+                .into_response()
+        }
+    }
+}
+
 // Handler for refreshing all devices
 async fn refresh_all_handler(State(app_state): State<AppState>) -> impl IntoResponse {
     // Create the devices HTML with updated status
+    let config = app_state.config.get();
     let mut devices_html = String::new();
 
-    if app_state.config.devices.is_empty() {
+    if config.devices.is_empty() {
         devices_html = r#"
         <div class="text-center py-16">
             <div class="bg-gray-800 rounded-full p-6 w-24 h-24 mx-auto mb-6">
@@ -247,7 +529,7 @@ async fn refresh_all_handler(State(app_state): State<AppState>) -> impl IntoResp
                 </svg>
             </div>
             <h3 class="text-xl font-semibold text-white mb-2">No devices configured</h3>
-            <p class="text-white/70 max-w-sm mx-auto">Start by adding devices to your config.yaml file or discover devices on your network</p>
+            <p class="text-white/70 max-w-sm mx-auto">Start by adding devices on the Devices page or discover devices on your network</p>
             <a href="/discovery" class="inline-flex items-center space-x-2 mt-6 bg-gray-700 hover:bg-gray-600 text-white font-medium py-3 px-6 rounded-xl transition-all duration-200">
                 <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
                     <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M21 21l-6-6m2-5a7 7 0 11-14 0 7 7 0 0114 0z"></path>
@@ -259,117 +541,407 @@ async fn refresh_all_handler(State(app_state): State<AppState>) -> impl IntoResp
     } else {
         devices_html.push_str("<div class=\"grid gap-6\">");
 
-        for (index, device) in app_state.config.devices.iter().enumerate() {
-            let status = ping_device(&device.ip_address).await;
-            let (status_class, status_text, _icon, status_bg_color) = match status {
-                DeviceStatus::Online => ("text-green-400", "Online", "●", "bg-green-500"),
-                DeviceStatus::Offline => ("text-red-400", "Offline", "●", "bg-red-500"),
-                DeviceStatus::Unreachable => {
-                    ("text-yellow-400", "Unreachable", "●", "bg-yellow-500")
-                }
-            };
+        let history = load_card_history(&app_state, None).await;
+        for device in config.devices.iter() {
+            let (status, latency_ms) = probe_device(&device.ip_address).await;
+            devices_html.push_str(&render_device_card(
+                &app_state, device, status, latency_ms, &history,
+            ));
+        }
 
-            let device_html = format!(
-                "<div class=\"bg-gray-800 rounded-2xl p-6 border border-gray-700 transition-all duration-300\">\
-                    <div class=\"flex flex-col lg:flex-row lg:items-center lg:justify-between space-y-4 lg:space-y-0\">\
-                        <div class=\"flex-1\">\
-                            <div class=\"flex items-center gap-4 mb-4\">\
-                                <div class=\"bg-gray-700 p-3 rounded-xl\">\
-                                    <svg class=\"w-6 h-6 text-white\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
-                                        <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M9.75 17L9 20l-1 1h8l-1-1-.75-3M3 13h18M5 17h14a2 2 0 002-2V5a2 2 0 00-2-2H5a2 2 0 00-2 2v10a2 2 0 002 2z\"></path>\
-                                    </svg>\
-                                </div>\
-                                <div>\
-                                    <h3 class=\"text-xl font-bold text-white mb-1\">{}</h3>\
-                                    <div id=\"status-{}\" class=\"flex items-center space-x-2\">\
-                                        <div class=\"flex items-center space-x-2 px-3 py-1 rounded-full bg-gray-700\">\
-                                            <span class=\"w-2 h-2 rounded-full {}\"></span>\
-                                            <span class=\"text-sm font-medium {}\">{}</span>\
-                                        </div>\
-                                    </div>\
-                                </div>\
-                            </div>\
-                            <div class=\"grid grid-cols-1 md:grid-cols-2 gap-3 text-sm\">\
-                                <div class=\"bg-gray-900 rounded-lg p-3\">\
-                                    <span class=\"text-gray-400 font-medium\">IP Address</span>\
-                                    <p class=\"text-white font-mono\">{}</p>\
-                                </div>\
-                                <div class=\"bg-gray-900 rounded-lg p-3\">\
-                                    <span class=\"text-gray-400 font-medium\">MAC Address</span>\
-                                    <p class=\"text-white font-mono text-sm\">{}</p>\
+        devices_html.push_str("</div>");
+    }
+
+    Html(devices_html).into_response()
+}
+
+// One dashboard card. The card and its activity line carry sse-swap so updates pushed over
+// /api/v1/events replace just what changed.
+pub fn render_device_card(
+    app_state: &AppState,
+    device: &Device,
+    status: DeviceStatus,
+    latency_ms: Option<f64>,
+    history: &CardHistory,
+) -> String {
+    // Element IDs and URLs use the device id, which stays the same across renames
+    let id = device.device_id();
+    let (status_class, status_text, _icon, status_bg_color) = match status {
+        DeviceStatus::Online => ("text-green-400", "Online", "●", "bg-green-500"),
+        DeviceStatus::Offline => ("text-red-400", "Offline", "●", "bg-red-500"),
+        DeviceStatus::Unreachable => ("text-yellow-400", "Unreachable", "●", "bg-yellow-500"),
+    };
+
+    // Only devices with a shutdown_command can be shut down
+    let shutdown_button = if device.shutdown_command.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<button id=\"shutdown-btn-{}\" hx-post=\"/shutdown/{}\" hx-target=\"#wake-response-{}\" hx-swap=\"innerHTML\" hx-indicator=\"#shutdown-btn-{}\" hx-confirm=\"Shut down {}?\" class=\"group flex-1 lg:flex-none bg-red-800 hover:bg-red-700 text-white font-medium py-3 px-4 rounded-xl transition-all duration-200 flex items-center justify-center space-x-2\">\
+                <svg class=\"w-4 h-4\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
+                    <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M18.36 6.64a9 9 0 11-12.73 0M12 2v10\"></path>\
+                </svg>\
+                <span>Shut down</span>\
+            </button>",
+            id, id, id, id,
+            html_escape::encode_double_quoted_attribute(&device.name)
+        )
+    };
+
+    format!(
+        "<div id=\"device-{}\" sse-swap=\"device-{}\" hx-swap=\"outerHTML\" class=\"bg-gray-800 rounded-2xl p-6 border border-gray-700 transition-all duration-300\">\
+            <div class=\"flex flex-col lg:flex-row lg:items-center lg:justify-between space-y-4 lg:space-y-0\">\
+                <div class=\"flex-1\">\
+                    <div class=\"flex items-center gap-4 mb-4\">\
+                        <div class=\"bg-gray-700 p-3 rounded-xl\">\
+                            <svg class=\"w-6 h-6 text-white\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
+                                <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M9.75 17L9 20l-1 1h8l-1-1-.75-3M3 13h18M5 17h14a2 2 0 002-2V5a2 2 0 00-2-2H5a2 2 0 00-2 2v10a2 2 0 002 2z\"></path>\
+                            </svg>\
+                        </div>\
+                        <div>\
+                            <h3 class=\"text-xl font-bold text-white mb-1\"><a href=\"/devices/{}\" class=\"hover:underline\">{}</a></h3>\
+                            <div id=\"status-{}\" class=\"flex items-center space-x-2\">\
+                                <div class=\"flex items-center space-x-2 px-3 py-1 rounded-full bg-gray-700\">\
+                                    <span class=\"w-2 h-2 rounded-full {}\"></span>\
+                                    <span class=\"text-sm font-medium {}\">{}</span>\
                                 </div>\
                             </div>\
                         </div>\
-                        <div class=\"flex flex-row lg:flex-col gap-3 lg:items-end\">\
-                            <button id=\"ping-btn-{}\" hx-get=\"/ping/{}\" hx-target=\"#status-{}\" hx-swap=\"innerHTML\" hx-indicator=\"#ping-btn-{}\" class=\"group flex-1 lg:flex-none bg-gray-700 hover:bg-gray-600 text-white font-medium py-3 px-4 rounded-xl transition-all duration-200 flex items-center justify-center space-x-2\">\
-                                <svg class=\"w-4 h-4\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
-                                    <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M9 19v-6a2 2 0 00-2-2H5a2 2 0 00-2 2v6a2 2 0 002 2h2a2 2 0 002-2zm0 0V9a2 2 0 012-2h2a2 2 0 012 2v10m-6 0a2 2 0 002 2h2a2 2 0 002-2m0 0V5a2 2 0 012-2h2a2 2 0 012 2v14a2 2 0 01-2 2h-2a2 2 0 01-2-2z\"></path>\
-                                </svg>\
-                                <span>Check</span>\
-                            </button>\
-                            <button id=\"wake-btn-{}\" hx-post=\"/wake/{}\" hx-target=\"#wake-response-{}\" hx-swap=\"innerHTML\" hx-indicator=\"#wake-btn-{}\" class=\"group flex-1 lg:flex-none bg-emerald-700 hover:bg-emerald-600 text-white font-medium py-3 px-4 rounded-xl transition-all duration-200 flex items-center justify-center space-x-2\">\
-                                <svg class=\"w-4 h-4\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
-                                    <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M13 10V3L4 14h7v7l9-11h-7z\"></path>\
-                                </svg>\
-                                <span>Wake</span>\
-                            </button>\
+                    </div>\
+                    <div class=\"grid grid-cols-1 md:grid-cols-2 gap-3 text-sm\">\
+                        <div class=\"bg-gray-900 rounded-lg p-3\">\
+                            <span class=\"text-gray-400 font-medium\">IP Address</span>\
+                            <p class=\"text-white font-mono\">{}</p>\
+                        </div>\
+                        <div class=\"bg-gray-900 rounded-lg p-3\">\
+                            <span class=\"text-gray-400 font-medium\">MAC Address</span>\
+                            <p class=\"text-white font-mono text-sm\">{}</p>\
                         </div>\
                     </div>\
-                    <div id=\"wake-response-{}\" class=\"mt-4 text-sm\"></div>\
-                </div>",
-                device.name, index, status_bg_color, status_class, status_text,
-                device.ip_address, device.mac_address,
-                index, device.name, index, index,
-                index, device.name, index, index, index
-            );
+                    {}\
+                </div>\
+                <div class=\"flex flex-row lg:flex-col gap-3 lg:items-end\">\
+                    <button id=\"ping-btn-{}\" hx-get=\"/ping/{}\" hx-target=\"#status-{}\" hx-swap=\"innerHTML\" hx-indicator=\"#ping-btn-{}\" class=\"group flex-1 lg:flex-none bg-gray-700 hover:bg-gray-600 text-white font-medium py-3 px-4 rounded-xl transition-all duration-200 flex items-center justify-center space-x-2\">\
+                        <svg class=\"w-4 h-4\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
+                            <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M9 19v-6a2 2 0 00-2-2H5a2 2 0 00-2 2v6a2 2 0 002 2h2a2 2 0 002-2zm0 0V9a2 2 0 012-2h2a2 2 0 012 2v10m-6 0a2 2 0 002 2h2a2 2 0 002-2m0 0V5a2 2 0 012-2h2a2 2 0 012 2v14a2 2 0 01-2 2h-2a2 2 0 01-2-2z\"></path>\
+                        </svg>\
+                        <span>Check</span>\
+                    </button>\
+                    <button id=\"wake-btn-{}\" hx-post=\"/wake/{}\" hx-target=\"#wake-response-{}\" hx-swap=\"innerHTML\" hx-indicator=\"#wake-btn-{}\" class=\"group flex-1 lg:flex-none bg-emerald-700 hover:bg-emerald-600 text-white font-medium py-3 px-4 rounded-xl transition-all duration-200 flex items-center justify-center space-x-2\">\
+                        <svg class=\"w-4 h-4\" fill=\"none\" stroke=\"currentColor\" viewBox=\"0 0 24 24\">\
+                            <path stroke-linecap=\"round\" stroke-linejoin=\"round\" stroke-width=\"2\" d=\"M13 10V3L4 14h7v7l9-11h-7z\"></path>\
+                        </svg>\
+                        <span>Wake</span>\
+                    </button>\
+                    {}\
+                </div>\
+            </div>\
+            <div id=\"activity-{}\" sse-swap=\"activity-{}\" hx-swap=\"innerHTML\" class=\"mt-4 text-sm text-gray-400\">{}</div>\
+            <div id=\"wake-response-{}\" class=\"mt-4 text-sm\"></div>\
+        </div>",
+        id, id, id, html_escape::encode_text(&device.name), id, status_bg_color, status_class, status_text,
+        html_escape::encode_text(&device.ip_address), html_escape::encode_text(&device.mac_address),
+        render_card_history(app_state, device, latency_ms, history),
+        id, id, id, id,
+        id, id, id, id, shutdown_button,
+        id, id, render_activity(app_state, &id, history.last_actions.get(&id)),
+        id
+    )
+}
+
+// The dashboard's availability bar covers the last 24 hours in 30 minute slices
+const DAY_SECONDS: u64 = 86_400;
+const CARD_TIMELINE_BUCKETS: usize = 48;
+
+// History for the cards of every device, or of one, read on the blocking pool
+async fn load_card_history(app_state: &AppState, device_id: Option<String>) -> CardHistory {
+    let now = unix_now();
+    app_state
+        .history
+        .blocking(move |history| {
+            history.card_history(device_id.as_deref(), now - DAY_SECONDS, now + 1)
+        })
+        .await
+}
+
+// Uptime, latency, last change, availability bar and latency sparkline for a dashboard card
+fn render_card_history(
+    app_state: &AppState,
+    device: &Device,
+    latency_ms: Option<f64>,
+    history: &CardHistory,
+) -> String {
+    let id = device.device_id();
+    let now = unix_now();
+    let samples = history.samples.get(&id).map(Vec::as_slice).unwrap_or(&[]);
+    let buckets = timeline(
+        samples,
+        now + 1 - DAY_SECONDS,
+        now + 1,
+        CARD_TIMELINE_BUCKETS,
+    );
+    let last_change = app_state
+        .statuses
+        .get(&id)
+        .and_then(|state| state.since)
+        .or_else(|| history.last_changes.get(&id).copied());
+
+    format!(
+        r#"<div class="mt-4 space-y-2">
+            <div class="flex flex-wrap gap-x-6 gap-y-1 text-xs text-gray-400">
+                <span>Uptime 24h <span class="text-white font-medium">{}</span></span>
+                <span>Latency <span class="text-white font-medium">{}</span></span>
+                <span>Last change <span class="text-white font-medium">{}</span></span>
+            </div>
+            {}
+            {}
+        </div>"#,
+        format_percent(uptime_percent(samples)),
+        format_latency(latency_ms),
+        last_change
+            .map(|at| format!("{} ago", format_age(now.saturating_sub(at))))
+            .unwrap_or_else(|| "—".to_string()),
+        render_availability_bar(&buckets, "%H:%M"),
+        render_sparkline(&buckets)
+    )
+}
+
+// How long after a wake the card keeps saying it is waiting for the device
+const WAKE_PROGRESS_SECONDS: u64 = 600;
+
+// The card's activity line: the last wake or shutdown and, after a wake, whether the device
+// has come up since
+fn render_activity(
+    app_state: &AppState,
+    device_id: &str,
+    last_action: Option<&ActionRecord>,
+) -> String {
+    let Some(action) = last_action else {
+        return String::new();
+    };
+
+    let now = unix_now();
+    let verb = match (action.action, action.success) {
+        (ActionKind::Wake, true) => "Wake sent",
+        (ActionKind::Wake, false) => "Wake failed",
+        (ActionKind::Shutdown, true) => "Shut down",
+        (ActionKind::Shutdown, false) => "Shutdown failed",
+    };
+    let mut line = format!(
+        "{} {} ago from {}",
+        verb,
+        format_age(now.saturating_sub(action.at)),
+        html_escape::encode_text(&action.source)
+    );
 
-            devices_html.push_str(&device_html);
+    if action.action == ActionKind::Wake && action.success {
+        match app_state.statuses.get(device_id) {
+            Some(state) if state.status == DeviceStatus::Online => {
+                if let Some(since) = state.since.filter(|since| *since >= action.at) {
+                    line.push_str(&format!(
+                        " • online after {}",
+                        format_age(since - action.at)
+                    ));
+                }
+            }
+            _ if now.saturating_sub(action.at) < WAKE_PROGRESS_SECONDS => {
+                line.push_str(" • waiting for it to come online…");
+            }
+            _ => {}
         }
+    }
 
-        devices_html.push_str("</div>");
+    line
+}
+
+// One coloured slice per bucket: green all online, red never online, amber in between
+fn render_availability_bar(buckets: &[TimelineBucket], time_format: &str) -> String {
+    let slices: String = buckets
+        .iter()
+        .map(|bucket| {
+            let (color, label) = match bucket.uptime() {
+                None => ("bg-gray-700", "no data".to_string()),
+                Some(uptime) if uptime >= 1.0 => ("bg-green-500", "online".to_string()),
+                Some(uptime) if uptime <= 0.0 => ("bg-red-500", "offline".to_string()),
+                Some(uptime) => ("bg-yellow-500", format!("{:.0}% online", uptime * 100.0)),
+            };
+            format!(
+                r#"<div class="flex-1 {}" title="{}–{}: {}"></div>"#,
+                color,
+                format_local_time(bucket.start, time_format),
+                format_local_time(bucket.end, time_format),
+                label
+            )
+        })
+        .collect();
+    format!(
+        r#"<div class="availability-bar flex gap-px h-3 rounded overflow-hidden">{}</div>"#,
+        slices
+    )
+}
+
+// Average latency per bucket as a step line scaled to the slowest bucket
+fn render_sparkline(buckets: &[TimelineBucket]) -> String {
+    let max = buckets
+        .iter()
+        .filter_map(|bucket| bucket.latency_ms)
+        .fold(0.0, f64::max);
+    if max <= 0.0 {
+        return r#"<p class="text-xs text-gray-500">No latency data yet</p>"#.to_string();
     }
 
-    Html(devices_html).into_response()
+    // Buckets without an answered ping break the line
+    let mut lines = Vec::new();
+    let mut points = Vec::new();
+    for (index, bucket) in buckets.iter().enumerate() {
+        match bucket.latency_ms {
+            Some(latency) => {
+                let y = 23.0 - latency / max * 22.0;
+                points.push(format!("{},{:.1} {},{:.1}", index, y, index + 1, y));
+            }
+            None if !points.is_empty() => lines.push(points.split_off(0).join(" ")),
+            None => {}
+        }
+    }
+    if !points.is_empty() {
+        lines.push(points.join(" "));
+    }
+
+    let polylines: String = lines
+        .iter()
+        .map(|points| {
+            format!(
+                r#"<polyline points="{}" fill="none" stroke="currentColor" stroke-width="1.5" vector-effect="non-scaling-stroke"/>"#,
+                points
+            )
+        })
+        .collect();
+    format!(
+        r#"<svg class="latency-sparkline w-full h-6 text-emerald-400" viewBox="0 0 {} 24" preserveAspectRatio="none"><title>Latency, up to {}</title>{}</svg>"#,
+        buckets.len(),
+        format_latency(Some(max)),
+        polylines
+    )
+}
+
+// Rounded down, so a single missed check never shows as 100%
+fn format_percent(percent: Option<f64>) -> String {
+    match percent.map(|percent| (percent * 10.0).floor() / 10.0) {
+        Some(percent) if percent.fract() == 0.0 => format!("{:.0}%", percent),
+        Some(percent) => format!("{:.1}%", percent),
+        None => "—".to_string(),
+    }
+}
+
+fn format_latency(latency_ms: Option<f64>) -> String {
+    match latency_ms {
+        Some(ms) if ms < 10.0 => format!("{:.1} ms", ms),
+        Some(ms) => format!("{:.0} ms", ms),
+        None => "—".to_string(),
+    }
+}
+
+// "45s", "12m", "3h 12m", "2d 4h"
+pub fn format_age(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+// Unix seconds in the server's local time zone
+fn format_local_time(at: u64, format: &str) -> String {
+    chrono::DateTime::from_timestamp(at as i64, 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format(format)
+                .to_string()
+        })
+        .unwrap_or_default()
 }
 
 // Enum for device status
-#[derive(Debug, Clone)]
-enum DeviceStatus {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceStatus {
     Online,
     Offline,
     Unreachable,
 }
 
+impl DeviceStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceStatus::Online => "online",
+            DeviceStatus::Offline => "offline",
+            DeviceStatus::Unreachable => "unreachable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "online" => Some(DeviceStatus::Online),
+            "offline" => Some(DeviceStatus::Offline),
+            "unreachable" => Some(DeviceStatus::Unreachable),
+            _ => None,
+        }
+    }
+}
+
 // Function to ping a device and determine its status
-async fn ping_device(ip: &str) -> DeviceStatus {
+pub async fn ping_device(ip: &str) -> DeviceStatus {
+    probe_device(ip).await.0
+}
+
+// Ping a device once, returning its status and the round-trip time when it answered
+pub async fn probe_device(ip: &str) -> (DeviceStatus, Option<f64>) {
     // Use ping command to check device status
     let output = Command::new("ping")
-        .args(&["-c", "1", "-W", "2", ip]) // 1 packet, 2 second timeout
+        .args(["-c", "1", "-W", "2", ip]) // 1 packet, 2 second timeout
         .output()
         .await;
 
     match output {
         Ok(output) => {
             if output.status.success() {
-                DeviceStatus::Online
+                let latency = parse_ping_latency(&String::from_utf8_lossy(&output.stdout));
+                (DeviceStatus::Online, latency)
             } else {
-                DeviceStatus::Offline
+                (DeviceStatus::Offline, None)
             }
         }
-        Err(_) => DeviceStatus::Unreachable,
+        Err(_) => (DeviceStatus::Unreachable, None),
     }
 }
 
+// The "time=0.45 ms" of a ping reply, in milliseconds
+pub fn parse_ping_latency(output: &str) -> Option<f64> {
+    let re = Regex::new(r"time[=<]\s*([0-9.]+)\s*ms").ok()?;
+    re.captures(output)?.get(1)?.as_str().parse().ok()
+}
+
 // Discovery page handler
-async fn discovery_handler(State(app_state): State<AppState>) -> impl IntoResponse {
+async fn discovery_handler(State(app_state): State<AppState>, jar: CookieJar) -> impl IntoResponse {
+    let (jar, _session_id) = discovery_session(jar);
+    let config = app_state.config.get();
+    let new_devices = app_state.seen_devices.unacknowledged().await;
     let data = json!({
-        "devices": &app_state.config.devices,
-        "device_count": app_state.config.devices.len(),
-        "config_data": &app_state.config, // Add config_data to the context
+        "new_devices": new_devices,
+        "devices": &config.devices,
+        "device_count": config.devices.len(),
+        "config_data": &*config, // Add config_data to the context
+        "discovery": &config.discovery,
+        "dhcp_import_enabled": !config.discovery.dhcp_leases.is_empty(),
     });
 
     match app_state.handlebars.render("discovery", &data) {
-        Ok(rendered_html) => Html(rendered_html).into_response(),
+        Ok(rendered_html) => (jar, Html(rendered_html)).into_response(),
         Err(e) => {
             eprintln!("Error rendering discovery template: {}", e);
             (
@@ -423,31 +995,18 @@ async fn discovery_interfaces_handler() -> impl IntoResponse {
 // Network scan handler
 async fn discovery_scan_handler(
     State(app_state): State<AppState>,
+    jar: CookieJar,
     body: String,
 ) -> impl IntoResponse {
+    let (jar, session_id) = discovery_session(jar);
     println!("Starting network discovery scan...");
 
-    // Parse selected interfaces from request body
-    let selected_interfaces: Vec<String> = if body.is_empty() {
-        Vec::new() // If no body, scan all interfaces
+    // Parse selected interfaces and scan overrides from request body
+    let params: Vec<(String, String)> = if body.is_empty() {
+        Vec::new() // If no body, scan all interfaces with configured settings
     } else {
         match serde_urlencoded::from_str::<Vec<(String, String)>>(&body) {
-            Ok(params) => params
-                .into_iter()
-                .filter_map(|(key, value)| {
-                    if key == "interfaces" && !value.is_empty() {
-                        // Basic validation: ensure interface name doesn't contain dangerous characters
-                        if value.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.') {
-                            Some(value)
-                        } else {
-                            eprintln!("Warning: Invalid interface name received: {}", value);
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+            Ok(params) => params,
             Err(e) => {
                 eprintln!("Failed to parse request body: {}", e);
                 Vec::new() // Fallback to scanning all interfaces
@@ -455,78 +1014,372 @@ async fn discovery_scan_handler(
         }
     };
 
+    let selected_interfaces: Vec<String> = params
+        .iter()
+        .filter_map(|(key, value)| {
+            if key == "interfaces" && !value.is_empty() {
+                // Basic validation: ensure interface name doesn't contain dangerous characters
+                if value
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+                {
+                    Some(value.clone())
+                } else {
+                    eprintln!("Warning: Invalid interface name received: {}", value);
+                    None
+                }
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let scan_options = apply_scan_overrides(&app_state.config.get().discovery, &params);
+
     // Discover devices on the network
-    let discovered_devices = discover_network_devices(Some(selected_interfaces)).await;
+    let started = Instant::now();
+    let discovered_devices =
+        discover_network_devices(Some(selected_interfaces), &scan_options).await;
+    app_state
+        .metrics
+        .record_scan("manual", started.elapsed(), discovered_devices.len());
+
+    // Store discovered devices for this session so generate-config can find them
+    let scan_id = app_state
+        .discovered_devices
+        .insert(&session_id, discovered_devices.clone())
+        .await;
 
-    // Store discovered devices in app state for later use
-    {
-        let mut storage = app_state.discovered_devices.lock().await;
-        storage.insert("latest_scan".to_string(), discovered_devices.clone());
-    }
+    let config = app_state.config.get();
+    let diff = diff_discovered_devices(&config, &discovered_devices);
+    (jar, Html(render_discovery_results(&scan_id, &diff)))
+}
 
-    let mut discovered_devices_html = String::new();
+// Import devices from the configured DHCP lease files into the discovery flow
+async fn discovery_import_dhcp_handler(
+    State(app_state): State<AppState>,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    let (jar, session_id) = discovery_session(jar);
+    let config = app_state.config.get();
+    let sources = &config.discovery.dhcp_leases;
 
-    if discovered_devices.is_empty() {
-        discovered_devices_html = r#"
-        <div class="text-center py-16">
-            <div class="bg-gray-800 rounded-full p-6 w-24 h-24 mx-auto mb-6">
-                <svg class="w-12 h-12 text-gray-500 mx-auto" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M13 16h-1v-4h-1m1-4h.01M21 12a9 9 0 11-18 0 9 9 0 0118 0z"></path>
-                </svg>
-            </div>
-            <h3 class="text-xl font-semibold text-white mb-2">No devices found</h3>
-            <p class="text-white/70">Ensure your devices are on the same network and WOL is enabled.</p>
-        </div>
-        "#.to_string();
-    } else {
-        discovered_devices_html.push_str("<form id=\"discovery-form\" hx-post=\"/discovery/generate-config\" hx-target=\"#config-preview\" hx-swap=\"outerHTML\">");
-        discovered_devices_html.push_str("<div class=\"space-y-4\">");
+    if sources.is_empty() {
+        return (
+            jar,
+            (
+                StatusCode::BAD_REQUEST,
+                Html(
+                    r#"<p class="text-red-400 font-medium">No DHCP lease files are configured under <code>discovery.dhcp_leases</code>.</p>"#
+                        .to_string(),
+                ),
+            ),
+        )
+            .into_response();
+    }
 
-        // Add select all/unselect all controls
-        discovered_devices_html.push_str(r#"
-        <div class="flex items-center justify-between p-4 bg-white/5 rounded-lg backdrop-blur-sm border border-white/10">
-            <div class="flex items-center space-x-3">
-                <input type="checkbox" id="select-all" class="form-checkbox h-5 w-5 bg-gray-900 border-gray-600 text-emerald-600 focus:ring-emerald-500 rounded">
-                <label for="select-all" class="text-white font-medium">Select All Devices</label>
-            </div>
-            <span class="text-white/60 text-sm" id="selection-count">0 devices selected</span>
-        </div>
-        "#);
+    let report = import_dhcp_leases(sources).await;
+    println!(
+        "Imported {} device(s) from {} DHCP lease file(s), {} error(s)",
+        report.devices.len(),
+        sources.len(),
+        report.errors.len()
+    );
 
-        for device in discovered_devices {
-            let device_html = format!(
-                r#"<div class="bg-gray-800 p-4 rounded-lg border border-gray-700 flex items-center justify-between">
-                    <div class="flex items-center gap-4">
-                        <input type="checkbox" name="selected_devices" value='{{"ip_address":"{}","mac_address":"{}","hostname":"{}"}}' class="device-checkbox form-checkbox h-5 w-5 bg-gray-900 border-gray-600 text-emerald-600 focus:ring-emerald-500 rounded">
-                        <div>
-                            <p class="font-semibold text-white">{}</p>
-                            <p class="text-sm text-gray-400">{}</p>
-                        </div>
+    (
+        jar,
+        Html(render_import_results(&app_state, &session_id, report).await),
+    )
+        .into_response()
+}
+
+// Import devices from an uploaded nmap XML or CSV inventory file
+async fn discovery_import_file_handler(
+    State(app_state): State<AppState>,
+    jar: CookieJar,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let (jar, session_id) = discovery_session(jar);
+    let mut upload: Option<(String, String)> = None;
+    let mut format = InventoryFormat::Auto;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return import_upload_error(jar, format!("Invalid upload: {}", e)),
+        };
+
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or("upload").to_string();
+                let bytes = match field.bytes().await {
+                    Ok(bytes) => bytes,
+                    Err(e) => return import_upload_error(jar, format!("Invalid upload: {}", e)),
+                };
+                match String::from_utf8(bytes.to_vec()) {
+                    Ok(contents) => upload = Some((file_name, contents)),
+                    Err(_) => {
+                        return import_upload_error(
+                            jar,
+                            "The uploaded file is not UTF-8 text.".to_string(),
+                        )
+                    }
+                }
+            }
+            Some("format") => {
+                let value = field.text().await.unwrap_or_default();
+                format = match value.as_str() {
+                    "nmap" => InventoryFormat::Nmap,
+                    "csv" => InventoryFormat::Csv,
+                    _ => InventoryFormat::Auto,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let Some((file_name, contents)) = upload else {
+        return import_upload_error(jar, "Choose an nmap XML or CSV file to import.".to_string());
+    };
+
+    let report = parse_inventory(format, &contents, &file_name);
+    println!(
+        "Imported {} device(s) from {}, {} error(s)",
+        report.devices.len(),
+        file_name,
+        report.errors.len()
+    );
+
+    (
+        jar,
+        Html(render_import_results(&app_state, &session_id, report).await),
+    )
+        .into_response()
+}
+
+fn import_upload_error(jar: CookieJar, message: String) -> axum::response::Response {
+    (
+        jar,
+        (
+            StatusCode::BAD_REQUEST,
+            Html(format!(
+                r#"<p class="text-red-400 font-medium">{}</p>"#,
+                html_escape::encode_text(&message)
+            )),
+        ),
+    )
+        .into_response()
+}
+
+// Store imported devices as a scan for this session and render them like scan results
+async fn render_import_results(
+    app_state: &AppState,
+    session_id: &str,
+    report: ImportReport,
+) -> String {
+    let scan_id = app_state
+        .discovered_devices
+        .insert(session_id, report.devices.clone())
+        .await;
+    let diff = diff_discovered_devices(&app_state.config.get(), &report.devices);
+
+    let mut html = render_import_errors(&report.errors);
+    html.push_str(&render_discovery_results(&scan_id, &diff));
+    html
+}
+
+// List records that could not be imported
+fn render_import_errors(errors: &[ImportError]) -> String {
+    if errors.is_empty() {
+        return String::new();
+    }
+
+    let mut html = format!(
+        r#"<div class="mb-6 p-4 rounded-lg border border-red-500/40 bg-red-500/10">
+            <p class="text-red-300 font-medium mb-2">{} record(s) could not be imported</p>
+            <ul class="text-sm text-white/70 font-mono space-y-1">"#,
+        errors.len()
+    );
+    for error in errors {
+        let location = match error.line {
+            Some(line) => format!("{}:{}", error.source, line),
+            None => error.source.clone(),
+        };
+        html.push_str(&format!(
+            "<li>{}: {}</li>",
+            html_escape::encode_text(&location),
+            html_escape::encode_text(&error.message)
+        ));
+    }
+    html.push_str("</ul></div>");
+    html
+}
+
+// Render a discovery diff as the select-and-generate-config form
+pub fn render_discovery_results(scan_id: &str, diff: &DiscoveryDiff) -> String {
+    let mut discovered_devices_html = String::new();
+
+    if diff.is_empty() {
+        discovered_devices_html = r#"
+        <div class="text-center py-16">
+            <div class="bg-gray-800 rounded-full p-6 w-24 h-24 mx-auto mb-6">
+                <svg class="w-12 h-12 text-gray-500 mx-auto" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M13 16h-1v-4h-1m1-4h.01M21 12a9 9 0 11-18 0 9 9 0 0118 0z"></path>
+                </svg>
+            </div>
+            <h3 class="text-xl font-semibold text-white mb-2">No devices found</h3>
+            <p class="text-white/70">Ensure your devices are on the same network and WOL is enabled.</p>
+        </div>
+        "#.to_string();
+        return discovered_devices_html;
+    }
+
+    discovered_devices_html.push_str("<form id=\"discovery-form\" hx-post=\"/discovery/generate-config\" hx-target=\"#config-preview\" hx-swap=\"outerHTML\">");
+    discovered_devices_html.push_str(&format!(
+        "<input type=\"hidden\" name=\"scan_id\" value=\"{}\">",
+        html_escape::encode_double_quoted_attribute(scan_id)
+    ));
+    discovered_devices_html.push_str("<div class=\"space-y-8\">");
+
+    // New devices: selectable additions
+    discovered_devices_html.push_str(&format!(
+        r#"<section class="space-y-4">
+        <h3 class="text-lg font-semibold text-white">New devices <span class="text-white/60 text-sm">({})</span></h3>"#,
+        diff.new_devices.len()
+    ));
+    if diff.new_devices.is_empty() {
+        discovered_devices_html.push_str(r#"<p class="text-white/60 text-sm">Every discovered device is already configured.</p>"#);
+    } else {
+        // Add select all/unselect all controls
+        discovered_devices_html.push_str(r#"
+        <div class="flex items-center justify-between p-4 bg-white/5 rounded-lg backdrop-blur-sm border border-white/10">
+            <div class="flex items-center space-x-3">
+                <input type="checkbox" id="select-all" class="form-checkbox h-5 w-5 bg-gray-900 border-gray-600 text-emerald-600 focus:ring-emerald-500 rounded">
+                <label for="select-all" class="text-white font-medium">Select All Devices</label>
+            </div>
+            <span class="text-white/60 text-sm" id="selection-count">0 devices selected</span>
+        </div>
+        "#);
+
+        for device in &diff.new_devices {
+            let value = json!({
+                "ip_address": device.ip_address,
+                "mac_address": device.mac_address.as_deref().unwrap_or("N/A"),
+                "hostname": device.hostname.as_deref().unwrap_or("N/A"),
+            })
+            .to_string();
+            let device_html = format!(
+                r#"<div class="bg-gray-800 p-4 rounded-lg border border-gray-700 flex items-center justify-between">
+                    <div class="flex items-center gap-4">
+                        <input type="checkbox" name="selected_devices" value="{}" class="device-checkbox form-checkbox h-5 w-5 bg-gray-900 border-gray-600 text-emerald-600 focus:ring-emerald-500 rounded">
+                        <div>
+                            <p class="font-semibold text-white">{}</p>
+                            <p class="text-sm text-gray-400">{}</p>
+                        </div>
                     </div>
                     <span class="text-xs font-mono text-gray-500 bg-gray-900 px-2 py-1 rounded-md">{}</span>
                 </div>"#,
-                device.ip_address,
-                device.mac_address.as_deref().unwrap_or("N/A"),
-                device.hostname.as_deref().unwrap_or("N/A"),
-                device.hostname.as_deref().unwrap_or(&device.ip_address),
-                device.ip_address,
-                device.mac_address.as_deref().unwrap_or("N/A")
+                html_escape::encode_double_quoted_attribute(&value),
+                html_escape::encode_text(device.hostname.as_deref().unwrap_or(&device.ip_address)),
+                html_escape::encode_text(&device.ip_address),
+                html_escape::encode_text(device.mac_address.as_deref().unwrap_or("N/A"))
             );
             discovered_devices_html.push_str(&device_html);
         }
+    }
+    discovered_devices_html.push_str("</section>");
+
+    // Known devices whose address or hostname changed: selectable updates
+    if !diff.changed.is_empty() {
+        discovered_devices_html.push_str(&format!(
+            r#"<section class="space-y-4">
+            <h3 class="text-lg font-semibold text-white">Changed devices <span class="text-white/60 text-sm">({})</span></h3>"#,
+            diff.changed.len()
+        ));
+        for change in &diff.changed {
+            let mut options_html = String::new();
+            if let Some(ip) = &change.new_ip_address {
+                options_html.push_str(&update_option_html(
+                    &change.device.mac_address,
+                    "ip_address",
+                    &format!("IP {} → {}", change.device.ip_address, ip),
+                ));
+            }
+            if let Some(name) = &change.new_name {
+                options_html.push_str(&update_option_html(
+                    &change.device.mac_address,
+                    "name",
+                    &format!("Name {} → {}", change.device.name, name),
+                ));
+            }
+            discovered_devices_html.push_str(&format!(
+                r#"<div class="bg-gray-800 p-4 rounded-lg border border-amber-700/60">
+                    <div class="flex items-center justify-between mb-2">
+                        <p class="font-semibold text-white">{}</p>
+                        <span class="text-xs font-mono text-gray-500 bg-gray-900 px-2 py-1 rounded-md">{}</span>
+                    </div>
+                    <div class="flex flex-col gap-2">{}</div>
+                </div>"#,
+                html_escape::encode_text(&change.device.name),
+                html_escape::encode_text(&change.device.mac_address),
+                options_html
+            ));
+        }
+        discovered_devices_html.push_str("</section>");
+    }
 
-        discovered_devices_html.push_str("</div>");
-        discovered_devices_html.push_str(r#"
+    // Informational sections
+    if !diff.unchanged.is_empty() {
+        let names: Vec<String> = diff
+            .unchanged
+            .iter()
+            .map(|device| html_escape::encode_text(&device.name).to_string())
+            .collect();
+        discovered_devices_html.push_str(&format!(
+            r#"<section>
+                <h3 class="text-lg font-semibold text-white mb-2">Known and unchanged <span class="text-white/60 text-sm">({})</span></h3>
+                <p class="text-sm text-white/70">{}</p>
+            </section>"#,
+            diff.unchanged.len(),
+            names.join(", ")
+        ));
+    }
+    if !diff.not_seen.is_empty() {
+        let names: Vec<String> = diff
+            .not_seen
+            .iter()
+            .map(|device| html_escape::encode_text(&device.name).to_string())
+            .collect();
+        discovered_devices_html.push_str(&format!(
+            r#"<section>
+                <h3 class="text-lg font-semibold text-white mb-2">Configured but not seen <span class="text-white/60 text-sm">({})</span></h3>
+                <p class="text-sm text-white/70">{}</p>
+            </section>"#,
+            diff.not_seen.len(),
+            names.join(", ")
+        ));
+    }
+
+    discovered_devices_html.push_str("</div>");
+    discovered_devices_html.push_str(r#"
         <div class="mt-8 text-right">
             <button type="submit" class="bg-emerald-600 hover:bg-emerald-500 text-white font-bold py-3 px-6 rounded-lg transition-colors">
                 Generate Configuration
             </button>
         </div>
         "#);
-        discovered_devices_html.push_str("</form>");
+    discovered_devices_html.push_str("</form>");
 
+    if !diff.new_devices.is_empty() {
         // Add JavaScript for select all functionality
-        discovered_devices_html.push_str(r#"
+        discovered_devices_html.push_str(SELECT_ALL_SCRIPT);
+    }
+
+    discovered_devices_html
+}
+
+const SELECT_ALL_SCRIPT: &str = r#"
         <script>
             document.getElementById('select-all').addEventListener('change', function() {
                 const checkboxes = document.querySelectorAll('.device-checkbox');
@@ -569,29 +1422,59 @@ async fn discovery_scan_handler(
             // Initialize counts
             updateSelectionCount();
         </script>
-        "#);
-    }
-
-    Html(discovered_devices_html)
+        "#;
+
+// Checkbox offering to apply one field of a discovered change to the config
+fn update_option_html(mac_address: &str, field: &str, label: &str) -> String {
+    let value = json!({ "mac_address": mac_address, "field": field }).to_string();
+    format!(
+        r#"<label class="flex items-center gap-3 text-sm text-white/80">
+            <input type="checkbox" name="apply_updates" value="{}" class="update-checkbox form-checkbox h-4 w-4 bg-gray-900 border-gray-600 text-amber-500 rounded">
+            <span class="font-mono">{}</span>
+        </label>"#,
+        html_escape::encode_double_quoted_attribute(&value),
+        html_escape::encode_text(label)
+    )
 }
 
 // Handler for generating YAML config from discovered devices
 async fn generate_config_handler(
     State(app_state): State<AppState>,
+    jar: CookieJar,
     body: String,
 ) -> impl IntoResponse {
-    let selected_json_strings: Vec<String> =
-        serde_urlencoded::from_str::<Vec<(String, String)>>(&body)
-            .unwrap_or_default()
-            .into_iter()
-            .filter_map(|(key, value)| {
-                if key == "selected_devices" {
-                    Some(value)
-                } else {
-                    None
-                }
-            })
-            .collect();
+    let (jar, session_id) = discovery_session(jar);
+    let params: Vec<(String, String)> =
+        serde_urlencoded::from_str::<Vec<(String, String)>>(&body).unwrap_or_default();
+
+    let scan_id = params
+        .iter()
+        .find(|(key, _)| key == "scan_id")
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+
+    let selected_json_strings: Vec<String> = params
+        .iter()
+        .filter_map(|(key, value)| {
+            if key == "selected_devices" {
+                Some(value.clone())
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Requested updates as (MAC, field) pairs; values are taken from the scan, not the form
+    let requested_updates: Vec<(String, String)> = params
+        .iter()
+        .filter(|(key, _)| key == "apply_updates")
+        .filter_map(|(_, value)| serde_json::from_str::<serde_json::Value>(value).ok())
+        .filter_map(|json_value| {
+            let mac = json_value.get("mac_address")?.as_str()?;
+            let field = json_value.get("field")?.as_str()?;
+            Some((normalize_mac(mac), field.to_string()))
+        })
+        .collect();
 
     // Parse the JSON strings to extract IP addresses
     let selected_ips: Vec<String> = selected_json_strings
@@ -599,35 +1482,80 @@ async fn generate_config_handler(
         .filter_map(|json_str| {
             // Parse the JSON string to extract the IP address
             if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(json_str) {
-                if let Some(ip) = json_value.get("ip_address").and_then(|v| v.as_str()) {
-                    Some(ip.to_string())
-                } else {
-                    None
-                }
+                json_value
+                    .get("ip_address")
+                    .and_then(|v| v.as_str())
+                    .map(|ip| ip.to_string())
             } else {
                 None
             }
         })
         .collect();
 
-    // Get the discovered devices from storage
-    let discovered_devices = {
-        let storage = app_state.discovered_devices.lock().await;
-        storage.get("latest_scan").cloned().unwrap_or_default()
+    // Get this session's scan results; expired or foreign scans are treated as empty
+    let discovered_devices = match app_state
+        .discovered_devices
+        .get(&scan_id, &session_id)
+        .await
+    {
+        Some(scan) => scan.devices,
+        None => {
+            eprintln!("Scan '{}' not found or expired for this session", scan_id);
+            return (
+                jar,
+                (
+                    StatusCode::NOT_FOUND,
+                    Html(
+                        r#"<div id="config-preview" class="glass rounded-2xl p-6 sm:p-8 mb-8">
+                            <p class="text-red-400 font-medium">This scan has expired. Please run a new network scan.</p>
+                        </div>"#
+                            .to_string(),
+                    ),
+                ),
+            )
+                .into_response();
+        }
     };
 
-    let selected_devices: Vec<DiscoveredDevice> = discovered_devices
+    let config = app_state.config.get();
+    let diff = diff_discovered_devices(&config, &discovered_devices);
+    let updates: Vec<DeviceUpdate> = diff
+        .changed
+        .iter()
+        .filter_map(|change| {
+            let mac = normalize_mac(&change.device.mac_address);
+            let wants = |field: &str| {
+                requested_updates
+                    .iter()
+                    .any(|(update_mac, update_field)| *update_mac == mac && update_field == field)
+            };
+            let update = DeviceUpdate {
+                mac_address: change.device.mac_address.clone(),
+                ip_address: change
+                    .new_ip_address
+                    .clone()
+                    .filter(|_| wants("ip_address")),
+                name: change.new_name.clone().filter(|_| wants("name")),
+            };
+            (update.ip_address.is_some() || update.name.is_some()).then_some(update)
+        })
+        .collect();
+
+    let selected_devices: Vec<DiscoveredDevice> = diff
+        .new_devices
         .into_iter()
         .filter(|device| selected_ips.contains(&device.ip_address))
         .collect();
 
-    let config_yaml = generate_config_yaml(&app_state.config, &selected_devices).await;
+    // Secrets stay on the server; the preview only needs the devices to be right
+    let config_yaml =
+        generate_config_yaml_with_updates(&config.redacted(), &selected_devices, &updates).await;
 
-    // Store the generated config for download
-    {
-        let mut stored_config = GENERATED_CONFIG.lock().await;
-        *stored_config = Some(config_yaml.clone());
-    }
+    // Store the generated config with the scan for download
+    app_state
+        .discovered_devices
+        .set_generated_config(&scan_id, &session_id, config_yaml.clone())
+        .await;
 
     let response_html = format!(
         r#"<div id="config-preview" class="glass rounded-2xl p-6 sm:p-8 mb-8 card-hover">
@@ -639,7 +1567,7 @@ async fn generate_config_handler(
                 </div>
             </div>
             <div class="bg-white/10 backdrop-blur-sm rounded-xl p-4 border border-white/20 mb-6 overflow-x-auto">
-                <pre class="text-sm text-white/90 font-mono"><code>{}</code></pre>
+                <pre class="text-sm text-white/90 font-mono"><code id="generated-config">{}</code></pre>
             </div>
             <div class="flex flex-col sm:flex-row sm:items-center sm:justify-between gap-4">
                 <p class="text-sm text-white/70">Review the configuration and save it to your <code class="bg-white/20 text-white px-2 py-1 rounded-md font-mono text-xs">config.yaml</code> file.</p>
@@ -650,7 +1578,7 @@ async fn generate_config_handler(
                         </svg>
                         <span>Copy</span>
                     </button>
-                    <a href="/discovery/download-config" download="config.yaml" class="bg-emerald-600 hover:bg-emerald-500 text-white font-semibold py-2 px-4 rounded-lg transition-all duration-200 flex items-center space-x-2 no-underline">
+                    <a href="/discovery/download-config?scan_id={}" download="config.yaml" class="bg-emerald-600 hover:bg-emerald-500 text-white font-semibold py-2 px-4 rounded-lg transition-all duration-200 flex items-center space-x-2 no-underline">
                         <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
                             <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 10v6m0 0l-3-3m3 3l3-3m2 8H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z"></path>
                         </svg>
@@ -660,7 +1588,8 @@ async fn generate_config_handler(
             </div>
             <script>
                 document.getElementById('copy-button').addEventListener('click', () => {{
-                    const textToCopy = `{}`;
+                    // textContent gives back the YAML exactly as generated, without the HTML escaping
+                    const textToCopy = document.getElementById('generated-config').textContent;
                     navigator.clipboard.writeText(textToCopy).then(() => {{
                         // Show success feedback
                         const button = document.getElementById('copy-button');
@@ -676,26 +1605,39 @@ async fn generate_config_handler(
                 }});
             </script>
         </div>"#,
-        config_yaml,
-        config_yaml.replace("`", "\\`")
+        html_escape::encode_text(&config_yaml),
+        scan_id
     );
 
-    Html(response_html)
+    (jar, Html(response_html)).into_response()
 }
 
-// Store the generated config temporarily
-static GENERATED_CONFIG: tokio::sync::Mutex<Option<String>> = tokio::sync::Mutex::const_new(None);
+// Query parameters for the config download
+#[derive(Debug, serde::Deserialize)]
+struct DownloadConfigParams {
+    scan_id: Option<String>,
+}
 
 // Config download handler
-async fn download_config_handler(State(app_state): State<AppState>) -> impl IntoResponse {
-    // Check if we have a generated config, otherwise use current config
-    let config_content = {
-        let stored_config = GENERATED_CONFIG.lock().await;
-        if let Some(config) = stored_config.as_ref() {
-            config.clone()
-        } else {
-            generate_config_yaml(&app_state.config, &[]).await
-        }
+async fn download_config_handler(
+    State(app_state): State<AppState>,
+    jar: CookieJar,
+    Query(params): Query<DownloadConfigParams>,
+) -> impl IntoResponse {
+    let (jar, session_id) = discovery_session(jar);
+
+    // Use this session's generated config if there is one, otherwise the current config
+    let generated = match params.scan_id.as_deref() {
+        Some(scan_id) => app_state
+            .discovered_devices
+            .get(scan_id, &session_id)
+            .await
+            .and_then(|scan| scan.generated_config),
+        None => None,
+    };
+    let config_content = match generated {
+        Some(config) => config,
+        None => generate_config_yaml(&app_state.config.get().redacted(), &[]).await,
     };
 
     let headers = [
@@ -706,7 +1648,728 @@ async fn download_config_handler(State(app_state): State<AppState>) -> impl Into
         ),
     ];
 
-    (headers, config_content).into_response()
+    (jar, headers, config_content).into_response()
+}
+
+// Export the configured devices as CSV, JSON, /etc/ethers or an Ansible inventory
+async fn export_devices_handler(
+    State(app_state): State<AppState>,
+    Path(format): Path<String>,
+) -> impl IntoResponse {
+    let Some(format) = ExportFormat::from_name(&format) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!(
+                "Unknown export format '{}'. Use csv, json, ethers or ansible.",
+                format
+            ),
+        )
+            .into_response();
+    };
+
+    match export_devices(&app_state.config.get().devices, format) {
+        Ok(body) => {
+            let disposition = format!("attachment; filename=\"{}\"", format.file_name());
+            (
+                [
+                    (header::CONTENT_TYPE, format.content_type().to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to export devices: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to export devices",
+            )
+                .into_response()
+        }
+    }
+}
+
+// Handler for the device manager page
+async fn devices_page_handler(State(app_state): State<AppState>) -> impl IntoResponse {
+    let new_device_count = app_state.seen_devices.unacknowledged().await.len();
+    let data = json!({
+        "manager": render_device_manager(&app_state, None, None),
+        "new_device_count": new_device_count,
+    });
+
+    match app_state.handlebars.render("devices", &data) {
+        Ok(rendered_html) => Html(rendered_html).into_response(),
+        Err(e) => {
+            eprintln!("Error rendering devices template: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Html("<h1>Error</h1><p>Failed to render devices page.</p>"),
+            )
+                .into_response()
+        }
+    }
+}
+
+// The detail page shows the last week as a timeline and uptime over up to 30 days
+const DETAIL_TIMELINE_DAYS: u64 = 7;
+const DETAIL_TIMELINE_BUCKETS: usize = 84;
+const DETAIL_UPTIME_DAYS: [u64; 3] = [1, 7, 30];
+
+// Handler for a device's detail page: longer history and every wake and shutdown
+async fn device_detail_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    let config = app_state.config.get();
+    let (status, detail, device_name) = match config.find_device(&device_id) {
+        Some(device) => (
+            StatusCode::OK,
+            render_device_detail(&app_state, device).await,
+            device.name.clone(),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!(
+                r#"<div class="bg-gray-800 rounded-2xl p-6 border border-gray-700 text-red-400 font-medium">Device '{}' not found</div>"#,
+                html_escape::encode_text(&device_id)
+            ),
+            "Unknown device".to_string(),
+        ),
+    };
+
+    let new_device_count = app_state.seen_devices.unacknowledged().await.len();
+    let data = json!({
+        "detail": detail,
+        "device_name": device_name,
+        "new_device_count": new_device_count,
+    });
+
+    match app_state.handlebars.render("device", &data) {
+        Ok(rendered_html) => (status, Html(rendered_html)).into_response(),
+        Err(e) => {
+            eprintln!("Error rendering device template: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Html("<h1>Error</h1><p>Failed to render device page.</p>"),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn render_device_detail(app_state: &AppState, device: &Device) -> String {
+    let id = device.device_id();
+    let now = unix_now();
+    let longest = DETAIL_UPTIME_DAYS[DETAIL_UPTIME_DAYS.len() - 1] * DAY_SECONDS;
+    let query = HistoryQuery {
+        device_id: Some(id.clone()),
+        limit: MAX_HISTORY_LIMIT,
+        ..Default::default()
+    };
+    let (samples, changes, actions) = {
+        let id = id.clone();
+        app_state
+            .history
+            .blocking(move |history| {
+                (
+                    history.samples(&id, now.saturating_sub(longest), now + 1),
+                    history.status_changes(&query),
+                    history.actions(&query),
+                )
+            })
+            .await
+    };
+
+    let uptimes: String = DETAIL_UPTIME_DAYS
+        .iter()
+        .map(|days| {
+            let since = now.saturating_sub(days * DAY_SECONDS);
+            let start = samples.partition_point(|sample| sample.at < since);
+            format!(
+                r#"<div class="bg-gray-900 rounded-lg p-3">
+                    <span class="text-gray-400 font-medium text-sm">Uptime {}</span>
+                    <p class="text-white text-lg font-semibold">{}</p>
+                </div>"#,
+                if *days == 1 {
+                    "24h".to_string()
+                } else {
+                    format!("{} days", days)
+                },
+                format_percent(uptime_percent(&samples[start..]))
+            )
+        })
+        .collect();
+
+    let timeline_start = now + 1 - DETAIL_TIMELINE_DAYS * DAY_SECONDS;
+    let buckets = timeline(&samples, timeline_start, now + 1, DETAIL_TIMELINE_BUCKETS);
+
+    let state = app_state.statuses.get(&id);
+    let current = match &state {
+        Some(state) => format!(
+            "{} · {} · changed {}",
+            state.status.as_str(),
+            format_latency(state.latency_ms),
+            state
+                .since
+                .map(|at| format!("{} ago", format_age(now.saturating_sub(at))))
+                .unwrap_or_else(|| "—".to_string())
+        ),
+        None => "Not checked yet".to_string(),
+    };
+
+    let changes: String = changes
+        .iter()
+        .map(|change| {
+            format!(
+                r#"<tr class="border-t border-gray-700"><td class="py-2 pr-4 font-mono text-xs">{}</td><td class="py-2 pr-4">{}</td><td class="py-2 text-gray-400">{}</td></tr>"#,
+                format_local_time(change.at, "%Y-%m-%d %H:%M:%S"),
+                change.status.as_str(),
+                change.previous.map(DeviceStatus::as_str).unwrap_or("—")
+            )
+        })
+        .collect();
+    let actions: String = actions
+        .iter()
+        .map(|action| {
+            let result = if action.success {
+                r#"<span class="text-emerald-400">ok</span>"#.to_string()
+            } else {
+                format!(
+                    r#"<span class="text-red-400">failed</span> <span class="text-gray-400">{}</span>"#,
+                    html_escape::encode_text(action.detail.as_deref().unwrap_or(""))
+                )
+            };
+            format!(
+                r#"<tr class="border-t border-gray-700"><td class="py-2 pr-4 font-mono text-xs">{}</td><td class="py-2 pr-4">{}</td><td class="py-2 pr-4">{}</td><td class="py-2">{}</td></tr>"#,
+                format_local_time(action.at, "%Y-%m-%d %H:%M:%S"),
+                action.action.as_str(),
+                html_escape::encode_text(&action.source),
+                result
+            )
+        })
+        .collect();
+    let empty_row = |columns: usize, text: &str| {
+        format!(
+            r#"<tr><td colspan="{}" class="py-2 text-gray-500">{}</td></tr>"#,
+            columns, text
+        )
+    };
+
+    format!(
+        r#"<div id="device-detail" class="space-y-6">
+            <div class="bg-gray-800 rounded-2xl p-6 border border-gray-700">
+                <a href="/" class="text-sm text-gray-400 hover:text-white">&larr; Dashboard</a>
+                <h2 class="text-2xl font-bold text-white mt-2">{}</h2>
+                <p class="text-gray-400 text-sm mt-1"><span class="font-mono">{}</span> · <span class="font-mono">{}</span> · <span class="font-mono">{}</span></p>
+                <p class="text-white mt-2">{}</p>
+                <div class="grid grid-cols-3 gap-3 mt-4">{}</div>
+            </div>
+            <div class="bg-gray-800 rounded-2xl p-6 border border-gray-700 space-y-2">
+                <h3 class="text-lg font-semibold text-white">Last {} days</h3>
+                {}
+                {}
+            </div>
+            <div class="bg-gray-800 rounded-2xl p-6 border border-gray-700">
+                <h3 class="text-lg font-semibold text-white mb-2">Wake and shutdown attempts</h3>
+                <table class="w-full text-sm text-white text-left">
+                    <thead class="text-gray-400"><tr><th class="py-2 pr-4">Time</th><th class="py-2 pr-4">Action</th><th class="py-2 pr-4">Source</th><th class="py-2">Result</th></tr></thead>
+                    <tbody>{}</tbody>
+                </table>
+            </div>
+            <div class="bg-gray-800 rounded-2xl p-6 border border-gray-700">
+                <h3 class="text-lg font-semibold text-white mb-2">Status changes</h3>
+                <table class="w-full text-sm text-white text-left">
+                    <thead class="text-gray-400"><tr><th class="py-2 pr-4">Time</th><th class="py-2 pr-4">Status</th><th class="py-2">Previous</th></tr></thead>
+                    <tbody>{}</tbody>
+                </table>
+            </div>
+        </div>"#,
+        html_escape::encode_text(&device.name),
+        id,
+        html_escape::encode_text(&device.ip_address),
+        html_escape::encode_text(&device.mac_address),
+        current,
+        uptimes,
+        DETAIL_TIMELINE_DAYS,
+        render_availability_bar(&buckets, "%a %H:%M"),
+        render_sparkline(&buckets),
+        if actions.is_empty() {
+            empty_row(4, "No wake or shutdown recorded")
+        } else {
+            actions
+        },
+        if changes.is_empty() {
+            empty_row(3, "No status changes recorded")
+        } else {
+            changes
+        }
+    )
+}
+
+// The device manager without a form open (used by Cancel)
+async fn device_manager_handler(State(app_state): State<AppState>) -> impl IntoResponse {
+    Html(render_device_manager(&app_state, None, None))
+}
+
+// The device manager with the form filled in for one device
+async fn edit_device_form_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> impl IntoResponse {
+    let device = read_config_file(&app_state.config).ok().and_then(|file| {
+        find_device_index(&file.devices, &device_id).map(|index| file.devices[index].clone())
+    });
+    match device {
+        Some(device) => Html(render_device_manager(
+            &app_state,
+            Some(DeviceFormState {
+                original_id: Some(device.device_id()),
+                device,
+            }),
+            None,
+        ))
+        .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Html(render_device_manager(
+                &app_state,
+                None,
+                Some(Err(EditError::NotFound(device_id).to_string())),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+// Fields of the add/edit device form. Groups and tags are comma-separated.
+#[derive(Debug, serde::Deserialize)]
+struct DeviceForm {
+    #[serde(default)]
+    config_hash: String,
+    // Left empty to use the id generated from the MAC address
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    mac_address: String,
+    #[serde(default)]
+    ip_address: String,
+    #[serde(default)]
+    groups: String,
+    #[serde(default)]
+    tags: String,
+}
+
+impl DeviceForm {
+    fn device(&self) -> Device {
+        let list = |text: &str| {
+            text.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        Device {
+            id: Some(self.id.trim().to_string()).filter(|id| !id.is_empty()),
+            name: self.name.trim().to_string(),
+            mac_address: self.mac_address.trim().to_string(),
+            ip_address: self.ip_address.trim().to_string(),
+            groups: list(&self.groups),
+            tags: list(&self.tags),
+            // Not editable here; the editor leaves it untouched in the file
+            shutdown_command: Vec::new(),
+        }
+    }
+}
+
+// Form fields for the edit buttons that only carry the file hash and a position
+#[derive(Debug, serde::Deserialize)]
+struct DeviceActionForm {
+    #[serde(default)]
+    config_hash: String,
+    #[serde(default)]
+    position: usize,
+}
+
+// Add a device to the config file
+async fn add_device_handler(
+    State(app_state): State<AppState>,
+    Form(form): Form<DeviceForm>,
+) -> impl IntoResponse {
+    let device = form.device();
+    let name = device.name.clone();
+    let form_state = DeviceFormState {
+        original_id: None,
+        device: device.clone(),
+    };
+    apply_device_edit_response(
+        &app_state,
+        form.config_hash,
+        DeviceEdit::Add(device),
+        Some(form_state),
+        format!("Added {}", name),
+    )
+    .await
+}
+
+// Change a device in the config file
+async fn update_device_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+    Form(form): Form<DeviceForm>,
+) -> impl IntoResponse {
+    let device = form.device();
+    let form_state = DeviceFormState {
+        original_id: Some(device_id.clone()),
+        device: device.clone(),
+    };
+    let message = format!("Saved {}", device.name);
+    apply_device_edit_response(
+        &app_state,
+        form.config_hash,
+        DeviceEdit::Update {
+            id: device_id,
+            device,
+        },
+        Some(form_state),
+        message,
+    )
+    .await
+}
+
+// Remove a device from the config file
+async fn delete_device_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+    Form(form): Form<DeviceActionForm>,
+) -> impl IntoResponse {
+    let message = format!("Removed {}", device_label(&app_state, &device_id));
+    apply_device_edit_response(
+        &app_state,
+        form.config_hash,
+        DeviceEdit::Delete { id: device_id },
+        None,
+        message,
+    )
+    .await
+}
+
+// Move a device to another position in the list
+async fn move_device_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+    Form(form): Form<DeviceActionForm>,
+) -> impl IntoResponse {
+    let message = format!("Moved {}", device_label(&app_state, &device_id));
+    apply_device_edit_response(
+        &app_state,
+        form.config_hash,
+        DeviceEdit::Move {
+            id: device_id,
+            position: form.position,
+        },
+        None,
+        message,
+    )
+    .await
+}
+
+// Name of the device for messages, falling back to the id
+fn device_label(app_state: &AppState, device_id: &str) -> String {
+    app_state
+        .config
+        .get()
+        .find_device(device_id)
+        .map(|device| device.name.clone())
+        .unwrap_or_else(|| device_id.to_string())
+}
+
+// Write the edit, reload the running config from the file and re-render the manager.
+// On failure the form keeps what was typed so it can be corrected.
+async fn apply_device_edit_response(
+    app_state: &AppState,
+    config_hash: String,
+    edit: DeviceEdit,
+    form: Option<DeviceFormState>,
+    success_message: String,
+) -> axum::response::Response {
+    // Reading, checking and writing the file, the write lock and the reload all block
+    let app_state = app_state.clone();
+    tokio::task::spawn_blocking(move || {
+        device_edit_response(&app_state, &config_hash, edit, form, success_message)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Device manager: edit failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+fn device_edit_response(
+    app_state: &AppState,
+    config_hash: &str,
+    edit: DeviceEdit,
+    form: Option<DeviceFormState>,
+    success_message: String,
+) -> axum::response::Response {
+    match apply_device_edit(&app_state.config, config_hash, &edit) {
+        Ok(_) => {
+            println!("Device manager: {}", success_message);
+            reload_and_report(&app_state.config, &app_state.events, "device manager");
+            Html(render_device_manager(
+                app_state,
+                None,
+                Some(Ok(success_message)),
+            ))
+            .into_response()
+        }
+        Err(e) => {
+            eprintln!("Device manager: edit rejected: {}", e);
+            let (status, form) = match &e {
+                EditError::Conflict => (StatusCode::CONFLICT, None),
+                EditError::NotFound(_) => (StatusCode::NOT_FOUND, None),
+                EditError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, form),
+                EditError::NoFile | EditError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, form),
+            };
+            let message = match &e {
+                // Positions in the edited text mean nothing to the user; the field path does
+                EditError::Invalid(issues) => issues
+                    .iter()
+                    .map(|issue| format!("{}: {}", issue.path, issue.message))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => e.to_string(),
+            };
+            (
+                status,
+                Html(render_device_manager(app_state, form, Some(Err(message)))),
+            )
+                .into_response()
+        }
+    }
+}
+
+// What the add/edit form shows; `original_id` is set when editing an existing device
+struct DeviceFormState {
+    original_id: Option<String>,
+    device: Device,
+}
+
+// The device list with its controls, and the add/edit form. Everything comes from the file
+// on disk so the hash sent back with an edit matches what the user saw.
+fn render_device_manager(
+    app_state: &AppState,
+    form: Option<DeviceFormState>,
+    notice: Option<Result<String, String>>,
+) -> String {
+    let file = read_config_file(&app_state.config);
+    let (devices, hash, source, editable) = match &file {
+        Ok(file) => (
+            file.devices.clone(),
+            file.hash.clone(),
+            file.path.display().to_string(),
+            true,
+        ),
+        // Fall back to the running config, read-only
+        Err(_) => (
+            app_state.config.get().devices.clone(),
+            String::new(),
+            app_state
+                .config
+                .path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "the running configuration".to_string()),
+            false,
+        ),
+    };
+
+    let mut html = String::from(r#"<div id="device-manager" class="space-y-6">"#);
+
+    let notice = match (&file, notice) {
+        (_, Some(notice)) => Some(notice),
+        (Err(EditError::NoFile), None) => Some(Err(
+            "The configuration was not loaded from a file, so devices cannot be edited here."
+                .to_string(),
+        )),
+        (Err(e), None) => Some(Err(format!(
+            "The config file cannot be edited until it loads again:\n{}",
+            e
+        ))),
+        (Ok(_), None) => None,
+    };
+    match notice {
+        Some(Ok(message)) => html.push_str(&format!(
+            r#"<div id="device-manager-notice" class="p-4 rounded-xl border border-emerald-500/40 bg-emerald-500/10 text-emerald-300 font-medium">{}</div>"#,
+            html_escape::encode_text(&message)
+        )),
+        Some(Err(message)) => html.push_str(&format!(
+            r#"<div id="device-manager-notice" role="alert" class="p-4 rounded-xl border border-red-500/40 bg-red-500/10"><pre class="text-sm text-red-300 font-mono whitespace-pre-wrap">{}</pre></div>"#,
+            html_escape::encode_text(&message)
+        )),
+        None => {}
+    }
+
+    html.push_str(&format!(
+        r#"<div class="glass rounded-2xl card-padding">
+            <h2 class="text-2xl sm:text-3xl font-bold text-white mb-2">Manage Devices</h2>
+            <p class="text-white/70 text-sm mb-6">Changes are written to <code class="bg-white/20 text-white px-2 py-1 rounded-md font-mono text-xs">{}</code> and take effect immediately.</p>"#,
+        html_escape::encode_text(&source)
+    ));
+
+    if devices.is_empty() {
+        html.push_str(r#"<p class="text-white/70">No devices configured yet.</p>"#);
+    } else {
+        html.push_str(
+            r#"<div class="overflow-x-auto"><table class="w-full text-sm text-left">
+                <thead class="text-gray-400 border-b border-gray-700">
+                    <tr><th class="py-2 pr-4">Name</th><th class="py-2 pr-4">ID</th><th class="py-2 pr-4">MAC Address</th><th class="py-2 pr-4">IP Address</th><th class="py-2 pr-4">Groups</th><th class="py-2 pr-4">Tags</th><th class="py-2"></th></tr>
+                </thead>
+                <tbody>"#,
+        );
+        for (index, device) in devices.iter().enumerate() {
+            let id = device.device_id();
+            let mut actions = String::new();
+            if editable {
+                let move_button = |position: usize, label: &str, title: &str| {
+                    format!(
+                        r##"<button hx-post="/devices/{}/move" hx-vals='{{"position": {}, "config_hash": "{}"}}' hx-target="#device-manager" hx-swap="outerHTML" title="{}" class="bg-gray-700 hover:bg-gray-600 text-white py-1 px-2 rounded-lg">{}</button>"##,
+                        id, position, hash, title, label
+                    )
+                };
+                if index > 0 {
+                    actions.push_str(&move_button(index - 1, "↑", "Move up"));
+                }
+                if index + 1 < devices.len() {
+                    actions.push_str(&move_button(index + 1, "↓", "Move down"));
+                }
+                actions.push_str(&format!(
+                    r##"<button hx-get="/devices/{}/edit" hx-target="#device-manager" hx-swap="outerHTML" class="bg-gray-700 hover:bg-gray-600 text-white py-1 px-3 rounded-lg">Edit</button>
+                    <button hx-post="/devices/{}/delete" hx-vals='{{"config_hash": "{}"}}' hx-confirm="{}" hx-target="#device-manager" hx-swap="outerHTML" class="bg-red-700 hover:bg-red-600 text-white py-1 px-3 rounded-lg">Delete</button>"##,
+                    id,
+                    id,
+                    hash,
+                    html_escape::encode_double_quoted_attribute(&format!(
+                        "Remove {} from the config?",
+                        device.name
+                    )),
+                ));
+            }
+            html.push_str(&format!(
+                r#"<tr class="border-b border-gray-800 text-white">
+                    <td class="py-2 pr-4 font-medium">{}</td>
+                    <td class="py-2 pr-4 font-mono text-white/70">{}</td>
+                    <td class="py-2 pr-4 font-mono">{}</td>
+                    <td class="py-2 pr-4 font-mono">{}</td>
+                    <td class="py-2 pr-4 text-white/70">{}</td>
+                    <td class="py-2 pr-4 text-white/70">{}</td>
+                    <td class="py-2"><div class="flex gap-2 justify-end">{}</div></td>
+                </tr>"#,
+                html_escape::encode_text(&device.name),
+                id,
+                html_escape::encode_text(&device.mac_address),
+                html_escape::encode_text(&device.ip_address),
+                html_escape::encode_text(&device.groups.join(", ")),
+                html_escape::encode_text(&device.tags.join(", ")),
+                actions
+            ));
+        }
+        html.push_str("</tbody></table></div>");
+    }
+    html.push_str("</div>");
+
+    if editable {
+        html.push_str(&render_device_form(form, &hash));
+    }
+
+    html.push_str("</div>");
+    html
+}
+
+fn render_device_form(form: Option<DeviceFormState>, hash: &str) -> String {
+    let form = form.unwrap_or(DeviceFormState {
+        original_id: None,
+        device: Device::default(),
+    });
+    let (title, action, button, cancel) = match &form.original_id {
+        Some(id) => (
+            format!("Edit {}", html_escape::encode_text(&form.device.name)),
+            format!(
+                "/devices/{}",
+                html_escape::encode_double_quoted_attribute(id)
+            ),
+            "Save Changes",
+            r##"<button type="button" hx-get="/devices/manager" hx-target="#device-manager" hx-swap="outerHTML" class="bg-white/20 hover:bg-white/30 text-white font-semibold py-2 px-4 rounded-lg">Cancel</button>"##,
+        ),
+        None => (
+            "Add Device".to_string(),
+            "/devices".to_string(),
+            "Add Device",
+            "",
+        ),
+    };
+    let field = |name: &str, label: &str, value: &str, placeholder: &str| {
+        format!(
+            r#"<label class="block">
+                <span class="text-gray-400 text-sm font-medium">{}</span>
+                <input type="text" name="{}" value="{}" placeholder="{}" class="mt-1 w-full bg-gray-900 border border-gray-600 rounded-lg px-3 py-2 text-white font-mono text-sm">
+            </label>"#,
+            label,
+            name,
+            html_escape::encode_double_quoted_attribute(value),
+            placeholder
+        )
+    };
+    let device = &form.device;
+
+    format!(
+        r##"<form id="device-form" hx-post="{}" hx-target="#device-manager" hx-swap="outerHTML" class="glass rounded-2xl card-padding">
+            <h3 class="text-xl font-bold text-white mb-4">{}</h3>
+            <input type="hidden" name="config_hash" value="{}">
+            <div class="grid grid-cols-1 md:grid-cols-3 gap-4 mb-4">{}{}{}{}{}{}</div>
+            <div class="flex gap-3">
+                <button type="submit" class="bg-emerald-600 hover:bg-emerald-500 text-white font-semibold py-2 px-4 rounded-lg">{}</button>
+                {}
+            </div>
+        </form>"##,
+        action,
+        title,
+        hash,
+        field("name", "Name", &device.name, "Desktop PC"),
+        field(
+            "id",
+            "ID (optional)",
+            device.id.as_deref().unwrap_or(""),
+            "generated from the MAC address"
+        ),
+        field(
+            "mac_address",
+            "MAC Address",
+            &device.mac_address,
+            "AA:BB:CC:DD:EE:FF"
+        ),
+        field(
+            "ip_address",
+            "IP Address or Hostname",
+            &device.ip_address,
+            "192.168.1.100"
+        ),
+        field(
+            "groups",
+            "Groups (comma-separated)",
+            &device.groups.join(", "),
+            "office, servers"
+        ),
+        field(
+            "tags",
+            "Tags (comma-separated)",
+            &device.tags.join(", "),
+            "linux"
+        ),
+        button,
+        cancel
+    )
 }
 
 // Discovered device structure
@@ -718,8 +2381,83 @@ pub struct DiscoveredDevice {
     pub status: String,
 }
 
+// Apply per-request scan settings on top of the configured discovery defaults.
+// Unknown keys and unparsable values are ignored so a bad field never aborts a scan.
+pub fn apply_scan_overrides(
+    base: &DiscoveryConfig,
+    params: &[(String, String)],
+) -> DiscoveryConfig {
+    let mut options = base.clone();
+
+    for (key, value) in params {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match key.as_str() {
+            "max_concurrency" => {
+                if let Ok(v) = value.parse() {
+                    options.max_concurrency = v;
+                }
+            }
+            "packets_per_second" => {
+                if let Ok(v) = value.parse() {
+                    options.packets_per_second = v;
+                }
+            }
+            "host_timeout_seconds" => {
+                if let Ok(v) = value.parse() {
+                    options.host_timeout_seconds = v;
+                }
+            }
+            "retries" => {
+                if let Ok(v) = value.parse() {
+                    options.retries = v;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Keep request overrides within sane bounds
+    options.max_concurrency = options.max_concurrency.clamp(1, MAX_SCAN_CONCURRENCY);
+    options.host_timeout_seconds = options.host_timeout_seconds.clamp(1, 30);
+    options.retries = options.retries.min(5);
+
+    options
+}
+
+// Shared packets-per-second limiter for a single scan
+struct ProbeRateLimiter {
+    interval: Option<Mutex<tokio::time::Interval>>,
+}
+
+impl ProbeRateLimiter {
+    fn new(packets_per_second: u32) -> Self {
+        if packets_per_second == 0 {
+            return Self { interval: None };
+        }
+        let period = Duration::from_secs_f64(1.0 / packets_per_second as f64);
+        let mut interval = tokio::time::interval(period);
+        // Never burst to catch up on missed ticks
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            interval: Some(Mutex::new(interval)),
+        }
+    }
+
+    async fn acquire(&self) {
+        if let Some(interval) = &self.interval {
+            interval.lock().await.tick().await;
+        }
+    }
+}
+
 // Network discovery function
-async fn discover_network_devices(selected_interfaces: Option<Vec<String>>) -> Vec<DiscoveredDevice> {
+pub async fn discover_network_devices(
+    selected_interfaces: Option<Vec<String>>,
+    options: &DiscoveryConfig,
+) -> Vec<DiscoveredDevice> {
     let mut discovered_devices = Vec::new();
 
     // Get network interfaces
@@ -736,6 +2474,9 @@ async fn discover_network_devices(selected_interfaces: Option<Vec<String>>) -> V
         return discovered_devices;
     }
 
+    // One limiter for the whole scan so the rate holds across interfaces
+    let limiter = ProbeRateLimiter::new(options.packets_per_second);
+
     let mut scanned_interfaces = 0;
     for interface in interfaces {
         // Filter by selected interfaces if provided
@@ -753,14 +2494,21 @@ async fn discover_network_devices(selected_interfaces: Option<Vec<String>>) -> V
             // Use a common subnet assumption for simplicity
             let network_str = format!("{}/24", addr.ip());
             if let Ok(network) = network_str.parse::<Ipv4Net>() {
-                println!("Scanning network: {} on interface {}", network, interface.name);
+                println!(
+                    "Scanning network: {} on interface {}",
+                    network, interface.name
+                );
                 scanned_interfaces += 1;
 
                 // Scan the network range
-                let scan_results = scan_network_range(network).await;
+                let scan_results =
+                    scan_network_range(network, options, &limiter, INTERFACE_SCAN_MAX_HOSTS).await;
                 discovered_devices.extend(scan_results);
             } else {
-                eprintln!("Warning: Failed to parse network for interface {}: {}", interface.name, network_str);
+                eprintln!(
+                    "Warning: Failed to parse network for interface {}: {}",
+                    interface.name, network_str
+                );
             }
         }
     }
@@ -772,50 +2520,83 @@ async fn discover_network_devices(selected_interfaces: Option<Vec<String>>) -> V
     discovered_devices
 }
 
-// Scan network range function
-async fn scan_network_range(network: Ipv4Net) -> Vec<DiscoveredDevice> {
-    let mut devices = Vec::new();
-    let mut scan_futures = Vec::new();
-
-    // Limit scan to reasonable range (e.g., first 254 hosts)
-    let host_count = std::cmp::min(network.hosts().count(), 254);
-
-    for host_ip in network.hosts().take(host_count) {
-        let ip_str = host_ip.to_string();
-        scan_futures.push(scan_single_host(ip_str));
+// Interface scans assume a /24
+const INTERFACE_SCAN_MAX_HOSTS: usize = 254;
+
+// Largest range `wololo scan --cidr` will sweep (a /20)
+pub const CIDR_SCAN_MAX_HOSTS: usize = 4094;
+
+// Scan an explicit range instead of the interfaces' /24s
+pub async fn scan_cidr(
+    network: Ipv4Net,
+    options: &DiscoveryConfig,
+) -> Result<Vec<DiscoveredDevice>, String> {
+    let host_count = network.hosts().count();
+    if host_count > CIDR_SCAN_MAX_HOSTS {
+        return Err(format!(
+            "{} has {} hosts; ranges larger than {} hosts (a /20) are not scanned",
+            network, host_count, CIDR_SCAN_MAX_HOSTS
+        ));
     }
 
-    let results = join_all(scan_futures).await;
+    let limiter = ProbeRateLimiter::new(options.packets_per_second);
+    Ok(scan_network_range(network, options, &limiter, host_count).await)
+}
 
-    for result in results {
-        if let Some(device) = result {
-            devices.push(device);
-        }
-    }
+// Scan network range function
+async fn scan_network_range(
+    network: Ipv4Net,
+    options: &DiscoveryConfig,
+    limiter: &ProbeRateLimiter,
+    max_hosts: usize,
+) -> Vec<DiscoveredDevice> {
+    // Limit scan to reasonable range (e.g., first 254 hosts)
+    let host_count = std::cmp::min(network.hosts().count(), max_hosts);
+
+    // At most max_concurrency hosts (and their ping/arp/nslookup processes) in flight
+    let mut devices: Vec<DiscoveredDevice> = stream::iter(network.hosts().take(host_count))
+        .map(|host_ip| scan_single_host(host_ip.to_string(), options, limiter))
+        .buffer_unordered(options.concurrency())
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+
+    // buffer_unordered yields in completion order; keep results in address order
+    devices.sort_by_key(|device| device.ip_address.parse::<Ipv4Addr>().ok());
 
     devices
 }
 
 // Scan single host function
-async fn scan_single_host(ip: String) -> Option<DiscoveredDevice> {
-    // Ping the host
-    let ping_result = Command::new("ping")
-        .args(&["-c", "1", "-W", "1", &ip])
-        .output()
-        .await;
-
-    if let Ok(output) = ping_result {
-        if output.status.success() {
-            // Host is reachable, try to get hostname and MAC
-            let hostname = get_hostname(&ip).await;
-            let mac_address = get_mac_address(&ip).await;
-
-            return Some(DiscoveredDevice {
-                ip_address: ip,
-                mac_address,
-                hostname,
-                status: "Online".to_string(),
-            });
+async fn scan_single_host(
+    ip: String,
+    options: &DiscoveryConfig,
+    limiter: &ProbeRateLimiter,
+) -> Option<DiscoveredDevice> {
+    let timeout = options.host_timeout_seconds.max(1).to_string();
+
+    for _attempt in 0..=options.retries {
+        limiter.acquire().await;
+
+        // Ping the host
+        let ping_result = Command::new("ping")
+            .args(["-c", "1", "-W", &timeout, &ip])
+            .output()
+            .await;
+
+        if let Ok(output) = ping_result {
+            if output.status.success() {
+                // Host is reachable, try to get hostname and MAC
+                let hostname = get_hostname(&ip).await;
+                let mac_address = get_mac_address(&ip).await;
+
+                return Some(DiscoveredDevice {
+                    ip_address: ip,
+                    mac_address,
+                    hostname,
+                    status: "Online".to_string(),
+                });
+            }
         }
     }
 
@@ -841,7 +2622,7 @@ async fn get_hostname(ip: &str) -> Option<String> {
 // Get MAC address function
 async fn get_mac_address(ip: &str) -> Option<String> {
     // Try to get MAC from ARP table
-    if let Ok(output) = Command::new("arp").args(&["-n", ip]).output().await {
+    if let Ok(output) = Command::new("arp").args(["-n", ip]).output().await {
         if output.status.success() {
             let output_str = String::from_utf8_lossy(&output.stdout);
             let mac_regex = Regex::new(r"([0-9a-fA-F]{2}[:-]){5}([0-9a-fA-F]{2})").unwrap();
@@ -854,22 +2635,149 @@ async fn get_mac_address(ip: &str) -> Option<String> {
     None
 }
 
+// Result of comparing discovered devices with the configured ones, matched by MAC
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DiscoveryDiff {
+    // Discovered devices whose MAC is not configured (or that have no MAC at all)
+    pub new_devices: Vec<DiscoveredDevice>,
+    // Configured devices seen at the same IP and under the same name
+    pub unchanged: Vec<Device>,
+    // Configured devices seen with a different IP or hostname
+    pub changed: Vec<DeviceChange>,
+    // Configured devices that did not show up in the results
+    pub not_seen: Vec<Device>,
+}
+
+impl DiscoveryDiff {
+    // True when nothing was discovered at all
+    pub fn is_empty(&self) -> bool {
+        self.new_devices.is_empty() && self.unchanged.is_empty() && self.changed.is_empty()
+    }
+}
+
+// A configured device together with what discovery saw for it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceChange {
+    pub device: Device,
+    pub discovered: DiscoveredDevice,
+    pub new_ip_address: Option<String>,
+    pub new_name: Option<String>,
+}
+
+// Changes to apply to a configured device, identified by MAC address
+#[derive(Debug, Clone, Default)]
+pub struct DeviceUpdate {
+    pub mac_address: String,
+    pub ip_address: Option<String>,
+    pub name: Option<String>,
+}
+
+// Canonical form used to compare MAC addresses ("AA-BB-..." == "aa:bb:...")
+pub fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_ascii_lowercase().replace('-', ":")
+}
+
+// Split discovered devices into new, unchanged, changed and not-seen relative to the config
+pub fn diff_discovered_devices(config: &Config, discovered: &[DiscoveredDevice]) -> DiscoveryDiff {
+    let configured: HashMap<String, &Device> = config
+        .devices
+        .iter()
+        .map(|device| (normalize_mac(&device.mac_address), device))
+        .collect();
+
+    let mut diff = DiscoveryDiff::default();
+    let mut seen_macs = std::collections::HashSet::new();
+
+    for found in discovered {
+        let mac = match &found.mac_address {
+            Some(mac) => normalize_mac(mac),
+            None => {
+                diff.new_devices.push(found.clone());
+                continue;
+            }
+        };
+
+        // The same host can show up once per scanned interface
+        if !seen_macs.insert(mac.clone()) {
+            continue;
+        }
+
+        match configured.get(&mac) {
+            Some(device) => {
+                let new_ip_address =
+                    (found.ip_address != device.ip_address).then(|| found.ip_address.clone());
+                let new_name = found
+                    .hostname
+                    .as_ref()
+                    .filter(|hostname| !hostname.eq_ignore_ascii_case(&device.name))
+                    .cloned();
+
+                if new_ip_address.is_none() && new_name.is_none() {
+                    diff.unchanged.push((*device).clone());
+                } else {
+                    diff.changed.push(DeviceChange {
+                        device: (*device).clone(),
+                        discovered: found.clone(),
+                        new_ip_address,
+                        new_name,
+                    });
+                }
+            }
+            None => diff.new_devices.push(found.clone()),
+        }
+    }
+
+    diff.not_seen = config
+        .devices
+        .iter()
+        .filter(|device| !seen_macs.contains(&normalize_mac(&device.mac_address)))
+        .cloned()
+        .collect();
+
+    diff
+}
+
 // Generate config YAML function
 pub async fn generate_config_yaml(
     current_config: &crate::config::Config,
     selected_devices: &[DiscoveredDevice],
+) -> String {
+    generate_config_yaml_with_updates(current_config, selected_devices, &[]).await
+}
+
+// Generate config YAML with selected additions and updates to known devices applied
+pub async fn generate_config_yaml_with_updates(
+    current_config: &crate::config::Config,
+    selected_devices: &[DiscoveredDevice],
+    updates: &[DeviceUpdate],
 ) -> String {
     let mut updated_config = current_config.clone();
 
-    let existing_macs: std::collections::HashSet<String> = updated_config
+    for update in updates {
+        let mac = normalize_mac(&update.mac_address);
+        if let Some(device) = updated_config
+            .devices
+            .iter_mut()
+            .find(|device| normalize_mac(&device.mac_address) == mac)
+        {
+            if let Some(ip_address) = &update.ip_address {
+                device.ip_address = ip_address.clone();
+            }
+            if let Some(name) = &update.name {
+                device.name = name.clone();
+            }
+        }
+    }
+
+    let mut existing_macs: std::collections::HashSet<String> = updated_config
         .devices
         .iter()
-        .map(|d| d.mac_address.clone())
+        .map(|d| normalize_mac(&d.mac_address))
         .collect();
 
     for device in selected_devices {
         if let Some(mac_address) = &device.mac_address {
-            if !existing_macs.contains(mac_address) {
+            if existing_macs.insert(normalize_mac(mac_address)) {
                 let new_device = crate::config::Device {
                     name: device
                         .hostname
@@ -877,6 +2785,7 @@ pub async fn generate_config_yaml(
                         .unwrap_or_else(|| format!("New-Device-{}", mac_address.replace(":", ""))),
                     mac_address: mac_address.clone(),
                     ip_address: device.ip_address.clone(),
+                    ..Default::default()
                 };
                 updated_config.devices.push(new_device);
             }
@@ -899,16 +2808,262 @@ pub async fn generate_config_yaml(
     yaml_string
 }
 
+// API: devices background discovery has flagged as new and nobody has dismissed yet
+async fn new_devices_api_handler(State(app_state): State<AppState>) -> impl IntoResponse {
+    let devices = app_state.seen_devices.unacknowledged().await;
+    Json(json!({
+        "count": devices.len(),
+        "devices": devices,
+    }))
+}
+
+// Query parameters for acknowledging new devices
+#[derive(Debug, serde::Deserialize)]
+struct AcknowledgeParams {
+    mac_address: Option<String>,
+}
+
+// API: dismiss the alert for one MAC address, or for all new devices when none is given
+async fn acknowledge_new_devices_handler(
+    State(app_state): State<AppState>,
+    Query(params): Query<AcknowledgeParams>,
+) -> impl IntoResponse {
+    let acknowledged = app_state
+        .seen_devices
+        .acknowledge(params.mac_address.as_deref())
+        .await;
+    Json(json!({ "acknowledged": acknowledged }))
+}
+
+// Query parameters for the live event stream
+#[derive(Debug, Default, serde::Deserialize)]
+struct EventStreamParams {
+    format: Option<String>,
+}
+
+// Server-Sent Events: every event on the bus, either as HTML fragments named after the
+// element they replace (for the dashboard's sse-swap) or as JSON named after the event type
+async fn event_stream_handler(
+    State(app_state): State<AppState>,
+    Query(params): Query<EventStreamParams>,
+) -> Response {
+    let json_format = match params.format.as_deref() {
+        None | Some("html") => false,
+        Some("json") => true,
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format '{}'. Use html or json.", other),
+            )
+                .into_response()
+        }
+    };
+
+    let receiver = app_state.events.subscribe();
+    let stream = stream::unfold(
+        (app_state, receiver),
+        move |(app_state, mut receiver)| async move {
+            loop {
+                let sse_event = match receiver.recv().await {
+                    Ok(event) if json_format => json_sse_event(&event),
+                    Ok(event) => html_sse_event(&app_state, &event).await,
+                    // Events were dropped; tell the client to reload what it shows
+                    Err(RecvError::Lagged(missed)) if json_format => Some(
+                        SseEvent::default()
+                            .event("lagged")
+                            .data(json!({ "type": "lagged", "missed": missed }).to_string()),
+                    ),
+                    Err(RecvError::Lagged(_)) => {
+                        Some(SseEvent::default().event("refresh").data(""))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                if let Some(sse_event) = sse_event {
+                    return Some((Ok::<_, Infallible>(sse_event), (app_state, receiver)));
+                }
+            }
+        },
+    );
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn json_sse_event(event: &Event) -> Option<SseEvent> {
+    SseEvent::default()
+        .event(event.kind())
+        .json_data(event)
+        .ok()
+}
+
+async fn html_sse_event(app_state: &AppState, event: &Event) -> Option<SseEvent> {
+    match event {
+        Event::StatusChanged { change } => {
+            let config = app_state.config.get();
+            let device = config.find_device(&change.device_id)?;
+            let latency_ms = app_state
+                .statuses
+                .get(&change.device_id)
+                .and_then(|state| state.latency_ms);
+            let history = load_card_history(app_state, Some(change.device_id.clone())).await;
+            Some(
+                SseEvent::default()
+                    .event(format!("device-{}", change.device_id))
+                    .data(render_device_card(
+                        app_state,
+                        device,
+                        change.status,
+                        latency_ms,
+                        &history,
+                    )),
+            )
+        }
+        Event::DeviceAction { action } => Some(
+            SseEvent::default()
+                .event(format!("activity-{}", action.device_id))
+                .data(render_activity(app_state, &action.device_id, Some(action))),
+        ),
+        Event::NewDevice { .. } => {
+            let count = app_state.seen_devices.unacknowledged().await.len();
+            Some(
+                SseEvent::default()
+                    .event("new-devices")
+                    .data(render_new_device_badge(count)),
+            )
+        }
+        // The device list may have changed, so the dashboard reloads all cards
+        Event::ConfigReloaded { .. } => Some(SseEvent::default().event("refresh").data("")),
+        Event::ConfigReloadFailed { .. } | Event::WakeUnverified { .. } => None,
+    }
+}
+
+// Same badge as the one in index.html's navigation
+fn render_new_device_badge(count: usize) -> String {
+    if count == 0 {
+        return String::new();
+    }
+    format!(
+        r#"<span id="new-device-badge" title="New devices detected on the network" class="bg-amber-500 text-white text-xs px-2 py-0.5 rounded-full font-semibold">{} new</span>"#,
+        count
+    )
+}
+
+// Query parameters shared by the history endpoints
+#[derive(Debug, serde::Deserialize)]
+struct HistoryParams {
+    // Device id or name
+    device: Option<String>,
+    // Unix seconds or RFC 3339
+    since: Option<String>,
+    until: Option<String>,
+    // "wake" or "shutdown"; actions only
+    action: Option<String>,
+    limit: Option<usize>,
+}
+
+impl HistoryParams {
+    fn query(&self, config: &Config) -> Result<HistoryQuery, String> {
+        let time = |name: &str, value: &Option<String>| match value {
+            None => Ok(None),
+            Some(value) => parse_timestamp(value).map(Some).ok_or_else(|| {
+                format!(
+                    "Invalid {} '{}'. Use Unix seconds or an RFC 3339 time.",
+                    name, value
+                )
+            }),
+        };
+        let action = match &self.action {
+            None => None,
+            Some(action) => Some(
+                ActionKind::from_name(action)
+                    .ok_or_else(|| format!("Unknown action '{}'. Use wake or shutdown.", action))?,
+            ),
+        };
+
+        Ok(HistoryQuery {
+            // Devices removed from the config keep their history under their old id
+            device_id: self.device.as_ref().map(|device| {
+                config
+                    .find_device(device)
+                    .map(Device::device_id)
+                    .unwrap_or_else(|| device.clone())
+            }),
+            since: time("since", &self.since)?,
+            until: time("until", &self.until)?,
+            action,
+            limit: self.limit.unwrap_or(0),
+        })
+    }
+}
+
+// API: recorded status changes, newest first
+async fn status_history_handler(
+    State(app_state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+) -> impl IntoResponse {
+    match params.query(&app_state.config.get()) {
+        Ok(query) => {
+            let changes = app_state
+                .history
+                .blocking(move |history| history.status_changes(&query))
+                .await;
+            Json(json!({
+                "count": changes.len(),
+                "status_changes": changes,
+            }))
+            .into_response()
+        }
+        Err(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+    }
+}
+
+// API: recorded wake and shutdown actions, newest first
+async fn action_history_handler(
+    State(app_state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+) -> impl IntoResponse {
+    match params.query(&app_state.config.get()) {
+        Ok(query) => {
+            let actions = app_state
+                .history
+                .blocking(move |history| history.actions(&query))
+                .await;
+            Json(json!({
+                "count": actions.len(),
+                "actions": actions,
+            }))
+            .into_response()
+        }
+        Err(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+    }
+}
+
 // Helper function to parse MAC address from string to MacAddr
 fn parse_mac_address(mac_str: &str) -> Result<MacAddr, String> {
     MacAddr::from_str(mac_str).map_err(|e| format!("Failed to parse MAC address: {}", e))
 }
 
+// Devices may be configured by hostname; wake packets need the IPv4 address behind it
+async fn resolve_ipv4(host: &str) -> Result<Ipv4Addr, Box<dyn std::error::Error>> {
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return Ok(ip);
+    }
+
+    tokio::net::lookup_host((host, 0))
+        .await?
+        .find_map(|addr| match addr.ip() {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        })
+        .ok_or_else(|| format!("{} has no IPv4 address", host).into())
+}
+
 // Helper function to calculate broadcast address from IP address
-fn calculate_broadcast_address(
+async fn calculate_broadcast_address(
     ip_str: &str,
 ) -> Result<std::net::IpAddr, Box<dyn std::error::Error>> {
-    let ip: Ipv4Addr = ip_str.parse()?;
+    let ip = resolve_ipv4(ip_str).await?;
 
     // For simplicity, assume a /24 network (255.255.255.0 subnet mask)
     // In a real implementation, you might want to detect the actual subnet mask
@@ -925,14 +3080,67 @@ pub fn app_router(app_state: AppState) -> Router {
     Router::new()
         .route("/", get(root_handler))
         .route("/hello", get(hello_handler))
+        .route(
+            "/devices",
+            get(devices_page_handler).post(add_device_handler),
+        )
+        .route("/devices/manager", get(device_manager_handler))
+        .route(
+            "/devices/:device_id",
+            get(device_detail_handler).post(update_device_handler),
+        )
+        .route("/devices/:device_id/edit", get(edit_device_form_handler))
+        .route("/devices/:device_id/delete", post(delete_device_handler))
+        .route("/devices/:device_id/move", post(move_device_handler))
         .route("/discovery", get(discovery_handler))
         .route("/discovery/interfaces", get(discovery_interfaces_handler))
         .route("/discovery/scan", post(discovery_scan_handler))
+        .route(
+            "/discovery/import-dhcp",
+            post(discovery_import_dhcp_handler),
+        )
+        .route("/discovery/import", post(discovery_import_file_handler))
         .route("/discovery/generate-config", post(generate_config_handler))
         .route("/discovery/download-config", get(download_config_handler))
-        .route("/wake/:device_name", post(wake_device_handler))
-        .route("/ping/:device_name", get(ping_device_handler))
+        .route(
+            "/api/v1/devices/export/:format",
+            get(export_devices_handler),
+        )
+        .route(
+            "/api/v1/discovery/new-devices",
+            get(new_devices_api_handler),
+        )
+        .route("/api/v1/history/status", get(status_history_handler))
+        .route("/api/v1/history/actions", get(action_history_handler))
+        .route("/api/v1/events", get(event_stream_handler))
+        .route("/api/v1/ws", get(websocket_handler))
+        .route(
+            "/api/v1/discovery/new-devices/acknowledge",
+            post(acknowledge_new_devices_handler),
+        )
+        .route("/wake/:device_id", post(wake_device_handler))
+        .route("/shutdown/:device_id", post(shutdown_device_handler))
+        .route("/ping/:device_id", get(ping_device_handler))
         .route("/refresh-all", get(refresh_all_handler))
+        .route("/metrics", get(metrics_handler))
+        .route(
+            "/api/v1/tokens",
+            get(list_tokens_handler).post(create_token_handler),
+        )
+        .route("/api/v1/tokens/:token_id", delete(revoke_token_handler))
+        .route("/logout", post(logout_handler))
+        // Everything above needs a login or API token when auth is enabled
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_login,
+        ))
+        .route("/login", get(login_page_handler).post(login_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            count_requests,
+        ))
         // Assets service will be added by main.rs
         .with_state(app_state) // Use with_state to make AppState available to handlers
 }
@@ -0,0 +1,321 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Path, State},
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::auth::CurrentUser;
+use crate::background_discovery::unix_now;
+use crate::config::{Config, Device};
+use crate::json_store::JsonStore;
+use crate::routes::AppState;
+
+// Every token starts with this, so they are easy to spot in scripts and secret scanners
+pub const TOKEN_PREFIX: &str = "wol_";
+
+// last_used_at is only written to disk when it moves by at least this many seconds
+const LAST_USED_RESOLUTION: u64 = 60;
+
+// What a token may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "status:read")]
+    StatusRead,
+    #[serde(rename = "wake")]
+    Wake,
+    #[serde(rename = "shutdown")]
+    Shutdown,
+    #[serde(rename = "discovery")]
+    Discovery,
+    #[serde(rename = "config:write")]
+    ConfigWrite,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::StatusRead => "status:read",
+            Scope::Wake => "wake",
+            Scope::Shutdown => "shutdown",
+            Scope::Discovery => "discovery",
+            Scope::ConfigWrite => "config:write",
+        }
+    }
+}
+
+// The scope a route needs, by its path pattern. None for routes tokens may not use at all,
+// such as managing tokens.
+pub fn required_scope(method: &Method, route: &str) -> Option<Scope> {
+    match route {
+        "/wake/:device_id" => Some(Scope::Wake),
+        "/shutdown/:device_id" => Some(Scope::Shutdown),
+        "/devices" | "/devices/:device_id" if method == Method::POST => Some(Scope::ConfigWrite),
+        "/devices/:device_id/delete" | "/devices/:device_id/move" => Some(Scope::ConfigWrite),
+        "/logout" => None,
+        _ if route.starts_with("/api/v1/tokens") => None,
+        _ if route.starts_with("/discovery") || route.starts_with("/api/v1/discovery") => {
+            Some(Scope::Discovery)
+        }
+        _ => Some(Scope::StatusRead),
+    }
+}
+
+// A token as stored. Only the SHA-256 of the secret is kept.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    // When either is set, only these devices (by id) and devices in these groups
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    // Unix seconds
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub last_used_at: Option<u64>,
+}
+
+impl ApiToken {
+    fn is_limited(&self) -> bool {
+        !self.devices.is_empty() || !self.groups.is_empty()
+    }
+
+    // Whether the token may act on this device
+    pub fn allows_device(&self, device: &Device) -> bool {
+        !self.is_limited()
+            || self.devices.contains(&device.device_id())
+            || device
+                .groups
+                .iter()
+                .any(|group| self.groups.contains(group))
+    }
+
+    // Why the token may not make a request needing `scope`, on `device` when the route names
+    // one (`Some(None)` for a route naming a device that does not exist)
+    pub fn check(&self, scope: Scope, device: Option<Option<&Device>>) -> Result<(), String> {
+        if !self.scopes.contains(&scope) {
+            return Err(format!("Token lacks the {} scope", scope.as_str()));
+        }
+        match device {
+            // Unknown devices are left to the route, which answers 404
+            Some(Some(device)) if !self.allows_device(device) => Err(format!(
+                "Token is not allowed to use device '{}'",
+                device.name
+            )),
+            None if self.is_limited() => {
+                Err("Token is limited to devices and this route is not for one".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The token as shown in the API, without its hash
+    pub fn summary(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "scopes": self.scopes,
+            "devices": self.devices,
+            "groups": self.groups,
+            "created_at": self.created_at,
+            "created_by": self.created_by,
+            "last_used_at": self.last_used_at,
+        })
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// A new random secret: the prefix and 32 random bytes in hex
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}{}", TOKEN_PREFIX, hex)
+}
+
+// Persistent list of API tokens
+#[derive(Clone)]
+pub struct TokenStore {
+    tokens: JsonStore<Vec<ApiToken>>,
+}
+
+impl TokenStore {
+    // Load the tokens from disk; a missing or unreadable file starts without tokens
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self {
+            tokens: JsonStore::load(path),
+        }
+    }
+
+    // A store that is never written to disk (used by tests)
+    pub fn in_memory() -> Self {
+        Self {
+            tokens: JsonStore::in_memory(),
+        }
+    }
+
+    pub async fn all(&self) -> Vec<ApiToken> {
+        self.tokens.lock().await.clone()
+    }
+
+    // Store a new token and return it with its secret, which is not kept
+    pub async fn create(&self, mut token: ApiToken) -> (ApiToken, String) {
+        let secret = generate_secret();
+        token.hash = hash_secret(&secret);
+        let mut tokens = self.tokens.lock().await;
+        tokens.push(token.clone());
+        tokens.save().await;
+        (token, secret)
+    }
+
+    // Delete a token; false when there was none with that id
+    pub async fn revoke(&self, id: &str) -> bool {
+        let mut tokens = self.tokens.lock().await;
+        let before = tokens.len();
+        tokens.retain(|token| token.id != id);
+        let removed = tokens.len() != before;
+        if removed {
+            tokens.save().await;
+        }
+        removed
+    }
+
+    // The token with this secret, with its last use set to `now`
+    pub async fn authenticate(&self, secret: &str, now: u64) -> Option<ApiToken> {
+        let hash = hash_secret(secret);
+        let mut tokens = self.tokens.lock().await;
+        let token = tokens.iter_mut().find(|token| token.hash == hash)?;
+        let changed = token
+            .last_used_at
+            .is_none_or(|last| now >= last + LAST_USED_RESOLUTION);
+        if changed {
+            token.last_used_at = Some(now);
+        }
+        let token = token.clone();
+        if changed {
+            tokens.save().await;
+        }
+        Some(token)
+    }
+}
+
+// The secret of an `Authorization: Bearer` header
+pub fn bearer_secret(headers: &axum::http::HeaderMap) -> Option<&str> {
+    let value = headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let (scheme, secret) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| secret.trim())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    scopes: Vec<Scope>,
+    #[serde(default)]
+    devices: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+// Check a create request against the config; devices are returned by id
+fn resolve_devices(request: &CreateTokenRequest, config: &Config) -> Result<Vec<String>, String> {
+    if request.name.trim().is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if request.scopes.is_empty() {
+        return Err("scopes must list at least one scope".to_string());
+    }
+    for group in &request.groups {
+        if !config
+            .devices
+            .iter()
+            .any(|device| device.groups.contains(group))
+        {
+            return Err(format!("No device is in group '{}'", group));
+        }
+    }
+    request
+        .devices
+        .iter()
+        .map(|name| {
+            config
+                .find_device(name)
+                .map(|device| device.device_id())
+                .ok_or_else(|| format!("Unknown device '{}'", name))
+        })
+        .collect()
+}
+
+// Handler for GET /api/v1/tokens
+pub async fn list_tokens_handler(State(app_state): State<AppState>) -> Response {
+    let tokens: Vec<serde_json::Value> = app_state
+        .tokens
+        .all()
+        .await
+        .iter()
+        .map(ApiToken::summary)
+        .collect();
+    Json(tokens).into_response()
+}
+
+// Handler for POST /api/v1/tokens. The secret is only ever shown in this response.
+pub async fn create_token_handler(
+    State(app_state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    Json(request): Json<CreateTokenRequest>,
+) -> Response {
+    let devices = match resolve_devices(&request, &app_state.config.get()) {
+        Ok(devices) => devices,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        name: request.name.trim().to_string(),
+        hash: String::new(),
+        scopes: request.scopes,
+        devices,
+        groups: request.groups,
+        created_at: unix_now(),
+        created_by: user.map(|Extension(CurrentUser(username))| username),
+        last_used_at: None,
+    };
+    let (token, secret) = app_state.tokens.create(token).await;
+    println!("API token '{}' created", token.name);
+
+    let mut body = token.summary();
+    body["token"] = json!(secret);
+    (StatusCode::CREATED, Json(body)).into_response()
+}
+
+// Handler for DELETE /api/v1/tokens/:token_id
+pub async fn revoke_token_handler(
+    State(app_state): State<AppState>,
+    Path(token_id): Path<String>,
+) -> Response {
+    if app_state.tokens.revoke(&token_id).await {
+        println!("API token {} revoked", token_id);
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "Token not found").into_response()
+    }
+}
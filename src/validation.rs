@@ -0,0 +1,991 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+use crate::auth::{is_valid_hash, load_users};
+use crate::config::{
+    apply_env_overrides, interpolate_value, AuthConfig, ChannelKind, Config, MAX_SCAN_CONCURRENCY,
+    RESERVED_DEVICE_IDS,
+};
+use crate::events::Event as ServerEvent;
+use crate::notifications::parse_time;
+use crate::routes::{normalize_mac, DeviceStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // The server refuses to start
+    Error,
+    // Logged, but the config is used
+    Warning,
+}
+
+// One problem found in a config file
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    // File the config came from
+    pub source: String,
+    // Dotted path to the offending value, e.g. "devices[2].mac_address"
+    pub path: String,
+    // 1-based position in the YAML, when known
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, ": {}: ", severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+// Every error found in a config, as one error value for `?` and Box<dyn Error>
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<ConfigIssue>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|issue| issue.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+// A config that loaded, with the warnings found along the way
+pub type CheckedConfig = (Config, Vec<ConfigIssue>);
+
+// Read, parse and validate a config file. Errors (of any kind) come back all at once.
+pub fn check_config_file(path: &str) -> Result<CheckedConfig, Vec<ConfigIssue>> {
+    let contents = read_config_text(path)?;
+    check_config(&contents, path, &|name| std::env::var(name).ok())
+}
+
+// The text of a config file, with a read error reported like any other issue
+pub fn read_config_text(path: &str) -> Result<String, Vec<ConfigIssue>> {
+    std::fs::read_to_string(path).map_err(|e| {
+        vec![ConfigIssue {
+            severity: Severity::Error,
+            source: path.to_string(),
+            path: String::new(),
+            line: None,
+            column: None,
+            message: e.to_string(),
+        }]
+    })
+}
+
+// Parse YAML (with ${VAR} interpolation and WOLOLO_* overrides) and validate the result
+pub fn check_config(
+    contents: &str,
+    source: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<CheckedConfig, Vec<ConfigIssue>> {
+    let issue = |line: Option<usize>, column: Option<usize>, message: String| ConfigIssue {
+        severity: Severity::Error,
+        source: source.to_string(),
+        path: String::new(),
+        line,
+        column,
+        message,
+    };
+    let yaml_issue = |e: serde_yaml::Error| {
+        let location = e.location();
+        // The position is reported separately, so drop serde_yaml's own " at line N column M"
+        let mut message = e.to_string();
+        if let Some(index) = message.rfind(" at line ") {
+            message.truncate(index);
+        }
+        issue(
+            location.as_ref().map(|l| l.line()),
+            location.as_ref().map(|l| l.column()),
+            message,
+        )
+    };
+
+    let mut value: serde_yaml::Value =
+        serde_yaml::from_str(contents).map_err(|e| vec![yaml_issue(e)])?;
+    interpolate_value(&mut value, env, &yaml_quoted_paths(contents))
+        .map_err(|e| vec![issue(None, None, e)])?;
+    let mut config: Config = serde_yaml::from_value(value).map_err(|e| {
+        // Values carry no positions; parsing the raw text again usually finds the same
+        // type error with a line and column
+        match serde_yaml::from_str::<Config>(contents) {
+            Err(located) => vec![yaml_issue(located)],
+            Ok(_) => vec![issue(None, None, e.to_string())],
+        }
+    })?;
+    apply_env_overrides(&mut config, env).map_err(|e| vec![issue(None, None, e)])?;
+
+    let positions = yaml_positions(contents);
+    let issues = validate_config(&config, source, &positions);
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        return Err(issues);
+    }
+    Ok((config, issues))
+}
+
+// Line and column (both 1-based) of every value in the first YAML document, keyed by path
+pub fn yaml_positions(contents: &str) -> HashMap<String, (usize, usize)> {
+    let mut recorder = PositionRecorder::default();
+    // A scan error just leaves the map incomplete; serde_yaml reports it properly
+    let _ = Parser::new_from_str(contents).load(&mut recorder, false);
+    recorder.positions
+}
+
+// Paths of the values written as quoted or block scalars, which must stay strings
+pub fn yaml_quoted_paths(contents: &str) -> HashSet<String> {
+    let mut recorder = PositionRecorder::default();
+    let _ = Parser::new_from_str(contents).load(&mut recorder, false);
+    recorder.quoted
+}
+
+enum Frame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct PositionRecorder {
+    stack: Vec<Frame>,
+    positions: HashMap<String, (usize, usize)>,
+    quoted: HashSet<String>,
+}
+
+impl PositionRecorder {
+    // Path of the node about to start
+    fn node_path(&self) -> String {
+        match self.stack.last() {
+            Some(Frame::Mapping { path, key }) => {
+                let key = key.as_deref().unwrap_or("");
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            }
+            Some(Frame::Sequence { path, index }) => format!("{}[{}]", path, index),
+            None => String::new(),
+        }
+    }
+
+    // The current node is finished; move the parent on to its next key or item
+    fn node_done(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { key, .. }) => *key = None,
+            Some(Frame::Sequence { index, .. }) => *index += 1,
+            None => {}
+        }
+    }
+
+    fn expecting_key(&self) -> bool {
+        matches!(self.stack.last(), Some(Frame::Mapping { key: None, .. }))
+    }
+
+    fn record(&mut self, path: String, mark: Marker) {
+        self.positions
+            .entry(path)
+            .or_insert((mark.line(), mark.col() + 1));
+    }
+}
+
+impl MarkedEventReceiver for PositionRecorder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(text, ..) if self.expecting_key() => {
+                if let Some(Frame::Mapping { path, key }) = self.stack.last_mut() {
+                    // A block mapping's start mark is its first colon; point at the first key instead
+                    let path = path.clone();
+                    *key = Some(text);
+                    self.record(path, mark);
+                }
+            }
+            Event::Scalar(_, style, ..) if style != TScalarStyle::Plain => {
+                let path = self.node_path();
+                self.quoted.insert(path.clone());
+                self.record(path, mark);
+                self.node_done();
+            }
+            Event::Scalar(..) | Event::Alias(_) => {
+                let path = self.node_path();
+                self.record(path, mark);
+                self.node_done();
+            }
+            Event::MappingStart(..) => {
+                let path = self.node_path();
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.node_path();
+                self.record(path.clone(), mark);
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                if let Some(Frame::Mapping { path, .. }) = self.stack.pop() {
+                    // Empty mappings have no key to take the position from
+                    self.record(path, mark);
+                }
+                self.node_done();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Keys serde understands; anything else is most likely a typo
+const TOP_LEVEL_KEYS: &[&str] = &[
+    "server",
+    "sync",
+    "sound",
+    "discovery",
+    "poller",
+    "history",
+    "webhooks",
+    "mqtt",
+    "notifications",
+    "auth",
+    "devices",
+];
+const DEVICE_KEYS: &[&str] = &[
+    "id",
+    "name",
+    "mac_address",
+    "ip_address",
+    "groups",
+    "tags",
+    "shutdown_command",
+];
+const WEBHOOK_ENDPOINT_KEYS: &[&str] = &["url", "events", "headers", "secret", "template"];
+
+// Check the semantics serde cannot: address syntax, duplicates and odd settings
+pub fn validate_config(
+    config: &Config,
+    source: &str,
+    positions: &HashMap<String, (usize, usize)>,
+) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut push = |severity: Severity, path: String, message: String| {
+        // Fall back to the enclosing item when the value itself is missing from the file
+        let position = positions.get(&path).or_else(|| {
+            path.rfind(['.', '['])
+                .and_then(|end| positions.get(&path[..end]))
+        });
+        issues.push(ConfigIssue {
+            severity,
+            source: source.to_string(),
+            line: position.map(|p| p.0),
+            column: position.map(|p| p.1),
+            path,
+            message,
+        });
+    };
+
+    // Server
+    if config.server.ip.parse::<IpAddr>().is_err() {
+        push(
+            Severity::Error,
+            "server.ip".to_string(),
+            format!("'{}' is not an IP address", config.server.ip),
+        );
+    }
+    if config.server.port == 0 {
+        push(
+            Severity::Warning,
+            "server.port".to_string(),
+            "port 0 picks a random port on every start".to_string(),
+        );
+    }
+    if !config.server.external_url.starts_with("http://")
+        && !config.server.external_url.starts_with("https://")
+    {
+        push(
+            Severity::Warning,
+            "server.external_url".to_string(),
+            format!(
+                "'{}' should start with http:// or https://",
+                config.server.external_url
+            ),
+        );
+    }
+
+    // Sync
+    if config.sync.enabled {
+        if config.sync.interval_seconds == 0 {
+            push(
+                Severity::Error,
+                "sync.interval_seconds".to_string(),
+                "must be at least 1 when sync is enabled".to_string(),
+            );
+        } else if config.sync.interval_seconds < 5 {
+            push(
+                Severity::Warning,
+                "sync.interval_seconds".to_string(),
+                format!(
+                    "{} seconds pings every device very often; 5 or more is recommended",
+                    config.sync.interval_seconds
+                ),
+            );
+        }
+    }
+
+    // Poller and history
+    if config.poller.enabled && config.poller.interval_seconds == 0 {
+        push(
+            Severity::Error,
+            "poller.interval_seconds".to_string(),
+            "must be at least 1 when the poller is enabled".to_string(),
+        );
+    }
+    if !(1..=MAX_SCAN_CONCURRENCY).contains(&config.poller.max_concurrency) {
+        push(
+            Severity::Error,
+            "poller.max_concurrency".to_string(),
+            format!("must be between 1 and {}", MAX_SCAN_CONCURRENCY),
+        );
+    }
+    if config.history.enabled {
+        if config.history.path.trim().is_empty() {
+            push(
+                Severity::Error,
+                "history.path".to_string(),
+                "must not be empty when history is enabled".to_string(),
+            );
+        }
+        if config.history.retention_days == 0 {
+            push(
+                Severity::Error,
+                "history.retention_days".to_string(),
+                "must be at least 1".to_string(),
+            );
+        }
+    }
+
+    // Webhooks
+    if config.webhooks.max_attempts == 0 {
+        push(
+            Severity::Error,
+            "webhooks.max_attempts".to_string(),
+            "must be at least 1".to_string(),
+        );
+    }
+    if !config.webhooks.endpoints.is_empty() && config.webhooks.queue_path.trim().is_empty() {
+        push(
+            Severity::Error,
+            "webhooks.queue_path".to_string(),
+            "must not be empty when webhooks are configured".to_string(),
+        );
+    }
+    // Queued deliveries find their endpoint by URL
+    let mut webhook_urls: HashMap<&str, usize> = HashMap::new();
+    for (index, endpoint) in config.webhooks.endpoints.iter().enumerate() {
+        if !endpoint.url.starts_with("http://") && !endpoint.url.starts_with("https://") {
+            push(
+                Severity::Error,
+                format!("webhooks.endpoints[{}].url", index),
+                format!("'{}' must start with http:// or https://", endpoint.url),
+            );
+        } else if let Some(first) = webhook_urls.get(endpoint.url.as_str()) {
+            push(
+                Severity::Error,
+                format!("webhooks.endpoints[{}].url", index),
+                format!(
+                    "'{}' is already used by webhooks.endpoints[{}]; urls must be unique",
+                    endpoint.url, first
+                ),
+            );
+        } else {
+            webhook_urls.insert(&endpoint.url, index);
+        }
+        for (event_index, kind) in endpoint.events.iter().enumerate() {
+            if !ServerEvent::KINDS.contains(&kind.as_str()) {
+                push(
+                    Severity::Error,
+                    format!("webhooks.endpoints[{}].events[{}]", index, event_index),
+                    format!(
+                        "unknown event type '{}'; use one of: {}",
+                        kind,
+                        ServerEvent::KINDS.join(", ")
+                    ),
+                );
+            }
+        }
+        if let Some(template) = &endpoint.template {
+            if let Err(e) = handlebars::Template::compile(template) {
+                push(
+                    Severity::Error,
+                    format!("webhooks.endpoints[{}].template", index),
+                    format!("is not a valid Handlebars template: {}", e),
+                );
+            }
+        }
+    }
+
+    // MQTT
+    if config.mqtt.enabled {
+        let mqtt = &config.mqtt;
+        if mqtt.host.trim().is_empty() {
+            push(
+                Severity::Error,
+                "mqtt.host".to_string(),
+                "must not be empty when MQTT is enabled".to_string(),
+            );
+        }
+        if mqtt.port == 0 {
+            push(
+                Severity::Error,
+                "mqtt.port".to_string(),
+                "must not be 0".to_string(),
+            );
+        }
+        if mqtt.client_id.trim().is_empty() {
+            push(
+                Severity::Error,
+                "mqtt.client_id".to_string(),
+                "must not be empty".to_string(),
+            );
+        }
+        let prefix = mqtt.topic_prefix.trim_end_matches('/');
+        if prefix.is_empty() || prefix.contains(['+', '#']) {
+            push(
+                Severity::Error,
+                "mqtt.topic_prefix".to_string(),
+                format!(
+                    "'{}' must be a topic without the wildcards + and #",
+                    mqtt.topic_prefix
+                ),
+            );
+        }
+        if mqtt.password.is_some() && mqtt.username.is_none() {
+            push(
+                Severity::Warning,
+                "mqtt.password".to_string(),
+                "is ignored without mqtt.username".to_string(),
+            );
+        }
+        match &mqtt.ca_file {
+            Some(_) if !mqtt.tls => push(
+                Severity::Warning,
+                "mqtt.ca_file".to_string(),
+                "is ignored unless mqtt.tls is true".to_string(),
+            ),
+            Some(path) if !std::path::Path::new(path).exists() => push(
+                Severity::Error,
+                "mqtt.ca_file".to_string(),
+                format!("'{}' does not exist", path),
+            ),
+            _ => {}
+        }
+    }
+    if config.mqtt.home_assistant.enabled {
+        if !config.mqtt.enabled {
+            push(
+                Severity::Warning,
+                "mqtt.home_assistant.enabled".to_string(),
+                "has no effect unless mqtt.enabled is true".to_string(),
+            );
+        }
+        let prefix = &config.mqtt.home_assistant.discovery_prefix;
+        if prefix.trim_end_matches('/').is_empty() || prefix.contains(['+', '#']) {
+            push(
+                Severity::Error,
+                "mqtt.home_assistant.discovery_prefix".to_string(),
+                format!("'{}' must be a topic without the wildcards + and #", prefix),
+            );
+        }
+    }
+
+    // Notifications
+    let mut channel_names: HashMap<&str, usize> = HashMap::new();
+    for (index, channel) in config.notifications.channels.iter().enumerate() {
+        let path = |field: &str| format!("notifications.channels[{}].{}", index, field);
+
+        if channel.name.trim().is_empty() {
+            push(
+                Severity::Error,
+                path("name"),
+                "must not be empty".to_string(),
+            );
+        } else if let Some(first) = channel_names.insert(&channel.name, index) {
+            push(
+                Severity::Error,
+                path("name"),
+                format!(
+                    "'{}' is already used by notifications.channels[{}]",
+                    channel.name, first
+                ),
+            );
+        }
+        match &channel.kind {
+            ChannelKind::Smtp {
+                host,
+                username,
+                password,
+                from,
+                to,
+                ..
+            } => {
+                if host.trim().is_empty() {
+                    push(
+                        Severity::Error,
+                        path("host"),
+                        "must not be empty".to_string(),
+                    );
+                }
+                if from.trim().is_empty() {
+                    push(
+                        Severity::Error,
+                        path("from"),
+                        "must not be empty".to_string(),
+                    );
+                }
+                if to.is_empty() {
+                    push(
+                        Severity::Error,
+                        path("to"),
+                        "must list at least one address".to_string(),
+                    );
+                }
+                if password.is_some() && username.is_none() {
+                    push(
+                        Severity::Warning,
+                        path("password"),
+                        "is ignored without a username".to_string(),
+                    );
+                }
+            }
+            ChannelKind::Ntfy { url, priority, .. } | ChannelKind::Gotify { url, priority, .. } => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    push(
+                        Severity::Error,
+                        path("url"),
+                        format!("'{}' must start with http:// or https://", url),
+                    );
+                }
+                if priority.is_some_and(|priority| priority > 10) {
+                    push(
+                        Severity::Error,
+                        path("priority"),
+                        "must be between 0 and 10".to_string(),
+                    );
+                }
+            }
+            ChannelKind::Push { url, .. } => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    push(
+                        Severity::Error,
+                        path("url"),
+                        format!("'{}' must start with http:// or https://", url),
+                    );
+                }
+            }
+        }
+        for (field, template) in [
+            ("title_template", &channel.title_template),
+            ("template", &channel.template),
+        ] {
+            if let Some(Err(e)) = template.as_deref().map(handlebars::Template::compile) {
+                push(
+                    Severity::Error,
+                    path(field),
+                    format!("is not a valid Handlebars template: {}", e),
+                );
+            }
+        }
+        if let Some(quiet_hours) = &channel.quiet_hours {
+            for (field, value) in [("start", &quiet_hours.start), ("end", &quiet_hours.end)] {
+                if parse_time(value).is_none() {
+                    push(
+                        Severity::Error,
+                        path(&format!("quiet_hours.{}", field)),
+                        format!("'{}' is not a time like 22:30", value),
+                    );
+                }
+            }
+        }
+    }
+    for (index, rule) in config.notifications.rules.iter().enumerate() {
+        let path = |field: &str| format!("notifications.rules[{}].{}", index, field);
+
+        if rule.channels.is_empty() {
+            push(
+                Severity::Error,
+                path("channels"),
+                "must name at least one channel".to_string(),
+            );
+        }
+        for channel in &rule.channels {
+            if !channel_names.contains_key(channel.as_str()) {
+                push(
+                    Severity::Error,
+                    path("channels"),
+                    format!("unknown channel '{}'", channel),
+                );
+            }
+        }
+        for kind in &rule.events {
+            if !ServerEvent::KINDS.contains(&kind.as_str()) {
+                push(
+                    Severity::Error,
+                    path("events"),
+                    format!(
+                        "unknown event type '{}'; use one of: {}",
+                        kind,
+                        ServerEvent::KINDS.join(", ")
+                    ),
+                );
+            }
+        }
+        for status in &rule.statuses {
+            if DeviceStatus::from_name(status).is_none() {
+                push(
+                    Severity::Error,
+                    path("statuses"),
+                    format!(
+                        "unknown status '{}'; use online, offline or unreachable",
+                        status
+                    ),
+                );
+            }
+        }
+        for device in &rule.devices {
+            if config.find_device(device).is_none() {
+                push(
+                    Severity::Warning,
+                    path("devices"),
+                    format!("'{}' does not match any device", device),
+                );
+            }
+        }
+    }
+
+    // Auth
+    let auth = &config.auth;
+    let mut usernames: HashMap<&str, usize> = HashMap::new();
+    for (index, user) in auth.users.iter().enumerate() {
+        let path = |field: &str| format!("auth.users[{}].{}", index, field);
+        if user.username.is_empty() {
+            push(
+                Severity::Error,
+                path("username"),
+                "must not be empty".to_string(),
+            );
+        } else if let Some(first) = usernames.insert(&user.username, index) {
+            push(
+                Severity::Error,
+                path("username"),
+                format!(
+                    "'{}' is already used by auth.users[{}]",
+                    user.username, first
+                ),
+            );
+        }
+        if !is_valid_hash(&user.password_hash) {
+            push(
+                Severity::Error,
+                path("password_hash"),
+                "is not an Argon2 hash; create one with `wololo hash-password`".to_string(),
+            );
+        }
+    }
+    let file_users = match &auth.users_file {
+        Some(_) => match load_users(&AuthConfig {
+            users: Vec::new(),
+            ..auth.clone()
+        }) {
+            Ok(users) => users,
+            Err(e) => {
+                push(Severity::Error, "auth.users_file".to_string(), e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+    for user in &file_users {
+        if !is_valid_hash(&user.password_hash) {
+            push(
+                Severity::Error,
+                "auth.users_file".to_string(),
+                format!(
+                    "the password_hash of '{}' is not an Argon2 hash",
+                    user.username
+                ),
+            );
+        }
+    }
+    if auth.enabled {
+        if auth.users.is_empty() && auth.users_file.is_none() {
+            push(
+                Severity::Error,
+                "auth.users".to_string(),
+                "must list at least one user when auth is enabled".to_string(),
+            );
+        }
+        if auth.session_hours == 0 {
+            push(
+                Severity::Error,
+                "auth.session_hours".to_string(),
+                "must be at least 1".to_string(),
+            );
+        }
+        if auth
+            .session_secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() < 16)
+        {
+            push(
+                Severity::Warning,
+                "auth.session_secret".to_string(),
+                "is short; use at least 16 random characters".to_string(),
+            );
+        }
+    }
+    if auth.tokens_path.is_empty() {
+        push(
+            Severity::Error,
+            "auth.tokens_path".to_string(),
+            "must not be empty".to_string(),
+        );
+    }
+
+    // Discovery
+    if config.discovery.max_concurrency == 0 {
+        push(
+            Severity::Warning,
+            "discovery.max_concurrency".to_string(),
+            "0 is treated as 1".to_string(),
+        );
+    } else if config.discovery.max_concurrency > MAX_SCAN_CONCURRENCY {
+        push(
+            Severity::Error,
+            "discovery.max_concurrency".to_string(),
+            format!("must be at most {}", MAX_SCAN_CONCURRENCY),
+        );
+    }
+    for (index, lease) in config.discovery.dhcp_leases.iter().enumerate() {
+        if !std::path::Path::new(&lease.path).exists() {
+            push(
+                Severity::Warning,
+                format!("discovery.dhcp_leases[{}].path", index),
+                format!("'{}' does not exist", lease.path),
+            );
+        }
+    }
+
+    // Devices
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    let mut macs: HashMap<String, usize> = HashMap::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for (index, device) in config.devices.iter().enumerate() {
+        let path = |field: &str| format!("devices[{}].{}", index, field);
+
+        if let Some(id) = &device.id {
+            if !is_valid_device_id(id) {
+                push(
+                    Severity::Error,
+                    path("id"),
+                    format!(
+                        "'{}' is not a valid id (use lowercase letters, digits, '-' and '_')",
+                        id
+                    ),
+                );
+            } else if RESERVED_DEVICE_IDS.contains(&id.as_str()) {
+                push(
+                    Severity::Error,
+                    path("id"),
+                    format!("'{}' is reserved for a page under /devices/", id),
+                );
+            }
+        }
+        let id = device.device_id();
+        if let Some(first) = ids.get(&id) {
+            // Generated ids only repeat when MAC addresses do, which is reported below
+            if device.id.is_some() || config.devices[*first].id.is_some() {
+                push(
+                    Severity::Error,
+                    path(if device.id.is_some() {
+                        "id"
+                    } else {
+                        "mac_address"
+                    }),
+                    format!(
+                        "id '{}' is already used by devices[{}]; ids must be unique",
+                        id, first
+                    ),
+                );
+            }
+        } else {
+            ids.insert(id, index);
+        }
+
+        if device.name.trim().is_empty() {
+            push(
+                Severity::Error,
+                path("name"),
+                "must not be empty".to_string(),
+            );
+        } else if let Some(first) = names.get(device.name.as_str()) {
+            push(
+                Severity::Error,
+                path("name"),
+                format!(
+                    "'{}' is already used by devices[{}]; names must be unique",
+                    device.name, first
+                ),
+            );
+        } else {
+            names.insert(&device.name, index);
+        }
+
+        match parse_mac(&device.mac_address) {
+            None => push(
+                Severity::Error,
+                path("mac_address"),
+                format!(
+                    "'{}' is not a MAC address (expected XX:XX:XX:XX:XX:XX)",
+                    device.mac_address
+                ),
+            ),
+            Some(octets) => {
+                if octets == [0; 6] || octets == [0xff; 6] {
+                    push(
+                        Severity::Error,
+                        path("mac_address"),
+                        format!("'{}' cannot belong to a device", device.mac_address),
+                    );
+                } else if octets[0] & 1 == 1 {
+                    push(
+                        Severity::Warning,
+                        path("mac_address"),
+                        format!("'{}' is a multicast address", device.mac_address),
+                    );
+                }
+
+                let key = normalize_mac(&device.mac_address);
+                if let Some(first) = macs.get(&key) {
+                    push(
+                        Severity::Error,
+                        path("mac_address"),
+                        format!(
+                            "'{}' is already used by devices[{}]; MAC addresses must be unique",
+                            device.mac_address, first
+                        ),
+                    );
+                } else {
+                    macs.insert(key, index);
+                }
+            }
+        }
+
+        match device.ip_address.parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) => {}
+            Ok(IpAddr::V6(_)) => push(
+                Severity::Warning,
+                path("ip_address"),
+                "wake packets are only broadcast on IPv4 networks".to_string(),
+            ),
+            Err(_) if is_valid_hostname(&device.ip_address) => {}
+            Err(_) => push(
+                Severity::Error,
+                path("ip_address"),
+                format!(
+                    "'{}' is neither an IP address nor a hostname",
+                    device.ip_address
+                ),
+            ),
+        }
+    }
+
+    // Unknown keys
+    let mut unknown: Vec<(&String, &(usize, usize))> = positions
+        .iter()
+        .filter(|(path, _)| is_unknown_key(path))
+        .collect();
+    unknown.sort_by_key(|(_, position)| **position);
+    for (path, _) in unknown {
+        push(
+            Severity::Warning,
+            path.clone(),
+            "unknown key is ignored".to_string(),
+        );
+    }
+
+    issues.sort_by_key(|issue| (issue.line.unwrap_or(usize::MAX), issue.column));
+    issues
+}
+
+fn is_unknown_key(path: &str) -> bool {
+    if !path.contains(['.', '[']) {
+        return !path.is_empty() && !TOP_LEVEL_KEYS.contains(&path);
+    }
+    // devices[N].key
+    if let Some(rest) = path.strip_prefix("devices[") {
+        if let Some((_, key)) = rest.split_once("].") {
+            return !key.contains(['.', '[']) && !DEVICE_KEYS.contains(&key);
+        }
+    }
+    // webhooks.endpoints[N].key
+    if let Some(rest) = path.strip_prefix("webhooks.endpoints[") {
+        if let Some((_, key)) = rest.split_once("].") {
+            return !key.contains(['.', '[']) && !WEBHOOK_ENDPOINT_KEYS.contains(&key);
+        }
+    }
+    false
+}
+
+// Ids appear in URLs and HTML element IDs
+fn is_valid_device_id(id: &str) -> bool {
+    id.len() <= 64
+        && id.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let parts: Vec<&str> = mac.trim().split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let mut octets = [0u8; 6];
+    for (octet, part) in octets.iter_mut().zip(parts) {
+        if part.len() != 2 {
+            return None;
+        }
+        *octet = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(octets)
+}
+
+// RFC 1123 host name: dot-separated labels of letters, digits and inner hyphens
+fn is_valid_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        && !host.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
@@ -0,0 +1,426 @@
+use futures::stream::{self, StreamExt};
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::background_discovery::unix_now;
+use crate::config::{Config, WebhookEndpoint};
+use crate::events::Event;
+use crate::json_store::JsonStore;
+use crate::routes::AppState;
+
+// Deliveries kept in the queue; the oldest are dropped beyond this
+pub const MAX_QUEUED_DELIVERIES: usize = 1000;
+
+// How long one request may take
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Endpoints sent to at the same time; each endpoint gets one request at a time
+const MAX_CONCURRENT_ENDPOINTS: usize = 8;
+
+// The first retry waits this long, doubling with every failed attempt up to the maximum
+const RETRY_BASE_SECONDS: u64 = 10;
+const RETRY_MAX_SECONDS: u64 = 3600;
+
+// A webhook request waiting to be sent. Headers and the secret are looked up by URL when it
+// is sent, so they are never written to the queue file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Delivery {
+    pub id: String,
+    pub url: String,
+    // Event type, e.g. "status_changed"
+    pub event: String,
+    pub body: String,
+    // Failed attempts so far
+    pub attempts: u32,
+    // Unix seconds
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+}
+
+// Persistent queue of deliveries that have not succeeded yet
+#[derive(Clone)]
+pub struct WebhookQueue {
+    deliveries: JsonStore<Vec<Delivery>>,
+    added: Arc<Notify>,
+}
+
+impl WebhookQueue {
+    // Load the queue from disk; a missing or unreadable file starts an empty queue
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self {
+            deliveries: JsonStore::load(path),
+            added: Arc::new(Notify::new()),
+        }
+    }
+
+    // A queue that is never written to disk (used by tests)
+    pub fn in_memory() -> Self {
+        Self {
+            deliveries: JsonStore::in_memory(),
+            added: Arc::new(Notify::new()),
+        }
+    }
+
+    pub async fn push(&self, new_deliveries: Vec<Delivery>) {
+        if new_deliveries.is_empty() {
+            return;
+        }
+        {
+            let mut deliveries = self.deliveries.lock().await;
+            deliveries.extend(new_deliveries);
+            if deliveries.len() > MAX_QUEUED_DELIVERIES {
+                let dropped = deliveries.len() - MAX_QUEUED_DELIVERIES;
+                eprintln!(
+                    "Webhook queue is full; dropping the {} oldest deliveries",
+                    dropped
+                );
+                deliveries.drain(..dropped);
+            }
+            deliveries.save().await;
+        }
+        self.added.notify_one();
+    }
+
+    pub async fn all(&self) -> Vec<Delivery> {
+        self.deliveries.lock().await.clone()
+    }
+
+    // Deliveries that can be sent now, in queue order. A delivery waiting for a retry holds
+    // back everything queued after it for the same URL, so an endpoint sees events in order.
+    pub async fn due(&self, now: u64) -> Vec<Delivery> {
+        let deliveries = self.deliveries.lock().await;
+        let mut waiting: HashSet<&str> = HashSet::new();
+        deliveries
+            .iter()
+            .filter(|delivery| {
+                if delivery.next_attempt_at > now {
+                    waiting.insert(&delivery.url);
+                }
+                !waiting.contains(delivery.url.as_str())
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Seconds until the next delivery is due, if any is queued
+    async fn next_due_in(&self, now: u64) -> Option<u64> {
+        let deliveries = self.deliveries.lock().await;
+        deliveries
+            .iter()
+            .map(|delivery| delivery.next_attempt_at.saturating_sub(now))
+            .min()
+    }
+
+    // Apply the outcome of a round of sending and save the queue once
+    async fn finish_round(&self, finished: Vec<Attempted>) {
+        if finished.is_empty() {
+            return;
+        }
+        let mut removed = HashSet::new();
+        let mut rescheduled = HashMap::new();
+        for attempted in finished {
+            match attempted {
+                Attempted::Done(id) => {
+                    removed.insert(id);
+                }
+                Attempted::Retry(delivery) => {
+                    rescheduled.insert(delivery.id.clone(), delivery);
+                }
+            }
+        }
+
+        let mut deliveries = self.deliveries.lock().await;
+        deliveries.retain(|delivery| !removed.contains(&delivery.id));
+        for delivery in deliveries.iter_mut() {
+            if let Some(updated) = rescheduled.remove(&delivery.id) {
+                *delivery = updated;
+            }
+        }
+        deliveries.save().await;
+    }
+}
+
+// `{{json value}}` writes the value as JSON (quoted and escaped for strings), for building
+// webhook bodies out of event fields
+fn json_helper(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = helper
+        .param(0)
+        .map(|param| param.value().to_string())
+        .unwrap_or_else(|| "null".to_string());
+    out.write(&value)?;
+    Ok(())
+}
+
+// Helpers webhook and notification templates can use
+fn register_template_helpers(handlebars: &mut Handlebars) {
+    handlebars.register_helper("json", Box::new(json_helper));
+}
+
+// Registry for templates written in the config. Their output is JSON or plain text, not
+// HTML, so values are written unescaped; `{{json value}}` quotes and escapes one for JSON.
+pub fn template_registry() -> &'static Handlebars<'static> {
+    static REGISTRY: OnceLock<Handlebars<'static>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        register_template_helpers(&mut handlebars);
+        handlebars
+    })
+}
+
+// The request body for an endpoint: its template rendered with the event, or the event JSON.
+// A template that fails to render falls back to the event JSON.
+pub fn render_body(endpoint: &WebhookEndpoint, event: &Event) -> String {
+    let data = serde_json::to_value(event).unwrap_or_default();
+    let Some(template) = &endpoint.template else {
+        return data.to_string();
+    };
+    match template_registry().render_template(template, &data) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!(
+                "Webhook template for {} failed: {}. Sending the event JSON instead.",
+                endpoint.url, e
+            );
+            data.to_string()
+        }
+    }
+}
+
+// One delivery per endpoint that wants this event
+pub fn deliveries_for(event: &Event, config: &Config, now: u64) -> Vec<Delivery> {
+    config
+        .webhooks
+        .endpoints
+        .iter()
+        .filter(|endpoint| {
+            endpoint.events.is_empty() || endpoint.events.iter().any(|kind| kind == event.kind())
+        })
+        .map(|endpoint| Delivery {
+            id: Uuid::new_v4().to_string(),
+            url: endpoint.url.clone(),
+            event: event.kind().to_string(),
+            body: render_body(endpoint, event),
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        })
+        .collect()
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Value of the X-Wololo-Signature header: "sha256=" and the hex HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+// Seconds to wait after the given number of failed attempts
+pub fn retry_delay(attempts: u32) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (RETRY_BASE_SECONDS << doublings).min(RETRY_MAX_SECONDS)
+}
+
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .user_agent(concat!("wololo/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+}
+
+async fn send(
+    client: &reqwest::Client,
+    endpoint: &WebhookEndpoint,
+    delivery: &Delivery,
+) -> Result<(), String> {
+    let mut request = client
+        .post(&endpoint.url)
+        .header("Content-Type", "application/json")
+        .header("X-Wololo-Event", &delivery.event)
+        .header("X-Wololo-Delivery", &delivery.id);
+    if let Some(secret) = &endpoint.secret {
+        request = request.header("X-Wololo-Signature", sign(secret, &delivery.body));
+    }
+    for (name, value) in &endpoint.headers {
+        request = request.header(name, value);
+    }
+
+    let response = request
+        .body(delivery.body.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+// What happened to a delivery in one round
+enum Attempted {
+    // Sent, given up on, or its endpoint was removed from the config
+    Done(String),
+    Retry(Delivery),
+}
+
+// Send every delivery that is due. Endpoints are sent to in parallel (up to
+// MAX_CONCURRENT_ENDPOINTS) and each endpoint's deliveries in queue order; after a failure the
+// rest for that endpoint wait for the retry. Failures are retried with backoff until
+// webhooks.max_attempts is reached. Returns how many were sent successfully.
+pub async fn deliver_due(app_state: &AppState, client: &reqwest::Client, now: u64) -> usize {
+    let config = app_state.config.get();
+
+    let mut by_url: Vec<(String, Vec<Delivery>)> = Vec::new();
+    for delivery in app_state.webhooks.due(now).await {
+        match by_url.iter_mut().find(|(url, _)| *url == delivery.url) {
+            Some((_, deliveries)) => deliveries.push(delivery),
+            None => by_url.push((delivery.url.clone(), vec![delivery])),
+        }
+    }
+
+    let rounds: Vec<(usize, Vec<Attempted>)> = stream::iter(by_url)
+        .map(|(url, deliveries)| {
+            let config = &config;
+            async move {
+                // Deliveries for endpoints removed from the config are dropped
+                let Some(endpoint) = config
+                    .webhooks
+                    .endpoints
+                    .iter()
+                    .find(|endpoint| endpoint.url == url)
+                else {
+                    let dropped = deliveries
+                        .into_iter()
+                        .map(|delivery| Attempted::Done(delivery.id))
+                        .collect();
+                    return (0, dropped);
+                };
+
+                let mut sent = 0;
+                let mut finished = Vec::new();
+                for delivery in deliveries {
+                    match send(client, endpoint, &delivery).await {
+                        Ok(()) => {
+                            sent += 1;
+                            finished.push(Attempted::Done(delivery.id));
+                        }
+                        Err(e) => {
+                            let attempts = delivery.attempts + 1;
+                            if attempts >= config.webhooks.max_attempts.max(1) {
+                                eprintln!(
+                                    "Giving up on {} webhook to {} after {} attempts: {}",
+                                    delivery.event, delivery.url, attempts, e
+                                );
+                                finished.push(Attempted::Done(delivery.id));
+                            } else {
+                                eprintln!(
+                                    "{} webhook to {} failed ({}); retrying in {} seconds",
+                                    delivery.event,
+                                    delivery.url,
+                                    e,
+                                    retry_delay(attempts)
+                                );
+                                finished.push(Attempted::Retry(Delivery {
+                                    attempts,
+                                    next_attempt_at: now + retry_delay(attempts),
+                                    last_error: Some(e),
+                                    ..delivery
+                                }));
+                            }
+                            // Later events for this endpoint wait for the next round
+                            break;
+                        }
+                    }
+                }
+                (sent, finished)
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_ENDPOINTS)
+        .collect()
+        .await;
+
+    let sent = rounds.iter().map(|(sent, _)| sent).sum();
+    app_state
+        .webhooks
+        .finish_round(
+            rounds
+                .into_iter()
+                .flat_map(|(_, finished)| finished)
+                .collect(),
+        )
+        .await;
+    sent
+}
+
+// Queue a delivery for every event a webhook wants, and send queued deliveries as they
+// become due
+pub fn spawn_webhooks(app_state: AppState) -> JoinHandle<()> {
+    let mut events = app_state.events.subscribe();
+    let queue_state = app_state.clone();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let config = queue_state.config.get();
+                    let deliveries = deliveries_for(&event, &config, unix_now());
+                    queue_state.webhooks.push(deliveries).await;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("Webhooks fell behind; {} events were not sent", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let waiting = app_state.webhooks.all().await.len();
+        if waiting > 0 {
+            println!("{} webhook deliveries waiting from the last run", waiting);
+        }
+
+        let client = http_client();
+        loop {
+            deliver_due(&app_state, &client, unix_now()).await;
+
+            // Sleep until the next retry is due or a new delivery is queued
+            let wait = app_state
+                .webhooks
+                .next_due_in(unix_now())
+                .await
+                .unwrap_or(RETRY_MAX_SECONDS)
+                .max(1);
+            tokio::select! {
+                _ = app_state.webhooks.added.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            }
+        }
+    })
+}
@@ -0,0 +1,369 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+use crate::config::{Config, Device};
+use crate::events::Event;
+use crate::poller::probe_devices;
+use crate::routes::{probe_device, shutdown_and_record, wake_and_record, AppState, DeviceStatus};
+use crate::tokens::{ApiToken, Scope};
+
+// Source recorded in history for actions sent over the WebSocket
+const WEBSOCKET_SOURCE: &str = "websocket";
+
+// Messages a client sends. `id` is any JSON value and is echoed back in the reply.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    // Replace the connection's event filter
+    Subscribe {
+        #[serde(default)]
+        id: Option<Value>,
+        #[serde(flatten)]
+        subscription: Subscription,
+    },
+    Wake {
+        #[serde(default)]
+        id: Option<Value>,
+        device: String,
+    },
+    Shutdown {
+        #[serde(default)]
+        id: Option<Value>,
+        device: String,
+    },
+    // Ping one device now, or every device when `device` is left out
+    Status {
+        #[serde(default)]
+        id: Option<Value>,
+        #[serde(default)]
+        device: Option<String>,
+    },
+}
+
+// Messages the server sends
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    // Answer to the client message with the same id
+    Reply {
+        id: Option<Value>,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        result: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    // An event from the event bus that passed the connection's filter
+    Event {
+        event: Event,
+    },
+    // The connection fell behind and this many events were dropped
+    Lagged {
+        missed: u64,
+    },
+}
+
+impl ServerMessage {
+    fn ok(id: Option<Value>, result: Value) -> Self {
+        ServerMessage::Reply {
+            id,
+            ok: true,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Option<Value>, error: String) -> Self {
+        ServerMessage::Reply {
+            id,
+            ok: false,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+// Which events a connection receives. Empty lists do not filter. Events about a device pass
+// when the device matches any of `devices` (id or name), `groups` or `tags`; events that are
+// not about a device are only filtered by `events`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    pub devices: Vec<String>,
+    pub groups: Vec<String>,
+    pub tags: Vec<String>,
+    // Event types, e.g. "status_changed"
+    pub events: Vec<String>,
+}
+
+impl Subscription {
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .events
+            .iter()
+            .find(|kind| !Event::KINDS.contains(&kind.as_str()))
+        {
+            Some(kind) => Err(format!(
+                "Unknown event type '{}'. Use one of: {}",
+                kind,
+                Event::KINDS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, event: &Event, config: &Config) -> bool {
+        if !self.events.is_empty() && !self.events.iter().any(|kind| kind == event.kind()) {
+            return false;
+        }
+        let Some(device_id) = event.device_id() else {
+            return true;
+        };
+        if self.devices.is_empty() && self.groups.is_empty() && self.tags.is_empty() {
+            return true;
+        }
+
+        let named = self.devices.iter().any(|key| {
+            key == device_id
+                || config
+                    .find_device(key)
+                    .is_some_and(|device| device.device_id() == device_id)
+        });
+        // Groups and tags come from the current config, so a removed device only matches by id
+        let labelled = config
+            .devices
+            .iter()
+            .find(|device| device.device_id() == device_id)
+            .is_some_and(|device| {
+                device
+                    .groups
+                    .iter()
+                    .any(|group| self.groups.contains(group))
+                    || device.tags.iter().any(|tag| self.tags.contains(tag))
+            });
+        named || labelled
+    }
+}
+
+impl ClientMessage {
+    // The API token scope needed to send this message
+    pub fn scope(&self) -> Scope {
+        match self {
+            ClientMessage::Wake { .. } => Scope::Wake,
+            ClientMessage::Shutdown { .. } => Scope::Shutdown,
+            ClientMessage::Subscribe { .. } | ClientMessage::Status { .. } => Scope::StatusRead,
+        }
+    }
+
+    fn id(&self) -> Option<Value> {
+        match self {
+            ClientMessage::Subscribe { id, .. }
+            | ClientMessage::Wake { id, .. }
+            | ClientMessage::Shutdown { id, .. }
+            | ClientMessage::Status { id, .. } => id.clone(),
+        }
+    }
+}
+
+// Handle one client message and build its reply
+pub async fn run_command(app_state: &AppState, message: ClientMessage) -> ServerMessage {
+    let config = app_state.config.get();
+    let not_found = |key: &str| format!("Device '{}' not found", key);
+
+    match message {
+        ClientMessage::Subscribe { id, subscription } => match subscription.validate() {
+            Ok(()) => ServerMessage::ok(id, json!({ "subscription": subscription })),
+            Err(e) => ServerMessage::error(id, e),
+        },
+        ClientMessage::Wake { id, device } => {
+            let Some(device) = config.find_device(&device) else {
+                return ServerMessage::error(id, not_found(&device));
+            };
+            match wake_and_record(app_state, device, WEBSOCKET_SOURCE).await {
+                Ok(()) => {
+                    println!("Wake-on-LAN packet sent to device: {}", device.name);
+                    ServerMessage::ok(id, device_json(device))
+                }
+                Err(e) => ServerMessage::error(id, e.to_string()),
+            }
+        }
+        ClientMessage::Shutdown { id, device } => {
+            let Some(device) = config.find_device(&device) else {
+                return ServerMessage::error(id, not_found(&device));
+            };
+            if device.shutdown_command.is_empty() {
+                return ServerMessage::error(
+                    id,
+                    format!("{} has no shutdown_command configured", device.name),
+                );
+            }
+            match shutdown_and_record(app_state, device, WEBSOCKET_SOURCE).await {
+                Ok(()) => {
+                    println!("Shutdown command run for device: {}", device.name);
+                    ServerMessage::ok(id, device_json(device))
+                }
+                Err(e) => ServerMessage::error(id, e),
+            }
+        }
+        ClientMessage::Status { id, device: None } => {
+            // Same limit on pings in flight as a poller round
+            let devices: Vec<Value> = probe_devices(&config)
+                .await
+                .iter()
+                .map(|(device, status, latency_ms)| status_json(device, *status, *latency_ms))
+                .collect();
+            ServerMessage::ok(id, json!({ "devices": devices }))
+        }
+        ClientMessage::Status {
+            id,
+            device: Some(device),
+        } => {
+            let Some(device) = config.find_device(&device) else {
+                return ServerMessage::error(id, not_found(&device));
+            };
+            let (status, latency_ms) = probe_device(&device.ip_address).await;
+            ServerMessage::ok(id, status_json(device, status, latency_ms))
+        }
+    }
+}
+
+fn device_json(device: &Device) -> Value {
+    json!({
+        "device_id": device.device_id(),
+        "device_name": device.name,
+    })
+}
+
+fn status_json(device: &Device, status: DeviceStatus, latency_ms: Option<f64>) -> Value {
+    json!({
+        "device_id": device.device_id(),
+        "device_name": device.name,
+        "status": status,
+        "latency_ms": latency_ms,
+    })
+}
+
+// Whether a connection opened with `token` (None for a login session) may send `message`
+fn token_allows(token: Option<&ApiToken>, message: &ClientMessage) -> bool {
+    token.is_none_or(|token| token.scopes.contains(&message.scope()))
+}
+
+// Browsers send the page's Origin with every upgrade but, unlike fetch, do not enforce CORS
+// on WebSockets, so a page on another site could use the visitor's session. Only this
+// server's own pages (same host, or server.external_url) may connect from a browser; clients
+// without an Origin header, such as scripts with a token, are not affected.
+pub fn origin_allowed(headers: &HeaderMap, config: &Config) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    let same_host = match (
+        origin.split_once("://"),
+        headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok()),
+    ) {
+        (Some((_, origin_host)), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    };
+    same_host
+        || reqwest::Url::parse(&config.server.external_url)
+            .is_ok_and(|url| url.origin().ascii_serialization() == origin)
+}
+
+// Handler for GET /api/v1/ws. Connections opened with an API token may only send commands
+// within its scopes.
+pub async fn websocket_handler(
+    websocket: WebSocketUpgrade,
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    token: Option<Extension<ApiToken>>,
+) -> Response {
+    if !origin_allowed(&headers, &app_state.config.get()) {
+        eprintln!("Rejected WebSocket connection from another origin");
+        return (
+            StatusCode::FORBIDDEN,
+            "Cross-origin WebSocket connections are not allowed",
+        )
+            .into_response();
+    }
+    let token = token.map(|Extension(token)| token);
+    websocket.on_upgrade(move |socket| handle_socket(socket, app_state, token))
+}
+
+async fn handle_socket(mut socket: WebSocket, app_state: AppState, token: Option<ApiToken>) {
+    let mut events = app_state.events.subscribe();
+    let mut subscription = Subscription::default();
+    // Commands run in their own tasks so a slow shutdown does not hold up events
+    let (reply_sender, mut replies) = mpsc::unbounded_channel();
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => match parse_client_message(&text) {
+                    Ok(message) if !token_allows(token.as_ref(), &message) => {
+                        let error = format!("Token lacks the {} scope", message.scope().as_str());
+                        Some(ServerMessage::error(message.id(), error))
+                    }
+                    Ok(message) => {
+                        if let ClientMessage::Subscribe { subscription: requested, .. } = &message {
+                            if requested.validate().is_ok() {
+                                subscription = requested.clone();
+                            }
+                        }
+                        let app_state = app_state.clone();
+                        let reply_sender = reply_sender.clone();
+                        tokio::spawn(async move {
+                            let _ = reply_sender.send(run_command(&app_state, message).await);
+                        });
+                        None
+                    }
+                    Err(reply) => Some(reply),
+                },
+                // Pings are answered by axum; binary messages are not part of the protocol
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            event = events.recv() => match event {
+                Ok(event) if subscription.matches(&event, &app_state.config.get()) => {
+                    Some(ServerMessage::Event { event })
+                }
+                Ok(_) => None,
+                Err(RecvError::Lagged(missed)) => Some(ServerMessage::Lagged { missed }),
+                Err(RecvError::Closed) => break,
+            },
+            Some(reply) = replies.recv() => Some(reply),
+        };
+
+        if let Some(message) = outgoing {
+            let Ok(text) = serde_json::to_string(&message) else {
+                continue;
+            };
+            if socket.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// Parse a client message; on failure the error reply still carries the id when there is one
+pub fn parse_client_message(text: &str) -> Result<ClientMessage, ServerMessage> {
+    serde_json::from_str(text).map_err(|e| {
+        let id = serde_json::from_str::<Value>(text)
+            .ok()
+            .and_then(|value| value.get("id").cloned());
+        ServerMessage::error(id, format!("Invalid message: {}", e))
+    })
+}
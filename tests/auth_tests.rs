@@ -0,0 +1,269 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use std::sync::OnceLock;
+use tower::ServiceExt;
+use wololo::auth::*;
+use wololo::config::{AuthConfig, Config, User};
+use wololo::routes::{app_router, AppState};
+
+// Argon2 is slow in debug builds, so every test shares one hash of "hunter2"
+fn password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("hunter2").unwrap())
+}
+
+fn auth_config() -> AuthConfig {
+    AuthConfig {
+        enabled: true,
+        users: vec![User {
+            username: "admin".to_string(),
+            password_hash: password_hash().to_string(),
+        }],
+        session_secret: Some("a session secret for the tests".to_string()),
+        ..Default::default()
+    }
+}
+
+fn app() -> Router {
+    app_router(AppState::new_for_test(Config {
+        auth: auth_config(),
+        ..Default::default()
+    }))
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, headers, String::from_utf8_lossy(&body).to_string())
+}
+
+fn get(uri: &str) -> axum::http::request::Builder {
+    Request::builder().uri(uri)
+}
+
+async fn log_in(app: Router, password: &str) -> (StatusCode, HeaderMap, String) {
+    let body = format!("username=admin&password={}&next=%2Fdevices", password);
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/login")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap();
+    send(app, request).await
+}
+
+// "name=value" of the Set-Cookie header, for sending back
+fn session_from(headers: &HeaderMap) -> String {
+    let set_cookie = headers[header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+#[test]
+fn test_passwords() {
+    assert!(password_hash().starts_with("$argon2id$"));
+    assert!(is_valid_hash(password_hash()));
+    assert!(!is_valid_hash("hunter2"));
+    assert!(verify_password(password_hash(), "hunter2"));
+    assert!(!verify_password(password_hash(), "hunter3"));
+
+    let auth = auth_config();
+    assert_eq!(
+        authenticate(&auth.users, "admin", "hunter2").map(|user| user.username),
+        Some("admin".to_string())
+    );
+    assert!(authenticate(&auth.users, "admin", "wrong").is_none());
+    assert!(authenticate(&auth.users, "nobody", "hunter2").is_none());
+}
+
+#[test]
+fn test_users_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("users.yaml");
+    std::fs::write(
+        &path,
+        format!(
+            "- username: alice\n  password_hash: \"{}\"\n",
+            password_hash()
+        ),
+    )
+    .unwrap();
+    let auth = AuthConfig {
+        users_file: Some(path.to_string_lossy().to_string()),
+        ..auth_config()
+    };
+
+    let users = load_users(&auth).unwrap();
+    let names: Vec<&str> = users.iter().map(|user| user.username.as_str()).collect();
+    assert_eq!(names, ["admin", "alice"]);
+    assert!(authenticate(&users, "alice", "hunter2").is_some());
+}
+
+#[test]
+fn test_session_cookies_expire_and_are_signed() {
+    let auth = auth_config();
+    let key = session_key(&auth);
+    let jar = SignedCookieJar::new(key.clone()).add(session_cookie(&auth.users[0], &auth, 1_000));
+    assert_eq!(
+        session_user(&jar, &auth.users, 1_000),
+        Some("admin".to_string())
+    );
+    // session_hours defaults to 24
+    assert_eq!(
+        session_user(&jar, &auth.users, 1_000 + 24 * 3600 - 1),
+        Some("admin".to_string())
+    );
+    assert_eq!(session_user(&jar, &auth.users, 1_000 + 24 * 3600), None);
+
+    // Removing the user or changing their password ends the session
+    let removed = AuthConfig {
+        users: Vec::new(),
+        ..auth.clone()
+    };
+    assert_eq!(session_user(&jar, &removed.users, 1_000), None);
+    let changed = AuthConfig {
+        users: vec![User {
+            username: "admin".to_string(),
+            password_hash: "$argon2id$v=19$m=19456,t=2,p=1$bmV3c2FsdA$bmV3aGFzaA".to_string(),
+        }],
+        ..auth.clone()
+    };
+    assert_eq!(session_user(&jar, &changed.users, 1_000), None);
+
+    // Another secret does not accept the cookie
+    let mut headers = HeaderMap::new();
+    let signed = jar.get(SESSION_COOKIE).unwrap();
+    let cookie = format!("{}={}", SESSION_COOKIE, signed.value());
+    headers.insert(header::COOKIE, cookie.parse().unwrap());
+    let other_key = session_key(&AuthConfig {
+        session_secret: Some("another secret".to_string()),
+        ..auth.clone()
+    });
+    assert_eq!(
+        session_user(
+            &SignedCookieJar::from_headers(&headers, other_key),
+            &auth.users,
+            1_000
+        ),
+        None
+    );
+
+    // Nor a cookie whose value was changed
+    let tampered = cookie.replace("admin", "root");
+    headers.insert(header::COOKIE, tampered.parse().unwrap());
+    assert_eq!(
+        session_user(
+            &SignedCookieJar::from_headers(&headers, key),
+            &auth.users,
+            1_000
+        ),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_unauthenticated_requests() {
+    // Pages redirect to the login page and come back afterwards
+    let request = get("/devices?view=list")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = send(app(), request).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        headers[header::LOCATION],
+        "/login?next=%2Fdevices%3Fview%3Dlist"
+    );
+
+    // htmx is told to load the login page
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/wake/nas")
+        .header("HX-Request", "true")
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = send(app(), request).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(headers["HX-Redirect"], "/login");
+
+    // Scripts get a 401
+    let request = get("/api/v1/history/actions").body(Body::empty()).unwrap();
+    let (status, _, body) = send(app(), request).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, "Login required");
+
+    // Health checks and the login page stay open
+    for uri in ["/healthz", "/login"] {
+        let (status, _, _) = send(app(), get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_login_and_logout() {
+    let (status, headers, body) = log_in(app(), "wrong").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(!headers.contains_key(header::SET_COOKIE));
+    assert_eq!(body, "login Wrong username or password");
+
+    let (status, headers, _) = log_in(app(), "hunter2").await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(headers[header::LOCATION], "/devices");
+    let set_cookie = headers[header::SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("HttpOnly"));
+    assert!(set_cookie.contains("SameSite=Lax"));
+    assert!(set_cookie.contains("Max-Age=86400"));
+    let session = session_from(&headers);
+
+    // The cookie opens the API
+    let request = get("/api/v1/history/actions")
+        .header(header::COOKIE, &session)
+        .body(Body::empty())
+        .unwrap();
+    let (status, _, _) = send(app(), request).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Logging out clears it
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/logout")
+        .header(header::COOKIE, &session)
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = send(app(), request).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(headers[header::LOCATION], "/login");
+    assert!(session_from(&headers).ends_with('='));
+}
+
+#[tokio::test]
+async fn test_login_only_redirects_within_the_server() {
+    let body = "username=admin&password=hunter2&next=%2F%2Fevil.example.com";
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/login")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap();
+    let (status, headers, _) = send(app(), request).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(headers[header::LOCATION], "/");
+}
+
+#[tokio::test]
+async fn test_disabled_auth_lets_everything_through() {
+    let app = app_router(AppState::new_for_test(Config::default()));
+    let request = get("/api/v1/history/actions").body(Body::empty()).unwrap();
+    let (status, _, _) = send(app.clone(), request).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // There is nothing to log in to
+    let (status, headers, _) = send(app, get("/login").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(headers[header::LOCATION], "/");
+}
@@ -0,0 +1,126 @@
+use wololo::background_discovery::SeenDevices;
+use wololo::events::{Event, EventBus};
+use wololo::*;
+
+fn found(ip: &str, mac: &str) -> routes::DiscoveredDevice {
+    routes::DiscoveredDevice {
+        ip_address: ip.to_string(),
+        mac_address: Some(mac.to_string()),
+        hostname: None,
+        status: "Online".to_string(),
+    }
+}
+
+fn config_with_device(mac: &str) -> Config {
+    Config {
+        devices: vec![Device {
+            name: "Configured".to_string(),
+            mac_address: mac.to_string(),
+            ip_address: "192.168.1.2".to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_first_scan_is_baseline() {
+    let seen = SeenDevices::in_memory();
+    let config = Config::default();
+
+    let new_devices = seen
+        .record(&[found("192.168.1.10", "AA:BB:CC:DD:EE:01")], &config)
+        .await;
+
+    assert!(new_devices.is_empty());
+    assert!(seen.unacknowledged().await.is_empty());
+    assert_eq!(seen.all().await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_new_mac_is_reported_once() {
+    let seen = SeenDevices::in_memory();
+    let config = config_with_device("AA:BB:CC:DD:EE:02");
+    seen.record(&[found("192.168.1.10", "AA:BB:CC:DD:EE:01")], &config)
+        .await;
+
+    let scan = vec![
+        found("192.168.1.10", "aa-bb-cc-dd-ee-01"), // already seen, different spelling
+        found("192.168.1.2", "AA:BB:CC:DD:EE:02"),  // configured
+        found("192.168.1.99", "AA:BB:CC:DD:EE:03"), // unknown
+    ];
+    let new_devices = seen.record(&scan, &config).await;
+    assert_eq!(new_devices.len(), 1);
+    assert_eq!(new_devices[0].mac_address, "AA:BB:CC:DD:EE:03");
+
+    // Seeing it again does not raise a second alert
+    let new_devices = seen.record(&scan, &config).await;
+    assert!(new_devices.is_empty());
+    assert_eq!(seen.unacknowledged().await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_acknowledge_new_devices() {
+    let seen = SeenDevices::in_memory();
+    let config = Config::default();
+    seen.record(&[found("192.168.1.1", "AA:BB:CC:DD:EE:00")], &config)
+        .await;
+    seen.record(
+        &[
+            found("192.168.1.2", "AA:BB:CC:DD:EE:01"),
+            found("192.168.1.3", "AA:BB:CC:DD:EE:02"),
+        ],
+        &config,
+    )
+    .await;
+    assert_eq!(seen.unacknowledged().await.len(), 2);
+
+    assert_eq!(seen.acknowledge(Some("aa:bb:cc:dd:ee:01")).await, 1);
+    assert_eq!(seen.unacknowledged().await.len(), 1);
+
+    assert_eq!(seen.acknowledge(None).await, 1);
+    assert!(seen.unacknowledged().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_seen_devices_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("seen.json");
+    let config = Config::default();
+
+    {
+        let seen = SeenDevices::load(&path);
+        seen.record(&[found("192.168.1.1", "AA:BB:CC:DD:EE:00")], &config)
+            .await;
+        seen.record(&[found("192.168.1.2", "AA:BB:CC:DD:EE:01")], &config)
+            .await;
+    }
+
+    let reloaded = SeenDevices::load(&path);
+    assert_eq!(reloaded.all().await.len(), 2);
+    let pending = reloaded.unacknowledged().await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].mac_address, "AA:BB:CC:DD:EE:01");
+}
+
+#[tokio::test]
+async fn test_event_bus_delivers_new_device_events() {
+    let bus = EventBus::new();
+    let mut receiver = bus.subscribe();
+
+    let seen = SeenDevices::in_memory();
+    let config = Config::default();
+    seen.record(&[found("192.168.1.1", "AA:BB:CC:DD:EE:00")], &config)
+        .await;
+    for device in seen
+        .record(&[found("192.168.1.2", "AA:BB:CC:DD:EE:01")], &config)
+        .await
+    {
+        bus.publish(Event::NewDevice { device });
+    }
+
+    let event = receiver.recv().await.unwrap();
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["type"], "new_device");
+    assert_eq!(json["device"]["mac_address"], "AA:BB:CC:DD:EE:01");
+}
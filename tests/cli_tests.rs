@@ -0,0 +1,119 @@
+use clap::Parser;
+use std::io::Write;
+use tempfile::NamedTempFile;
+use wololo::cli::*;
+use wololo::config::{Config, Device};
+
+fn config() -> Config {
+    Config {
+        devices: vec![
+            Device {
+                name: "build-1".to_string(),
+                mac_address: "00:11:22:33:44:01".to_string(),
+                ip_address: "192.168.1.21".to_string(),
+                groups: vec!["build".to_string()],
+                ..Default::default()
+            },
+            Device {
+                name: "build-2".to_string(),
+                mac_address: "00:11:22:33:44:02".to_string(),
+                ip_address: "192.168.1.22".to_string(),
+                groups: vec!["build".to_string()],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_cli_parsing() {
+    let cli = Cli::try_parse_from(["wololo"]).unwrap();
+    assert!(cli.command.is_none());
+    assert_eq!(cli.config.to_str(), Some("config.yaml"));
+
+    let cli =
+        Cli::try_parse_from(["wololo", "wake", "build", "--config", "/etc/wololo.yaml"]).unwrap();
+    assert!(matches!(cli.command, Some(Command::Wake { ref target }) if target == "build"));
+    assert_eq!(cli.config.to_str(), Some("/etc/wololo.yaml"));
+
+    let cli = Cli::try_parse_from(["wololo", "scan", "--cidr", "10.0.0.0/28", "--json"]).unwrap();
+    match cli.command {
+        Some(Command::Scan { cidr, output }) => {
+            assert_eq!(cidr.unwrap().to_string(), "10.0.0.0/28");
+            assert!(output.json);
+        }
+        _ => panic!("expected scan"),
+    }
+
+    assert!(Cli::try_parse_from(["wololo", "scan", "--cidr", "not-a-range"]).is_err());
+    assert!(Cli::try_parse_from(["wololo", "config"]).is_err());
+
+    let cli = Cli::try_parse_from(["wololo", "config", "print", "--show-secrets"]).unwrap();
+    assert!(matches!(
+        cli.command,
+        Some(Command::Config {
+            command: ConfigCommand::Print { show_secrets: true }
+        })
+    ));
+}
+
+#[test]
+fn test_config_print_redacts_secrets() {
+    let mut config = config();
+    config.auth.session_secret = Some("session-secret".to_string());
+    config.mqtt.password = Some("mqtt-pass".to_string());
+    config.mqtt.username = Some("wololo".to_string());
+
+    let redacted = config.redacted();
+    assert_eq!(
+        redacted.auth.session_secret.as_deref(),
+        Some(wololo::config::REDACTED)
+    );
+    assert_eq!(
+        redacted.mqtt.password.as_deref(),
+        Some(wololo::config::REDACTED)
+    );
+    // Only secrets are hidden
+    assert_eq!(redacted.mqtt.username.as_deref(), Some("wololo"));
+    assert_eq!(redacted.devices[0].mac_address, "00:11:22:33:44:01");
+    assert_eq!(config.mqtt.password.as_deref(), Some("mqtt-pass"));
+}
+
+#[test]
+fn test_devices_matching_name_or_group() {
+    let config = config();
+    assert_eq!(config.devices_matching("build-2").len(), 1);
+    assert_eq!(config.devices_matching("build").len(), 2);
+    assert!(config.devices_matching("office").is_empty());
+}
+
+#[tokio::test]
+async fn test_unknown_target_exit_code() {
+    let config = config();
+    assert_eq!(wake_command(&config, "office").await, EXIT_NOT_FOUND);
+    assert_eq!(
+        status_command(&config, Some("office"), false).await,
+        EXIT_NOT_FOUND
+    );
+}
+
+#[test]
+fn test_config_validate_exit_codes() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(
+        file,
+        "devices:\n  - name: pc\n    mac_address: \"AA:BB:CC:DD:EE:FF\"\n    ip_address: \"192.168.1.5\""
+    )
+    .unwrap();
+    assert_eq!(config_validate_command(file.path()), EXIT_OK);
+
+    let mut broken = NamedTempFile::new().unwrap();
+    writeln!(broken, "devices: [").unwrap();
+    assert_eq!(config_validate_command(broken.path()), EXIT_CONFIG);
+
+    assert_eq!(
+        config_validate_command(std::path::Path::new("/nonexistent/config.yaml")),
+        EXIT_CONFIG
+    );
+}
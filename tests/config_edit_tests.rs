@@ -0,0 +1,218 @@
+use tempfile::TempDir;
+use wololo::config::{Config, Device};
+use wololo::config_edit::*;
+use wololo::reload::ConfigHandle;
+
+const CONFIG: &str = r#"# Home lab
+server:
+  ip: "0.0.0.0"
+  port: 3000 # behind the proxy
+
+devices:
+  # Main workstation
+  - name: "Desktop"
+    mac_address: "AA:BB:CC:DD:EE:01" # onboard NIC
+    ip_address: "192.168.1.10"
+    location: office
+  - name: NAS
+    mac_address: "AA:BB:CC:DD:EE:02"
+    ip_address: "192.168.1.20"
+    groups: [storage]
+
+# Keep the sound on
+sound:
+  enabled: true
+"#;
+
+fn devices_of(contents: &str) -> Vec<Device> {
+    serde_yaml::from_str::<Config>(contents).unwrap().devices
+}
+
+fn edit(contents: &str, edit: DeviceEdit) -> String {
+    let updated = edit_devices_text(contents, &devices_of(contents), &edit).unwrap();
+    // Whatever the edit, the result must still be a loadable config
+    serde_yaml::from_str::<Config>(&updated).unwrap();
+    updated
+}
+
+fn laptop() -> Device {
+    Device {
+        name: "Laptop".to_string(),
+        mac_address: "AA:BB:CC:DD:EE:03".to_string(),
+        ip_address: "192.168.1.30".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_add_keeps_the_rest_of_the_file() {
+    let updated = edit(CONFIG, DeviceEdit::Add(laptop()));
+
+    let names: Vec<String> = devices_of(&updated).into_iter().map(|d| d.name).collect();
+    assert_eq!(names, ["Desktop", "NAS", "Laptop"]);
+    // Everything around the new entry is unchanged, including comments and unknown keys
+    let cut = CONFIG.find("\n\n# Keep the sound on").unwrap() + 1;
+    assert!(updated.starts_with(&CONFIG[..cut]));
+    assert!(updated.ends_with(&CONFIG[cut..]));
+    assert!(updated[cut..].starts_with("  - name: Laptop\n"));
+}
+
+#[test]
+fn test_update_in_place_keeps_comments() {
+    let mut desktop = devices_of(CONFIG).remove(0);
+    desktop.ip_address = "192.168.1.11".to_string();
+    desktop.name = "Workstation".to_string();
+
+    let updated = edit(
+        CONFIG,
+        DeviceEdit::Update {
+            id: "Desktop".to_string(),
+            device: desktop,
+        },
+    );
+
+    assert!(updated.contains("  # Main workstation\n  - name: Workstation\n"));
+    assert!(updated.contains("mac_address: \"AA:BB:CC:DD:EE:01\" # onboard NIC\n"));
+    assert!(updated.contains("    ip_address: 192.168.1.11\n    location: office\n"));
+}
+
+#[test]
+fn test_update_of_groups_rebuilds_the_entry_with_unknown_keys() {
+    let mut desktop = devices_of(CONFIG).remove(0);
+    desktop.groups = vec!["office".to_string()];
+
+    let updated = edit(
+        CONFIG,
+        DeviceEdit::Update {
+            id: "Desktop".to_string(),
+            device: desktop,
+        },
+    );
+
+    let value: serde_yaml::Value = serde_yaml::from_str(&updated).unwrap();
+    assert_eq!(value["devices"][0]["location"].as_str(), Some("office"));
+    assert_eq!(value["devices"][0]["groups"][0].as_str(), Some("office"));
+    // The comment above the entry moves with it
+    assert!(updated.contains("  # Main workstation\n  - name: Desktop\n"));
+}
+
+#[test]
+fn test_delete_and_move() {
+    let moved = edit(
+        CONFIG,
+        DeviceEdit::Move {
+            id: "NAS".to_string(),
+            position: 0,
+        },
+    );
+    let names: Vec<String> = devices_of(&moved).into_iter().map(|d| d.name).collect();
+    assert_eq!(names, ["NAS", "Desktop"]);
+    assert!(moved.contains("  # Main workstation\n  - name: \"Desktop\"\n"));
+
+    let deleted = edit(
+        CONFIG,
+        DeviceEdit::Delete {
+            id: "Desktop".to_string(),
+        },
+    );
+    assert_eq!(devices_of(&deleted).len(), 1);
+    assert!(!deleted.contains("Main workstation"));
+
+    let emptied = edit(
+        &deleted,
+        DeviceEdit::Delete {
+            id: "NAS".to_string(),
+        },
+    );
+    assert!(emptied.contains("devices: []\n"));
+    assert!(devices_of(&emptied).is_empty());
+
+    let missing = edit_devices_text(
+        CONFIG,
+        &devices_of(CONFIG),
+        &DeviceEdit::Delete {
+            id: "Printer".to_string(),
+        },
+    );
+    assert!(matches!(missing, Err(EditError::NotFound(_))));
+}
+
+#[test]
+fn test_add_to_empty_or_missing_list() {
+    let empty = edit("devices: []\n", DeviceEdit::Add(laptop()));
+    assert_eq!(devices_of(&empty).len(), 1);
+
+    let missing =
+        edit_devices_text("sound:\n  enabled: false", &[], &DeviceEdit::Add(laptop())).unwrap();
+    assert!(missing.starts_with("sound:\n  enabled: false\ndevices:\n"));
+    assert_eq!(devices_of(&missing)[0].name, "Laptop");
+}
+
+#[test]
+fn test_backups_sort_by_time_then_counter() {
+    let dir = TempDir::new().unwrap();
+    for stamp in [
+        "20261018-142501-10",
+        "20261018-142501-2",
+        "20261019-090000",
+        "20261018-142501",
+    ] {
+        std::fs::write(dir.path().join(format!("config.yaml.{}.bak", stamp)), "").unwrap();
+    }
+
+    let names: Vec<String> = list_backups(dir.path(), "config.yaml")
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "config.yaml.20261018-142501.bak",
+            "config.yaml.20261018-142501-2.bak",
+            "config.yaml.20261018-142501-10.bak",
+            "config.yaml.20261019-090000.bak",
+        ]
+    );
+}
+
+#[test]
+fn test_apply_writes_atomically_with_backup_and_detects_conflicts() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, CONFIG).unwrap();
+    let handle = ConfigHandle::with_path(serde_yaml::from_str(CONFIG).unwrap(), &path);
+
+    let file = read_config_file(&handle).unwrap();
+    assert_eq!(file.hash, content_hash(CONFIG));
+
+    let written = apply_device_edit(&handle, &file.hash, &DeviceEdit::Add(laptop())).unwrap();
+    assert_eq!(written.devices.len(), 3);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written.contents);
+
+    let backups = list_backups(dir.path(), "config.yaml");
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_to_string(&backups[0]).unwrap(), CONFIG);
+
+    // The old hash no longer matches what is on disk
+    let stale = apply_device_edit(
+        &handle,
+        &file.hash,
+        &DeviceEdit::Delete {
+            id: "NAS".to_string(),
+        },
+    );
+    assert!(matches!(stale, Err(EditError::Conflict)));
+
+    // Edits that would make the config invalid are not written
+    let duplicate = apply_device_edit(&handle, &written.hash, &DeviceEdit::Add(laptop()));
+    assert!(matches!(duplicate, Err(EditError::Invalid(_))));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written.contents);
+
+    // No temp files are left behind
+    let leftovers = std::fs::read_dir(dir.path())
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
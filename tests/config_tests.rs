@@ -1,6 +1,7 @@
 use std::io::Write;
 use tempfile::NamedTempFile;
 use wololo::config::*;
+use wololo::validation::check_config;
 
 #[test]
 fn test_default_server_config() {
@@ -13,7 +14,7 @@ fn test_default_server_config() {
 #[test]
 fn test_default_sync_config() {
     let config = SyncConfig::default();
-    assert_eq!(config.enabled, true);
+    assert!(config.enabled);
     assert_eq!(config.interval_seconds, 60);
 }
 
@@ -49,7 +50,7 @@ devices:
     assert_eq!(config.server.external_url, "http://example.com:8080");
 
     // Test sync config
-    assert_eq!(config.sync.enabled, false);
+    assert!(!config.sync.enabled);
     assert_eq!(config.sync.interval_seconds, 120);
 
     // Test devices
@@ -73,7 +74,7 @@ devices:
     // Should use defaults for missing sections
     assert_eq!(config.server.ip, "127.0.0.1");
     assert_eq!(config.server.port, 3000);
-    assert_eq!(config.sync.enabled, true);
+    assert!(config.sync.enabled);
     assert_eq!(config.sync.interval_seconds, 60);
     assert_eq!(config.devices.len(), 1);
 }
@@ -83,14 +84,16 @@ fn test_load_nonexistent_config() {
     // Test with a file that doesn't exist (no file deletion needed)
     let result = load_config_from_path("nonexistent_test_file.yaml");
     assert!(result.is_err());
-    
+
     // Also test the error type to make sure it's a file not found error
     match result {
         Err(e) => {
             let error_string = e.to_string();
-            assert!(error_string.contains("nonexistent_test_file.yaml") || 
-                    error_string.contains("No such file") ||
-                    error_string.contains("cannot find"));
+            assert!(
+                error_string.contains("nonexistent_test_file.yaml")
+                    || error_string.contains("No such file")
+                    || error_string.contains("cannot find")
+            );
         }
         Ok(_) => panic!("Expected error but got Ok"),
     }
@@ -102,6 +105,7 @@ fn test_device_serialization() {
         name: "Test Device".to_string(),
         mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
         ip_address: "192.168.1.100".to_string(),
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&device).unwrap();
@@ -145,7 +149,10 @@ fn test_load_default_config() {
             Ok(config) => {
                 // Basic validation that we got a config
                 assert!(!config.devices.is_empty() || config.devices.is_empty()); // Always true, just checking it doesn't panic
-                println!("Successfully loaded default config with {} devices", config.devices.len());
+                println!(
+                    "Successfully loaded default config with {} devices",
+                    config.devices.len()
+                );
             }
             Err(e) => {
                 // If config.yaml exists but is invalid, that's still a valid test result
@@ -156,3 +163,179 @@ fn test_load_default_config() {
         println!("config.yaml doesn't exist, skipping default config test");
     }
 }
+
+#[test]
+fn test_default_discovery_config() {
+    let config = DiscoveryConfig::default();
+    assert_eq!(config.max_concurrency, 32);
+    assert_eq!(config.packets_per_second, 50);
+    assert_eq!(config.host_timeout_seconds, 1);
+    assert_eq!(config.retries, 0);
+}
+
+#[test]
+fn test_load_discovery_config() {
+    let config_content = r#"
+discovery:
+  max_concurrency: 8
+  packets_per_second: 0
+  retries: 2
+
+devices: []
+"#;
+
+    let config: Config = serde_yaml::from_str(config_content).unwrap();
+
+    assert_eq!(config.discovery.max_concurrency, 8);
+    assert_eq!(config.discovery.packets_per_second, 0);
+    assert_eq!(config.discovery.retries, 2);
+    // Missing fields fall back to defaults
+    assert_eq!(config.discovery.host_timeout_seconds, 1);
+}
+
+fn test_env(name: &str) -> Option<String> {
+    match name {
+        "HOST_IP" => Some("0.0.0.0".to_string()),
+        "PORT" => Some("8080".to_string()),
+        "NAS_MAC" => Some("AA:BB:CC:DD:EE:01".to_string()),
+        "EMPTY" => Some(String::new()),
+        _ => None,
+    }
+}
+
+// Parse through the same path the server uses, flattening the issues into one message
+fn parse_with_env(contents: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<Config, String> {
+    check_config(contents, "config.yaml", env)
+        .map(|(config, _)| config)
+        .map_err(|issues| {
+            issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+}
+
+#[test]
+fn test_config_interpolation() {
+    let config_content = r#"
+server:
+  ip: "${HOST_IP}"
+  port: ${PORT}
+  external_url: "http://${HOST_IP}:${PORT}/$${literal}"
+
+devices:
+  - name: "${NAS_NAME:-nas}"
+    mac_address: "${NAS_MAC}"
+    ip_address: "192.168.1.${EMPTY:-10}"
+"#;
+
+    let config = parse_with_env(config_content, &test_env).unwrap();
+
+    assert_eq!(config.server.ip, "0.0.0.0");
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.server.external_url, "http://0.0.0.0:8080/${literal}");
+    assert_eq!(config.devices[0].name, "nas");
+    assert_eq!(config.devices[0].mac_address, "AA:BB:CC:DD:EE:01");
+    assert_eq!(config.devices[0].ip_address, "192.168.1.10");
+}
+
+#[test]
+fn test_config_interpolation_missing_variable() {
+    let config_content = "server:\n  ip: \"${MISSING}\"\ndevices: []\n";
+    let error = parse_with_env(config_content, &test_env).unwrap_err();
+    assert!(error.to_string().contains("MISSING"));
+
+    assert!(interpolate_str("${UNTERMINATED", &test_env).is_err());
+    assert_eq!(interpolate_str("pa$word", &test_env).unwrap(), "pa$word");
+}
+
+#[test]
+fn test_config_interpolation_keeps_quoted_numbers_as_strings() {
+    let env = |name: &str| match name {
+        "NAS_NAME" => Some("12345".to_string()),
+        "PORT" => Some("8080".to_string()),
+        _ => None,
+    };
+    let config_content = r#"
+server:
+  port: ${PORT}
+devices:
+  - name: "${NAS_NAME}"
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+"#;
+
+    let config = parse_with_env(config_content, &env).unwrap();
+    assert_eq!(config.devices[0].name, "12345");
+    // Unquoted references still become numbers
+    assert_eq!(config.server.port, 8080);
+}
+
+#[test]
+fn test_config_env_overrides() {
+    let env = |name: &str| match name {
+        "WOLOLO_SERVER_PORT" => Some("9000".to_string()),
+        "WOLOLO_SYNC_ENABLED" => Some("off".to_string()),
+        "WOLOLO_SOUND_ENABLED" => Some("0".to_string()),
+        _ => None,
+    };
+
+    let config = parse_with_env("server:\n  port: 3000\ndevices: []\n", &env).unwrap();
+    assert_eq!(config.server.port, 9000);
+    assert!(!config.sync.enabled);
+    assert!(!config.sound.enabled);
+    // Untouched fields keep their file or default values
+    assert_eq!(config.server.ip, "127.0.0.1");
+    assert_eq!(config.sync.interval_seconds, 60);
+
+    let bad = |name: &str| (name == "WOLOLO_SERVER_PORT").then(|| "http".to_string());
+    let error = parse_with_env("devices: []\n", &bad).unwrap_err();
+    assert!(error.to_string().contains("WOLOLO_SERVER_PORT"));
+
+    // An empty override is ignored rather than rejected
+    let empty = |name: &str| (name == "WOLOLO_SERVER_PORT").then(String::new);
+    let config = parse_with_env("server:\n  port: 3000\ndevices: []\n", &empty).unwrap();
+    assert_eq!(config.server.port, 3000);
+}
+
+#[test]
+fn test_device_ids_and_lookup() {
+    let yaml = r#"
+devices:
+  - name: "Build Server / Rack 2"
+    mac_address: "AA-BB-CC-DD-EE-01"
+    ip_address: "192.168.1.10"
+  - id: nas
+    name: "NAS"
+    mac_address: "AA:BB:CC:DD:EE:02"
+    ip_address: "192.168.1.20"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+    assert_eq!(config.devices[0].device_id(), "aa-bb-cc-dd-ee-01");
+    assert_eq!(config.devices[1].device_id(), "nas");
+
+    assert_eq!(
+        config.find_device("aa-bb-cc-dd-ee-01").unwrap().ip_address,
+        "192.168.1.10"
+    );
+    assert_eq!(config.find_device("nas").unwrap().name, "NAS");
+    // Names still work as an alias
+    assert_eq!(
+        config
+            .find_device("Build Server / Rack 2")
+            .unwrap()
+            .ip_address,
+        "192.168.1.10"
+    );
+    assert!(config.find_device("aa-bb-cc-dd-ee-03").is_none());
+
+    // Generated ids are not written back
+    let yaml = serde_yaml::to_string(&config).unwrap();
+    let id_keys = yaml
+        .lines()
+        .filter(|line| line.trim_start_matches([' ', '-']).starts_with("id:"))
+        .count();
+    assert_eq!(id_keys, 1);
+}
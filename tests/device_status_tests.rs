@@ -18,6 +18,7 @@ async fn test_device_creation() {
         name: "Test Device".to_string(),
         mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
         ip_address: "192.168.1.100".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(device.name, "Test Device");
@@ -39,6 +40,7 @@ fn test_mac_address_formats() {
             name: "Test".to_string(),
             mac_address: mac.to_string(),
             ip_address: "192.168.1.1".to_string(),
+            ..Default::default()
         };
         assert_eq!(device.mac_address, mac);
     }
@@ -60,6 +62,7 @@ fn test_ip_address_formats() {
             name: "Test".to_string(),
             mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
             ip_address: ip.to_string(),
+            ..Default::default()
         };
         assert_eq!(device.ip_address, ip);
     }
@@ -71,6 +74,7 @@ fn test_device_clone() {
         name: "Original Device".to_string(),
         mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
         ip_address: "192.168.1.100".to_string(),
+        ..Default::default()
     };
 
     let device2 = device1.clone();
@@ -123,6 +127,7 @@ fn test_device_json_serialization() {
         name: "JSON Test Device".to_string(),
         mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
         ip_address: "192.168.1.100".to_string(),
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&device).unwrap();
@@ -143,6 +148,7 @@ fn test_device_yaml_serialization() {
         name: "YAML Test Device".to_string(),
         mac_address: "11:22:33:44:55:66".to_string(),
         ip_address: "10.0.0.1".to_string(),
+        ..Default::default()
     };
 
     let yaml = serde_yaml::to_string(&device).unwrap();
@@ -174,6 +180,7 @@ fn test_device_name_edge_cases() {
             name: name.to_string(),
             mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
             ip_address: "192.168.1.1".to_string(),
+            ..Default::default()
         };
 
         // Should be able to create and serialize
@@ -181,3 +188,19 @@ fn test_device_name_edge_cases() {
         let _deserialized: Device = serde_json::from_str(&json).unwrap();
     }
 }
+
+#[test]
+fn test_ping_latency_parsing() {
+    let reply = "PING 192.168.1.10 (192.168.1.10) 56(84) bytes of data.\n\
+                 64 bytes from 192.168.1.10: icmp_seq=1 ttl=64 time=0.452 ms\n";
+    assert_eq!(routes::parse_ping_latency(reply), Some(0.452));
+    // BusyBox and macOS style
+    assert_eq!(
+        routes::parse_ping_latency("seq=0 ttl=64 time=12 ms"),
+        Some(12.0)
+    );
+    assert_eq!(
+        routes::parse_ping_latency("1 packets transmitted, 0 received"),
+        None
+    );
+}
@@ -37,10 +37,18 @@ async fn test_config_generation_with_discovered_devices() {
             interval_seconds: 30,
         },
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![Device {
             name: "Existing Device".to_string(),
             mac_address: "11:22:33:44:55:66".to_string(),
             ip_address: "192.168.1.50".to_string(),
+            ..Default::default()
         }],
     };
 
@@ -97,10 +105,18 @@ async fn test_config_generation_no_discovered_devices() {
             interval_seconds: 60,
         },
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![Device {
             name: "Only Device".to_string(),
             mac_address: "FF:EE:DD:CC:BB:AA".to_string(),
             ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
         }],
     };
 
@@ -140,6 +156,13 @@ async fn test_discovery_with_special_characters() {
         server: ServerConfig::default(),
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![],
     };
 
@@ -149,3 +172,236 @@ async fn test_discovery_with_special_characters() {
     assert!(config_yaml.contains("name: device-with-dash"));
     assert!(config_yaml.contains("name: device.with.dots"));
 }
+
+#[test]
+fn test_scan_overrides_from_request() {
+    let base = DiscoveryConfig::default();
+    let params = vec![
+        ("interfaces".to_string(), "eth0".to_string()),
+        ("max_concurrency".to_string(), "4".to_string()),
+        ("packets_per_second".to_string(), "10".to_string()),
+        ("host_timeout_seconds".to_string(), "2".to_string()),
+        ("retries".to_string(), "1".to_string()),
+    ];
+
+    let options = routes::apply_scan_overrides(&base, &params);
+
+    assert_eq!(options.max_concurrency, 4);
+    assert_eq!(options.packets_per_second, 10);
+    assert_eq!(options.host_timeout_seconds, 2);
+    assert_eq!(options.retries, 1);
+}
+
+#[test]
+fn test_scan_overrides_ignore_invalid_and_clamp() {
+    let base = DiscoveryConfig {
+        max_concurrency: 16,
+        packets_per_second: 20,
+        host_timeout_seconds: 1,
+        retries: 0,
+        result_ttl_seconds: 60,
+        ..Default::default()
+    };
+    let params = vec![
+        ("max_concurrency".to_string(), "100000".to_string()),
+        ("packets_per_second".to_string(), "fast".to_string()),
+        ("host_timeout_seconds".to_string(), "".to_string()),
+        ("retries".to_string(), "99".to_string()),
+    ];
+
+    let options = routes::apply_scan_overrides(&base, &params);
+
+    assert_eq!(options.max_concurrency, 256);
+    assert_eq!(options.packets_per_second, 20);
+    assert_eq!(options.host_timeout_seconds, 1);
+    assert_eq!(options.retries, 5);
+
+    // Zero concurrency would stall the scan
+    let params = vec![("max_concurrency".to_string(), "0".to_string())];
+    assert_eq!(
+        routes::apply_scan_overrides(&base, &params).max_concurrency,
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_scan_results_are_isolated_per_session() {
+    let store = routes::ScanStore::new(std::time::Duration::from_secs(60));
+    let devices = vec![routes::DiscoveredDevice {
+        ip_address: "192.168.1.100".to_string(),
+        mac_address: Some("AA:BB:CC:DD:EE:FF".to_string()),
+        hostname: Some("alice-pc".to_string()),
+        status: "Online".to_string(),
+    }];
+
+    let alice_scan = store.insert("alice", devices.clone()).await;
+    let bob_scan = store.insert("bob", vec![]).await;
+    assert_ne!(alice_scan, bob_scan);
+
+    // Each session sees only its own scan
+    assert_eq!(
+        store.get(&alice_scan, "alice").await.unwrap().devices.len(),
+        1
+    );
+    assert!(store.get(&alice_scan, "bob").await.is_none());
+    assert!(store
+        .get(&bob_scan, "bob")
+        .await
+        .unwrap()
+        .devices
+        .is_empty());
+
+    // Generated configs follow the same ownership rule
+    assert!(
+        !store
+            .set_generated_config(&alice_scan, "bob", "bob: yaml".to_string())
+            .await
+    );
+    assert!(
+        store
+            .set_generated_config(&alice_scan, "alice", "alice: yaml".to_string())
+            .await
+    );
+    let scan = store.get(&alice_scan, "alice").await.unwrap();
+    assert_eq!(scan.generated_config.as_deref(), Some("alice: yaml"));
+    assert!(store
+        .get(&bob_scan, "bob")
+        .await
+        .unwrap()
+        .generated_config
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_scan_results_expire() {
+    let store = routes::ScanStore::new(std::time::Duration::from_millis(20));
+    let scan_id = store.insert("session", vec![]).await;
+    assert_eq!(store.len().await, 1);
+
+    tokio::time::sleep(std::time::Duration::from_millis(40)).await;
+
+    assert!(store.get(&scan_id, "session").await.is_none());
+    assert!(store.is_empty().await);
+}
+
+fn discovered(ip: &str, mac: Option<&str>, hostname: Option<&str>) -> routes::DiscoveredDevice {
+    routes::DiscoveredDevice {
+        ip_address: ip.to_string(),
+        mac_address: mac.map(|m| m.to_string()),
+        hostname: hostname.map(|h| h.to_string()),
+        status: "Online".to_string(),
+    }
+}
+
+fn diff_test_config() -> Config {
+    Config {
+        devices: vec![
+            Device {
+                name: "nas".to_string(),
+                mac_address: "AA:AA:AA:AA:AA:01".to_string(),
+                ip_address: "192.168.1.10".to_string(),
+                ..Default::default()
+            },
+            Device {
+                name: "build-01".to_string(),
+                mac_address: "AA:AA:AA:AA:AA:02".to_string(),
+                ip_address: "192.168.1.20".to_string(),
+                ..Default::default()
+            },
+            Device {
+                name: "printer".to_string(),
+                mac_address: "AA:AA:AA:AA:AA:03".to_string(),
+                ip_address: "192.168.1.30".to_string(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_discovery_diff_categories() {
+    let config = diff_test_config();
+    let found = vec![
+        // Known, same IP, no hostname: unchanged
+        discovered("192.168.1.10", Some("aa-aa-aa-aa-aa-01"), None),
+        // Known, new IP and hostname: changed
+        discovered(
+            "192.168.1.21",
+            Some("AA:AA:AA:AA:AA:02"),
+            Some("build01.lan"),
+        ),
+        // Seen twice on two interfaces: counted once
+        discovered(
+            "192.168.1.21",
+            Some("AA:AA:AA:AA:AA:02"),
+            Some("build01.lan"),
+        ),
+        // Unknown MAC: new
+        discovered("192.168.1.40", Some("BB:BB:BB:BB:BB:01"), Some("laptop")),
+        // No MAC: new, cannot be matched
+        discovered("192.168.1.50", None, None),
+    ];
+
+    let diff = routes::diff_discovered_devices(&config, &found);
+
+    assert_eq!(diff.unchanged.len(), 1);
+    assert_eq!(diff.unchanged[0].name, "nas");
+
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].device.name, "build-01");
+    assert_eq!(
+        diff.changed[0].new_ip_address.as_deref(),
+        Some("192.168.1.21")
+    );
+    assert_eq!(diff.changed[0].new_name.as_deref(), Some("build01.lan"));
+
+    assert_eq!(diff.new_devices.len(), 2);
+    assert_eq!(diff.not_seen.len(), 1);
+    assert_eq!(diff.not_seen[0].name, "printer");
+    assert!(!diff.is_empty());
+}
+
+#[test]
+fn test_discovery_diff_hostname_matching_is_case_insensitive() {
+    let config = diff_test_config();
+    let found = vec![discovered(
+        "192.168.1.10",
+        Some("AA:AA:AA:AA:AA:01"),
+        Some("NAS"),
+    )];
+
+    let diff = routes::diff_discovered_devices(&config, &found);
+
+    assert_eq!(diff.unchanged.len(), 1);
+    assert!(diff.changed.is_empty());
+}
+
+#[tokio::test]
+async fn test_config_generation_applies_updates() {
+    let config = diff_test_config();
+    let updates = vec![routes::DeviceUpdate {
+        mac_address: "aa-aa-aa-aa-aa-02".to_string(),
+        ip_address: Some("192.168.1.21".to_string()),
+        name: None,
+    }];
+    let additions = vec![
+        discovered("192.168.1.40", Some("BB:BB:BB:BB:BB:01"), Some("laptop")),
+        // Already configured under a different MAC spelling: not added twice
+        discovered("192.168.1.10", Some("aa:aa:aa:aa:aa:01"), Some("nas-dup")),
+    ];
+
+    let config_yaml =
+        routes::generate_config_yaml_with_updates(&config, &additions, &updates).await;
+    let generated: Config = serde_yaml::from_str(&config_yaml).unwrap();
+
+    assert_eq!(generated.devices.len(), 4);
+    let build = generated
+        .devices
+        .iter()
+        .find(|d| d.name == "build-01")
+        .unwrap();
+    assert_eq!(build.ip_address, "192.168.1.21");
+    assert!(generated.devices.iter().any(|d| d.name == "laptop"));
+    assert!(!generated.devices.iter().any(|d| d.name == "nas-dup"));
+}
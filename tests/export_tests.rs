@@ -0,0 +1,77 @@
+use wololo::config::Device;
+use wololo::export::*;
+use wololo::import::parse_device_csv;
+
+fn devices() -> Vec<Device> {
+    vec![
+        Device {
+            id: Some("nas".to_string()),
+            name: "NAS Box".to_string(),
+            mac_address: "AA:BB:CC:DD:EE:01".to_string(),
+            ip_address: "192.168.1.10".to_string(),
+            groups: vec!["servers".to_string(), "home lab".to_string()],
+            tags: vec!["storage".to_string()],
+            ..Default::default()
+        },
+        Device {
+            name: "desktop".to_string(),
+            mac_address: "AA-BB-CC-DD-EE-02".to_string(),
+            ip_address: "192.168.1.11".to_string(),
+            groups: vec!["servers".to_string()],
+            ..Default::default()
+        },
+    ]
+}
+
+#[test]
+fn test_export_csv_round_trips_through_import() {
+    let csv = export_csv(&devices()).unwrap();
+    assert!(csv.starts_with("name,mac_address,ip_address,groups,tags\n"));
+    assert!(csv.contains("NAS Box,AA:BB:CC:DD:EE:01,192.168.1.10,servers;home lab,storage\n"));
+
+    let report = parse_device_csv(&csv, "devices.csv");
+    assert!(report.errors.is_empty());
+    assert_eq!(report.devices.len(), 2);
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("NAS Box"));
+}
+
+#[test]
+fn test_export_json() {
+    let json: serde_json::Value = serde_json::from_str(&export_json(&devices()).unwrap()).unwrap();
+    assert_eq!(
+        json[0]["groups"],
+        serde_json::json!(["servers", "home lab"])
+    );
+    assert_eq!(json[1]["tags"], serde_json::json!([]));
+    assert_eq!(json[0]["id"], "nas");
+    assert_eq!(json[1]["id"], "aa-bb-cc-dd-ee-02");
+}
+
+#[test]
+fn test_export_ethers() {
+    let ethers = export_ethers(&devices());
+    assert!(ethers.contains("aa:bb:cc:dd:ee:01 nas-box\n"));
+    assert!(ethers.contains("aa:bb:cc:dd:ee:02 desktop\n"));
+}
+
+#[test]
+fn test_export_ansible_inventory() {
+    let yaml = export_ansible_inventory(&devices()).unwrap();
+    let inventory: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+
+    let hosts = &inventory["all"]["hosts"];
+    assert_eq!(hosts["nas-box"]["ansible_host"], "192.168.1.10");
+    assert_eq!(hosts["desktop"]["mac_address"], "AA-BB-CC-DD-EE-02");
+
+    let children = &inventory["all"]["children"];
+    assert!(children["servers"]["hosts"]["nas-box"].is_mapping());
+    assert!(children["servers"]["hosts"]["desktop"].is_mapping());
+    assert!(children["home_lab"]["hosts"]["nas-box"].is_mapping());
+}
+
+#[test]
+fn test_host_name() {
+    assert_eq!(host_name("Living Room TV"), "living-room-tv");
+    assert_eq!(host_name("  nas.lan "), "nas.lan");
+    assert_eq!(host_name("???"), "device");
+}
@@ -0,0 +1,79 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use std::time::Duration;
+use tower::ServiceExt;
+use wololo::config::{Config, PollerConfig};
+use wololo::health::check_poller;
+use wololo::poller::PollRound;
+use wololo::routes::{self, AppState};
+
+async fn get_json(app_state: AppState, uri: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = routes::app_router(app_state)
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_healthz_and_readyz() {
+    let (status, json) = get_json(AppState::new_for_test(Config::default()), "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["status"], "ok");
+
+    // The poller has not finished a round, so the server is not ready yet
+    let (status, json) = get_json(AppState::new_for_test(Config::default()), "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["ready"], false);
+    assert_eq!(json["config"], true);
+    assert_eq!(json["templates"], true);
+    assert_eq!(json["poller"], false);
+    // Only whether each check passed; reload errors and ping output are not public
+    let mut keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["config", "ping", "poller", "ready", "templates"]);
+
+    // Without the poller only ping permissions decide, which depend on the machine
+    let config = Config {
+        poller: PollerConfig {
+            enabled: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (status, json) = get_json(AppState::new_for_test(config), "/readyz").await;
+    assert_eq!(json["poller"], true);
+    assert_eq!(json["ready"], json["ping"]);
+    let expected = if json["ready"] == true {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    assert_eq!(status, expected);
+}
+
+#[test]
+fn test_poller_check_tracks_lag() {
+    let app_state = AppState::new_for_test(Config::default());
+    app_state.statuses.finish_round(PollRound {
+        finished_at: 1_000,
+        duration: Duration::from_secs(1),
+        interval_seconds: 60,
+    });
+
+    let check = check_poller(&app_state, 1_030);
+    assert!(check.ok);
+    assert_eq!(check.last_round_age_seconds, Some(30));
+    assert_eq!(check.lag_seconds, Some(0));
+
+    // Overdue by more than a whole interval
+    let check = check_poller(&app_state, 1_200);
+    assert!(!check.ok);
+    assert_eq!(check.lag_seconds, Some(140));
+    assert!(check.error.unwrap().contains("200 seconds ago"));
+}
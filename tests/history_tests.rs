@@ -0,0 +1,322 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Method, Request, StatusCode},
+};
+use tempfile::TempDir;
+use tower::ServiceExt;
+use wololo::config::{Config, Device};
+use wololo::history::*;
+use wololo::poller::StatusBoard;
+use wololo::routes::{self, AppState, DeviceStatus};
+
+fn device(name: &str, mac: &str) -> Device {
+    Device {
+        name: name.to_string(),
+        mac_address: mac.to_string(),
+        ip_address: "192.168.1.10".to_string(),
+        ..Default::default()
+    }
+}
+
+fn change(device_id: &str, status: DeviceStatus, at: u64) -> StatusChange {
+    StatusChange {
+        device_id: device_id.to_string(),
+        device_name: device_id.to_uppercase(),
+        status,
+        previous: None,
+        at,
+    }
+}
+
+fn action(device_id: &str, kind: ActionKind, success: bool, at: u64) -> ActionRecord {
+    ActionRecord {
+        device_id: device_id.to_string(),
+        device_name: device_id.to_uppercase(),
+        action: kind,
+        source: "web".to_string(),
+        success,
+        detail: (!success).then(|| "boom".to_string()),
+        at,
+    }
+}
+
+#[test]
+fn test_queries_filter_by_device_time_and_action() {
+    let history = History::in_memory();
+    history.record_status_change(&change("nas", DeviceStatus::Online, 100));
+    history.record_status_change(&change("nas", DeviceStatus::Offline, 200));
+    history.record_status_change(&change("desktop", DeviceStatus::Online, 300));
+    history.record_action(&action("nas", ActionKind::Wake, true, 150));
+    history.record_action(&action("nas", ActionKind::Shutdown, false, 250));
+
+    // Newest first
+    let all = history.status_changes(&HistoryQuery::default());
+    let times: Vec<u64> = all.iter().map(|change| change.at).collect();
+    assert_eq!(times, [300, 200, 100]);
+
+    let nas = history.status_changes(&HistoryQuery {
+        device_id: Some("nas".to_string()),
+        since: Some(150),
+        ..Default::default()
+    });
+    assert_eq!(nas, [change("nas", DeviceStatus::Offline, 200)]);
+
+    let until = history.status_changes(&HistoryQuery {
+        until: Some(200),
+        limit: 1,
+        ..Default::default()
+    });
+    assert_eq!(until.len(), 1);
+    assert_eq!(until[0].at, 200);
+
+    let shutdowns = history.actions(&HistoryQuery {
+        action: Some(ActionKind::Shutdown),
+        ..Default::default()
+    });
+    assert_eq!(shutdowns, [action("nas", ActionKind::Shutdown, false, 250)]);
+
+    let latest = history.latest_statuses();
+    assert_eq!(latest.get("nas"), Some(&DeviceStatus::Offline));
+    assert_eq!(latest.get("desktop"), Some(&DeviceStatus::Online));
+}
+
+#[test]
+fn test_prune_and_persistence() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("history.db");
+
+    {
+        let history = History::open(&path).unwrap();
+        history.record_status_change(&change("nas", DeviceStatus::Online, 100));
+        history.record_action(&action("nas", ActionKind::Wake, true, 100));
+        history.record_action(&action("nas", ActionKind::Wake, true, 500));
+        history.record_sample(&sample(DeviceStatus::Online, None, 100));
+        assert_eq!(history.prune(200), 3);
+    }
+
+    // Everything that was not pruned survives reopening the file
+    let history = History::open(&path).unwrap();
+    assert!(history.status_changes(&HistoryQuery::default()).is_empty());
+    assert_eq!(history.actions(&HistoryQuery::default()).len(), 1);
+
+    // A disabled store swallows writes
+    let disabled = History::disabled();
+    disabled.record_action(&action("nas", ActionKind::Wake, true, 100));
+    assert!(disabled.actions(&HistoryQuery::default()).is_empty());
+}
+
+#[tokio::test]
+async fn test_record_device_action_from_async_code() {
+    let history = History::in_memory();
+    let events = wololo::events::EventBus::new();
+    let mut subscriber = events.subscribe();
+
+    let record = record_device_action(
+        &history,
+        &events,
+        &device("NAS", "AA:BB:CC:DD:EE:01"),
+        ActionKind::Wake,
+        "web",
+        Err("no route".to_string()),
+    )
+    .await;
+    assert!(!record.success);
+    assert!(subscriber.try_recv().is_ok());
+
+    let actions = history
+        .blocking(|history| history.actions(&HistoryQuery::default()))
+        .await;
+    assert_eq!(actions, vec![record]);
+    // Reads from a disabled store come back empty
+    let disabled = History::disabled();
+    assert_eq!(
+        disabled.blocking(|history| history.prune(u64::MAX)).await,
+        0
+    );
+}
+
+#[test]
+fn test_card_history_for_every_device_at_once() {
+    let history = History::in_memory();
+    history.record_status_change(&change("nas", DeviceStatus::Online, 100));
+    history.record_status_change(&change("nas", DeviceStatus::Offline, 300));
+    history.record_status_change(&change("pc", DeviceStatus::Online, 200));
+    history.record_action(&action("nas", ActionKind::Wake, true, 150));
+    history.record_action(&action("nas", ActionKind::Shutdown, false, 250));
+    history.record_sample(&sample(DeviceStatus::Online, Some(2.0), 50));
+    history.record_sample(&sample(DeviceStatus::Online, Some(1.0), 120));
+    history.record_sample(&sample(DeviceStatus::Offline, None, 310));
+
+    let cards = history.card_history(None, 100, 400);
+    assert_eq!(cards.samples["nas"].len(), 2);
+    assert_eq!(cards.samples["nas"][0].at, 120);
+    assert_eq!(cards.last_changes["nas"], 300);
+    assert_eq!(cards.last_changes["pc"], 200);
+    assert_eq!(cards.last_actions["nas"].action, ActionKind::Shutdown);
+    assert!(!cards.last_actions.contains_key("pc"));
+
+    let cards = history.card_history(Some("pc"), 100, 400);
+    assert!(cards.samples.is_empty());
+    assert_eq!(cards.last_changes.len(), 1);
+    assert!(cards.last_actions.is_empty());
+}
+
+fn sample(status: DeviceStatus, latency_ms: Option<f64>, at: u64) -> Sample {
+    Sample {
+        device_id: "nas".to_string(),
+        status,
+        latency_ms,
+        at,
+    }
+}
+
+#[test]
+fn test_samples_make_a_timeline() {
+    let history = History::in_memory();
+    for (status, latency, at) in [
+        (DeviceStatus::Online, Some(1.0), 0),
+        (DeviceStatus::Online, Some(3.0), 10),
+        (DeviceStatus::Offline, None, 20),
+        (DeviceStatus::Online, Some(2.0), 30),
+        (DeviceStatus::Online, Some(2.0), 99),
+    ] {
+        history.record_sample(&sample(status, latency, at));
+    }
+
+    // `until` is exclusive
+    let samples = history.samples("nas", 0, 99);
+    assert_eq!(samples.len(), 4);
+    assert_eq!(uptime_percent(&samples), Some(75.0));
+    assert_eq!(uptime_percent(&[]), None);
+
+    let buckets = timeline(&samples, 0, 40, 4);
+    let uptimes: Vec<Option<f64>> = buckets.iter().map(TimelineBucket::uptime).collect();
+    assert_eq!(uptimes, [Some(1.0), Some(1.0), Some(0.0), Some(1.0)]);
+    assert_eq!(buckets[2].latency_ms, None);
+    assert_eq!((buckets[3].start, buckets[3].end), (30, 40));
+
+    let merged = timeline(&samples, 0, 40, 2);
+    assert_eq!(merged[0].latency_ms, Some(2.0));
+    assert_eq!(merged[1].uptime(), Some(0.5));
+    assert_eq!(timeline(&[], 0, 40, 2)[0].uptime(), None);
+}
+
+#[test]
+fn test_parse_timestamp() {
+    assert_eq!(parse_timestamp("1700000000"), Some(1_700_000_000));
+    assert_eq!(parse_timestamp("2024-05-01T08:00:00Z"), Some(1_714_550_400));
+    assert_eq!(
+        parse_timestamp("2024-05-01T10:00:00+02:00"),
+        Some(1_714_550_400)
+    );
+    assert_eq!(parse_timestamp("yesterday"), None);
+}
+
+#[test]
+fn test_status_board_reports_only_transitions() {
+    let board = StatusBoard::new();
+    let nas = device("NAS", "AA:BB:CC:DD:EE:01");
+
+    let first = board.update(&nas, DeviceStatus::Online, None, 100).unwrap();
+    assert_eq!(first.previous, None);
+    assert_eq!(first.device_id, "aa-bb-cc-dd-ee-01");
+    assert!(board
+        .update(&nas, DeviceStatus::Online, None, 160)
+        .is_none());
+
+    let offline = board
+        .update(&nas, DeviceStatus::Offline, None, 220)
+        .unwrap();
+    assert_eq!(offline.previous, Some(DeviceStatus::Online));
+    let state = board.get("aa-bb-cc-dd-ee-01").unwrap();
+    assert_eq!((state.since, state.checked_at), (Some(220), 220));
+
+    // A status restored from history is not a change
+    let restored = StatusBoard::new();
+    restored.restore("aa-bb-cc-dd-ee-01", DeviceStatus::Offline, Some(220));
+    assert!(restored
+        .update(&nas, DeviceStatus::Offline, None, 300)
+        .is_none());
+    assert_eq!(restored.get("aa-bb-cc-dd-ee-01").unwrap().since, Some(220));
+}
+
+#[tokio::test]
+async fn test_shutdown_is_recorded_and_queryable() {
+    let mut ok = device("NAS", "AA:BB:CC:DD:EE:01");
+    ok.shutdown_command = vec!["true".to_string()];
+    let mut failing = device("Desktop", "AA:BB:CC:DD:EE:02");
+    failing.shutdown_command = vec!["false".to_string()];
+    let config = Config {
+        devices: vec![ok, failing, device("Printer", "AA:BB:CC:DD:EE:03")],
+        ..Default::default()
+    };
+    let app = routes::app_router(AppState::new_for_test(config));
+
+    let post = |uri: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(post("/shutdown/NAS")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(post("/shutdown/Desktop"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    // Devices without a shutdown_command cannot be shut down, and nothing is recorded
+    let response = app
+        .clone()
+        .oneshot(post("/shutdown/Printer"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .clone()
+        .oneshot(get(
+            "/api/v1/history/actions?device=NAS&action=shutdown&since=2024-01-01T00:00:00Z",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["count"], 1);
+    assert_eq!(json["actions"][0]["device_id"], "aa-bb-cc-dd-ee-01");
+    assert_eq!(json["actions"][0]["source"], "web");
+    assert_eq!(json["actions"][0]["success"], true);
+
+    let response = app
+        .clone()
+        .oneshot(get("/api/v1/history/actions"))
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["count"], 2);
+    assert_eq!(json["actions"][0]["success"], false);
+
+    let response = app
+        .clone()
+        .oneshot(get("/api/v1/history/status?since=yesterday"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The detail page lists every attempt
+    let response = app.clone().oneshot(get("/devices/NAS")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("Wake and shutdown attempts"));
+    assert!(body.contains("<td class=\"py-2 pr-4\">shutdown</td><td class=\"py-2 pr-4\">web</td>"));
+
+    let response = app.oneshot(get("/devices/Nope")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,230 @@
+use std::io::Write;
+use tempfile::NamedTempFile;
+use wololo::config::{DhcpLeaseFormat, DhcpLeaseSource};
+use wololo::import::*;
+
+const DNSMASQ_LEASES: &str = "\
+1718000000 aa:bb:cc:dd:ee:01 192.168.1.10 nas 01:aa:bb:cc:dd:ee:01
+1718000100 aa:bb:cc:dd:ee:02 192.168.1.11 * *
+duid 00:01:00:01:2c:1f:aa:bb:cc:dd:ee:ff
+1718000200 not-a-mac 192.168.1.12 broken *
+1718000300 aa:bb:cc:dd:ee:01 192.168.1.13 nas *
+";
+
+const ISC_LEASES: &str = r#"
+# The format of this file is documented in the dhcpd.leases(5) manual page.
+server-duid "\000\001\000\001";
+
+lease 10.0.0.20 {
+  starts 4 2024/06/13 10:00:00;
+  ends 4 2024/06/13 22:00:00;
+  binding state active;
+  hardware ethernet 00:11:22:33:44:55;
+  client-hostname "build-01";
+}
+lease 10.0.0.21 {
+  binding state free;
+  hardware ethernet 00:11:22:33:44:56;
+}
+lease 10.0.0.22 {
+  binding state active;
+  client-hostname "no-mac";
+}
+"#;
+
+const KEA_LEASES: &str = "\
+address,hwaddr,client_id,valid_lifetime,expire,subnet_id,fqdn_fwd,fqdn_rev,hostname,state,user_context
+172.16.0.5,66:77:88:99:aa:bb,,3600,1718003600,1,0,0,printer.lan.,0,
+172.16.0.6,66:77:88:99:aa:bc,,3600,1718003600,1,0,0,old,2,
+bogus,66:77:88:99:aa:bd,,3600,1718003600,1,0,0,,0,
+";
+
+#[test]
+fn test_parse_dnsmasq_leases() {
+    let report = parse_dnsmasq_leases(DNSMASQ_LEASES, "dnsmasq.leases");
+
+    // The later lease for the same MAC wins
+    assert_eq!(report.devices.len(), 2);
+    let nas = report
+        .devices
+        .iter()
+        .find(|d| d.hostname.as_deref() == Some("nas"))
+        .unwrap();
+    assert_eq!(nas.ip_address, "192.168.1.13");
+    assert_eq!(nas.mac_address.as_deref(), Some("AA:BB:CC:DD:EE:01"));
+
+    // "*" means the client sent no hostname
+    let anonymous = report
+        .devices
+        .iter()
+        .find(|d| d.ip_address == "192.168.1.11")
+        .unwrap();
+    assert_eq!(anonymous.hostname, None);
+
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, Some(4));
+}
+
+#[test]
+fn test_parse_isc_leases() {
+    let report = parse_isc_leases(ISC_LEASES, "dhcpd.leases");
+
+    assert_eq!(report.devices.len(), 1);
+    assert_eq!(report.devices[0].ip_address, "10.0.0.20");
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("build-01"));
+
+    // The free lease is skipped silently, the lease without a MAC is reported
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].message.contains("hardware ethernet"));
+}
+
+#[test]
+fn test_parse_kea_leases() {
+    let report = parse_kea_leases(KEA_LEASES, "kea-leases4.csv");
+
+    assert_eq!(report.devices.len(), 1);
+    assert_eq!(report.devices[0].ip_address, "172.16.0.5");
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("printer.lan"));
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, Some(4));
+}
+
+#[test]
+fn test_detect_lease_format() {
+    assert_eq!(
+        detect_lease_format("x.csv", KEA_LEASES),
+        DhcpLeaseFormat::Kea
+    );
+    assert_eq!(
+        detect_lease_format("/var/lib/dhcp/dhcpd.leases", ""),
+        DhcpLeaseFormat::Isc
+    );
+    assert_eq!(
+        detect_lease_format("leases", ISC_LEASES),
+        DhcpLeaseFormat::Isc
+    );
+    assert_eq!(
+        detect_lease_format("dnsmasq.leases", DNSMASQ_LEASES),
+        DhcpLeaseFormat::Dnsmasq
+    );
+}
+
+#[tokio::test]
+async fn test_import_dhcp_leases_from_files() {
+    let mut dnsmasq = NamedTempFile::new().unwrap();
+    dnsmasq.write_all(DNSMASQ_LEASES.as_bytes()).unwrap();
+    let mut kea = NamedTempFile::new().unwrap();
+    kea.write_all(KEA_LEASES.as_bytes()).unwrap();
+
+    let sources = vec![
+        DhcpLeaseSource {
+            path: dnsmasq.path().to_string_lossy().to_string(),
+            format: DhcpLeaseFormat::Dnsmasq,
+        },
+        DhcpLeaseSource {
+            path: kea.path().to_string_lossy().to_string(),
+            format: DhcpLeaseFormat::Auto,
+        },
+        DhcpLeaseSource {
+            path: "/nonexistent/dhcpd.leases".to_string(),
+            format: DhcpLeaseFormat::Isc,
+        },
+    ];
+
+    let report = import_dhcp_leases(&sources).await;
+
+    assert_eq!(report.devices.len(), 3);
+    assert!(report.devices.iter().all(|d| d.status == "DHCP lease"));
+    // One bad dnsmasq line, one bad Kea row, one unreadable file
+    assert_eq!(report.errors.len(), 3);
+    assert!(report
+        .errors
+        .iter()
+        .any(|e| e.source == "/nonexistent/dhcpd.leases" && e.line.is_none()));
+}
+
+const NMAP_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE nmaprun>
+<nmaprun scanner="nmap" args="nmap -sn -oX - 192.168.1.0/24" version="7.94">
+<host><status state="up" reason="arp-response"/>
+<address addr="192.168.1.20" addrtype="ipv4"/>
+<address addr="AA:BB:CC:00:00:20" addrtype="mac" vendor="Synology"/>
+<hostnames><hostname name="nas.lan" type="PTR"/></hostnames>
+</host>
+<host><status state="down" reason="no-response"/>
+<address addr="192.168.1.21" addrtype="ipv4"/>
+</host>
+<host><status state="up" reason="localhost-response"/>
+<address addr="192.168.1.22" addrtype="ipv4"/>
+<hostnames/>
+</host>
+<host><status state="up" reason="arp-response"/>
+<address addr="192.168.1.23" addrtype="ipv4"/>
+<address addr="ZZ:BB:CC:00:00:23" addrtype="mac"/>
+</host>
+<runstats><hosts up="3" down="1" total="4"/></runstats>
+</nmaprun>
+"#;
+
+#[test]
+fn test_parse_nmap_xml() {
+    let report = parse_nmap_xml(NMAP_XML, "scan.xml");
+
+    assert_eq!(report.devices.len(), 2);
+    assert_eq!(report.devices[0].ip_address, "192.168.1.20");
+    assert_eq!(
+        report.devices[0].mac_address.as_deref(),
+        Some("AA:BB:CC:00:00:20")
+    );
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("nas.lan"));
+    // Hosts without a MAC (e.g. the scanning machine itself) are still listed
+    assert_eq!(report.devices[1].ip_address, "192.168.1.22");
+    assert_eq!(report.devices[1].mac_address, None);
+
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, Some(16));
+    assert!(report.errors[0].message.contains("ZZ:BB:CC:00:00:23"));
+
+    let report = parse_nmap_xml("<nmaprun><host>", "broken.xml");
+    assert!(report.devices.is_empty());
+    assert!(report.errors[0].message.contains("invalid XML"));
+}
+
+#[test]
+fn test_parse_device_csv() {
+    let with_header = "\
+ip,name,mac_address
+10.0.0.5,desktop,aa-bb-cc-dd-ee-05
+10.0.0.6,laptop,not-a-mac
+# spare
+999.0.0.7,printer,aa:bb:cc:dd:ee:07
+";
+    let report = parse_device_csv(with_header, "devices.csv");
+    assert_eq!(report.devices.len(), 1);
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("desktop"));
+    assert_eq!(
+        report.devices[0].mac_address.as_deref(),
+        Some("AA:BB:CC:DD:EE:05")
+    );
+    assert_eq!(report.devices[0].ip_address, "10.0.0.5");
+    let lines: Vec<Option<usize>> = report.errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, vec![Some(3), Some(5)]);
+
+    // Without a header the columns are name, mac, ip
+    let report = parse_device_csv("server, aa:bb:cc:dd:ee:08 , 10.0.0.8\n", "devices.csv");
+    assert!(report.errors.is_empty());
+    assert_eq!(report.devices[0].hostname.as_deref(), Some("server"));
+    assert_eq!(report.devices[0].ip_address, "10.0.0.8");
+}
+
+#[test]
+fn test_parse_inventory_detects_format() {
+    assert_eq!(detect_inventory_format(NMAP_XML), InventoryFormat::Nmap);
+    assert_eq!(
+        detect_inventory_format("name,mac,ip\n"),
+        InventoryFormat::Csv
+    );
+
+    let report = parse_inventory(InventoryFormat::Auto, NMAP_XML, "scan.xml");
+    assert_eq!(report.devices.len(), 2);
+}
@@ -20,10 +20,18 @@ async fn test_full_application_flow() {
             interval_seconds: 30,
         },
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![Device {
             name: "Test PC".to_string(),
             mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
             ip_address: "192.168.1.100".to_string(),
+            ..Default::default()
         }],
     };
 
@@ -76,6 +84,13 @@ async fn test_discovery_workflow() {
         server: ServerConfig::default(),
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![],
     });
     let app = routes::app_router(app_state);
@@ -108,6 +123,13 @@ async fn test_error_handling() {
         server: ServerConfig::default(),
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![],
     });
     let app = routes::app_router(app_state);
@@ -144,16 +166,25 @@ async fn test_app_state_management() {
         server: ServerConfig::default(),
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![
             Device {
                 name: "Device 1".to_string(),
                 mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
                 ip_address: "192.168.1.1".to_string(),
+                ..Default::default()
             },
             Device {
                 name: "Device 2".to_string(),
                 mac_address: "11:22:33:44:55:66".to_string(),
                 ip_address: "192.168.1.2".to_string(),
+                ..Default::default()
             },
         ],
     };
@@ -161,9 +192,9 @@ async fn test_app_state_management() {
     let app_state = AppState::new_for_test(config);
 
     // Test config access
-    assert_eq!(app_state.config.devices.len(), 2);
-    assert_eq!(app_state.config.devices[0].name, "Device 1");
-    assert_eq!(app_state.config.devices[1].name, "Device 2");
+    assert_eq!(app_state.config.get().devices.len(), 2);
+    assert_eq!(app_state.config.get().devices[0].name, "Device 1");
+    assert_eq!(app_state.config.get().devices[1].name, "Device 2");
 
     // Test discovered devices storage
     let discovered_devices = vec![routes::DiscoveredDevice {
@@ -173,15 +204,19 @@ async fn test_app_state_management() {
         status: "Online".to_string(),
     }];
 
-    {
-        let mut storage = app_state.discovered_devices.lock().await;
-        storage.insert("test_scan".to_string(), discovered_devices.clone());
-    }
+    let scan_id = app_state
+        .discovered_devices
+        .insert("test_session", discovered_devices.clone())
+        .await;
 
     // Verify storage
     {
-        let storage = app_state.discovered_devices.lock().await;
-        let stored_devices = storage.get("test_scan").unwrap();
+        let scan = app_state
+            .discovered_devices
+            .get(&scan_id, "test_session")
+            .await
+            .unwrap();
+        let stored_devices = scan.devices;
         assert_eq!(stored_devices.len(), 1);
         assert_eq!(stored_devices[0].ip_address, "192.168.1.100");
     }
@@ -197,6 +232,7 @@ async fn test_concurrent_requests() {
             name: "Test Device".to_string(),
             mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
             ip_address: "192.168.1.100".to_string(),
+            ..Default::default()
         }],
     });
 
@@ -239,12 +275,19 @@ async fn test_config_validation() {
         },
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![],
     };
 
     // App should still be created (validation happens at runtime)
     let app_state = AppState::new_for_test(config_with_invalid_ip);
-    assert_eq!(app_state.config.server.ip, "invalid.ip.format");
+    assert_eq!(app_state.config.get().server.ip, "invalid.ip.format");
 
     // Test with extreme port values
     let config_with_extreme_port = Config {
@@ -255,11 +298,18 @@ async fn test_config_validation() {
         },
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![],
     };
 
     let app_state = AppState::new_for_test(config_with_extreme_port);
-    assert_eq!(app_state.config.server.port, 0);
+    assert_eq!(app_state.config.get().server.port, 0);
 }
 
 #[tokio::test]
@@ -269,9 +319,10 @@ async fn test_large_device_list() {
     let mut devices = Vec::new();
     for i in 0..100 {
         devices.push(Device {
-            name: format!("Device {}", i),
+            name: format!("Device {, ..Default::default() }", i),
             mac_address: format!("AA:BB:CC:DD:EE:{:02X}", i),
             ip_address: format!("192.168.1.{}", i + 1),
+            ..Default::default()
         });
     }
 
@@ -279,6 +330,13 @@ async fn test_large_device_list() {
         server: ServerConfig::default(),
         sync: SyncConfig::default(),
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices,
     };
 
@@ -0,0 +1,117 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Method, Request, StatusCode},
+};
+use std::time::Duration;
+use tower::ServiceExt;
+use wololo::config::{Config, Device};
+use wololo::poller::PollRound;
+use wololo::routes::{self, AppState, DeviceStatus};
+
+fn test_state() -> AppState {
+    AppState::new_for_test(Config {
+        devices: vec![
+            Device {
+                name: "NAS \"main\"".to_string(),
+                mac_address: "AA:BB:CC:DD:EE:01".to_string(),
+                ip_address: "192.0.2.10".to_string(),
+                groups: vec!["servers".to_string(), "office".to_string()],
+                tags: vec!["storage".to_string()],
+                ..Default::default()
+            },
+            Device {
+                name: "Desktop".to_string(),
+                mac_address: "not a mac".to_string(),
+                ip_address: "192.0.2.11".to_string(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    })
+}
+
+async fn scrape(app: &axum::Router) -> String {
+    let request = Request::builder()
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics_exposition() {
+    let app_state = test_state();
+    let config = app_state.config.get();
+    app_state
+        .statuses
+        .update(&config.devices[0], DeviceStatus::Online, Some(2.5), 1_000);
+    app_state
+        .statuses
+        .update(&config.devices[1], DeviceStatus::Offline, None, 1_000);
+    app_state.statuses.finish_round(PollRound {
+        finished_at: 1_000,
+        duration: Duration::from_millis(1500),
+        interval_seconds: 60,
+    });
+    app_state
+        .metrics
+        .record_scan("manual", Duration::from_secs(4), 7);
+    let app = routes::app_router(app_state);
+
+    // One wake that is sent and one that fails on the invalid MAC address
+    for uri in ["/wake/NAS%20%22main%22", "/wake/Desktop", "/wake/Nope"] {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let body = scrape(&app).await;
+    let nas = r#"device="NAS \"main\"",device_id="aa-bb-cc-dd-ee-01",group="servers,office",tags="storage""#;
+    assert!(body.contains(&format!("wololo_device_up{{{}}} 1\n", nas)));
+    assert!(body.contains(r#"wololo_device_up{device="Desktop","#));
+    assert!(body.contains(&format!(
+        "wololo_device_probe_latency_seconds{{{}}} 0.0025\n",
+        nas
+    )));
+    assert!(body.contains(r#"wololo_wake_failures_total{device="Desktop","#));
+    assert!(!body.contains(r#"wololo_wake_failures_total{device="NAS"#));
+    assert_eq!(body.matches("wololo_wake_attempts_total{").count(), 2);
+    assert!(body.contains(r#"backend="wol",source="web"} 1"#));
+    assert!(body.contains("wololo_discovery_scan_duration_seconds_count{kind=\"manual\"} 1\n"));
+    assert!(body.contains("wololo_discovery_last_scan_hosts{kind=\"manual\"} 7\n"));
+    assert!(body.contains("wololo_poller_last_round_duration_seconds 1.5\n"));
+    assert!(body.contains("# TYPE wololo_poller_lag_seconds gauge\n"));
+
+    // Requests are counted by route pattern, not by the device in the URL
+    assert!(body.contains(
+        r#"wololo_http_requests_total{method="POST",route="/wake/:device_id",status="200"} 1"#
+    ));
+    assert!(body.contains(
+        r#"wololo_http_requests_total{method="POST",route="/wake/:device_id",status="404"} 1"#
+    ));
+    let body = scrape(&app).await;
+    assert!(body
+        .contains(r#"wololo_http_requests_total{method="GET",route="/metrics",status="200"} 1"#));
+}
+
+#[test]
+fn test_poller_lag() {
+    let round = PollRound {
+        finished_at: 1_000,
+        duration: Duration::from_secs(2),
+        interval_seconds: 60,
+    };
+    assert_eq!(round.lag(1_030), 0);
+    assert_eq!(round.lag(1_060), 0);
+    assert_eq!(round.lag(1_090), 30);
+}
@@ -0,0 +1,387 @@
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS};
+use rumqttd::{Broker, ConnectionSettings, RouterConfig, ServerSettings};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use wololo::config::{Config, Device, HomeAssistantConfig, MqttConfig};
+use wololo::events::Event;
+use wololo::history::{HistoryQuery, StatusChange};
+use wololo::home_assistant::{discovery_messages, node_id};
+use wololo::mqtt::*;
+use wololo::routes::{AppState, DeviceStatus};
+
+const NAS_ID: &str = "aa-bb-cc-dd-ee-01";
+const DESKTOP_ID: &str = "aa-bb-cc-dd-ee-02";
+
+#[test]
+fn test_topics_and_commands() {
+    let topics = Topics::new("home/wololo/");
+    assert_eq!(topics.availability(), "home/wololo/status");
+    assert_eq!(
+        topics.device_status("nas"),
+        "home/wololo/devices/nas/status"
+    );
+    assert_eq!(
+        topics.command_target("home/wololo/devices/nas/command"),
+        Some(CommandTarget::Device("nas"))
+    );
+    assert_eq!(
+        topics.command_target("home/wololo/command"),
+        Some(CommandTarget::All)
+    );
+    assert_eq!(
+        topics.command_target("home/wololo/devices/nas/status"),
+        None
+    );
+    assert_eq!(topics.command_target("other/devices/nas/command"), None);
+
+    assert_eq!(MqttCommand::parse(b" Wake\n"), Some(MqttCommand::Wake));
+    assert_eq!(MqttCommand::parse(b"shutdown"), Some(MqttCommand::Shutdown));
+    assert_eq!(MqttCommand::parse(b"refresh"), Some(MqttCommand::Refresh));
+    assert_eq!(MqttCommand::parse(b"reboot"), None);
+}
+
+#[test]
+fn test_options() {
+    let options = mqtt_options(&MqttConfig {
+        username: Some("wololo".to_string()),
+        password: Some("secret".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(options.broker_address(), ("localhost".to_string(), 1883));
+    let login = options.credentials().unwrap();
+    assert_eq!(
+        (login.username.as_str(), login.password.as_str()),
+        ("wololo", "secret")
+    );
+    let will = options.last_will().unwrap();
+    assert_eq!(will.topic, "wololo/status");
+    assert_eq!(&will.message[..], OFFLINE.as_bytes());
+    assert!(will.retain);
+
+    let error = mqtt_options(&MqttConfig {
+        tls: true,
+        ca_file: Some("/nonexistent/ca.pem".to_string()),
+        ..Default::default()
+    })
+    .unwrap_err();
+    assert!(error.contains("/nonexistent/ca.pem"));
+}
+
+// Start an in-process broker that only accepts the given user, and return its port
+fn start_broker(username: &str, password: &str) -> u16 {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server = ServerSettings {
+        name: "v4-1".to_string(),
+        listen: ([127, 0, 0, 1], port).into(),
+        tls: None,
+        next_connection_delay_ms: 1,
+        connections: ConnectionSettings {
+            connection_timeout_ms: 60_000,
+            max_payload_size: 20_480,
+            max_inflight_count: 100,
+            auth: Some(HashMap::from([(
+                username.to_string(),
+                password.to_string(),
+            )])),
+            external_auth: None,
+            dynamic_filters: true,
+        },
+    };
+    let config = rumqttd::Config {
+        router: RouterConfig {
+            max_connections: 10,
+            max_outgoing_packet_count: 200,
+            max_segment_size: 104_857_600,
+            max_segment_count: 10,
+            ..Default::default()
+        },
+        v4: Some(HashMap::from([("1".to_string(), server)])),
+        ..Default::default()
+    };
+    std::thread::spawn(move || Broker::new(config).start().unwrap());
+
+    // Wait until it listens
+    while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    port
+}
+
+// A client that subscribes to every topic and forwards each message. Returns once
+// the subscription is in place.
+async fn observer(port: u16) -> (AsyncClient, Messages) {
+    let mut options = MqttOptions::new("observer", "127.0.0.1", port);
+    options.set_credentials("wololo", "secret");
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    client.subscribe("#", QoS::AtLeastOnce).await.unwrap();
+
+    loop {
+        if let MqttEvent::Incoming(Packet::SubAck(_)) = event_loop.poll().await.unwrap() {
+            break;
+        }
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                    let payload = String::from_utf8_lossy(&publish.payload).to_string();
+                    let _ = sender.send((publish.topic, payload));
+                }
+                Ok(_) => {}
+                Err(e) => panic!("observer lost the connection: {}", e),
+            }
+        }
+    });
+    (
+        client,
+        Messages {
+            receiver,
+            skipped: Vec::new(),
+        },
+    )
+}
+
+// Messages the observer received, as (topic, payload)
+struct Messages {
+    receiver: mpsc::UnboundedReceiver<(String, String)>,
+    // Received but not asked for yet
+    skipped: Vec<(String, String)>,
+}
+
+// Payload of the next message on a topic. Messages on other topics are kept for later calls.
+async fn next_on(messages: &mut Messages, topic: &str) -> String {
+    if let Some(index) = messages.skipped.iter().position(|(t, _)| t == topic) {
+        return messages.skipped.remove(index).1;
+    }
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let (received_topic, payload) = messages.receiver.recv().await.unwrap();
+            if received_topic == topic {
+                return payload;
+            }
+            messages.skipped.push((received_topic, payload));
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("nothing published to {} within 10 seconds", topic))
+}
+
+fn test_config(port: u16) -> Config {
+    Config {
+        mqtt: MqttConfig {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("wololo".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        },
+        devices: vec![
+            Device {
+                name: "NAS".to_string(),
+                mac_address: "AA:BB:CC:DD:EE:01".to_string(),
+                ip_address: "127.0.0.1".to_string(),
+                shutdown_command: vec!["true".to_string()],
+                ..Default::default()
+            },
+            Device {
+                name: "Desktop".to_string(),
+                mac_address: "AA:BB:CC:DD:EE:02".to_string(),
+                ip_address: "127.0.0.1".to_string(),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+async fn command(client: &AsyncClient, device_id: &str, payload: &str) {
+    client
+        .publish(
+            format!("wololo/devices/{}/command", device_id),
+            QoS::AtLeastOnce,
+            false,
+            payload.as_bytes().to_vec(),
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_statuses_and_commands_over_a_broker() {
+    let port = start_broker("wololo", "secret");
+    let app_state = AppState::new_for_test(test_config(port));
+    let config = app_state.config.get();
+    app_state
+        .statuses
+        .update(&config.devices[0], DeviceStatus::Online, Some(1.0), 100);
+
+    let (client, mut messages) = observer(port).await;
+    let connection = spawn_mqtt(app_state.clone()).unwrap();
+
+    assert_eq!(next_on(&mut messages, "wololo/status").await, ONLINE);
+    let nas_status = format!("wololo/devices/{}/status", NAS_ID);
+    assert_eq!(next_on(&mut messages, &nas_status).await, "online");
+
+    let nas_result = format!("wololo/devices/{}/result", NAS_ID);
+    command(&client, NAS_ID, "shutdown").await;
+    let result: Value = serde_json::from_str(&next_on(&mut messages, &nas_result).await).unwrap();
+    assert_eq!(result["command"], "shutdown");
+    assert_eq!(result["ok"], true);
+    let actions = app_state.history.actions(&HistoryQuery::default());
+    assert_eq!(actions[0].source, MQTT_SOURCE);
+
+    let desktop_result = format!("wololo/devices/{}/result", DESKTOP_ID);
+    command(&client, DESKTOP_ID, "SHUTDOWN").await;
+    let result: Value =
+        serde_json::from_str(&next_on(&mut messages, &desktop_result).await).unwrap();
+    assert_eq!(result["ok"], false);
+    assert_eq!(
+        result["error"],
+        "Desktop has no shutdown_command configured"
+    );
+
+    // The first check of a device is a change, so its status gets published too
+    command(&client, DESKTOP_ID, "refresh").await;
+    let result: Value =
+        serde_json::from_str(&next_on(&mut messages, &desktop_result).await).unwrap();
+    assert_eq!(result["ok"], true);
+    let desktop_status = format!("wololo/devices/{}/status", DESKTOP_ID);
+    assert_eq!(
+        next_on(&mut messages, &desktop_status).await,
+        result["status"].as_str().unwrap()
+    );
+
+    app_state.events.publish(Event::StatusChanged {
+        change: StatusChange {
+            device_id: NAS_ID.to_string(),
+            device_name: "NAS".to_string(),
+            status: DeviceStatus::Offline,
+            previous: Some(DeviceStatus::Online),
+            at: 200,
+        },
+    });
+    assert_eq!(next_on(&mut messages, &nas_status).await, "offline");
+
+    // Dropping the connection without a disconnect makes the broker publish the last will
+    connection.abort();
+    assert_eq!(next_on(&mut messages, "wololo/status").await, OFFLINE);
+}
+
+fn with_home_assistant(mut config: Config) -> Config {
+    config.mqtt.home_assistant = HomeAssistantConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    config
+}
+
+#[test]
+fn test_home_assistant_discovery_payloads() {
+    let topics = Topics::new("wololo");
+    assert!(discovery_messages(&test_config(1883), &topics).is_empty());
+
+    let messages = discovery_messages(&with_home_assistant(test_config(1883)), &topics);
+    let sensor = format!("homeassistant/binary_sensor/wololo/{}/config", NAS_ID);
+    let nas_wake = format!("homeassistant/button/wololo/{}_wake/config", NAS_ID);
+    let nas_shutdown = format!("homeassistant/button/wololo/{}_shutdown/config", NAS_ID);
+    let desktop_wake = format!("homeassistant/button/wololo/{}_wake/config", DESKTOP_ID);
+    // Desktop has no shutdown_command, so no shutdown button
+    let mut expected = vec![
+        sensor.clone(),
+        format!("homeassistant/binary_sensor/wololo/{}/config", DESKTOP_ID),
+        nas_wake.clone(),
+        nas_shutdown.clone(),
+        desktop_wake,
+    ];
+    expected.sort();
+    assert_eq!(messages.keys().cloned().collect::<Vec<_>>(), expected);
+
+    let sensor: Value = serde_json::from_str(&messages[&sensor]).unwrap();
+    assert_eq!(sensor["device_class"], "connectivity");
+    assert_eq!(
+        sensor["state_topic"],
+        format!("wololo/devices/{}/status", NAS_ID)
+    );
+    assert_eq!(sensor["availability_topic"], "wololo/status");
+    assert_eq!(sensor["unique_id"], format!("wololo_{}_status", NAS_ID));
+    assert_eq!(sensor["device"]["name"], "NAS");
+    assert_eq!(
+        sensor["device"]["connections"][0],
+        serde_json::json!(["mac", "aa:bb:cc:dd:ee:01"])
+    );
+
+    let shutdown: Value = serde_json::from_str(&messages[&nas_shutdown]).unwrap();
+    assert_eq!(
+        shutdown["command_topic"],
+        format!("wololo/devices/{}/command", NAS_ID)
+    );
+    assert_eq!(shutdown["payload_press"], "shutdown");
+    // All entities of a device belong to the same Home Assistant device
+    let wake: Value = serde_json::from_str(&messages[&nas_wake]).unwrap();
+    assert_eq!(wake["device"], sensor["device"]);
+    assert_ne!(wake["unique_id"], sensor["unique_id"]);
+
+    assert_eq!(node_id("wololo.home lab"), "wololo_home_lab");
+}
+
+#[tokio::test]
+async fn test_home_assistant_entities_follow_the_config() {
+    let port = start_broker("wololo", "secret");
+    let config = with_home_assistant(test_config(port));
+    let app_state = AppState::new_for_test(config.clone());
+    for device in &config.devices {
+        app_state
+            .statuses
+            .update(device, DeviceStatus::Online, None, 100);
+    }
+
+    let (client, mut messages) = observer(port).await;
+    let _connection = spawn_mqtt(app_state.clone()).unwrap();
+
+    let desktop_sensor = format!("homeassistant/binary_sensor/wololo/{}/config", DESKTOP_ID);
+    let nas_shutdown = format!("homeassistant/button/wololo/{}_shutdown/config", NAS_ID);
+    let nas_sensor = format!("homeassistant/binary_sensor/wololo/{}/config", NAS_ID);
+    assert!(next_on(&mut messages, &desktop_sensor)
+        .await
+        .contains("connectivity"));
+    assert!(next_on(&mut messages, &nas_shutdown)
+        .await
+        .contains("shutdown"));
+    assert!(next_on(&mut messages, &nas_sensor)
+        .await
+        .contains("connectivity"));
+    let desktop_status = format!("wololo/devices/{}/status", DESKTOP_ID);
+    assert_eq!(next_on(&mut messages, &desktop_status).await, "online");
+    assert_eq!(next_on(&mut messages, "wololo/status").await, ONLINE);
+
+    // Remove Desktop and the NAS shutdown command; their entities and status are cleared
+    let mut reloaded = config.clone();
+    reloaded.devices.pop();
+    reloaded.devices[0].shutdown_command.clear();
+    app_state.config.replace(reloaded);
+    app_state
+        .events
+        .publish(Event::ConfigReloaded { device_count: 1 });
+    assert_eq!(next_on(&mut messages, &nas_shutdown).await, "");
+    assert_eq!(next_on(&mut messages, &desktop_sensor).await, "");
+    assert_eq!(next_on(&mut messages, &desktop_status).await, "");
+
+    // Discovery is sent again when Home Assistant restarts
+    client
+        .publish("homeassistant/status", QoS::AtLeastOnce, false, "online")
+        .await
+        .unwrap();
+    assert!(next_on(&mut messages, &nas_sensor)
+        .await
+        .contains("connectivity"));
+}
@@ -0,0 +1,436 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, Uri},
+    routing::post,
+    Router,
+};
+use chrono::NaiveTime;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use wololo::config::{
+    ChannelKind, Config, Device, NotificationChannel, NotificationRule, NotificationsConfig,
+    QuietHours, SmtpSecurity,
+};
+use wololo::events::Event;
+use wololo::history::{ActionKind, ActionRecord, StatusChange};
+use wololo::notifications::*;
+use wololo::poller::verify_wake;
+use wololo::routes::{AppState, DeviceStatus};
+use wololo::webhooks::http_client;
+
+fn status_event(device_name: &str, status: DeviceStatus) -> Event {
+    Event::StatusChanged {
+        change: StatusChange {
+            device_id: device_name.to_lowercase(),
+            device_name: device_name.to_string(),
+            status,
+            previous: Some(DeviceStatus::Online),
+            at: 1_700_000_000,
+        },
+    }
+}
+
+fn action_event(device_name: &str, success: bool) -> Event {
+    Event::DeviceAction {
+        action: ActionRecord {
+            device_id: device_name.to_lowercase(),
+            device_name: device_name.to_string(),
+            action: ActionKind::Wake,
+            source: "web".to_string(),
+            success,
+            detail: (!success).then(|| "network unreachable".to_string()),
+            at: 1_700_000_000,
+        },
+    }
+}
+
+fn channel(name: &str, kind: ChannelKind) -> NotificationChannel {
+    NotificationChannel {
+        name: name.to_string(),
+        kind,
+        title_template: None,
+        template: None,
+        quiet_hours: None,
+    }
+}
+
+fn push_channel(name: &str, url: &str) -> NotificationChannel {
+    channel(
+        name,
+        ChannelKind::Push {
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+        },
+    )
+}
+
+fn time(value: &str) -> NaiveTime {
+    parse_time(value).unwrap()
+}
+
+#[test]
+fn test_default_wording() {
+    let offline = default_notification(&status_event("build-01", DeviceStatus::Offline));
+    assert_eq!(offline.title, "build-01 went offline");
+    assert_eq!(offline.message, "build-01 went offline (was online)");
+
+    let failed = default_notification(&action_event("render-03", false));
+    assert_eq!(failed.title, "Wake of render-03 failed");
+    assert_eq!(
+        failed.message,
+        "Wake of render-03 failed: network unreachable"
+    );
+
+    let unverified = default_notification(&Event::WakeUnverified {
+        device_id: "render-03".to_string(),
+        device_name: "render-03".to_string(),
+        woken_at: 1_700_000_000,
+        waited_seconds: 300,
+    });
+    assert_eq!(unverified.title, "Wake of render-03 failed verification");
+    assert_eq!(
+        unverified.message,
+        "render-03 was still not online 300 seconds after being woken"
+    );
+}
+
+#[test]
+fn test_quiet_hours() {
+    let night = QuietHours {
+        start: "22:00".to_string(),
+        end: "07:00".to_string(),
+    };
+    assert!(in_quiet_hours(&night, time("23:30")));
+    assert!(in_quiet_hours(&night, time("00:00")));
+    assert!(in_quiet_hours(&night, time("06:59")));
+    assert!(!in_quiet_hours(&night, time("07:00")));
+    assert!(!in_quiet_hours(&night, time("12:00")));
+
+    let lunch = QuietHours {
+        start: "12:00".to_string(),
+        end: "13:00".to_string(),
+    };
+    assert!(in_quiet_hours(&lunch, time("12:30")));
+    assert!(!in_quiet_hours(&lunch, time("13:30")));
+
+    assert_eq!(parse_time("7:30"), NaiveTime::from_hms_opt(7, 30, 0));
+    assert_eq!(parse_time("25:00"), None);
+}
+
+#[test]
+fn test_rules_route_events_to_channels() {
+    let config = Config {
+        notifications: NotificationsConfig {
+            channels: vec![
+                push_channel("ops", "http://ops.lan"),
+                push_channel("render", "http://render.lan"),
+                push_channel("unused", "http://unused.lan"),
+            ],
+            rules: vec![
+                NotificationRule {
+                    channels: vec!["ops".to_string()],
+                    statuses: vec!["offline".to_string(), "unreachable".to_string()],
+                    ..Default::default()
+                },
+                NotificationRule {
+                    channels: vec!["ops".to_string(), "render".to_string()],
+                    groups: vec!["render-farm".to_string()],
+                    failures_only: true,
+                    ..Default::default()
+                },
+            ],
+        },
+        devices: vec![Device {
+            name: "render-03".to_string(),
+            mac_address: "AA:BB:CC:DD:EE:03".to_string(),
+            id: Some("render-03".to_string()),
+            groups: vec!["render-farm".to_string()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let names = |event: &Event| -> Vec<String> {
+        channels_for(event, &config)
+            .iter()
+            .map(|channel| channel.name.clone())
+            .collect()
+    };
+
+    assert_eq!(
+        names(&status_event("build-01", DeviceStatus::Offline)),
+        ["ops"]
+    );
+    assert!(names(&status_event("build-01", DeviceStatus::Online)).is_empty());
+    assert!(names(&status_event("render-03", DeviceStatus::Online)).is_empty());
+    assert_eq!(names(&action_event("render-03", false)), ["ops", "render"]);
+    assert!(names(&action_event("render-03", true)).is_empty());
+    assert!(names(&action_event("build-01", false)).is_empty());
+    let unverified = Event::WakeUnverified {
+        device_id: "render-03".to_string(),
+        device_name: "render-03".to_string(),
+        woken_at: 1_700_000_000,
+        waited_seconds: 300,
+    };
+    assert_eq!(names(&unverified), ["ops", "render"]);
+
+    // Without rules every channel gets every event
+    let everything = Config {
+        notifications: NotificationsConfig {
+            rules: Vec::new(),
+            ..config.notifications.clone()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        channels_for(&action_event("build-01", true), &everything).len(),
+        3
+    );
+}
+
+#[test]
+fn test_templates() {
+    let event = status_event("build-01", DeviceStatus::Offline);
+
+    let mut templated = push_channel("chat", "http://chat.lan");
+    templated.title_template = Some("[wololo] {{title}}".to_string());
+    templated.template = Some("{{change.device_name}} is {{change.status}}".to_string());
+    assert_eq!(
+        render_notification(&templated, &event),
+        Notification {
+            title: "[wololo] build-01 went offline".to_string(),
+            message: "build-01 is offline".to_string(),
+        }
+    );
+
+    // A template that does not render falls back to the built-in wording
+    templated.template = Some("{{unknown_helper change}}".to_string());
+    assert_eq!(
+        render_notification(&templated, &event).message,
+        "build-01 went offline (was online)"
+    );
+
+    // Notifications are plain text, so nothing is HTML-escaped
+    templated.template = Some("{{change.device_name}} is {{change.status}}".to_string());
+    let event = status_event("Tom's PC & NAS=1", DeviceStatus::Offline);
+    assert_eq!(
+        render_notification(&templated, &event),
+        Notification {
+            title: "[wololo] Tom's PC & NAS=1 went offline".to_string(),
+            message: "Tom's PC & NAS=1 is offline".to_string(),
+        }
+    );
+}
+
+// Every request an HTTP mock received, as (path and query, headers, body)
+type Requests = Arc<Mutex<Vec<(String, HeaderMap, String)>>>;
+
+async fn record(State(requests): State<Requests>, uri: Uri, headers: HeaderMap, body: String) {
+    requests
+        .lock()
+        .unwrap()
+        .push((uri.to_string(), headers, body));
+}
+
+// An HTTP server that records every POST; returns its base URL
+async fn http_mock(requests: Requests) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = Router::new().fallback(post(record)).with_state(requests);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+#[tokio::test]
+async fn test_http_channels() {
+    let requests = Requests::default();
+    let base = http_mock(requests.clone()).await;
+
+    let mut quiet = push_channel("quiet", &format!("{}/quiet", base));
+    quiet.quiet_hours = Some(QuietHours {
+        start: "22:00".to_string(),
+        end: "07:00".to_string(),
+    });
+    let state = AppState::new_for_test(Config {
+        notifications: NotificationsConfig {
+            channels: vec![
+                channel(
+                    "ntfy",
+                    ChannelKind::Ntfy {
+                        url: format!("{}/alerts", base),
+                        token: Some("tk_ntfy".to_string()),
+                        priority: Some(4),
+                    },
+                ),
+                channel(
+                    "gotify",
+                    ChannelKind::Gotify {
+                        url: format!("{}/", base),
+                        token: "app-token".to_string(),
+                        priority: Some(8),
+                    },
+                ),
+                NotificationChannel {
+                    kind: ChannelKind::Push {
+                        url: format!("{}/push", base),
+                        headers: BTreeMap::from([(
+                            "Authorization".to_string(),
+                            "Bearer push".to_string(),
+                        )]),
+                    },
+                    ..push_channel("push", "")
+                },
+                quiet,
+            ],
+            rules: Vec::new(),
+        },
+        ..Default::default()
+    });
+    let event = status_event("build-01", DeviceStatus::Offline);
+    let client = http_client();
+
+    // The quiet channel is skipped at night
+    assert_eq!(notify(&state, &client, &event, time("23:00")).await, 3);
+    let mut received = requests.lock().unwrap().clone();
+    received.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(received.len(), 3);
+
+    let (uri, headers, body) = &received[0];
+    assert_eq!(uri, "/alerts?title=build-01+went+offline&priority=4");
+    assert_eq!(headers["authorization"], "Bearer tk_ntfy");
+    assert_eq!(body, "build-01 went offline (was online)");
+
+    let (uri, headers, body) = &received[1];
+    assert_eq!(uri, "/message");
+    assert_eq!(headers["x-gotify-key"], "app-token");
+    let gotify: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(gotify["title"], "build-01 went offline");
+    assert_eq!(gotify["priority"], 8);
+
+    let (uri, headers, body) = &received[2];
+    assert_eq!(uri, "/push");
+    assert_eq!(headers["authorization"], "Bearer push");
+    let push: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(push["message"], "build-01 went offline (was online)");
+    assert_eq!(push["event"]["type"], "status_changed");
+
+    // During the day it gets one too
+    assert_eq!(notify(&state, &client, &event, time("12:00")).await, 4);
+    assert!(requests
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(uri, _, _)| uri == "/quiet"));
+}
+
+// A minimal SMTP server that accepts one message and returns its DATA
+async fn smtp_sink() -> (u16, tokio::sync::oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    writer.write_all(b"250 queued\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let reply: &[u8] = match line.get(..4).unwrap_or("").to_uppercase().as_str() {
+                "DATA" => {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        let _ = sender.send(data);
+    });
+    (port, receiver)
+}
+
+#[tokio::test]
+async fn test_smtp_channel() {
+    let (port, received) = smtp_sink().await;
+    let mut email = channel(
+        "email",
+        ChannelKind::Smtp {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "wololo <wololo@example.com>".to_string(),
+            to: vec!["admin@example.com".to_string()],
+        },
+    );
+    email.title_template = Some("[wololo] {{title}}".to_string());
+
+    let event = Event::WakeUnverified {
+        device_id: "render-03".to_string(),
+        device_name: "render-03".to_string(),
+        woken_at: 1_700_000_000,
+        waited_seconds: 300,
+    };
+    let notification = render_notification(&email, &event);
+    send(&http_client(), &email, &notification, &event)
+        .await
+        .unwrap();
+
+    let data = received.await.unwrap();
+    assert!(data.contains("From: wololo <wololo@example.com>"));
+    assert!(data.contains("To: admin@example.com"));
+    assert!(data.contains("Subject: [wololo] Wake of render-03 failed verification"));
+    assert!(data.contains("render-03 was still not online 300 seconds after being woken"));
+}
+
+#[tokio::test]
+async fn test_unanswered_wake_is_reported() {
+    let state = AppState::new_for_test(Config {
+        devices: vec![Device {
+            name: "render-03".to_string(),
+            mac_address: "AA:BB:CC:DD:EE:03".to_string(),
+            // TEST-NET-1: never answers
+            ip_address: "192.0.2.1".to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    });
+    let device = state.config.get().devices[0].clone();
+    let mut events = state.events.subscribe();
+
+    let action = ActionRecord::new(&device, ActionKind::Wake, "web", Ok(()));
+    assert!(!verify_wake(&state, &action, 300).await);
+    match events.recv().await.unwrap() {
+        Event::StatusChanged { .. } => {}
+        other => panic!("expected the status change first, got {:?}", other),
+    }
+    match events.recv().await.unwrap() {
+        Event::WakeUnverified {
+            device_id,
+            waited_seconds,
+            ..
+        } => {
+            assert_eq!(device_id, device.device_id());
+            assert_eq!(waited_seconds, 300);
+        }
+        other => panic!("expected wake_unverified, got {:?}", other),
+    }
+}
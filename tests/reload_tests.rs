@@ -0,0 +1,185 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use wololo::config::Config;
+use wololo::events::{Event, EventBus};
+use wololo::reload::*;
+
+fn device_yaml(names: &[&str]) -> String {
+    let mut yaml = String::from("devices:\n");
+    for (index, name) in names.iter().enumerate() {
+        yaml.push_str(&format!(
+            "  - name: {}\n    mac_address: \"AA:BB:CC:DD:EE:{:02X}\"\n    ip_address: \"192.168.1.{}\"\n",
+            name,
+            index + 1,
+            index + 10
+        ));
+    }
+    yaml
+}
+
+fn handle_for(dir: &TempDir, contents: &str) -> ConfigHandle {
+    let path = dir.path().join("config.yaml");
+    std::fs::write(&path, contents).unwrap();
+    let config: Config = serde_yaml::from_str(contents).unwrap();
+    ConfigHandle::with_path(config, path)
+}
+
+#[test]
+fn test_reload_replaces_config() {
+    let dir = TempDir::new().unwrap();
+    let handle = handle_for(&dir, &device_yaml(&["nas"]));
+    let snapshot = handle.get();
+
+    std::fs::write(
+        dir.path().join("config.yaml"),
+        device_yaml(&["nas", "desktop"]),
+    )
+    .unwrap();
+    assert!(handle.reload().is_ok());
+
+    assert_eq!(handle.get().devices.len(), 2);
+    // Snapshots taken before the reload are unaffected
+    assert_eq!(snapshot.devices.len(), 1);
+    assert!(handle.last_error().is_none());
+}
+
+#[test]
+fn test_invalid_reload_keeps_previous_config() {
+    let dir = TempDir::new().unwrap();
+    let handle = handle_for(&dir, &device_yaml(&["nas"]));
+
+    std::fs::write(dir.path().join("config.yaml"), device_yaml(&["nas", "nas"])).unwrap();
+    let issues = handle.reload().unwrap_err();
+    assert!(!issues.is_empty());
+
+    assert_eq!(handle.get().devices.len(), 1);
+    let error = handle.last_error().unwrap();
+    assert!(error.contains("names must be unique"));
+
+    // A later good reload clears the error
+    std::fs::write(
+        dir.path().join("config.yaml"),
+        device_yaml(&["nas", "desktop"]),
+    )
+    .unwrap();
+    assert!(handle.reload().is_ok());
+    assert!(handle.last_error().is_none());
+}
+
+#[tokio::test]
+async fn test_reload_and_report_publishes_events() {
+    let dir = TempDir::new().unwrap();
+    let handle = handle_for(&dir, &device_yaml(&["nas"]));
+    let bus = EventBus::new();
+    let mut receiver = bus.subscribe();
+
+    std::fs::write(dir.path().join("config.yaml"), "devices: [").unwrap();
+    reload_and_report(&handle, &bus, "test");
+    assert!(matches!(
+        receiver.recv().await.unwrap(),
+        Event::ConfigReloadFailed { .. }
+    ));
+
+    std::fs::write(
+        dir.path().join("config.yaml"),
+        device_yaml(&["nas", "desktop"]),
+    )
+    .unwrap();
+    reload_and_report(&handle, &bus, "test");
+    assert!(matches!(
+        receiver.recv().await.unwrap(),
+        Event::ConfigReloaded { device_count: 2 }
+    ));
+}
+
+#[tokio::test]
+async fn test_file_change_triggers_reload() {
+    let dir = TempDir::new().unwrap();
+    let handle = handle_for(&dir, &device_yaml(&["nas"]));
+    let bus = EventBus::new();
+    let mut receiver = bus.subscribe();
+    let task = spawn_config_reloader(handle.clone(), bus.clone()).unwrap();
+
+    // Replace the file the way editors do: write a temp file and rename it over the original
+    let tmp = dir.path().join("config.yaml.tmp");
+    std::fs::write(&tmp, device_yaml(&["nas", "desktop", "printer"])).unwrap();
+    std::fs::rename(&tmp, dir.path().join("config.yaml")).unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+        .await
+        .expect("no reload after the file changed")
+        .unwrap();
+    assert!(matches!(event, Event::ConfigReloaded { device_count: 3 }));
+    assert_eq!(handle.get().devices.len(), 3);
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn test_users_file_is_cached_and_watched() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("secrets")).unwrap();
+    let users_path = dir.path().join("secrets").join("users.yaml");
+    let user = |name: &str| format!("- username: {}\n  password_hash: \"x\"\n", name);
+    std::fs::write(&users_path, user("alice")).unwrap();
+    let handle = handle_for(
+        &dir,
+        &format!(
+            "auth:\n  users_file: \"{}\"\n{}",
+            users_path.display(),
+            device_yaml(&["nas"])
+        ),
+    );
+    assert_eq!(handle.users().len(), 1);
+
+    // Requests see the cached users until the file is read again
+    std::fs::write(&users_path, user("alice") + &user("bob")).unwrap();
+    assert_eq!(handle.users().len(), 1);
+    handle.refresh_users();
+    assert_eq!(handle.users().len(), 2);
+
+    // The watcher reads the users again when the file changes
+    let task = spawn_config_reloader(handle.clone(), EventBus::new()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    std::fs::write(&users_path, user("carol")).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while handle.users().len() != 1 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("users not read again after the file changed");
+    assert_eq!(handle.users()[0].username, "carol");
+
+    task.abort();
+}
+
+#[tokio::test]
+async fn test_watcher_skips_a_write_that_was_already_reloaded() {
+    let dir = TempDir::new().unwrap();
+    let handle = handle_for(&dir, &device_yaml(&["nas"]));
+    assert!(!handle.file_changed());
+    let bus = EventBus::new();
+    let mut receiver = bus.subscribe();
+    let task = spawn_config_reloader(handle.clone(), bus.clone()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // What the device manager does: write the file and reload right away
+    std::fs::write(
+        dir.path().join("config.yaml"),
+        device_yaml(&["nas", "desktop"]),
+    )
+    .unwrap();
+    reload_and_report(&handle, &bus, "device manager");
+    assert!(matches!(
+        receiver.recv().await.unwrap(),
+        Event::ConfigReloaded { device_count: 2 }
+    ));
+    assert!(!handle.file_changed());
+
+    // The watcher sees the same write but has nothing new to load
+    let again = tokio::time::timeout(Duration::from_secs(2), receiver.recv()).await;
+    assert!(again.is_err(), "reloaded twice: {:?}", again);
+
+    task.abort();
+}
@@ -18,16 +18,25 @@ fn create_test_app_state() -> AppState {
             interval_seconds: 30,
         },
         sound: SoundConfig::default(),
+        discovery: DiscoveryConfig::default(),
+        poller: PollerConfig::default(),
+        history: HistoryConfig::default(),
+        webhooks: WebhooksConfig::default(),
+        mqtt: MqttConfig::default(),
+        notifications: NotificationsConfig::default(),
+        auth: AuthConfig::default(),
         devices: vec![
             Device {
                 name: "Test Device 1".to_string(),
                 mac_address: "AA:BB:CC:DD:EE:FF".to_string(),
                 ip_address: "192.168.1.100".to_string(),
+                ..Default::default()
             },
             Device {
                 name: "Test Device 2".to_string(),
                 mac_address: "11:22:33:44:55:66".to_string(),
                 ip_address: "192.168.1.101".to_string(),
+                ..Default::default()
             },
         ],
     };
@@ -208,6 +217,364 @@ async fn test_discovery_scan_route() {
     assert!(body_str.contains("Discovered Devices") || body_str.contains("No devices discovered"));
 }
 
+#[tokio::test]
+async fn test_generate_config_unknown_scan() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/discovery/generate-config")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(Body::from("scan_id=doesnotexist"))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body_str = std::str::from_utf8(&body).unwrap();
+    assert!(body_str.contains("expired"));
+}
+
+#[tokio::test]
+async fn test_download_config_from_other_session() {
+    let app_state = create_test_app_state();
+    let scan_id = app_state
+        .discovered_devices
+        .insert("someone-else", vec![])
+        .await;
+    app_state
+        .discovered_devices
+        .set_generated_config(&scan_id, "someone-else", "secret: generated".to_string())
+        .await;
+    let app = routes::app_router(app_state);
+
+    // A request without the owner's session cookie falls back to the current config
+    let request = Request::builder()
+        .uri(format!("/discovery/download-config?scan_id={}", scan_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("set-cookie").is_some());
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body_str = std::str::from_utf8(&body).unwrap();
+    assert!(!body_str.contains("secret: generated"));
+    assert!(body_str.contains("Test Device 1"));
+}
+
+#[tokio::test]
+async fn test_generated_config_has_no_secrets() {
+    let config: Config = serde_yaml::from_str(
+        r#"
+mqtt:
+  password: "mqtt-pass-1"
+webhooks:
+  endpoints:
+    - url: "https://hooks.example.com/wol"
+      secret: "hook-secret-2"
+      headers:
+        Authorization: "Bearer hook-token-3"
+notifications:
+  channels:
+    - name: mail
+      type: smtp
+      host: smtp.example.com
+      password: "smtp-pass-4"
+      from: wololo@example.com
+      to: [me@example.com]
+    - name: phone
+      type: ntfy
+      url: https://ntfy.sh/alerts
+      token: "ntfy-token-5"
+    - name: gotify
+      type: gotify
+      url: https://gotify.lan
+      token: "gotify-token-6"
+auth:
+  session_secret: "session-secret-7"
+  users:
+    - username: admin
+      password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaC04"
+devices:
+  - name: NAS
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+"#,
+    )
+    .unwrap();
+    let secrets = [
+        "mqtt-pass-1",
+        "hook-secret-2",
+        "hook-token-3",
+        "smtp-pass-4",
+        "ntfy-token-5",
+        "gotify-token-6",
+        "session-secret-7",
+        "aGFzaC04",
+    ];
+    let app_state = AppState::new_for_test(config);
+    let scan_id = app_state
+        .discovered_devices
+        .insert(
+            "abc123",
+            vec![routes::DiscoveredDevice {
+                ip_address: "192.168.1.20".to_string(),
+                mac_address: Some("AA:BB:CC:DD:EE:02".to_string()),
+                hostname: Some("printer".to_string()),
+                status: "Online".to_string(),
+            }],
+        )
+        .await;
+    let app = routes::app_router(app_state);
+
+    let selected = serde_urlencoded::to_string([
+        ("scan_id", scan_id.as_str()),
+        ("selected_devices", r#"{"ip_address": "192.168.1.20"}"#),
+    ])
+    .unwrap();
+    let requests = [
+        Request::builder()
+            .method(Method::POST)
+            .uri("/discovery/generate-config")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .header("cookie", "wololo_discovery=abc123")
+            .body(Body::from(selected))
+            .unwrap(),
+        Request::builder()
+            .uri(format!("/discovery/download-config?scan_id={}", scan_id))
+            .header("cookie", "wololo_discovery=abc123")
+            .body(Body::empty())
+            .unwrap(),
+        Request::builder()
+            .uri("/discovery/download-config")
+            .body(Body::empty())
+            .unwrap(),
+    ];
+
+    for request in requests {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body_str = std::str::from_utf8(&body).unwrap();
+        assert!(body_str.contains("192.168.1.10"));
+        assert!(body_str.contains("&lt;redacted&gt;") || body_str.contains("<redacted>"));
+        for secret in secrets {
+            assert!(!body_str.contains(secret), "{} leaked", secret);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_generated_config_preview_is_escaped() {
+    let app_state = create_test_app_state();
+    let hostname = "</code><script>alert(1)</script>`${document.cookie}`";
+    let scan_id = app_state
+        .discovered_devices
+        .insert(
+            "abc123",
+            vec![routes::DiscoveredDevice {
+                ip_address: "192.168.1.20".to_string(),
+                mac_address: Some("AA:BB:CC:DD:EE:02".to_string()),
+                hostname: Some(hostname.to_string()),
+                status: "Online".to_string(),
+            }],
+        )
+        .await;
+    let app = routes::app_router(app_state);
+
+    let form = serde_urlencoded::to_string([
+        ("scan_id", scan_id.as_str()),
+        ("selected_devices", r#"{"ip_address": "192.168.1.20"}"#),
+    ])
+    .unwrap();
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/discovery/generate-config")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .header("cookie", "wololo_discovery=abc123")
+                .body(Body::from(form))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body_str = std::str::from_utf8(&body).unwrap();
+    assert!(body_str.contains("&lt;/code&gt;&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(!body_str.contains("<script>alert(1)"));
+    assert!(!body_str.contains("${document.cookie}`;"));
+}
+
+#[tokio::test]
+async fn test_new_devices_api() {
+    let app_state = create_test_app_state();
+    let config = app_state.config.get();
+    let baseline = vec![routes::DiscoveredDevice {
+        ip_address: "192.168.1.1".to_string(),
+        mac_address: Some("00:00:00:00:00:01".to_string()),
+        hostname: None,
+        status: "Online".to_string(),
+    }];
+    let intruder = vec![routes::DiscoveredDevice {
+        ip_address: "192.168.1.66".to_string(),
+        mac_address: Some("00:00:00:00:00:66".to_string()),
+        hostname: Some("unknown-box".to_string()),
+        status: "Online".to_string(),
+    }];
+    app_state.seen_devices.record(&baseline, &config).await;
+    app_state.seen_devices.record(&intruder, &config).await;
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .uri("/api/v1/discovery/new-devices")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["count"], 1);
+    assert_eq!(json["devices"][0]["hostname"], "unknown-box");
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/v1/discovery/new-devices/acknowledge")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["acknowledged"], 1);
+}
+
+#[tokio::test]
+async fn test_import_dhcp_without_sources() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/discovery/import-dhcp")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_import_inventory_upload() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let body = "--boundary\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"devices.csv\"\r\n\
+Content-Type: text/csv\r\n\r\n\
+name,mac,ip\n\
+media-box,aa:bb:cc:dd:ee:99,192.168.1.99\n\
+broken,xx,192.168.1.98\n\r\n\
+--boundary--\r\n";
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/discovery/import")
+        .header("content-type", "multipart/form-data; boundary=boundary")
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body_str.contains("media-box"));
+    assert!(body_str.contains("devices.csv:3"));
+    assert!(body_str.contains("name=\"scan_id\""));
+}
+
+#[tokio::test]
+async fn test_export_devices_route() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .uri("/api/v1/devices/export/ethers")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"ethers\""
+    );
+
+    let request = Request::builder()
+        .uri("/api/v1/devices/export/xml")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_device_manager_writes_config_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("config.yaml");
+    let contents = "# Lab\ndevices:\n  - name: NAS\n    mac_address: \"AA:BB:CC:DD:EE:01\"\n    ip_address: \"192.168.1.20\"\n";
+    std::fs::write(&path, contents).unwrap();
+    let config: Config = serde_yaml::from_str(contents).unwrap();
+    let app_state = AppState {
+        config: reload::ConfigHandle::with_path(config.clone(), &path),
+        ..AppState::new_for_test(config)
+    };
+    let hash = config_edit::content_hash(contents);
+
+    let add = |hash: String| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/devices")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(Body::from(format!(
+                "config_hash={}&name=Laptop&mac_address=AA%3ABB%3ACC%3ADD%3AEE%3A02&ip_address=192.168.1.30&groups=office%2C+mobile&tags=",
+                hash
+            )))
+            .unwrap()
+    };
+
+    let response = routes::app_router(app_state.clone())
+        .oneshot(add(hash.clone()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8(body.to_vec())
+        .unwrap()
+        .contains("Added Laptop"));
+
+    // Written to disk with the comment kept, and the running config reloaded
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(written.starts_with("# Lab\n"));
+    let running = app_state.config.get();
+    assert_eq!(running.devices.len(), 2);
+    assert_eq!(running.devices[1].groups, ["office", "mobile"]);
+
+    // Submitting against the old file again is a conflict
+    let response = routes::app_router(app_state.clone())
+        .oneshot(add(hash))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written);
+}
+
 #[tokio::test]
 async fn test_assets_route() {
     let app_state = create_test_app_state();
@@ -236,3 +603,164 @@ async fn test_invalid_route() {
     let response = app.oneshot(request).await.unwrap();
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_dashboard_uses_device_ids() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .uri("/refresh-all")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    // Neither device sets an id, so both are addressed by their MAC address
+    assert!(body.contains(r#"hx-post="/wake/11-22-33-44-55-66""#));
+    assert!(body.contains(r#"id="status-aa-bb-cc-dd-ee-ff""#));
+}
+
+#[tokio::test]
+async fn test_dashboard_cards_show_recent_history() {
+    let app_state = create_test_app_state();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    for (status, latency_ms, ago) in [
+        (DeviceStatus::Online, Some(0.8), 600),
+        (DeviceStatus::Offline, None, 300),
+    ] {
+        app_state.history.record_sample(&history::Sample {
+            device_id: "aa-bb-cc-dd-ee-ff".to_string(),
+            status,
+            latency_ms,
+            at: now - ago,
+        });
+    }
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .uri("/refresh-all")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body.contains(r#"<a href="/devices/aa-bb-cc-dd-ee-ff""#));
+    assert!(body.contains(r#"Uptime 24h <span class="text-white font-medium">50%</span>"#));
+    assert_eq!(body.matches("availability-bar").count(), 2);
+    // Only the first device has latency samples
+    assert_eq!(body.matches("latency-sparkline").count(), 1);
+    assert!(body.contains("No latency data yet"));
+}
+
+#[tokio::test]
+async fn test_unknown_device_key_is_escaped() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state);
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/wake/%3Cb%3Enope%3C%2Fb%3E")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("&lt;b&gt;nope&lt;/b&gt;"));
+}
+
+// Read the next event off an SSE response, skipping keep-alive comments
+async fn next_sse_event(body: &mut Body) -> String {
+    use http_body_util::BodyExt;
+    loop {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("no event within 5 seconds")
+            .unwrap()
+            .unwrap();
+        let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        if !text.starts_with(':') {
+            return text;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_event_stream_pushes_cards_and_json() {
+    let app_state = create_test_app_state();
+    let app = routes::app_router(app_state.clone());
+    let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/api/v1/events")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut html = response.into_body();
+    let mut json = app
+        .clone()
+        .oneshot(get("/api/v1/events?format=json"))
+        .await
+        .unwrap()
+        .into_body();
+
+    // A wake followed by the device coming online
+    let config = app_state.config.get();
+    let device = &config.devices[0];
+    let record = history::record_device_action(
+        &app_state.history,
+        &app_state.events,
+        device,
+        history::ActionKind::Wake,
+        "cli",
+        Ok(()),
+    )
+    .await;
+    let change = app_state
+        .statuses
+        .update(device, DeviceStatus::Online, Some(1.5), record.at + 20)
+        .unwrap();
+    app_state
+        .events
+        .publish(events::Event::StatusChanged { change });
+
+    let activity = next_sse_event(&mut html).await;
+    assert!(activity.starts_with("event: activity-aa-bb-cc-dd-ee-ff\n"));
+    assert!(activity.contains("Wake sent"));
+    assert!(activity.contains("from cli"));
+
+    // Only the card that changed is sent, ready to replace itself
+    let card = next_sse_event(&mut html).await;
+    assert!(card.starts_with("event: device-aa-bb-cc-dd-ee-ff\n"));
+    assert!(card.contains(
+        r#"id="device-aa-bb-cc-dd-ee-ff" sse-swap="device-aa-bb-cc-dd-ee-ff" hx-swap="outerHTML""#
+    ));
+    // The activity line swaps its contents, not the whole card
+    assert!(card.contains(
+        r#"id="activity-aa-bb-cc-dd-ee-ff" sse-swap="activity-aa-bb-cc-dd-ee-ff" hx-swap="innerHTML""#
+    ));
+    assert!(card.contains("Online"));
+    assert!(card.contains("online after 20s"));
+    assert!(!card.contains("11-22-33-44-55-66"));
+
+    let action = next_sse_event(&mut json).await;
+    assert!(action.starts_with("event: device_action\n"));
+    let data = action
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .unwrap();
+    let data: serde_json::Value = serde_json::from_str(data).unwrap();
+    assert_eq!(data["type"], "device_action");
+    assert_eq!(data["action"]["source"], "cli");
+    assert!(next_sse_event(&mut json)
+        .await
+        .starts_with("event: status_changed\n"));
+
+    let response = app.oneshot(get("/api/v1/events?format=xml")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
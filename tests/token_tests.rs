@@ -0,0 +1,427 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+    response::IntoResponse,
+    Router,
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower::ServiceExt;
+use wololo::auth::session_cookie;
+use wololo::config::{AuthConfig, Config, Device, User};
+use wololo::routes::{app_router, AppState};
+use wololo::tokens::*;
+
+fn device(name: &str, mac: &str, groups: &[&str]) -> Device {
+    Device {
+        name: name.to_string(),
+        mac_address: mac.to_string(),
+        ip_address: "192.0.2.10".to_string(),
+        groups: groups.iter().map(|group| group.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn app_state() -> AppState {
+    AppState::new_for_test(Config {
+        devices: vec![
+            device("nas", "AA:BB:CC:DD:EE:01", &["servers"]),
+            device("desktop", "AA:BB:CC:DD:EE:02", &["office"]),
+            device("backup", "AA:BB:CC:DD:EE:03", &["servers"]),
+        ],
+        auth: AuthConfig {
+            enabled: true,
+            // Sessions are only checked against the hash, never a password
+            users: vec![User {
+                username: "admin".to_string(),
+                password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2g".to_string(),
+            }],
+            session_secret: Some("a session secret for the tests".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+// "name=value" of a logged-in session cookie, signed with the app's key
+fn session(app_state: &AppState) -> String {
+    let auth = app_state.config.get().auth.clone();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let jar = SignedCookieJar::new(app_state.session_key.clone()).add(session_cookie(
+        &auth.users[0],
+        &auth,
+        now,
+    ));
+    let response = jar.into_response();
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&body).to_string())
+}
+
+fn with_token(method: Method, uri: &str, secret: &str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", secret))
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Create a token through the API and return the response body
+async fn create(app_state: &AppState, request: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/v1/tokens")
+        .header(header::COOKIE, session(app_state))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(request.to_string()))
+        .unwrap();
+    let (status, body) = send(app_router(app_state.clone()), request).await;
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[test]
+fn test_required_scopes() {
+    let cases = [
+        (Method::POST, "/wake/:device_id", Some(Scope::Wake)),
+        (Method::POST, "/shutdown/:device_id", Some(Scope::Shutdown)),
+        (Method::GET, "/ping/:device_id", Some(Scope::StatusRead)),
+        (
+            Method::GET,
+            "/api/v1/history/actions",
+            Some(Scope::StatusRead),
+        ),
+        (Method::GET, "/devices", Some(Scope::StatusRead)),
+        (Method::POST, "/devices", Some(Scope::ConfigWrite)),
+        (
+            Method::POST,
+            "/devices/:device_id/delete",
+            Some(Scope::ConfigWrite),
+        ),
+        (Method::POST, "/discovery/scan", Some(Scope::Discovery)),
+        (
+            Method::GET,
+            "/api/v1/discovery/new-devices",
+            Some(Scope::Discovery),
+        ),
+        (Method::GET, "/api/v1/tokens", None),
+        (Method::POST, "/logout", None),
+    ];
+    for (method, route, scope) in cases {
+        assert_eq!(
+            required_scope(&method, route),
+            scope,
+            "{} {}",
+            method,
+            route
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_create_list_and_revoke() {
+    let app_state = app_state();
+    let (status, created) = create(
+        &app_state,
+        json!({"name": "phone", "scopes": ["wake", "status:read"], "devices": ["nas"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let secret = created["token"].as_str().unwrap().to_string();
+    assert!(secret.starts_with(TOKEN_PREFIX));
+    assert_eq!(created["created_by"], "admin");
+    // Devices are stored by id
+    assert_eq!(created["devices"], json!(["aa-bb-cc-dd-ee-01"]));
+
+    // The list never shows the secret or its hash
+    let request = Request::builder()
+        .uri("/api/v1/tokens")
+        .header(header::COOKIE, session(&app_state))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send(app_router(app_state.clone()), request).await;
+    assert_eq!(status, StatusCode::OK);
+    let tokens: Vec<Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["name"], "phone");
+    assert_eq!(tokens[0]["last_used_at"], Value::Null);
+    assert!(!body.contains(&secret));
+    assert!(tokens[0].get("hash").is_none());
+
+    // Tokens cannot manage tokens
+    let (status, _) = send(
+        app_router(app_state.clone()),
+        with_token(Method::GET, "/api/v1/tokens", &secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let uri = format!("/api/v1/tokens/{}", created["id"].as_str().unwrap());
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri(&uri)
+        .header(header::COOKIE, session(&app_state))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(app_router(app_state.clone()), request).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // A revoked token no longer works, and revoking again finds nothing
+    let (status, _) = send(
+        app_router(app_state.clone()),
+        with_token(Method::POST, "/wake/nas", &secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri(&uri)
+        .header(header::COOKIE, session(&app_state))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(app_router(app_state), request).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_create_rejects_bad_requests() {
+    let app_state = app_state();
+    let (status, _) = create(&app_state, json!({"name": "x", "scopes": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create(
+        &app_state,
+        json!({"name": "x", "scopes": ["wake"], "devices": ["toaster"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create(
+        &app_state,
+        json!({"name": "x", "scopes": ["wake"], "groups": ["kitchen"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    // Unknown scopes are refused by the JSON parser
+    let (status, _) = create(&app_state, json!({"name": "x", "scopes": ["admin"]})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(app_state.tokens.all().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_scopes_are_enforced() {
+    let app_state = app_state();
+    let (_, created) = create(
+        &app_state,
+        json!({"name": "status", "scopes": ["status:read"]}),
+    )
+    .await;
+    let secret = created["token"].as_str().unwrap();
+    let app = app_router(app_state.clone());
+
+    let (status, _) = send(
+        app.clone(),
+        with_token(Method::GET, "/api/v1/history/actions", secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(app.clone(), with_token(Method::POST, "/wake/nas", secret)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "Token lacks the wake scope");
+
+    let (status, _) = send(
+        app.clone(),
+        with_token(Method::POST, "/discovery/scan", secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(app, with_token(Method::POST, "/wake/nas", "wol_nope")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, "Invalid API token");
+}
+
+#[tokio::test]
+async fn test_tokens_limited_to_devices_and_groups() {
+    let app_state = app_state();
+    let (_, created) = create(
+        &app_state,
+        json!({"name": "shortcut", "scopes": ["wake"], "devices": ["desktop"], "groups": ["servers"]}),
+    )
+    .await;
+    let secret = created["token"].as_str().unwrap();
+    let app = app_router(app_state.clone());
+
+    // By device, by group, and by id
+    for uri in ["/wake/desktop", "/wake/nas", "/wake/aa-bb-cc-dd-ee-03"] {
+        let (status, _) = send(app.clone(), with_token(Method::POST, uri, secret)).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+    }
+
+    let token = app_state.tokens.all().await.remove(0);
+    let laptop = device("laptop", "AA:BB:CC:DD:EE:04", &["mobile"]);
+    assert!(!token.allows_device(&laptop));
+    assert_eq!(
+        token.check(Scope::Wake, Some(Some(&laptop))),
+        Err("Token is not allowed to use device 'laptop'".to_string())
+    );
+
+    // Unknown devices are still a 404 from the route
+    let (status, _) = send(
+        app.clone(),
+        with_token(Method::POST, "/wake/toaster", secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Routes for all devices are out of reach
+    let (status, _) = send(
+        app,
+        with_token(Method::GET, "/api/v1/history/actions", secret),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_store_keeps_hashes_and_last_use() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("api_tokens.json");
+    let store = TokenStore::load(&path);
+    let (token, secret) = store
+        .create(ApiToken {
+            id: "t1".to_string(),
+            name: "backup script".to_string(),
+            hash: String::new(),
+            scopes: vec![Scope::Wake],
+            devices: Vec::new(),
+            groups: Vec::new(),
+            created_at: 1_000,
+            created_by: None,
+            last_used_at: None,
+        })
+        .await;
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains(&secret));
+    assert!(contents.contains(&token.hash));
+    assert_eq!(token.hash.len(), 64);
+
+    assert_eq!(
+        store
+            .authenticate(&secret, 2_000)
+            .await
+            .unwrap()
+            .last_used_at,
+        Some(2_000)
+    );
+    assert!(store.authenticate("wol_wrong", 2_000).await.is_none());
+    // Uses close together are not written again
+    store.authenticate(&secret, 2_030).await.unwrap();
+    let reloaded = TokenStore::load(&path).all().await;
+    assert_eq!(reloaded[0].last_used_at, Some(2_000));
+    store.authenticate(&secret, 2_100).await.unwrap();
+    let reloaded = TokenStore::load(&path).all().await;
+    assert_eq!(reloaded[0].last_used_at, Some(2_100));
+
+    assert!(store.revoke("t1").await);
+    assert!(TokenStore::load(&path).all().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_revoked_token_stays_revoked_on_disk() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("api_tokens.json");
+    let store = TokenStore::load(&path);
+    let mut secrets = Vec::new();
+    for id in ["t1", "t2"] {
+        let (_, secret) = store
+            .create(ApiToken {
+                id: id.to_string(),
+                name: id.to_string(),
+                hash: String::new(),
+                scopes: vec![Scope::StatusRead],
+                devices: Vec::new(),
+                groups: Vec::new(),
+                created_at: 1_000,
+                created_by: None,
+                last_used_at: None,
+            })
+            .await;
+        secrets.push(secret);
+    }
+
+    // Uses of the other token write the file while t1 is revoked
+    let uses = (0..20u64).map(|i| {
+        let store = store.clone();
+        let secret = secrets[1].clone();
+        tokio::spawn(async move { store.authenticate(&secret, 2_000 + i * 60).await })
+    });
+    let revoke = tokio::spawn({
+        let store = store.clone();
+        async move { store.revoke("t1").await }
+    });
+    for handle in uses {
+        handle.await.unwrap().unwrap();
+    }
+    assert!(revoke.await.unwrap());
+
+    let reloaded = TokenStore::load(&path).all().await;
+    assert_eq!(reloaded, store.all().await);
+    let ids: Vec<&str> = reloaded.iter().map(|token| token.id.as_str()).collect();
+    assert_eq!(ids, ["t2"]);
+}
+
+#[test]
+fn test_bearer_header() {
+    let mut headers = axum::http::HeaderMap::new();
+    assert_eq!(bearer_secret(&headers), None);
+    headers.insert(header::AUTHORIZATION, "Bearer wol_abc".parse().unwrap());
+    assert_eq!(bearer_secret(&headers), Some("wol_abc"));
+    headers.insert(header::AUTHORIZATION, "Basic YWRtaW4=".parse().unwrap());
+    assert_eq!(bearer_secret(&headers), None);
+}
+
+#[tokio::test]
+async fn test_websocket_commands_need_their_scope() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+
+    let app_state = app_state();
+    let (_, created) = create(
+        &app_state,
+        json!({"name": "dashboard", "scopes": ["status:read"]}),
+    )
+    .await;
+    let secret = created["token"].as_str().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = app_router(app_state);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let mut request = format!("ws://{}/api/v1/ws", address)
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        header::AUTHORIZATION,
+        format!("Bearer {}", secret).parse().unwrap(),
+    );
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    let wake = json!({"type": "wake", "id": 1, "device": "nas"});
+    socket.send(Message::text(wake.to_string())).await.unwrap();
+    let reply = match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+        other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(reply["ok"], false);
+    assert_eq!(reply["error"], "Token lacks the wake scope");
+}
@@ -0,0 +1,364 @@
+use wololo::validation::*;
+
+fn no_env(_: &str) -> Option<String> {
+    None
+}
+
+const VALID: &str = r#"# Living room and office
+server:
+  ip: "0.0.0.0"
+  port: 3000
+devices:
+  - name: nas
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+  - name: desktop
+    mac_address: "AA-BB-CC-DD-EE-02"
+    ip_address: "desktop.lan"
+"#;
+
+#[test]
+fn test_valid_config_has_no_issues() {
+    let (config, warnings) = check_config(VALID, "config.yaml", &no_env).unwrap();
+    assert_eq!(config.devices.len(), 2);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
+
+#[test]
+fn test_all_errors_reported_with_positions() {
+    let contents = r#"server:
+  ip: "0.0.0.0"
+sync:
+  interval_seconds: 0
+devices:
+  - name: nas
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+  - name: nas
+    mac_address: "aa-bb-cc-dd-ee-01"
+    ip_address: "192.168.1.11"
+  - name: printer
+    mac_address: "not-a-mac"
+    ip_address: "300.1.1.1"
+"#;
+
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let summary: Vec<(Severity, &str, Option<usize>, Option<usize>)> = issues
+        .iter()
+        .map(|issue| {
+            (
+                issue.severity,
+                issue.path.as_str(),
+                issue.line,
+                issue.column,
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            (Severity::Error, "sync.interval_seconds", Some(4), Some(21)),
+            (Severity::Error, "devices[1].name", Some(9), Some(11)),
+            (
+                Severity::Error,
+                "devices[1].mac_address",
+                Some(10),
+                Some(18)
+            ),
+            (
+                Severity::Error,
+                "devices[2].mac_address",
+                Some(13),
+                Some(18)
+            ),
+            (Severity::Error, "devices[2].ip_address", Some(14), Some(17)),
+        ]
+    );
+    assert_eq!(
+        issues[1].to_string(),
+        "config.yaml:9:11: error: devices[1].name: 'nas' is already used by devices[0]; names must be unique"
+    );
+}
+
+#[test]
+fn test_reserved_device_ids() {
+    let contents = r#"devices:
+  - id: manager
+    name: nas
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+"#;
+
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(
+        issues[0].to_string(),
+        "config.yaml:2:9: error: devices[0].id: 'manager' is reserved for a page under /devices/"
+    );
+
+    // An id generated from the name steps around the route instead
+    let device = wololo::config::Device {
+        name: "Manager".to_string(),
+        mac_address: "unknown".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(device.device_id(), "device-manager");
+}
+
+#[test]
+fn test_warnings_do_not_fail() {
+    let contents = r#"sync:
+  interval_seconds: 2
+devices:
+  - name: nas
+    mac_adress: "typo"
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+"#;
+
+    let (_, warnings) = check_config(contents, "config.yaml", &no_env).unwrap();
+    let paths: Vec<&str> = warnings.iter().map(|w| w.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec!["sync.interval_seconds", "devices[0].mac_adress"]
+    );
+    assert!(warnings.iter().all(|w| w.severity == Severity::Warning));
+}
+
+#[test]
+fn test_scan_concurrency_limit() {
+    let contents = "discovery:\n  max_concurrency: 100000\ndevices: []\n";
+    let errors = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "discovery.max_concurrency");
+    assert_eq!(errors[0].message, "must be at most 256");
+    assert_eq!(errors[0].line, Some(2));
+
+    let contents = "discovery:\n  max_concurrency: 256\ndevices: []\n";
+    let (config, _) = check_config(contents, "config.yaml", &no_env).unwrap();
+    assert_eq!(config.discovery.concurrency(), 256);
+    // The poller has its own setting
+    assert_eq!(config.poller.max_concurrency, 16);
+
+    let contents = "poller:\n  max_concurrency: 0\ndevices: []\n";
+    let errors = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    assert_eq!(errors[0].path, "poller.max_concurrency");
+    assert_eq!(errors[0].message, "must be between 1 and 256");
+}
+
+#[test]
+fn test_yaml_syntax_error_position() {
+    let issues = check_config("devices:\n  - name: [\n", "config.yaml", &no_env).unwrap_err();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].line.is_some());
+
+    let issues = check_config(
+        "server:\n  port: abc\ndevices: []\n",
+        "config.yaml",
+        &no_env,
+    )
+    .unwrap_err();
+    assert_eq!((issues[0].line, issues[0].column), (Some(2), Some(9)));
+    assert!(!issues[0].message.contains(" at line "));
+}
+
+#[test]
+fn test_yaml_positions() {
+    let positions = yaml_positions(VALID);
+    assert_eq!(positions.get("server.port"), Some(&(4, 9)));
+    assert_eq!(positions.get("devices[1]"), Some(&(9, 5)));
+    assert_eq!(positions.get("devices[1].ip_address"), Some(&(11, 17)));
+}
+
+#[test]
+fn test_device_id_errors() {
+    let contents = r#"devices:
+  - id: Build/01
+    name: build
+    mac_address: "AA:BB:CC:DD:EE:01"
+    ip_address: "192.168.1.10"
+  - id: aa-bb-cc-dd-ee-03
+    name: nas
+    mac_address: "AA:BB:CC:DD:EE:02"
+    ip_address: "192.168.1.11"
+  - name: printer
+    mac_address: "AA:BB:CC:DD:EE:03"
+    ip_address: "192.168.1.12"
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(String, Option<usize>)> = issues
+        .iter()
+        .map(|issue| (issue.path.clone(), issue.line))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("devices[0].id".to_string(), Some(2)),
+            // The printer's generated id collides with the id given to the NAS
+            ("devices[2].mac_address".to_string(), Some(11)),
+        ]
+    );
+}
+
+#[test]
+fn test_webhook_errors() {
+    let contents = r#"webhooks:
+  endpoints:
+    - url: "https://chat.example.com/hook"
+      events: [status_changed, wake]
+      template: '{"text": {{json change.device_name}'
+      secret: "s3cret"
+      retries: 3
+    - url: "chat.example.com"
+    - url: "https://chat.example.com/hook"
+devices: []
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(Severity, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "webhooks.endpoints[0].events[1]"),
+            (Severity::Error, "webhooks.endpoints[0].template"),
+            (Severity::Warning, "webhooks.endpoints[0].retries"),
+            (Severity::Error, "webhooks.endpoints[1].url"),
+            (Severity::Error, "webhooks.endpoints[2].url"),
+        ]
+    );
+    assert!(issues[4]
+        .message
+        .contains("already used by webhooks.endpoints[0]"));
+}
+
+#[test]
+fn test_mqtt_errors() {
+    let contents = r#"mqtt:
+  enabled: true
+  port: 0
+  password: "secret"
+  ca_file: "/nonexistent/ca.pem"
+  topic_prefix: "home/#"
+devices: []
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(Severity, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "mqtt.port"),
+            (Severity::Warning, "mqtt.password"),
+            (Severity::Warning, "mqtt.ca_file"),
+            (Severity::Error, "mqtt.topic_prefix"),
+        ]
+    );
+
+    // Disabled sections are not checked
+    let contents = contents.replace("enabled: true", "enabled: false");
+    assert!(check_config(&contents, "config.yaml", &no_env).is_ok());
+
+    let contents = r#"mqtt:
+  home_assistant:
+    enabled: true
+    discovery_prefix: ""
+devices: []
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(Severity, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Warning, "mqtt.home_assistant.enabled"),
+            (Severity::Error, "mqtt.home_assistant.discovery_prefix"),
+        ]
+    );
+}
+
+#[test]
+fn test_notification_errors() {
+    let contents = r#"notifications:
+  channels:
+    - name: email
+      type: smtp
+      host: "mail.example.com"
+      from: "wololo@example.com"
+      to: []
+      quiet_hours:
+        start: "22:00"
+        end: "7pm"
+    - name: email
+      type: ntfy
+      url: "ntfy.sh/alerts"
+      template: "{{title"
+  rules:
+    - channels: [email, pager]
+      events: [status_changed]
+      statuses: [down]
+devices: []
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(Severity, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "notifications.channels[0].to"),
+            (Severity::Error, "notifications.channels[0].quiet_hours.end"),
+            (Severity::Error, "notifications.channels[1].name"),
+            (Severity::Error, "notifications.channels[1].url"),
+            (Severity::Error, "notifications.channels[1].template"),
+            (Severity::Error, "notifications.rules[0].channels"),
+            (Severity::Error, "notifications.rules[0].statuses"),
+        ]
+    );
+}
+
+#[test]
+fn test_auth_errors() {
+    let contents = r#"auth:
+  enabled: true
+  users:
+    - username: admin
+      password_hash: "hunter2"
+    - username: admin
+      password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHRzYWx0$2b4SSHNWAsBYUIFsHI4Y8L0Ia0ps4oAN1yJyomcPZ4U"
+  session_hours: 0
+  session_secret: "short"
+  tokens_path: ""
+devices: []
+"#;
+    let issues = check_config(contents, "config.yaml", &no_env).unwrap_err();
+    let found: Vec<(Severity, &str)> = issues
+        .iter()
+        .map(|issue| (issue.severity, issue.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "auth.users[0].password_hash"),
+            (Severity::Error, "auth.users[1].username"),
+            (Severity::Error, "auth.session_hours"),
+            (Severity::Warning, "auth.session_secret"),
+            (Severity::Error, "auth.tokens_path"),
+        ]
+    );
+
+    let issues = check_config(
+        "auth:\n  enabled: true\ndevices: []\n",
+        "config.yaml",
+        &no_env,
+    )
+    .unwrap_err();
+    assert_eq!(issues[0].path, "auth.users");
+}
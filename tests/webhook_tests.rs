@@ -0,0 +1,264 @@
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use wololo::config::{Config, WebhookEndpoint, WebhooksConfig};
+use wololo::events::Event;
+use wololo::history::StatusChange;
+use wololo::routes::{AppState, DeviceStatus};
+use wololo::webhooks::*;
+
+fn status_event() -> Event {
+    Event::StatusChanged {
+        change: StatusChange {
+            device_id: "aa-bb-cc-dd-ee-01".to_string(),
+            device_name: "NAS \"main\"".to_string(),
+            status: DeviceStatus::Online,
+            previous: Some(DeviceStatus::Offline),
+            at: 1_700_000_000,
+        },
+    }
+}
+
+fn app_state(endpoints: Vec<WebhookEndpoint>) -> AppState {
+    AppState::new_for_test(Config {
+        webhooks: WebhooksConfig {
+            endpoints,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_signature_and_backoff() {
+    assert_eq!(
+        sign("key", "The quick brown fox jumps over the lazy dog"),
+        "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+    let delays: Vec<u64> = (1..=4).map(retry_delay).collect();
+    assert_eq!(delays, [10, 20, 40, 80]);
+    assert_eq!(retry_delay(30), 3600);
+}
+
+#[test]
+fn test_deliveries_follow_filters_and_templates() {
+    let state = app_state(vec![
+        WebhookEndpoint {
+            url: "http://bot.lan/all".to_string(),
+            ..Default::default()
+        },
+        WebhookEndpoint {
+            url: "http://bot.lan/chat".to_string(),
+            events: vec!["status_changed".to_string()],
+            template: Some(
+                r#"{"text": {{json change.device_name}}, "online": {{json change.status}}}"#
+                    .to_string(),
+            ),
+            ..Default::default()
+        },
+        WebhookEndpoint {
+            url: "http://bot.lan/wakes".to_string(),
+            events: vec!["device_action".to_string()],
+            ..Default::default()
+        },
+    ]);
+    let config = state.config.get();
+
+    let deliveries = deliveries_for(&status_event(), &config, 100);
+    let urls: Vec<&str> = deliveries.iter().map(|d| d.url.as_str()).collect();
+    assert_eq!(urls, ["http://bot.lan/all", "http://bot.lan/chat"]);
+    assert!(deliveries.iter().all(|d| d.event == "status_changed"));
+
+    // Without a template the body is the event itself
+    let event: serde_json::Value = serde_json::from_str(&deliveries[0].body).unwrap();
+    assert_eq!(event["type"], "status_changed");
+    assert_eq!(event["change"]["previous"], "offline");
+
+    // `json` quotes and escapes values so the template stays valid JSON
+    let chat: serde_json::Value = serde_json::from_str(&deliveries[1].body).unwrap();
+    assert_eq!(chat["text"], "NAS \"main\"");
+    assert_eq!(chat["online"], "online");
+
+    // Plain values are written as they are, not HTML-escaped
+    let endpoint = WebhookEndpoint {
+        template: Some("{{change.device_name}} & {{change.status}}".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        render_body(&endpoint, &status_event()),
+        r#"NAS "main" & online"#
+    );
+}
+
+#[derive(Clone, Default)]
+struct Receiver {
+    failures_left: Arc<Mutex<u32>>,
+    received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+}
+
+async fn receive(State(receiver): State<Receiver>, headers: HeaderMap, body: String) -> StatusCode {
+    receiver.received.lock().unwrap().push((headers, body));
+    let mut failures_left = receiver.failures_left.lock().unwrap();
+    if *failures_left > 0 {
+        *failures_left -= 1;
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::NO_CONTENT
+    }
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_are_retried_and_signed() {
+    let receiver = Receiver {
+        failures_left: Arc::new(Mutex::new(1)),
+        ..Default::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/hook", post(receive))
+        .with_state(receiver.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let state = app_state(vec![WebhookEndpoint {
+        url: url.clone(),
+        headers: BTreeMap::from([("Authorization".to_string(), "Bearer token".to_string())]),
+        secret: Some("s3cret".to_string()),
+        ..Default::default()
+    }]);
+    let config = state.config.get();
+    state
+        .webhooks
+        .push(deliveries_for(&status_event(), &config, 1_000))
+        .await;
+    let client = http_client();
+
+    // The first attempt gets a 500 and is rescheduled
+    assert_eq!(deliver_due(&state, &client, 1_000).await, 0);
+    let queued = state.webhooks.all().await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].attempts, 1);
+    assert_eq!(queued[0].next_attempt_at, 1_010);
+    assert_eq!(
+        queued[0].last_error.as_deref(),
+        Some("HTTP 500 Internal Server Error")
+    );
+
+    // Not due yet
+    assert_eq!(deliver_due(&state, &client, 1_005).await, 0);
+    assert_eq!(receiver.received.lock().unwrap().len(), 1);
+
+    assert_eq!(deliver_due(&state, &client, 1_010).await, 1);
+    assert!(state.webhooks.all().await.is_empty());
+
+    let received = receiver.received.lock().unwrap();
+    let (headers, body) = &received[1];
+    assert_eq!(headers["content-type"], "application/json");
+    assert_eq!(headers["authorization"], "Bearer token");
+    assert_eq!(headers["x-wololo-event"], "status_changed");
+    assert_eq!(headers["x-wololo-delivery"], queued[0].id.as_str());
+    assert_eq!(headers["x-wololo-signature"], sign("s3cret", body).as_str());
+}
+
+#[tokio::test]
+async fn test_queue_survives_restarts_and_gives_up() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("queue.json");
+    let state = AppState {
+        webhooks: WebhookQueue::load(&path),
+        ..app_state(vec![WebhookEndpoint {
+            // Nothing listens on port 9 of the loopback address
+            url: "http://127.0.0.1:9/hook".to_string(),
+            ..Default::default()
+        }])
+    };
+    let config = state.config.get();
+    state
+        .webhooks
+        .push(deliveries_for(&status_event(), &config, 1_000))
+        .await;
+
+    let reloaded = WebhookQueue::load(&path).all().await;
+    assert_eq!(reloaded, state.webhooks.all().await);
+    assert_eq!(reloaded.len(), 1);
+    // Headers and secrets are looked up when sending and never written to the file
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("headers"));
+
+    // max_attempts defaults to 8
+    let client = http_client();
+    let mut now = 1_000;
+    for _ in 0..8 {
+        assert_eq!(deliver_due(&state, &client, now).await, 0);
+        now += 3_600;
+    }
+    assert!(state.webhooks.all().await.is_empty());
+    assert_eq!(WebhookQueue::load(&path).all().await, []);
+}
+
+#[tokio::test]
+async fn test_deliveries_to_an_endpoint_stay_in_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = Receiver::default();
+    let app = Router::new()
+        .route("/ok", post(receive))
+        .with_state(receiver.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("queue.json");
+    let state = AppState {
+        webhooks: WebhookQueue::load(&path),
+        ..app_state(vec![
+            WebhookEndpoint {
+                url: format!("http://{}/ok", addr),
+                ..Default::default()
+            },
+            WebhookEndpoint {
+                url: "http://127.0.0.1:9/down".to_string(),
+                ..Default::default()
+            },
+        ])
+    };
+    let config = state.config.get();
+    for _ in 0..5 {
+        state
+            .webhooks
+            .push(deliveries_for(&status_event(), &config, 1_000))
+            .await;
+    }
+
+    let queued: Vec<String> = state
+        .webhooks
+        .all()
+        .await
+        .into_iter()
+        .filter(|delivery| delivery.url.ends_with("/ok"))
+        .map(|delivery| delivery.id)
+        .collect();
+
+    // The working endpoint gets all five, in the order they were queued
+    assert_eq!(deliver_due(&state, &http_client(), 1_000).await, 5);
+    let received: Vec<String> = receiver
+        .received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(headers, _)| headers["x-wololo-delivery"].to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(received, queued);
+
+    // The first delivery to the failing endpoint is rescheduled and holds back the other four
+    let reloaded = WebhookQueue::load(&path).all().await;
+    assert_eq!(reloaded, state.webhooks.all().await);
+    assert_eq!(reloaded.len(), 5);
+    assert!(reloaded
+        .iter()
+        .all(|delivery| delivery.url.ends_with("/down")));
+    let attempts: Vec<u32> = reloaded.iter().map(|delivery| delivery.attempts).collect();
+    assert_eq!(attempts, [1, 0, 0, 0, 0]);
+    assert!(state.webhooks.due(1_005).await.is_empty());
+    assert_eq!(state.webhooks.due(1_010).await.len(), 5);
+}
@@ -0,0 +1,296 @@
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use wololo::config::{Config, Device};
+use wololo::events::Event;
+use wololo::history::{ActionKind, ActionRecord};
+use wololo::routes::{self, AppState};
+use wololo::websocket::{parse_client_message, ClientMessage, Subscription};
+
+fn test_config() -> Config {
+    Config {
+        devices: vec![
+            Device {
+                name: "NAS".to_string(),
+                mac_address: "AA:BB:CC:DD:EE:01".to_string(),
+                ip_address: "192.0.2.10".to_string(),
+                groups: vec!["servers".to_string()],
+                shutdown_command: vec!["true".to_string()],
+                ..Default::default()
+            },
+            Device {
+                name: "Desktop".to_string(),
+                mac_address: "AA:BB:CC:DD:EE:02".to_string(),
+                ip_address: "192.0.2.11".to_string(),
+                tags: vec!["gaming".to_string()],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+fn action_event(device_id: &str) -> Event {
+    Event::DeviceAction {
+        action: ActionRecord {
+            device_id: device_id.to_string(),
+            device_name: device_id.to_string(),
+            action: ActionKind::Wake,
+            source: "web".to_string(),
+            success: true,
+            detail: None,
+            at: 100,
+        },
+    }
+}
+
+#[test]
+fn test_subscription_filters() {
+    let config = test_config();
+    let nas = action_event("aa-bb-cc-dd-ee-01");
+    let desktop = action_event("aa-bb-cc-dd-ee-02");
+    let reload = Event::ConfigReloaded { device_count: 2 };
+
+    let everything = Subscription::default();
+    assert!(everything.matches(&nas, &config) && everything.matches(&reload, &config));
+
+    // Devices by name or id, groups and tags are combined
+    for subscription in [
+        Subscription {
+            devices: vec!["NAS".to_string()],
+            ..Default::default()
+        },
+        Subscription {
+            devices: vec!["aa-bb-cc-dd-ee-01".to_string()],
+            ..Default::default()
+        },
+        Subscription {
+            groups: vec!["servers".to_string()],
+            ..Default::default()
+        },
+    ] {
+        assert!(subscription.matches(&nas, &config));
+        assert!(!subscription.matches(&desktop, &config));
+        // Events that are not about a device are not filtered by device
+        assert!(subscription.matches(&reload, &config));
+    }
+    let gaming = Subscription {
+        groups: vec!["servers".to_string()],
+        tags: vec!["gaming".to_string()],
+        ..Default::default()
+    };
+    assert!(gaming.matches(&nas, &config) && gaming.matches(&desktop, &config));
+
+    let statuses = Subscription {
+        events: vec!["status_changed".to_string()],
+        ..Default::default()
+    };
+    assert!(!statuses.matches(&nas, &config));
+    assert!(!statuses.matches(&reload, &config));
+    assert!(statuses.validate().is_ok());
+    let typo = Subscription {
+        events: vec!["status_change".to_string()],
+        ..Default::default()
+    };
+    assert!(typo.validate().unwrap_err().contains("status_change'"));
+}
+
+#[test]
+fn test_client_message_schema() {
+    let message =
+        parse_client_message(r#"{"type":"subscribe","id":1,"groups":["servers"]}"#).unwrap();
+    assert_eq!(
+        message,
+        ClientMessage::Subscribe {
+            id: Some(json!(1)),
+            subscription: Subscription {
+                groups: vec!["servers".to_string()],
+                ..Default::default()
+            },
+        }
+    );
+    assert_eq!(
+        parse_client_message(r#"{"type":"status"}"#).unwrap(),
+        ClientMessage::Status {
+            id: None,
+            device: None
+        }
+    );
+
+    // The id is echoed back even when the message is invalid
+    let error = parse_client_message(r#"{"type":"wake","id":"a1"}"#).unwrap_err();
+    let error = serde_json::to_value(error).unwrap();
+    assert_eq!(error["type"], "reply");
+    assert_eq!(error["id"], "a1");
+    assert_eq!(error["ok"], false);
+}
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+// Next text message, as JSON
+async fn receive(socket: &mut Socket) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message within 5 seconds")
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_commands_and_events() {
+    let app_state = AppState::new_for_test(test_config());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = routes::app_router(app_state.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/api/v1/ws", address))
+        .await
+        .unwrap();
+    send(
+        &mut socket,
+        json!({ "type": "subscribe", "id": 1, "devices": ["NAS"] }),
+    )
+    .await;
+    let reply = receive(&mut socket).await;
+    assert_eq!(
+        (reply["id"].clone(), reply["ok"].clone()),
+        (json!(1), json!(true))
+    );
+
+    // The shutdown reply and the recorded action both arrive; only NAS events pass the filter
+    send(
+        &mut socket,
+        json!({ "type": "shutdown", "id": "off", "device": "NAS" }),
+    )
+    .await;
+    let mut received = [receive(&mut socket).await, receive(&mut socket).await];
+    received.sort_by_key(|message| message["type"].as_str().unwrap().to_string());
+    assert_eq!(received[0]["event"]["type"], "device_action");
+    assert_eq!(received[0]["event"]["action"]["source"], "websocket");
+    assert_eq!(received[1]["type"], "reply");
+    assert_eq!(received[1]["id"], "off");
+    assert_eq!(received[1]["result"]["device_id"], "aa-bb-cc-dd-ee-01");
+
+    app_state.events.publish(action_event("aa-bb-cc-dd-ee-02"));
+    send(
+        &mut socket,
+        json!({ "type": "shutdown", "id": 3, "device": "Desktop" }),
+    )
+    .await;
+    let reply = receive(&mut socket).await;
+    assert_eq!(reply["id"], 3);
+    assert_eq!(reply["ok"], false);
+    assert_eq!(reply["error"], "Desktop has no shutdown_command configured");
+
+    send(
+        &mut socket,
+        json!({ "type": "wake", "id": 4, "device": "Nope" }),
+    )
+    .await;
+    let reply = receive(&mut socket).await;
+    assert_eq!(reply["error"], "Device 'Nope' not found");
+
+    send(&mut socket, json!({ "type": "reboot", "id": 5 })).await;
+    let reply = receive(&mut socket).await;
+    assert_eq!(reply["id"], 5);
+    assert!(reply["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid message"));
+}
+
+#[test]
+fn test_origin_check() {
+    use axum::http::{header, HeaderMap};
+    use wololo::websocket::origin_allowed;
+
+    let mut config = test_config();
+    config.server.external_url = "https://wol.example.com/".to_string();
+    let headers = |origin: Option<&str>| {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "192.168.1.5:3000".parse().unwrap());
+        if let Some(origin) = origin {
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+        }
+        headers
+    };
+
+    // Scripts send no Origin
+    assert!(origin_allowed(&headers(None), &config));
+    assert!(origin_allowed(
+        &headers(Some("http://192.168.1.5:3000")),
+        &config
+    ));
+    // Behind a proxy the Host may differ, but external_url still matches
+    assert!(origin_allowed(
+        &headers(Some("https://wol.example.com")),
+        &config
+    ));
+    assert!(!origin_allowed(
+        &headers(Some("https://evil.example")),
+        &config
+    ));
+    assert!(!origin_allowed(
+        &headers(Some("http://192.168.1.5:3001")),
+        &config
+    ));
+    assert!(!origin_allowed(&headers(Some("null")), &config));
+}
+
+#[tokio::test]
+async fn test_cross_origin_upgrade_is_rejected() {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let app_state = AppState::new_for_test(test_config());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = routes::app_router(app_state);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let mut request = format!("ws://{}/api/v1/ws", address)
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("origin", "https://evil.example".parse().unwrap());
+    match tokio_tungstenite::connect_async(request).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 403);
+        }
+        other => panic!("expected 403, got {:?}", other.map(|_| ())),
+    }
+
+    // The dashboard's own origin connects, and a status of every device comes back in order
+    let mut request = format!("ws://{}/api/v1/ws", address)
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("origin", format!("http://{}", address).parse().unwrap());
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    send(&mut socket, json!({ "type": "status", "id": 1 })).await;
+    let reply = receive(&mut socket).await;
+    let names: Vec<&str> = reply["result"]["devices"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|device| device["device_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["NAS", "Desktop"]);
+}